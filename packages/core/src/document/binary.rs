@@ -0,0 +1,1005 @@
+//! Compact binary document format
+//!
+//! JSON is convenient but large files (tens of thousands of nodes) spend most
+//! of their bytes on repeated keys and most of their load time in the parser.
+//! This format stores the same data much more tightly:
+//!
+//! ```text
+//! ┌──────────────────────────────────────────────┐
+//! │ "ANAT"  magic                  4 bytes       │
+//! │ format version                 u16           │
+//! │ reserved flags                 u16           │
+//! ├──────────────────────────────────────────────┤
+//! │ string table                                 │ ← every name, font, text
+//! │   count, then (len, utf8 bytes) per string   │   and order index, once
+//! ├──────────────────────────────────────────────┤
+//! │ document name (string ref), version          │
+//! │ root node record                             │
+//! ├──────────────────────────────────────────────┤
+//! │ chunk count                                  │
+//! │ chunk: kind u8, byte length u32, subtree     │ ← one chunk per page
+//! │ chunk: ...                                   │
+//! │ chunk: kind u8, byte length u32, image file  │ ← one chunk per image
+//! └──────────────────────────────────────────────┘
+//! ```
+//!
+//! Integers are LEB128 varints, floats are little-endian `f32`. A node record
+//! is `id, type tag, order index, properties, child count, children...`, so
+//! the tree structure is implied by the nesting and no parent ids are stored.
+//!
+//! Every page lives in its own length-prefixed chunk, which lets a reader
+//! skip or defer pages it doesn't need yet. Images in use are stored as the
+//! original PNG/JPEG bytes, one chunk each; their hashes are recomputed on
+//! load.
+//!
+//! ## Tags
+//!
+//! `Property`, `NodeType`, `ScaleMode`, `GradientType` and `BlendMode` are
+//! written as their index in [`PROPERTY_TAGS`], [`NODE_TYPE_TAGS`],
+//! [`SCALE_MODE_TAGS`], [`GRADIENT_TYPE_TAGS`] and [`BLEND_MODE_TAGS`]. New
+//! variants must be appended to the end of those tables; reordering them
+//! breaks every existing file. Encoding a value that is missing from its
+//! table is an error rather than a silently wrong tag.
+//!
+//! Subtrees nest at most [`MAX_DEPTH`] levels deep, so a crafted file can't
+//! exhaust the stack of the recursive reader.
+
+use super::{
+    check_tree, BlendMode, Color, ColorStop, Document, DocumentTree, Effect, EffectKind, FormatError, Gradient,
+    GradientType, ImagePaint, Node, NodeType, ObjectId, Paint, PaintKind, Property, PropertyValue, ScaleMode, Shadow,
+};
+use std::collections::HashMap;
+
+/// File signature
+const MAGIC: &[u8; 4] = b"ANAT";
+
+/// The binary format version written by this build.
+///
+/// - 1: initial format
+/// - 2: node records end with a list of overridden property tags
+/// - 3: order indices are base 62 keys (older files get fresh ones on load)
+/// - 4: image chunks and image paint values
+/// - 5: paint list values
+/// - 6: effect list values
+pub const BINARY_VERSION: u16 = 6;
+
+/// Deepest nesting of nodes below a chunk's root that is read or written
+const MAX_DEPTH: usize = 256;
+
+/// Chunk holding a child of the root (normally a page)
+const CHUNK_PAGE: u8 = 0;
+/// Chunk holding a subtree that isn't attached to the root. No longer
+/// written; loading a file that has one fails like any other orphan.
+const CHUNK_DETACHED: u8 = 1;
+/// Chunk holding an image file
+const CHUNK_IMAGE: u8 = 2;
+
+/// Wire tags for node types. Append only.
+const NODE_TYPE_TAGS: &[NodeType] = &[
+    NodeType::Document,
+    NodeType::Page,
+    NodeType::Frame,
+    NodeType::Group,
+    NodeType::Rectangle,
+    NodeType::Ellipse,
+    NodeType::Line,
+    NodeType::Vector,
+    NodeType::Text,
+    NodeType::Image,
+    NodeType::Component,
+    NodeType::Instance,
+    NodeType::BooleanOperation,
+    NodeType::Style,
+    NodeType::VariableCollection,
+    NodeType::VariableMode,
+    NodeType::Variable,
+];
+
+/// Wire tags for properties. Append only.
+const PROPERTY_TAGS: &[Property] = &[
+    Property::X,
+    Property::Y,
+    Property::Width,
+    Property::Height,
+    Property::Rotation,
+    Property::Opacity,
+    Property::Visible,
+    Property::Locked,
+    Property::FillColor,
+    Property::FillOpacity,
+    Property::StrokeColor,
+    Property::StrokeWidth,
+    Property::StrokeOpacity,
+    Property::StrokeAlign,
+    Property::StrokeCap,
+    Property::StrokeJoin,
+    Property::CornerRadius,
+    Property::Text,
+    Property::FontFamily,
+    Property::FontSize,
+    Property::FontWeight,
+    Property::FontStyle,
+    Property::TextAlign,
+    Property::LineHeight,
+    Property::LetterSpacing,
+    Property::BlurRadius,
+    Property::ShadowColor,
+    Property::ShadowOffsetX,
+    Property::ShadowOffsetY,
+    Property::ShadowBlur,
+    Property::ShadowSpread,
+    Property::LayoutMode,
+    Property::LayoutDirection,
+    Property::LayoutGap,
+    Property::LayoutPadding,
+    Property::LayoutAlign,
+    Property::Name,
+    Property::Description,
+    Property::ParentId,
+    Property::MainComponent,
+    Property::ComponentSource,
+    Property::LayoutSizingHorizontal,
+    Property::LayoutSizingVertical,
+    Property::ConstraintHorizontal,
+    Property::ConstraintVertical,
+    Property::BooleanOperation,
+    Property::VectorData,
+    Property::StyleType,
+    Property::FillStyle,
+    Property::StrokeStyle,
+    Property::TextStyle,
+    Property::EffectStyle,
+    Property::VariableType,
+    Property::VariableValues,
+    Property::VariableModes,
+    Property::ImageFill,
+    Property::Fills,
+    Property::Strokes,
+    Property::Effects,
+    Property::BlendMode,
+    Property::ClipContent,
+    Property::IsMask,
+    Property::MaskType,
+    Property::TextAutoResize,
+];
+
+/// Wire tags for image scale modes. Append only.
+const SCALE_MODE_TAGS: &[ScaleMode] = &[ScaleMode::Fill, ScaleMode::Fit, ScaleMode::Crop, ScaleMode::Tile];
+
+/// Wire tags for gradient types. Append only.
+const GRADIENT_TYPE_TAGS: &[GradientType] =
+    &[GradientType::Linear, GradientType::Radial, GradientType::Angular, GradientType::Diamond];
+
+/// Wire tags for blend modes. Append only.
+const BLEND_MODE_TAGS: &[BlendMode] = &[
+    BlendMode::Normal,
+    BlendMode::Multiply,
+    BlendMode::Screen,
+    BlendMode::Overlay,
+    BlendMode::Darken,
+    BlendMode::Lighten,
+    BlendMode::ColorDodge,
+    BlendMode::ColorBurn,
+    BlendMode::HardLight,
+    BlendMode::SoftLight,
+    BlendMode::Difference,
+    BlendMode::Exclusion,
+    BlendMode::Hue,
+    BlendMode::Saturation,
+    BlendMode::Color,
+    BlendMode::Luminosity,
+];
+
+// PropertyValue tags
+const VALUE_FLOAT: u8 = 0;
+const VALUE_INT: u8 = 1;
+const VALUE_FALSE: u8 = 2;
+const VALUE_TRUE: u8 = 3;
+const VALUE_STRING: u8 = 4;
+const VALUE_COLOR: u8 = 5;
+const VALUE_VEC2: u8 = 6;
+const VALUE_VEC4: u8 = 7;
+const VALUE_REF: u8 = 8;
+const VALUE_VARIABLE: u8 = 9;
+const VALUE_MAP: u8 = 10;
+const VALUE_IMAGE: u8 = 11;
+const VALUE_PAINTS: u8 = 12;
+const VALUE_EFFECTS: u8 = 13;
+
+// Paint kind tags
+const PAINT_SOLID: u8 = 0;
+const PAINT_GRADIENT: u8 = 1;
+const PAINT_IMAGE: u8 = 2;
+
+// Effect kind tags
+const EFFECT_DROP_SHADOW: u8 = 0;
+const EFFECT_INNER_SHADOW: u8 = 1;
+const EFFECT_LAYER_BLUR: u8 = 2;
+const EFFECT_BACKGROUND_BLUR: u8 = 3;
+
+impl Document {
+    /// Encode the document in the binary format
+    pub(crate) fn encode_binary(&self) -> Result<Vec<u8>, FormatError> {
+        let mut encoder = Encoder::default();
+        let tree = &self.tree;
+        let root_id = tree.root_id();
+
+        let name = encoder.intern(&self.name);
+        encoder.body.write_varint(name as u64);
+        encoder.body.write_varint(self.version as u64);
+
+        // The root record carries no children; they follow as chunks
+        if let Some(root) = tree.get(root_id) {
+            encoder.write_node(root);
+        }
+
+        let pages = tree.children(root_id);
+        let images = self.images_used_by(tree.iter());
+
+        encoder.body.write_varint((pages.len() + images.len()) as u64);
+        for id in pages {
+            encoder.write_chunk(tree, CHUNK_PAGE, id);
+        }
+        for image in images {
+            encoder.body.push(CHUNK_IMAGE);
+            encoder.body.extend_from_slice(&(image.bytes().len() as u32).to_le_bytes());
+            encoder.body.extend_from_slice(image.bytes());
+        }
+
+        encoder.finish()
+    }
+
+    /// Decode a document written by `encode_binary()`
+    pub(crate) fn decode_binary(bytes: &[u8]) -> Result<Document, FormatError> {
+        let mut reader = Reader::new(bytes);
+
+        if reader.read_bytes(4)? != MAGIC {
+            return Err(FormatError::Invalid("not an Anatsui binary document".into()));
+        }
+        let version = reader.read_u16()?;
+        if version == 0 || version > BINARY_VERSION {
+            return Err(FormatError::UnsupportedVersion {
+                found: version as u32,
+                supported: BINARY_VERSION as u32,
+            });
+        }
+        let _flags = reader.read_u16()?;
+
+        let string_count = reader.read_len()?;
+        let mut strings = Vec::with_capacity(string_count);
+        for _ in 0..string_count {
+            let len = reader.read_len()?;
+            let text = std::str::from_utf8(reader.read_bytes(len)?)
+                .map_err(|_| FormatError::Invalid("string table is not UTF-8".into()))?;
+            strings.push(text.to_string());
+        }
+
+        let mut decoder = Decoder {
+            version,
+            strings: &strings,
+            tree: DocumentTree::new(),
+        };
+        let name = decoder.read_string(&mut reader)?;
+        let doc_version = reader.read_varint()? as u32;
+
+        let root = decoder.read_node(&mut reader)?;
+        let root_id = root.id();
+        decoder.tree = DocumentTree::with_root(root);
+
+        let mut images = Vec::new();
+        let chunk_count = reader.read_len()?;
+        for _ in 0..chunk_count {
+            let kind = reader.read_u8()?;
+            let len = reader.read_u32()? as usize;
+            let bytes = reader.read_bytes(len)?;
+            if kind == CHUNK_IMAGE {
+                images.push(bytes);
+                continue;
+            }
+            let mut chunk = Reader::new(bytes);
+            let parent = match kind {
+                CHUNK_PAGE => Some(root_id),
+                CHUNK_DETACHED => None,
+                _ => return Err(FormatError::Invalid(format!("unknown chunk kind {}", kind))),
+            };
+            decoder.read_subtree(&mut chunk, parent, 0)?;
+            if !chunk.is_empty() {
+                return Err(FormatError::Invalid("trailing bytes in chunk".into()));
+            }
+        }
+
+        check_tree(&decoder.tree)?;
+        if version < 3 {
+            decoder.tree.reset_order_indices();
+        }
+        let mut document = Document::from_tree(decoder.tree, name, doc_version);
+        for bytes in images {
+            document
+                .insert_image(bytes.to_vec())
+                .map_err(|e| FormatError::Invalid(e.to_string()))?;
+        }
+        Ok(document)
+    }
+}
+
+/// Accumulates the string table and body while encoding
+#[derive(Default)]
+struct Encoder {
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
+    body: Vec<u8>,
+    /// First problem found; the output is discarded if set
+    error: Option<FormatError>,
+}
+
+impl Encoder {
+    /// Get the string table index for a string, adding it if needed
+    fn intern(&mut self, text: &str) -> u32 {
+        if let Some(&index) = self.string_ids.get(text) {
+            return index;
+        }
+        let index = self.strings.len() as u32;
+        self.strings.push(text.to_string());
+        self.string_ids.insert(text.to_string(), index);
+        index
+    }
+
+    /// Write one subtree as a length-prefixed chunk
+    fn write_chunk(&mut self, tree: &DocumentTree, kind: u8, id: ObjectId) {
+        let outer = std::mem::take(&mut self.body);
+        self.write_subtree(tree, id, 0);
+        let chunk = std::mem::replace(&mut self.body, outer);
+
+        self.body.push(kind);
+        self.body.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        self.body.extend_from_slice(&chunk);
+    }
+
+    fn write_subtree(&mut self, tree: &DocumentTree, id: ObjectId, depth: usize) {
+        let Some(node) = tree.get(id) else { return };
+        if depth > MAX_DEPTH {
+            self.fail(FormatError::Invalid(format!("nodes are nested deeper than {} levels", MAX_DEPTH)));
+            return;
+        }
+        self.write_node(node);
+
+        let children = tree.children(id);
+        self.body.write_varint(children.len() as u64);
+        for child_id in children {
+            self.write_subtree(tree, child_id, depth + 1);
+        }
+    }
+
+    fn write_node(&mut self, node: &Node) {
+        self.write_id(node.id());
+        let node_type = self.tag(NODE_TYPE_TAGS, node.node_type());
+        self.body.push(node_type);
+        let order_index = self.intern(node.order_index());
+        self.body.write_varint(order_index as u64);
+
+        // Sort by tag so the same document always encodes to the same bytes
+        let mut properties: Vec<(u8, &PropertyValue)> = node
+            .properties()
+            .iter()
+            .map(|(&property, value)| (self.tag(PROPERTY_TAGS, property), value))
+            .collect();
+        properties.sort_by_key(|&(tag, _)| tag);
+
+        self.body.write_varint(properties.len() as u64);
+        for (tag, value) in properties {
+            self.body.push(tag);
+            self.write_value(value);
+        }
+
+        self.body.write_varint(node.overrides().len() as u64);
+        for &property in node.overrides() {
+            let tag = self.tag(PROPERTY_TAGS, property);
+            self.body.push(tag);
+        }
+    }
+
+    fn write_value(&mut self, value: &PropertyValue) {
+        match value {
+            PropertyValue::Float(v) => {
+                self.body.push(VALUE_FLOAT);
+                self.body.write_f32(*v);
+            }
+            PropertyValue::Int(v) => {
+                self.body.push(VALUE_INT);
+                // Zigzag so small negative numbers stay small
+                self.body.write_varint(((*v << 1) ^ (*v >> 31)) as u32 as u64);
+            }
+            PropertyValue::Bool(v) => {
+                self.body.push(if *v { VALUE_TRUE } else { VALUE_FALSE });
+            }
+            PropertyValue::String(s) => {
+                self.body.push(VALUE_STRING);
+                let index = self.intern(s);
+                self.body.write_varint(index as u64);
+            }
+            PropertyValue::Color(c) => {
+                self.body.push(VALUE_COLOR);
+                for component in [c.r, c.g, c.b, c.a] {
+                    self.body.write_f32(component);
+                }
+            }
+            PropertyValue::Vec2(x, y) => {
+                self.body.push(VALUE_VEC2);
+                self.body.write_f32(*x);
+                self.body.write_f32(*y);
+            }
+            PropertyValue::Vec4(x, y, z, w) => {
+                self.body.push(VALUE_VEC4);
+                for component in [*x, *y, *z, *w] {
+                    self.body.write_f32(component);
+                }
+            }
+            PropertyValue::Ref(id) => {
+                self.body.push(VALUE_REF);
+                self.write_id(*id);
+            }
+            PropertyValue::Variable(id) => {
+                self.body.push(VALUE_VARIABLE);
+                self.write_id(*id);
+            }
+            PropertyValue::Map(entries) => {
+                self.body.push(VALUE_MAP);
+                self.body.write_varint(entries.len() as u64);
+                for (id, value) in entries {
+                    self.write_id(*id);
+                    self.write_value(value);
+                }
+            }
+            PropertyValue::Image(paint) => {
+                self.body.push(VALUE_IMAGE);
+                self.write_image_paint(paint);
+            }
+            PropertyValue::Paints(paints) => {
+                self.body.push(VALUE_PAINTS);
+                self.body.write_varint(paints.len() as u64);
+                for paint in paints {
+                    self.write_paint(paint);
+                }
+            }
+            PropertyValue::Effects(effects) => {
+                self.body.push(VALUE_EFFECTS);
+                self.body.write_varint(effects.len() as u64);
+                for effect in effects {
+                    self.write_effect(effect);
+                }
+            }
+        }
+    }
+
+    fn write_effect(&mut self, effect: &Effect) {
+        match effect.kind {
+            EffectKind::DropShadow(shadow) => {
+                self.body.push(EFFECT_DROP_SHADOW);
+                self.write_shadow(&shadow);
+            }
+            EffectKind::InnerShadow(shadow) => {
+                self.body.push(EFFECT_INNER_SHADOW);
+                self.write_shadow(&shadow);
+            }
+            EffectKind::LayerBlur { radius } => {
+                self.body.push(EFFECT_LAYER_BLUR);
+                self.body.write_f32(radius);
+            }
+            EffectKind::BackgroundBlur { radius } => {
+                self.body.push(EFFECT_BACKGROUND_BLUR);
+                self.body.write_f32(radius);
+            }
+        }
+        self.body.push(effect.visible as u8);
+    }
+
+    fn write_shadow(&mut self, shadow: &Shadow) {
+        self.write_color(shadow.color);
+        for component in [shadow.offset_x, shadow.offset_y, shadow.blur, shadow.spread] {
+            self.body.write_f32(component);
+        }
+    }
+
+    fn write_paint(&mut self, paint: &Paint) {
+        match &paint.kind {
+            PaintKind::Solid { color } => {
+                self.body.push(PAINT_SOLID);
+                self.write_color(*color);
+            }
+            PaintKind::Gradient(gradient) => {
+                self.body.push(PAINT_GRADIENT);
+                let gradient_type = self.tag(GRADIENT_TYPE_TAGS, gradient.gradient_type);
+                self.body.push(gradient_type);
+                for component in gradient.transform {
+                    self.body.write_f32(component);
+                }
+                self.body.write_varint(gradient.stops.len() as u64);
+                for stop in &gradient.stops {
+                    self.body.write_f32(stop.position);
+                    self.write_color(stop.color);
+                }
+            }
+            PaintKind::Image(image) => {
+                self.body.push(PAINT_IMAGE);
+                self.write_image_paint(image);
+            }
+        }
+        self.body.write_f32(paint.opacity);
+        self.body.push(paint.visible as u8);
+        let blend_mode = self.tag(BLEND_MODE_TAGS, paint.blend_mode);
+        self.body.push(blend_mode);
+    }
+
+    fn write_image_paint(&mut self, paint: &ImagePaint) {
+        let image = self.intern(&paint.image);
+        self.body.write_varint(image as u64);
+        let scale_mode = self.tag(SCALE_MODE_TAGS, paint.scale_mode);
+        self.body.push(scale_mode);
+        for component in paint.crop {
+            self.body.write_f32(component);
+        }
+        self.body.write_f32(paint.scale);
+    }
+
+    fn write_color(&mut self, color: Color) {
+        for component in [color.r, color.g, color.b, color.a] {
+            self.body.write_f32(component);
+        }
+    }
+
+    fn write_id(&mut self, id: ObjectId) {
+        self.body.write_varint(id.client_id() as u64);
+        self.body.write_varint(id.sequence() as u64);
+    }
+
+    /// The wire tag of a value, recording an error if its table lacks it
+    fn tag<T: PartialEq + std::fmt::Debug>(&mut self, tags: &[T], value: T) -> u8 {
+        match tag_of(tags, value) {
+            Ok(tag) => tag,
+            Err(error) => {
+                self.fail(error);
+                0
+            }
+        }
+    }
+
+    /// Remember the first error; encoding carries on but `finish()` returns it
+    fn fail(&mut self, error: FormatError) {
+        self.error.get_or_insert(error);
+    }
+
+    /// Assemble header, string table and body
+    fn finish(self) -> Result<Vec<u8>, FormatError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut out = Vec::with_capacity(self.body.len() + 64);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+
+        out.write_varint(self.strings.len() as u64);
+        for text in &self.strings {
+            out.write_varint(text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+
+        out.extend_from_slice(&self.body);
+        Ok(out)
+    }
+}
+
+/// Rebuilds the tree while decoding
+struct Decoder<'a> {
+    /// Format version of the input, for reading older layouts
+    version: u16,
+    strings: &'a [String],
+    tree: DocumentTree,
+}
+
+impl Decoder<'_> {
+    fn read_string(&self, reader: &mut Reader) -> Result<String, FormatError> {
+        let index = reader.read_varint()?;
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.strings.get(index))
+            .cloned()
+            .ok_or_else(|| FormatError::Invalid(format!("string index {} out of range", index)))
+    }
+
+    fn read_subtree(&mut self, reader: &mut Reader, parent: Option<ObjectId>, depth: usize) -> Result<(), FormatError> {
+        if depth > MAX_DEPTH {
+            return Err(FormatError::Invalid(format!("nodes are nested deeper than {} levels", MAX_DEPTH)));
+        }
+        let node = self.read_node(reader)?;
+        let id = node.id();
+        if self.tree.get(id).is_some() {
+            return Err(FormatError::Invalid(format!("duplicate node {}", id)));
+        }
+        self.tree.insert(node);
+        if let Some(parent_id) = parent {
+            self.tree.push_child(id, parent_id);
+        }
+
+        let child_count = reader.read_len()?;
+        for _ in 0..child_count {
+            self.read_subtree(reader, Some(id), depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn read_node(&self, reader: &mut Reader) -> Result<Node, FormatError> {
+        let id = read_id(reader)?;
+        let tag = reader.read_u8()?;
+        let node_type = *NODE_TYPE_TAGS
+            .get(tag as usize)
+            .ok_or_else(|| FormatError::Invalid(format!("unknown node type tag {}", tag)))?;
+
+        let mut node = Node::new(id, node_type);
+        node.set_order_index(self.read_string(reader)?);
+
+        let property_count = reader.read_len()?;
+        for _ in 0..property_count {
+            let property = read_property(reader)?;
+            let value = self.read_value(reader)?;
+            node.set_property(property, value);
+        }
+
+        if self.version >= 2 {
+            let override_count = reader.read_len()?;
+            for _ in 0..override_count {
+                node.set_overridden(read_property(reader)?, true);
+            }
+        }
+        Ok(node)
+    }
+
+    fn read_value(&self, reader: &mut Reader) -> Result<PropertyValue, FormatError> {
+        let tag = reader.read_u8()?;
+        let value = match tag {
+            VALUE_FLOAT => PropertyValue::Float(reader.read_f32()?),
+            VALUE_INT => {
+                let raw = reader.read_varint()? as u32;
+                PropertyValue::Int(((raw >> 1) as i32) ^ -((raw & 1) as i32))
+            }
+            VALUE_FALSE => PropertyValue::Bool(false),
+            VALUE_TRUE => PropertyValue::Bool(true),
+            VALUE_STRING => PropertyValue::String(self.read_string(reader)?),
+            VALUE_COLOR => PropertyValue::Color(Color::new(
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+            )),
+            VALUE_VEC2 => PropertyValue::Vec2(reader.read_f32()?, reader.read_f32()?),
+            VALUE_VEC4 => PropertyValue::Vec4(
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+                reader.read_f32()?,
+            ),
+            VALUE_REF => PropertyValue::Ref(read_id(reader)?),
+            VALUE_VARIABLE => PropertyValue::Variable(read_id(reader)?),
+            VALUE_MAP => {
+                let count = reader.read_len()?;
+                let mut entries = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let id = read_id(reader)?;
+                    entries.push((id, self.read_value(reader)?));
+                }
+                PropertyValue::Map(entries)
+            }
+            VALUE_IMAGE => PropertyValue::Image(self.read_image_paint(reader)?),
+            VALUE_PAINTS => {
+                let count = reader.read_len()?;
+                let mut paints = Vec::with_capacity(count);
+                for _ in 0..count {
+                    paints.push(self.read_paint(reader)?);
+                }
+                PropertyValue::Paints(paints)
+            }
+            VALUE_EFFECTS => {
+                let count = reader.read_len()?;
+                let mut effects = Vec::with_capacity(count);
+                for _ in 0..count {
+                    effects.push(read_effect(reader)?);
+                }
+                PropertyValue::Effects(effects)
+            }
+            _ => return Err(FormatError::Invalid(format!("unknown value tag {}", tag))),
+        };
+        Ok(value)
+    }
+
+    fn read_paint(&self, reader: &mut Reader) -> Result<Paint, FormatError> {
+        let tag = reader.read_u8()?;
+        let kind = match tag {
+            PAINT_SOLID => PaintKind::Solid { color: read_color(reader)? },
+            PAINT_GRADIENT => {
+                let gradient_type = read_tag(reader, GRADIENT_TYPE_TAGS, "gradient type")?;
+                let mut transform = [0.0; 6];
+                for component in transform.iter_mut() {
+                    *component = reader.read_f32()?;
+                }
+                let count = reader.read_len()?;
+                let mut stops = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let position = reader.read_f32()?;
+                    stops.push(ColorStop { position, color: read_color(reader)? });
+                }
+                PaintKind::Gradient(Gradient { gradient_type, stops, transform })
+            }
+            PAINT_IMAGE => PaintKind::Image(self.read_image_paint(reader)?),
+            _ => return Err(FormatError::Invalid(format!("unknown paint tag {}", tag))),
+        };
+        let mut paint = Paint::new(kind);
+        paint.opacity = reader.read_f32()?;
+        paint.visible = reader.read_u8()? != 0;
+        paint.blend_mode = read_tag(reader, BLEND_MODE_TAGS, "blend mode")?;
+        Ok(paint)
+    }
+
+    fn read_image_paint(&self, reader: &mut Reader) -> Result<ImagePaint, FormatError> {
+        let image = self.read_string(reader)?;
+        let scale_mode = read_tag(reader, SCALE_MODE_TAGS, "scale mode")?;
+        let mut paint = ImagePaint::new(&image, scale_mode);
+        for component in paint.crop.iter_mut() {
+            *component = reader.read_f32()?;
+        }
+        paint.scale = reader.read_f32()?;
+        Ok(paint)
+    }
+}
+
+/// The wire tag of a value in a tag table
+fn tag_of<T: PartialEq + std::fmt::Debug>(tags: &[T], value: T) -> Result<u8, FormatError> {
+    tags.iter()
+        .position(|t| *t == value)
+        .map(|tag| tag as u8)
+        .ok_or_else(|| FormatError::Invalid(format!("{:?} has no wire tag", value)))
+}
+
+/// Read a tag and look it up in a tag table
+fn read_tag<T: Copy>(reader: &mut Reader, tags: &[T], what: &str) -> Result<T, FormatError> {
+    let tag = reader.read_u8()?;
+    tags.get(tag as usize)
+        .copied()
+        .ok_or_else(|| FormatError::Invalid(format!("unknown {} tag {}", what, tag)))
+}
+
+fn read_effect(reader: &mut Reader) -> Result<Effect, FormatError> {
+    let tag = reader.read_u8()?;
+    let kind = match tag {
+        EFFECT_DROP_SHADOW | EFFECT_INNER_SHADOW => {
+            let shadow = Shadow {
+                color: read_color(reader)?,
+                offset_x: reader.read_f32()?,
+                offset_y: reader.read_f32()?,
+                blur: reader.read_f32()?,
+                spread: reader.read_f32()?,
+            };
+            if tag == EFFECT_DROP_SHADOW {
+                EffectKind::DropShadow(shadow)
+            } else {
+                EffectKind::InnerShadow(shadow)
+            }
+        }
+        EFFECT_LAYER_BLUR => EffectKind::LayerBlur { radius: reader.read_f32()? },
+        EFFECT_BACKGROUND_BLUR => EffectKind::BackgroundBlur { radius: reader.read_f32()? },
+        _ => return Err(FormatError::Invalid(format!("unknown effect tag {}", tag))),
+    };
+    let mut effect = Effect::new(kind);
+    effect.visible = reader.read_u8()? != 0;
+    Ok(effect)
+}
+
+fn read_color(reader: &mut Reader) -> Result<Color, FormatError> {
+    Ok(Color::new(reader.read_f32()?, reader.read_f32()?, reader.read_f32()?, reader.read_f32()?))
+}
+
+fn read_id(reader: &mut Reader) -> Result<ObjectId, FormatError> {
+    let client_id = reader.read_varint()? as u32;
+    let sequence = reader.read_varint()? as u32;
+    Ok(ObjectId::new(client_id, sequence))
+}
+
+fn read_property(reader: &mut Reader) -> Result<Property, FormatError> {
+    let tag = reader.read_u8()?;
+    PROPERTY_TAGS
+        .get(tag as usize)
+        .copied()
+        .ok_or_else(|| FormatError::Invalid(format!("unknown property tag {}", tag)))
+}
+
+/// Little helpers for writing primitives into a byte buffer
+trait WriteExt {
+    fn write_varint(&mut self, value: u64);
+    fn write_f32(&mut self, value: f32);
+}
+
+impl WriteExt for Vec<u8> {
+    fn write_varint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.push(byte);
+                return;
+            }
+            self.push(byte | 0x80);
+        }
+    }
+
+    fn write_f32(&mut self, value: f32) {
+        self.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Bounds-checked cursor over the input bytes
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], FormatError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| FormatError::Invalid("unexpected end of data".into()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, FormatError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, FormatError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, FormatError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_f32(&mut self) -> Result<f32, FormatError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, FormatError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(FormatError::Invalid("varint is too long".into()))
+    }
+
+    /// Read a count or byte length. Every element takes at least a byte, so
+    /// counts beyond the bytes left are rejected before anything is
+    /// allocated for them.
+    fn read_len(&mut self) -> Result<usize, FormatError> {
+        let value = self.read_varint()?;
+        if value > (self.data.len() - self.pos) as u64 {
+            return Err(FormatError::Invalid(format!("length {} is out of range", value)));
+        }
+        Ok(value as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_round_trip() {
+        let mut doc = Document::new("Binary");
+        let frame_id = doc.create_frame(1.5, -2.0, 320.0, 240.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        let text_id = doc.create_text(frame_id, 4.0, 4.0, "Héllo");
+        doc.set_node_property(rect_id, Property::StrokeCap, PropertyValue::Int(-3));
+        doc.set_node_property(rect_id, Property::Visible, PropertyValue::Bool(false));
+        doc.set_node_property(rect_id, Property::LayoutPadding, PropertyValue::Vec4(1.0, 2.0, 3.0, 4.0));
+        doc.add_gradient_fill(rect_id, "angular", Color::red(), Color::blue());
+        doc.set_fill_blend_mode(rect_id, 1, "multiply");
+        doc.add_drop_shadow(rect_id, Color::black(), 0.0, 2.0, 4.0, 1.0);
+        doc.add_background_blur(rect_id, 10.0);
+        let fills = doc.tree.get(rect_id).unwrap().fills();
+        let effects = doc.tree.get(rect_id).unwrap().effects();
+
+        let bytes = doc.encode_binary().unwrap();
+        let loaded = Document::decode_binary(&bytes).unwrap();
+
+        assert_eq!(loaded.name(), "Binary");
+        assert_eq!(loaded.version(), doc.version());
+        assert_eq!(loaded.tree.len(), doc.tree.len());
+        assert_eq!(loaded.get_children(frame_id), vec![rect_id, text_id]);
+        assert_eq!(loaded.get_node_x(frame_id), 1.5);
+        assert_eq!(loaded.get_node_y(frame_id), -2.0);
+
+        let rect = loaded.tree.get(rect_id).unwrap();
+        assert!(matches!(rect.get_property(Property::StrokeCap), Some(PropertyValue::Int(-3))));
+        assert!(!rect.visible());
+        assert_eq!(rect.fills(), fills);
+        assert_eq!(rect.effects(), effects);
+        let text = loaded.tree.get(text_id).unwrap();
+        assert!(matches!(text.get_property(Property::Text), Some(PropertyValue::String(s)) if s == "Héllo"));
+
+        // Encoding is deterministic
+        assert_eq!(loaded.encode_binary().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_binary_rejects_bad_input() {
+        assert!(Document::decode_binary(b"NOPE").is_err());
+        let bytes = Document::new("Truncated").encode_binary().unwrap();
+        for len in 0..bytes.len() {
+            assert!(Document::decode_binary(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_counts_fit_in_remaining_bytes() {
+        let mut data = Vec::new();
+        data.write_varint(8);
+        data.extend_from_slice(&[0; 8]);
+        assert_eq!(Reader::new(&data).read_len(), Ok(8));
+        assert!(Reader::new(&data[..8]).read_len().is_err());
+
+        // A header claiming far more strings than the file could hold
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.write_varint(1 << 40);
+        let Err(error) = Document::decode_binary(&bytes) else { panic!("decoded a bogus header") };
+        assert!(error.to_string().contains("out of range"));
+    }
+
+    #[test]
+    fn test_binary_depth_limit() {
+        let mut doc = Document::new("Deep");
+        let mut parent_id = doc.create_frame(0.0, 0.0, 10.0, 10.0);
+        for _ in 1..MAX_DEPTH {
+            parent_id = doc.insert_frame(Some(parent_id), 0.0, 0.0, 10.0, 10.0);
+        }
+        assert!(Document::decode_binary(&doc.encode_binary().unwrap()).is_ok());
+
+        doc.insert_frame(Some(parent_id), 0.0, 0.0, 10.0, 10.0);
+        assert!(doc.encode_binary().is_err());
+
+        // A crafted chunk nesting one node per level far past the limit
+        let rectangle = tag_of(NODE_TYPE_TAGS, NodeType::Rectangle).unwrap();
+        let mut chunk = Vec::new();
+        for sequence in 0..100_000 {
+            chunk.write_varint(1);
+            chunk.write_varint(sequence);
+            chunk.extend_from_slice(&[rectangle, 0, 0, 0, 1]);
+        }
+        let strings = [String::new()];
+        let mut decoder = Decoder { version: BINARY_VERSION, strings: &strings, tree: DocumentTree::new() };
+        let error = decoder.read_subtree(&mut Reader::new(&chunk), None, 0).unwrap_err();
+        assert!(error.to_string().contains("nested deeper"));
+    }
+
+    #[test]
+    fn test_missing_tag_is_an_error() {
+        assert_eq!(tag_of(SCALE_MODE_TAGS, ScaleMode::Tile), Ok(3));
+        assert!(tag_of(&SCALE_MODE_TAGS[..2], ScaleMode::Tile).is_err());
+    }
+}
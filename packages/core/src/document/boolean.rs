@@ -0,0 +1,427 @@
+//! Boolean operation nodes
+//!
+//! A `BooleanOperation` node combines the shapes of its children with
+//! `"union"`, `"subtract"`, `"intersect"` or `"exclude"` (see
+//! `geometry::boolean`). The first child (the bottom-most one) is the
+//! subject. The result is stored on the node as vector network JSON in
+//! `VectorData`, in the node's local space, and is what gets rendered and
+//! hit; the children themselves are not drawn.
+//!
+//! ## Re-evaluation
+//!
+//! `Document::apply()` queues a boolean node whenever the operation changes
+//! or one of its children is added, removed, moved, resized or reshaped,
+//! including through a variable's value or mode (operands are read with
+//! their bound values).
+//! After every evaluation the node is fitted to the result: its X/Y move to
+//! the result's top-left corner and the children are shifted back by the
+//! same amount, so nothing moves on the canvas.
+//!
+//! Those writes go through `apply()` too, so a nested boolean's new result
+//! queues the boolean around it. Undo/redo replay the recorded result
+//! instead of re-evaluating.
+
+use super::{local_transform, Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree};
+use crate::geometry::{boolean, shapes_bounds, BooleanOp, Contour, VectorNetwork};
+use crate::math::{Mat3, Rect, Vec2};
+use wasm_bindgen::prelude::*;
+
+/// Upper bound on evaluations per change, in case results never settle
+const MAX_BOOLEAN_PASSES: usize = 10_000;
+
+/// Offsets smaller than this are not worth re-fitting the node for
+const FIT_EPSILON: f32 = 1e-3;
+
+/// What a new boolean node copies from its subject, so it looks the same
+const PAINT_PROPERTIES: [Property; 8] = [
+    Property::FillColor,
+    Property::FillOpacity,
+    Property::ImageFill,
+    Property::Fills,
+    Property::StrokeColor,
+    Property::StrokeOpacity,
+    Property::StrokeWidth,
+    Property::Strokes,
+];
+
+/// Boolean nodes waiting to be re-evaluated after a change
+#[derive(Debug, Clone, Default)]
+pub(crate) struct BooleanQueue {
+    pending: Vec<ObjectId>,
+    running: bool,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Combine sibling nodes into a new boolean node.
+    ///
+    /// `operation` is `"union"`, `"subtract"`, `"intersect"` or `"exclude"`.
+    /// The boolean takes the place of the topmost node. Returns `None` if
+    /// the operation is unknown or the nodes don't share a parent.
+    pub fn create_boolean_operation(&mut self, ids: Vec<ObjectId>, operation: &str) -> Option<ObjectId> {
+        let op = BooleanOp::parse(operation)?;
+        let parent_id = self.tree.parent(*ids.first()?)?;
+        if ids.iter().any(|&id| self.tree.parent(id) != Some(parent_id)) {
+            return None;
+        }
+
+        let mut ids = ids;
+        ids.sort_by(|a, b| self.compare_z_order(*a, *b));
+        ids.dedup();
+        let topmost = *ids.last()?;
+
+        // Start with the union of the children's bounds; the first
+        // evaluation fits the node to the result
+        let bounds = ids
+            .iter()
+            .filter_map(|&id| self.tree.get(id))
+            .map(|node| {
+                let node = self.bound_node(node);
+                local_transform(&node).transform_rect(&Rect::new(0.0, 0.0, node.width(), node.height()))
+            })
+            .reduce(|a, b| {
+                let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+                Rect::new(x, y, a.right().max(b.right()) - x, a.bottom().max(b.bottom()) - y)
+            })?;
+
+        let boolean_id = ObjectId::random();
+        let mut node = Node::new(boolean_id, NodeType::BooleanOperation);
+        node.set_property(Property::X, PropertyValue::Float(bounds.x));
+        node.set_property(Property::Y, PropertyValue::Float(bounds.y));
+        node.set_property(Property::Width, PropertyValue::Float(bounds.width));
+        node.set_property(Property::Height, PropertyValue::Float(bounds.height));
+        node.set_property(Property::BooleanOperation, PropertyValue::String(op.as_str().into()));
+        if let Some(subject) = self.tree.get(ids[0]) {
+            for property in PAINT_PROPERTIES {
+                if let Some(value) = subject.get_property(property) {
+                    node.set_property(property, value.clone());
+                }
+            }
+        }
+        let (position, order_index) = self.placement(topmost);
+        node.set_order_index(order_index);
+
+        let subtree = Subtree::from_nodes(vec![(node, Some(parent_id))], position)?;
+
+        // Hold off evaluating until every child has been moved in and rebased
+        self.history.begin_group();
+        self.boolean_queue.running = true;
+        self.apply(Operation::Insert(subtree));
+        for id in ids {
+            let Some(child) = self.tree.get(id).map(|child| self.bound_node(child)) else { continue };
+            let (x, y, order_index) = (child.x(), child.y(), child.order_index().to_string());
+            self.apply(Operation::Move { id, parent: boolean_id, position: usize::MAX, order_index });
+            self.set_if_changed(id, Property::X, x - bounds.x);
+            self.set_if_changed(id, Property::Y, y - bounds.y);
+        }
+        self.boolean_queue.running = false;
+        self.run_booleans();
+        self.history.end_group();
+        Some(boolean_id)
+    }
+
+    /// Change how a boolean node combines its children
+    pub fn set_boolean_operation(&mut self, id: ObjectId, operation: &str) -> bool {
+        if BooleanOp::parse(operation).is_none() {
+            return false;
+        }
+        self.set_node_property(id, Property::BooleanOperation, PropertyValue::String(operation.into()));
+        true
+    }
+
+    /// The computed result of a boolean node
+    pub fn get_boolean_result(&self, id: ObjectId) -> Option<VectorNetwork> {
+        let node = self.tree.get(id)?;
+        if node.node_type() != NodeType::BooleanOperation {
+            return None;
+        }
+        Some(node.vector_network().unwrap_or_default())
+    }
+
+    /// Replace a boolean node with a plain vector node holding its current
+    /// result. Returns the new node.
+    pub fn flatten_boolean(&mut self, id: ObjectId) -> Option<ObjectId> {
+        let node = self.tree.get(id)?;
+        if node.node_type() != NodeType::BooleanOperation {
+            return None;
+        }
+        let parent_id = self.tree.parent(id)?;
+        let position = self.tree.index_in_parent(id).unwrap_or(usize::MAX);
+
+        let vector_id = ObjectId::random();
+        let mut vector = node.duplicate(vector_id);
+        vector.set_node_type(NodeType::Vector);
+        vector.remove_property(Property::BooleanOperation);
+        if vector.get_property(Property::VectorData).is_none() {
+            vector.set_property(Property::VectorData, PropertyValue::String(VectorNetwork::new().to_json()));
+        }
+
+        self.history.begin_group();
+        self.apply(Operation::Remove { id });
+        self.apply(Operation::Insert(Subtree::from_nodes(vec![(vector, Some(parent_id))], position)?));
+        self.history.end_group();
+        Some(vector_id)
+    }
+}
+
+impl Document {
+    /// Queue the boolean nodes an applied operation may have changed
+    pub(crate) fn invalidate_booleans(&mut self, inverse: &Operation) {
+        match inverse {
+            // Any child may be bound to the variable
+            Operation::SetProperty { id, property: Property::VariableValues, .. }
+                if self.tree.get(*id).map(Node::node_type) == Some(NodeType::Variable) =>
+            {
+                for consumer in self.get_variable_consumers(*id) {
+                    self.queue_boolean(Some(consumer));
+                    self.queue_boolean(self.tree.parent(consumer));
+                }
+            }
+            // Bound values in the subtree may resolve differently
+            Operation::SetProperty { id, property: Property::VariableModes, .. } => {
+                self.queue_boolean(self.tree.parent(*id));
+                let mut stack = vec![*id];
+                while let Some(node_id) = stack.pop() {
+                    self.queue_boolean(Some(node_id));
+                    stack.extend(self.tree.children(node_id));
+                }
+            }
+            Operation::SetProperty { id, property, .. } => {
+                if *property == Property::BooleanOperation {
+                    self.queue_boolean(Some(*id));
+                }
+                if affects_shape(*property) {
+                    self.queue_boolean(self.tree.parent(*id));
+                }
+            }
+            // Inserted
+            Operation::Remove { id } => self.queue_boolean(self.tree.parent(*id)),
+            // Removed
+            Operation::Insert(subtree) => self.queue_boolean(subtree.parent()),
+            Operation::Move { id, parent, .. } => {
+                self.queue_boolean(Some(*parent));
+                self.queue_boolean(self.tree.parent(*id));
+            }
+            Operation::SetOverride { .. } => {}
+        }
+    }
+
+    /// Evaluate every queued boolean node until the queue is empty
+    pub(crate) fn run_booleans(&mut self) {
+        if self.boolean_queue.running {
+            return;
+        }
+        self.boolean_queue.running = true;
+
+        let mut passes = 0;
+        while let Some(id) = self.boolean_queue.pending.pop() {
+            passes += 1;
+            if passes > MAX_BOOLEAN_PASSES {
+                self.boolean_queue.pending.clear();
+                break;
+            }
+            self.evaluate_boolean(id);
+        }
+
+        self.boolean_queue.running = false;
+    }
+
+    fn queue_boolean(&mut self, id: Option<ObjectId>) {
+        let Some(id) = id else { return };
+        let is_boolean = self.tree.get(id).is_some_and(|n| n.node_type() == NodeType::BooleanOperation);
+        if is_boolean && !self.boolean_queue.pending.contains(&id) {
+            self.boolean_queue.pending.push(id);
+        }
+    }
+
+    /// Recompute one boolean node's result and fit the node to it
+    fn evaluate_boolean(&mut self, id: ObjectId) {
+        let Some(node) = self.tree.get(id).map(|node| self.bound_node(node)) else { return };
+        let op = match node.get_property(Property::BooleanOperation) {
+            Some(PropertyValue::String(s)) => BooleanOp::parse(s).unwrap_or(BooleanOp::Union),
+            _ => BooleanOp::Union,
+        };
+
+        let children = self.tree.children(id);
+        let operands: Vec<Vec<Contour>> = children
+            .iter()
+            .filter_map(|&child| self.tree.get(child))
+            .map(|child| self.bound_node(child))
+            .filter(|child| child.visible())
+            .map(|child| {
+                let transform = local_transform(&child);
+                shape_contours(&child)
+                    .into_iter()
+                    .map(|contour| contour.into_iter().map(|p| transform.apply(Vec2::from(p)).to_array()).collect())
+                    .collect()
+            })
+            .collect();
+        let mut shapes = boolean(op, &operands);
+
+        if let Some(bounds) = shapes_bounds(&shapes) {
+            if bounds.x.abs() > FIT_EPSILON || bounds.y.abs() > FIT_EPSILON {
+                self.shift_origin(id, &bounds, &children);
+                for point in shapes.iter_mut().flatten().flatten() {
+                    point[0] -= bounds.x;
+                    point[1] -= bounds.y;
+                }
+            }
+            self.set_if_changed(id, Property::Width, bounds.width);
+            self.set_if_changed(id, Property::Height, bounds.height);
+        }
+
+        let data = PropertyValue::String(VectorNetwork::from_shapes(&shapes).to_json());
+        let current = self.tree.get(id).and_then(|n| n.get_property(Property::VectorData));
+        if current != Some(&data) {
+            self.apply(Operation::SetProperty { id, property: Property::VectorData, value: Some(data) });
+        }
+    }
+
+    /// Move a node's origin to `bounds.x/y` (in its local space) without
+    /// moving its children on the canvas
+    pub(crate) fn shift_origin(&mut self, id: ObjectId, bounds: &Rect, children: &[ObjectId]) {
+        let Some(node) = self.tree.get(id).map(|node| self.bound_node(node)) else { return };
+
+        // Keep local_transform(p) fixed for every child point p while the
+        // origin and the center of rotation both move:
+        // t' = t + c - c' + R(min + c' - c)
+        let rotation = Mat3::from_angle(node.rotation().to_radians());
+        let min = Vec2::new(bounds.x, bounds.y);
+        let old_center = Vec2::new(node.width(), node.height()) / 2.0;
+        let new_center = Vec2::new(bounds.width, bounds.height) / 2.0;
+        let origin = Vec2::new(node.x(), node.y()) + old_center - new_center
+            + rotation.transform_vector2(min + new_center - old_center);
+
+        self.set_if_changed(id, Property::X, origin.x);
+        self.set_if_changed(id, Property::Y, origin.y);
+        for &child_id in children {
+            let Some(child) = self.tree.get(child_id).map(|child| self.bound_node(child)) else { continue };
+            let (x, y) = (child.x(), child.y());
+            self.set_if_changed(child_id, Property::X, x - bounds.x);
+            self.set_if_changed(child_id, Property::Y, y - bounds.y);
+        }
+    }
+}
+
+/// A node's filled outline in its own local space
+fn shape_contours(node: &Node) -> Vec<Contour> {
+    let (width, height) = (node.width(), node.height());
+    match node.node_type() {
+        NodeType::Ellipse => {
+            VectorNetwork::from_ellipse(width / 2.0, height / 2.0, width / 2.0, height / 2.0).to_contours()
+        }
+        NodeType::Vector | NodeType::BooleanOperation => {
+            node.vector_network().map(|network| network.to_contours()).unwrap_or_default()
+        }
+        // Lines enclose no area
+        NodeType::Line | NodeType::Document | NodeType::Page | NodeType::Style => Vec::new(),
+        _ => VectorNetwork::from_rounded_rectangle(0.0, 0.0, width, height, node.corner_radius()).to_contours(),
+    }
+}
+
+/// Properties of a child that change its outline in the parent's space
+fn affects_shape(property: Property) -> bool {
+    matches!(
+        property,
+        Property::X
+            | Property::Y
+            | Property::Width
+            | Property::Height
+            | Property::Rotation
+            | Property::CornerRadius
+            | Property::Visible
+            | Property::VectorData
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_area(doc: &Document, id: ObjectId) -> f32 {
+        let network = doc.get_boolean_result(id).unwrap();
+        crate::geometry::shapes_area(&[network.to_contours()])
+    }
+
+    #[test]
+    fn test_boolean_node_tracks_children() {
+        let mut doc = Document::new("Booleans");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let a = doc.create_rectangle(frame_id, 10.0, 10.0, 40.0, 40.0);
+        let b = doc.create_rectangle(frame_id, 30.0, 30.0, 40.0, 40.0);
+
+        let boolean_id = doc.create_boolean_operation(vec![b, a], "subtract").unwrap();
+        assert_eq!(doc.get_children(frame_id), vec![boolean_id]);
+        assert_eq!(doc.get_children(boolean_id), vec![a, b]);
+        assert!((result_area(&doc, boolean_id) - 1200.0).abs() < 1e-2);
+
+        // Children keep their place on the canvas
+        let bounds = doc.get_absolute_bounds(b).unwrap();
+        assert_eq!((bounds.x, bounds.y), (30.0, 30.0));
+        assert_eq!((doc.get_node_x(boolean_id), doc.get_node_width(boolean_id)), (10.0, 40.0));
+
+        // Moving the cutter away refits the node to the whole subject
+        doc.set_node_x(b, 100.0);
+        assert!((result_area(&doc, boolean_id) - 1600.0).abs() < 1e-2);
+
+        // The subject moving left shifts the node, not the other child
+        let before = doc.get_absolute_bounds(b).unwrap();
+        doc.set_node_x(a, -10.0);
+        assert_eq!(doc.get_node_x(boolean_id), 0.0);
+        let after = doc.get_absolute_bounds(b).unwrap();
+        assert!((after.x - before.x).abs() < 1e-3);
+
+        assert!(doc.set_boolean_operation(boolean_id, "union"));
+        assert!((result_area(&doc, boolean_id) - 3200.0).abs() < 1e-2);
+        assert!(!doc.set_boolean_operation(boolean_id, "divide"));
+    }
+
+    #[test]
+    fn test_flatten_boolean() {
+        let mut doc = Document::new("Flatten");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let a = doc.create_ellipse(frame_id, 0.0, 0.0, 50.0, 50.0);
+        let b = doc.create_rectangle(frame_id, 25.0, 0.0, 50.0, 50.0);
+        let boolean_id = doc.create_boolean_operation(vec![a, b], "intersect").unwrap();
+        let result = doc.get_boolean_result(boolean_id).unwrap();
+
+        let vector_id = doc.flatten_boolean(boolean_id).unwrap();
+        assert!(!doc.has_node(boolean_id));
+        assert!(!doc.has_node(a));
+        assert_eq!(doc.get_children(frame_id), vec![vector_id]);
+        let vector = doc.get_node(vector_id).unwrap();
+        assert_eq!(vector.node_type(), NodeType::Vector);
+        assert_eq!(vector.vector_network().unwrap().segment_count(), result.segment_count());
+        assert!(result.segment_count() > 4);
+        assert_eq!(vector.x(), 25.0);
+
+        // Flattening is a single undo step
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(frame_id), vec![boolean_id]);
+        assert_eq!(doc.get_children(boolean_id), vec![a, b]);
+    }
+
+    #[test]
+    fn test_operands_use_bound_values() {
+        let mut doc = Document::new("Booleans");
+        let collection = doc.create_variable_collection("Sizes");
+        let light = doc.get_variable_modes(collection)[0];
+        let dark = doc.add_variable_mode(collection, "Dark").unwrap();
+        let size = doc.create_variable(collection, "number", "size").unwrap();
+        doc.set_variable_number(size, light, 40.0);
+        doc.set_variable_number(size, dark, 60.0);
+
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let a = doc.create_rectangle(frame_id, 10.0, 10.0, 40.0, 40.0);
+        let b = doc.create_rectangle(frame_id, 30.0, 30.0, 100.0, 40.0);
+        assert!(doc.bind_variable(b, Property::Width, size));
+        let boolean_id = doc.create_boolean_operation(vec![a, b], "union").unwrap();
+        assert!((result_area(&doc, boolean_id) - 2800.0).abs() < 1e-2);
+
+        // New values and modes re-evaluate the result
+        doc.set_variable_number(size, light, 20.0);
+        assert!((result_area(&doc, boolean_id) - 2000.0).abs() < 1e-2);
+        assert!(doc.set_variable_mode(frame_id, collection, dark));
+        assert!((result_area(&doc, boolean_id) - 3600.0).abs() < 1e-2);
+    }
+}
@@ -0,0 +1,376 @@
+//! Copy, paste and duplicate
+//!
+//! `copy()` turns the selected subtrees into a clipboard payload, a JSON
+//! string the frontend puts on the system clipboard. `paste()` inserts a
+//! payload under any parent, in this document or another one:
+//!
+//! ```text
+//! {
+//!   "format": "anatsui/nodes",
+//!   "schema_version": 2,
+//!   "subtrees": [
+//!     [ { "id": ..., "node_type": "Frame", ... },              ← root
+//!       { "id": ..., "node_type": "Text", ..., "parent": ... } ],
+//!     ...
+//!   ],
+//!   "images": [ "iVBORw0KGgo...", ... ]
+//! }
+//! ```
+//!
+//! Subtrees are listed in paint order and their nodes parents first, like
+//! the file format. Each root's X, Y and Rotation are stored in canvas
+//! coordinates, so it lands at the same spot on screen whatever it is pasted
+//! into. Images the nodes use travel with them, as in the file format.
+//!
+//! ## Pasting
+//!
+//! Every pasted node gets a fresh id. References between pasted nodes (an
+//! instance and its main component, copied together) point at the copies;
+//! references to anything else (styles, variables, a component that wasn't
+//! copied) are kept. Nodes copied out of an instance, without the instance,
+//! become plain nodes.
+//!
+//! A root that would land exactly on top of a sibling of the same size is
+//! moved down and to the right by `PASTE_OFFSET` until it doesn't, so pasting
+//! in place or duplicating never hides the original.
+//!
+//! `duplicate()` copies and pastes each copy right above its original.
+
+use super::{
+    local_transform, placement_for, remap_node, Document, Node, NodeType, ObjectId, Operation, Property,
+    PropertyValue, Subtree, SCHEMA_VERSION,
+};
+use crate::math::Vec2;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+
+/// Identifies our payloads among whatever else is on the clipboard
+const CLIPBOARD_FORMAT: &str = "anatsui/nodes";
+
+/// How far a pasted node moves when it would cover a sibling
+const PASTE_OFFSET: f32 = 10.0;
+
+/// Nodes closer than this count as being in the same spot
+const SAME_SPOT_EPSILON: f32 = 1e-2;
+
+/// A clipboard payload
+#[derive(Debug, Serialize, Deserialize)]
+struct Clipboard {
+    format: String,
+    schema_version: u32,
+    subtrees: Vec<Vec<ClipboardNode>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+/// A copied node together with its parent (none for the root of a subtree)
+#[derive(Debug, Serialize, Deserialize)]
+struct ClipboardNode {
+    #[serde(flatten)]
+    node: Node,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<ObjectId>,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Serialize nodes and everything inside them into a clipboard payload.
+    ///
+    /// Nodes inside other listed nodes come along with their ancestor.
+    /// Returns an empty string if nothing can be copied.
+    pub fn copy(&self, ids: Vec<ObjectId>) -> String {
+        let mut clipboard = self.clipboard_for(ids);
+        if clipboard.subtrees.is_empty() {
+            return String::new();
+        }
+        clipboard.images = self.encode_images(clipboard.subtrees.iter().flatten().map(|entry| &entry.node));
+        serde_json::to_string(&clipboard).unwrap_or_default()
+    }
+
+    /// Paste a payload from `copy()` under `parent_id`, starting at
+    /// `position` among its children (clamped to the end).
+    ///
+    /// Returns the new top-level nodes; empty if the payload isn't one of
+    /// ours, `parent_id` can't hold what it contains, or its subtrees are
+    /// malformed (see `DocumentTree::restore_subtree()`).
+    pub fn paste(&mut self, payload: &str, parent_id: ObjectId, position: usize) -> Vec<ObjectId> {
+        let Ok(mut clipboard) = serde_json::from_str::<Clipboard>(payload) else {
+            return Vec::new();
+        };
+        if clipboard.format != CLIPBOARD_FORMAT || clipboard.schema_version != SCHEMA_VERSION {
+            return Vec::new();
+        }
+        let images = std::mem::take(&mut clipboard.images);
+        let position = position.min(self.tree.children(parent_id).len());
+        let pasted = self.paste_subtrees(clipboard, |_, _, index| Some((parent_id, position + index)));
+
+        // Images are only kept once something that uses them was pasted.
+        // Ones that can't be read leave their paints drawing nothing.
+        if !pasted.is_empty() {
+            for image in &images {
+                self.insert_encoded_image(image).ok();
+            }
+        }
+        pasted
+    }
+
+    /// Copy nodes and paste each copy right above its original. Returns the
+    /// copies.
+    pub fn duplicate(&mut self, ids: Vec<ObjectId>) -> Vec<ObjectId> {
+        let clipboard = self.clipboard_for(ids);
+        self.paste_subtrees(clipboard, |doc, original, _| {
+            Some((doc.tree.parent(original)?, doc.tree.index_in_parent(original)? + 1))
+        })
+    }
+}
+
+impl Document {
+    /// Build a payload for the top-level canvas nodes among `ids`, in paint
+    /// order
+    fn clipboard_for(&self, ids: Vec<ObjectId>) -> Clipboard {
+        let mut ids: Vec<ObjectId> = ids
+            .into_iter()
+            .filter(|&id| self.tree.get(id).is_some_and(|n| n.node_type().is_canvas()))
+            .collect();
+        ids.sort_by(|a, b| self.compare_z_order(*a, *b));
+        ids.dedup();
+        let members = ids.clone();
+        ids.retain(|&id| !members.iter().any(|&other| other != id && self.is_in_subtree(id, other)));
+
+        let subtrees = ids
+            .into_iter()
+            .filter_map(|id| {
+                let mut entries: Vec<ClipboardNode> = self
+                    .tree
+                    .clone_subtree(id)?
+                    .into_nodes()
+                    .into_iter()
+                    .map(|(node, parent)| ClipboardNode { node, parent })
+                    .collect();
+
+                // Store the root's placement on the canvas
+                let root = entries.first_mut()?;
+                let bound = self.bound_node(&root.node);
+                let size = Vec2::new(bound.width(), bound.height());
+                let (x, y, rotation) = placement_for(&self.world_transform(id), size);
+                root.parent = None;
+                root.node.set_property(Property::X, PropertyValue::Float(x));
+                root.node.set_property(Property::Y, PropertyValue::Float(y));
+                set_rotation(&mut root.node, rotation);
+                Some(entries)
+            })
+            .collect();
+
+        Clipboard {
+            format: CLIPBOARD_FORMAT.into(),
+            schema_version: SCHEMA_VERSION,
+            subtrees,
+            images: Vec::new(),
+        }
+    }
+
+    /// Insert the subtrees of a payload under fresh ids, as one undo step.
+    ///
+    /// `target` picks the parent and position for each subtree from the
+    /// original root id and the subtree's index; subtrees it returns `None`
+    /// for, and malformed ones, are skipped.
+    fn paste_subtrees(
+        &mut self,
+        clipboard: Clipboard,
+        target: impl Fn(&Document, ObjectId, usize) -> Option<(ObjectId, usize)>,
+    ) -> Vec<ObjectId> {
+        // One map for every subtree, so references between them follow too
+        let new_ids: HashMap<ObjectId, ObjectId> = clipboard
+            .subtrees
+            .iter()
+            .flatten()
+            .map(|entry| (entry.node.id(), ObjectId::random()))
+            .collect();
+        let remap = |id: ObjectId| new_ids.get(&id).copied().unwrap_or(id);
+
+        let mut pasted = Vec::new();
+        self.history.begin_group();
+        for (index, entries) in clipboard.subtrees.into_iter().enumerate() {
+            let Some(original) = entries.first().map(|entry| entry.node.id()) else {
+                continue;
+            };
+            let Some((parent_id, position)) = target(self, original, index) else {
+                continue;
+            };
+
+            let mut nodes: Vec<(Node, Option<ObjectId>)> = entries
+                .iter()
+                .map(|entry| (remap_node(&entry.node, &remap), entry.parent.map(remap)))
+                .collect();
+            unlink_from_instances(&mut nodes);
+
+            let (root, root_parent) = &mut nodes[0];
+            let root_id = root.id();
+            self.place_pasted(root, parent_id);
+            root.set_order_index(String::new());
+            *root_parent = Some(parent_id);
+
+            let Some(subtree) = Subtree::from_nodes(nodes, position) else {
+                continue;
+            };
+            if self.apply(Operation::Insert(subtree)) {
+                pasted.push(root_id);
+            }
+        }
+        self.history.end_group();
+        pasted
+    }
+
+    /// Turn a pasted root's canvas placement into one relative to its new
+    /// parent, stepping it away from siblings it would cover
+    fn place_pasted(&self, root: &mut Node, parent_id: ObjectId) {
+        let size = Vec2::new(root.width(), root.height());
+        let local = self.world_transform(parent_id).inverse().multiply(&local_transform(root));
+        let (mut x, mut y, rotation) = placement_for(&local, size);
+
+        let siblings: Vec<&Node> = self
+            .tree
+            .children(parent_id)
+            .iter()
+            .filter_map(|&id| self.tree.get(id))
+            .collect();
+        let covers = |x: f32, y: f32| {
+            siblings.iter().any(|sibling| {
+                (sibling.x() - x).abs() < SAME_SPOT_EPSILON
+                    && (sibling.y() - y).abs() < SAME_SPOT_EPSILON
+                    && (sibling.width() - size.x).abs() < SAME_SPOT_EPSILON
+                    && (sibling.height() - size.y).abs() < SAME_SPOT_EPSILON
+            })
+        };
+        while covers(x, y) {
+            x += PASTE_OFFSET;
+            y += PASTE_OFFSET;
+        }
+
+        root.set_property(Property::X, PropertyValue::Float(x));
+        root.set_property(Property::Y, PropertyValue::Float(y));
+        set_rotation(root, rotation);
+    }
+}
+
+/// Set a rotation, without adding the property to nodes that never had one
+fn set_rotation(node: &mut Node, rotation: f32) {
+    if rotation != 0.0 || node.get_property(Property::Rotation).is_some() {
+        node.set_property(Property::Rotation, PropertyValue::Float(rotation));
+    }
+}
+
+/// Drop the component links of nodes that were copied out of an instance
+/// without the instance itself
+fn unlink_from_instances(nodes: &mut [(Node, Option<ObjectId>)]) {
+    let mut in_instance = HashSet::new();
+    for (node, parent) in nodes.iter_mut() {
+        if node.node_type() == NodeType::Instance || parent.is_some_and(|p| in_instance.contains(&p)) {
+            in_instance.insert(node.id());
+        } else {
+            node.remove_property(Property::ComponentSource);
+            for property in node.overrides().to_vec() {
+                node.set_overridden(property, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_paste_remaps_ids_and_references() {
+        let mut doc = Document::new("Clipboard");
+        let page_id = doc.get_active_page().unwrap();
+        let component_id = doc.create_component(0.0, 0.0, 50.0, 50.0);
+        doc.create_rectangle(component_id, 0.0, 0.0, 50.0, 50.0);
+        let instance_id = doc.create_instance(component_id, page_id, 100.0, 0.0).unwrap();
+
+        let payload = doc.copy(vec![instance_id, component_id]);
+        let pasted = doc.paste(&payload, page_id, usize::MAX);
+        assert_eq!(pasted.len(), 2);
+        assert!(!pasted.contains(&component_id) && !pasted.contains(&instance_id));
+        assert_eq!(doc.get_children(page_id)[2..], pasted[..]);
+
+        // Paint order kept, the instance follows the pasted component, and
+        // both are nudged off the originals
+        let (component_copy, instance_copy) = (pasted[0], pasted[1]);
+        assert_eq!(doc.get_main_component(instance_copy), Some(component_copy));
+        assert_eq!(doc.get_children(component_copy).len(), 1);
+        assert_eq!((doc.get_node_x(component_copy), doc.get_node_y(component_copy)), (10.0, 10.0));
+        assert_eq!((doc.get_node_x(instance_copy), doc.get_node_y(instance_copy)), (110.0, 10.0));
+
+        // One undo step removes the whole paste
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(page_id), vec![component_id, instance_id]);
+        assert!(doc.paste("not a payload", page_id, 0).is_empty());
+    }
+
+    #[test]
+    fn test_paste_keeps_canvas_position_and_duplicate() {
+        let mut doc = Document::new("Clipboard");
+        let page_id = doc.get_active_page().unwrap();
+        let frame_id = doc.create_frame(100.0, 100.0, 300.0, 300.0);
+        let rect_id = doc.create_rectangle(page_id, 150.0, 120.0, 20.0, 20.0);
+        let other_id = doc.create_rectangle(page_id, 0.0, 0.0, 20.0, 20.0);
+
+        // Pasted into the frame, the rectangle stays where it was on screen
+        let pasted = doc.paste(&doc.copy(vec![rect_id]), frame_id, 0);
+        assert_eq!((doc.get_node_x(pasted[0]), doc.get_node_y(pasted[0])), (50.0, 20.0));
+
+        // Copies go right above their originals; shapes can't be pasted
+        // directly under the document
+        let copies = doc.duplicate(vec![rect_id, other_id]);
+        assert_eq!(doc.get_children(page_id), vec![frame_id, rect_id, copies[0], other_id, copies[1]]);
+        assert_eq!(doc.get_node_x(copies[0]), 160.0);
+        assert!(doc.paste(&doc.copy(vec![rect_id]), doc.root_id(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_paste_rejects_malformed_payloads() {
+        let mut source = Document::new("Source");
+        let frame_id = source.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = source.create_rectangle(frame_id, 10.0, 10.0, 20.0, 20.0);
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[255, 0, 0, 255]).unwrap();
+        writer.finish().unwrap();
+        let hash = source.add_image(&png).unwrap();
+        assert!(source.set_image_fill(rect_id, &hash, "fill"));
+        let payload: serde_json::Value = serde_json::from_str(&source.copy(vec![frame_id])).unwrap();
+
+        let mut doc = Document::new("Target");
+        let page_id = doc.get_active_page().unwrap();
+        let target_id = doc.create_frame(200.0, 0.0, 100.0, 100.0);
+        doc.clear_history();
+        let with_child = |change: &dyn Fn(&mut serde_json::Value)| {
+            let mut payload = payload.clone();
+            change(&mut payload["subtrees"][0][1]);
+            payload.to_string()
+        };
+
+        // The child names a parent outside its subtree, shares the root's id,
+        // or has a type its parent can't hold
+        let target = serde_json::to_value(target_id).unwrap();
+        let root = payload["subtrees"][0][0]["id"].clone();
+        for payload in [
+            with_child(&|child| child["parent"] = target.clone()),
+            with_child(&|child| child["id"] = root.clone()),
+            with_child(&|child| child["node_type"] = "Page".into()),
+        ] {
+            assert!(doc.paste(&payload, page_id, usize::MAX).is_empty());
+            assert!(doc.get_children(target_id).is_empty());
+            assert!(doc.tree.validate().is_ok());
+            assert!(!doc.can_undo());
+            assert!(!doc.has_image(&hash));
+        }
+
+        assert_eq!(doc.paste(&payload.to_string(), page_id, usize::MAX).len(), 1);
+        assert!(doc.has_image(&hash));
+    }
+}
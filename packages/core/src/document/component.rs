@@ -0,0 +1,593 @@
+//! Components and instances
+//!
+//! A component is a reusable piece of design. An instance is a live copy of a
+//! component's subtree that updates whenever the component changes.
+//!
+//! ## How Instances Are Stored
+//!
+//! Instances are materialized: every node of the component has a real mirror
+//! node inside the instance, so rendering, hit testing and layout treat them
+//! like any other nodes.
+//!
+//! ```text
+//! Component "Button"  ◄──── MainComponent ────  Instance "Button"
+//!   ├─ Rectangle bg   ◄──── ComponentSource ──    ├─ Rectangle bg
+//!   └─ Text label     ◄──── ComponentSource ──    └─ Text label  (Text overridden)
+//! ```
+//!
+//! ## Overrides
+//!
+//! Editing a property on an instance node marks it as overridden. When the
+//! component changes, every mirror picks up the new value *except* for
+//! overridden properties. `reset_overrides()` drops the local values again.
+//!
+//! Propagation runs inside `Document::apply()`, so a component edit and all
+//! of the instance updates it causes form a single undo step. The instances
+//! of each component are kept in an [`InstanceIndex`], updated as nodes are
+//! inserted, removed or relinked, so an edit doesn't scan the whole tree.
+//!
+//! Below an instance's root, a mirror's id is derived from its parent's id
+//! and its source's id. Every client mirrors a component edit by itself, so
+//! this is what lets a later change to a mirror find it on the others.
+
+use super::{Document, DocumentTree, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree};
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+
+/// The instance nodes of every component, by component id
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InstanceIndex {
+    instances: HashMap<ObjectId, HashSet<ObjectId>>,
+}
+
+impl InstanceIndex {
+    /// Index every instance in a tree (used by the file loaders)
+    pub(crate) fn build(tree: &DocumentTree) -> Self {
+        let mut index = Self::default();
+        for node in tree.iter() {
+            index.add(node);
+        }
+        index
+    }
+
+    fn add(&mut self, node: &Node) {
+        if let Some(component_id) = node.main_component() {
+            self.instances.entry(component_id).or_default().insert(node.id());
+        }
+    }
+
+    fn remove(&mut self, id: ObjectId, component_id: ObjectId) {
+        if let Some(instances) = self.instances.get_mut(&component_id) {
+            instances.remove(&id);
+            if instances.is_empty() {
+                self.instances.remove(&component_id);
+            }
+        }
+    }
+
+    fn get(&self, component_id: ObjectId) -> impl Iterator<Item = ObjectId> + '_ {
+        self.instances.get(&component_id).into_iter().flatten().copied()
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Create a new component on the active page
+    pub fn create_component(&mut self, x: f32, y: f32, width: f32, height: f32) -> ObjectId {
+        let component_id = ObjectId::random();
+        let mut component = Node::new(component_id, NodeType::Component);
+
+        component.set_property(Property::X, PropertyValue::Float(x));
+        component.set_property(Property::Y, PropertyValue::Float(y));
+        component.set_property(Property::Width, PropertyValue::Float(width));
+        component.set_property(Property::Height, PropertyValue::Float(height));
+        component.set_property(Property::Name, PropertyValue::String("Component".into()));
+
+        let page_id = self.get_active_page();
+        self.apply(Operation::Insert(Subtree::leaf(component, page_id)));
+        component_id
+    }
+
+    /// Create an instance of a component under `parent_id`.
+    ///
+    /// Returns `None` if `component_id` isn't a component, or if the instance
+    /// would end up inside its own component, directly or through instances
+    /// (see `DocumentTree::check_component_use`).
+    pub fn create_instance(&mut self, component_id: ObjectId, parent_id: ObjectId, x: f32, y: f32) -> Option<ObjectId> {
+        let component = self.tree.get(component_id)?;
+        if component.node_type() != NodeType::Component {
+            return None;
+        }
+
+        let mut nodes = self.mirror_subtree(component_id, Some(parent_id), ObjectId::random())?;
+        let (root, _) = nodes.first_mut()?;
+        let instance_id = root.id();
+        root.set_node_type(NodeType::Instance);
+        root.remove_property(Property::ComponentSource);
+        root.set_property(Property::MainComponent, PropertyValue::Ref(component_id));
+        root.set_property(Property::X, PropertyValue::Float(x));
+        root.set_property(Property::Y, PropertyValue::Float(y));
+        // Placed on top of its new siblings, not where the component sits
+        root.set_order_index(String::new());
+
+        let subtree = Subtree::from_nodes(nodes, usize::MAX)?;
+        self.apply(Operation::Insert(subtree)).then_some(instance_id)
+    }
+
+    /// Get the component an instance was created from
+    pub fn get_main_component(&self, instance_id: ObjectId) -> Option<ObjectId> {
+        self.tree.get(instance_id)?.main_component()
+    }
+
+    /// Turn an instance into a plain frame, keeping its current appearance.
+    ///
+    /// Returns `false` if the node isn't an instance.
+    pub fn detach_instance(&mut self, instance_id: ObjectId) -> bool {
+        let is_instance = self
+            .tree
+            .get(instance_id)
+            .map(|node| node.node_type() == NodeType::Instance)
+            .unwrap_or(false);
+        if !is_instance {
+            return false;
+        }
+        let Some(subtree) = self.tree.clone_subtree(instance_id) else {
+            return false;
+        };
+
+        // Rebuild the same nodes without any component links
+        let position = subtree.position();
+        let nodes: Vec<(Node, Option<ObjectId>)> = subtree
+            .into_nodes()
+            .into_iter()
+            .map(|(mut node, parent)| {
+                if node.id() == instance_id {
+                    node.set_node_type(NodeType::Frame);
+                }
+                node.remove_property(Property::MainComponent);
+                node.remove_property(Property::ComponentSource);
+                for property in node.overrides().to_vec() {
+                    node.set_overridden(property, false);
+                }
+                (node, parent)
+            })
+            .collect();
+
+        let Some(detached) = Subtree::from_nodes(nodes, position) else {
+            return false;
+        };
+
+        self.history.begin_group();
+        self.apply(Operation::Remove { id: instance_id });
+        self.apply(Operation::Insert(detached));
+        self.history.end_group();
+        true
+    }
+
+    /// Drop every override in an instance (or in one node of it) and
+    /// restore the values from the main component.
+    pub fn reset_overrides(&mut self, id: ObjectId) {
+        let mut overridden = Vec::new();
+        self.collect_overrides(id, &mut overridden);
+
+        self.history.begin_group();
+        for (node_id, property) in overridden {
+            let value = self
+                .mirror_source(node_id)
+                .and_then(|source_id| self.tree.get(source_id))
+                .and_then(|source| source.get_property(property).cloned());
+            self.apply(Operation::SetOverride { id: node_id, property, overridden: false });
+            self.apply(Operation::SetProperty { id: node_id, property, value });
+        }
+        self.history.end_group();
+    }
+}
+
+impl Document {
+    /// Mark a property edited by the user as overridden if the node is part of an instance
+    pub(crate) fn mark_override(&mut self, id: ObjectId, property: Property) {
+        let Some(node) = self.tree.get(id) else { return };
+        let is_root = node.main_component().is_some();
+        if (is_root || node.component_source().is_some())
+            && is_linked_property(property, is_root)
+            && !node.is_overridden(property)
+        {
+            self.apply(Operation::SetOverride { id, property, overridden: true });
+        }
+    }
+
+    /// Update instances after a change made through `apply()`.
+    ///
+    /// `inverse` is the inverse of the operation that was just applied, which
+    /// tells us what the tree looked like before the change.
+    pub(crate) fn sync_instances(&mut self, inverse: &Operation) {
+        match inverse {
+            Operation::SetProperty { id, property, .. } => {
+                let Some(component_id) = self.containing_component(*id) else { return };
+                let is_root = *id == component_id;
+                if !is_linked_property(*property, is_root) {
+                    return;
+                }
+                let value = self.tree.get(*id).and_then(|n| n.get_property(*property).cloned());
+                for mirror_id in self.mirrors_of(component_id, *id) {
+                    let overridden = self.tree.get(mirror_id).map(|n| n.is_overridden(*property));
+                    if overridden == Some(false) {
+                        self.apply(Operation::SetProperty {
+                            id: mirror_id,
+                            property: *property,
+                            value: value.clone(),
+                        });
+                    }
+                }
+            }
+            // A node was inserted (its inverse removes it)
+            Operation::Remove { id } => {
+                if let Some(parent_id) = self.tree.parent(*id) {
+                    self.mirror_insert(*id, parent_id);
+                }
+            }
+            // A node was removed (its inverse re-inserts it)
+            Operation::Insert(subtree) => {
+                if let Some(parent_id) = subtree.parent() {
+                    self.mirror_remove(subtree.root(), parent_id);
+                }
+            }
+            // A node was moved from `parent`
+            Operation::Move { id, parent: old_parent, .. } => {
+                let Some(new_parent) = self.tree.parent(*id) else { return };
+                let old_component = self.containing_component(*old_parent);
+                let new_component = self.containing_component(new_parent);
+
+                if let Some(component_id) = old_component.filter(|&c| Some(c) == new_component) {
+                    let (position, order_index) = self.placement(*id);
+                    for (mirror_id, mirror_parent) in self
+                        .mirrors_of(component_id, *id)
+                        .into_iter()
+                        .zip(self.mirrors_of(component_id, new_parent))
+                    {
+                        self.apply(Operation::Move {
+                            id: mirror_id,
+                            parent: mirror_parent,
+                            position,
+                            order_index: order_index.clone(),
+                        });
+                    }
+                } else {
+                    self.mirror_remove(*id, *old_parent);
+                    self.mirror_insert(*id, new_parent);
+                }
+            }
+            Operation::SetOverride { .. } => {}
+        }
+    }
+
+    /// Clone a newly inserted component node into every instance
+    fn mirror_insert(&mut self, id: ObjectId, parent_id: ObjectId) {
+        let Some(component_id) = self.containing_component(parent_id) else { return };
+        let (position, _) = self.placement(id);
+        for mirror_parent in self.mirrors_of(component_id, parent_id) {
+            let subtree = self
+                .mirror_subtree(id, Some(mirror_parent), mirror_id(mirror_parent, id))
+                .and_then(|nodes| Subtree::from_nodes(nodes, position));
+            if let Some(subtree) = subtree {
+                self.apply(Operation::Insert(subtree));
+            }
+        }
+    }
+
+    /// Remove the mirrors of a node that was removed from a component.
+    ///
+    /// `id` no longer exists in the component, so mirrors are found by their
+    /// source link under the mirrors of its former parent.
+    fn mirror_remove(&mut self, id: ObjectId, former_parent: ObjectId) {
+        let Some(component_id) = self.containing_component(former_parent) else { return };
+        for mirror_parent in self.mirrors_of(component_id, former_parent) {
+            let mirror = self
+                .tree
+                .children(mirror_parent)
+                .into_iter()
+                .find(|&child| self.tree.get(child).and_then(|n| n.component_source()) == Some(id));
+            if let Some(mirror_id) = mirror {
+                self.apply(Operation::Remove { id: mirror_id });
+            }
+        }
+    }
+
+    /// Copy a subtree, linking every copy back to its source. The copy of
+    /// `source_id` gets `root_id`; the others get ids derived from their
+    /// parent's and source's (see `mirror_id`).
+    fn mirror_subtree(
+        &self,
+        source_id: ObjectId,
+        parent: Option<ObjectId>,
+        root_id: ObjectId,
+    ) -> Option<Vec<(Node, Option<ObjectId>)>> {
+        let source = self.tree.clone_subtree(source_id)?;
+        let mut new_ids = HashMap::new();
+        let mut nodes = Vec::new();
+
+        for (index, (node, old_parent)) in source.into_nodes().into_iter().enumerate() {
+            let (new_id, new_parent) = if index == 0 {
+                (root_id, parent)
+            } else {
+                let new_parent = old_parent.and_then(|p| new_ids.get(&p).cloned())?;
+                (mirror_id(new_parent, node.id()), Some(new_parent))
+            };
+            new_ids.insert(node.id(), new_id);
+
+            let mut copy = node.duplicate(new_id);
+            // Nested instances are kept in sync through their own mirrors
+            copy.remove_property(Property::MainComponent);
+            copy.set_property(Property::ComponentSource, PropertyValue::Ref(node.id()));
+            for property in node.overrides() {
+                copy.set_overridden(*property, false);
+            }
+            nodes.push((copy, new_parent));
+        }
+        Some(nodes)
+    }
+
+    /// Find the closest component containing `id` (or `id` itself)
+    pub(crate) fn containing_component(&self, id: ObjectId) -> Option<ObjectId> {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            if self.tree.get(node_id)?.node_type() == NodeType::Component {
+                return Some(node_id);
+            }
+            current = self.tree.parent(node_id);
+        }
+        None
+    }
+
+    /// Keep the instance index in step with a change made through
+    /// `apply_untracked()`; `inverse` is the inverse of that change
+    pub(crate) fn index_instances(&mut self, inverse: &Operation) {
+        match inverse {
+            // Nodes were inserted (the inverse removes them)
+            Operation::Remove { id } => {
+                let mut stack = vec![*id];
+                while let Some(node_id) = stack.pop() {
+                    if let Some(node) = self.tree.get(node_id) {
+                        self.instances.add(node);
+                    }
+                    stack.extend(self.tree.children(node_id));
+                }
+            }
+            // Nodes were removed (the inverse re-inserts them)
+            Operation::Insert(subtree) => {
+                for node in subtree.nodes() {
+                    if let Some(component_id) = node.main_component() {
+                        self.instances.remove(node.id(), component_id);
+                    }
+                }
+            }
+            Operation::SetProperty { id, property: Property::MainComponent, value } => {
+                if let Some(PropertyValue::Ref(old)) = value {
+                    self.instances.remove(*id, *old);
+                }
+                if let Some(node) = self.tree.get(*id) {
+                    self.instances.add(node);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// All instances of a component, except any inside the component itself
+    fn instances_of(&self, component_id: ObjectId) -> Vec<ObjectId> {
+        let mut instances: Vec<ObjectId> = self
+            .instances
+            .get(component_id)
+            .filter(|&id| !self.is_in_subtree(id, component_id))
+            .collect();
+        // A stable order, so the lists for two nodes of the component line up
+        instances.sort_by_key(|id| (id.client_id(), id.sequence()));
+        instances
+    }
+
+    /// The nodes mirroring `source_id` (a node inside `component_id`) in every instance
+    fn mirrors_of(&self, component_id: ObjectId, source_id: ObjectId) -> Vec<ObjectId> {
+        self.instances_of(component_id)
+            .into_iter()
+            .filter_map(|instance_id| {
+                if source_id == component_id {
+                    Some(instance_id)
+                } else {
+                    self.find_mirror(instance_id, source_id)
+                }
+            })
+            .collect()
+    }
+
+    /// Depth-first search of an instance for the mirror of `source_id`
+    fn find_mirror(&self, id: ObjectId, source_id: ObjectId) -> Option<ObjectId> {
+        self.tree.children(id).into_iter().find_map(|child| {
+            if self.tree.get(child)?.component_source() == Some(source_id) {
+                Some(child)
+            } else {
+                self.find_mirror(child, source_id)
+            }
+        })
+    }
+
+    /// The component node an instance node mirrors
+    fn mirror_source(&self, id: ObjectId) -> Option<ObjectId> {
+        let node = self.tree.get(id)?;
+        node.main_component().or_else(|| node.component_source())
+    }
+
+    fn collect_overrides(&self, id: ObjectId, out: &mut Vec<(ObjectId, Property)>) {
+        if let Some(node) = self.tree.get(id) {
+            out.extend(node.overrides().iter().map(|&property| (id, property)));
+            for child_id in self.tree.children(id) {
+                self.collect_overrides(child_id, out);
+            }
+        }
+    }
+
+    /// Position and order index of a node among its siblings
+    pub(crate) fn placement(&self, id: ObjectId) -> (usize, String) {
+        let position = self.tree.index_in_parent(id).unwrap_or(usize::MAX);
+        let order_index = self
+            .tree
+            .get(id)
+            .map(|n| n.order_index().to_string())
+            .unwrap_or_default();
+        (position, order_index)
+    }
+
+    /// Check whether `id` is `ancestor_id` or one of its descendants
+    pub(crate) fn is_in_subtree(&self, id: ObjectId, ancestor_id: ObjectId) -> bool {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            if node_id == ancestor_id {
+                return true;
+            }
+            current = self.tree.parent(node_id);
+        }
+        false
+    }
+}
+
+/// Whether a property is kept in sync between a component node and its mirrors.
+///
+/// An instance is positioned and named independently of its component, so
+/// those properties of the root are never linked.
+fn is_linked_property(property: Property, is_root: bool) -> bool {
+    match property {
+        Property::MainComponent | Property::ComponentSource | Property::ParentId => false,
+        Property::X | Property::Y | Property::Name => !is_root,
+        _ => true,
+    }
+}
+
+/// The id of the mirror of `source_id` under `parent_id`, the same on every
+/// client. A parent holds at most one mirror of each source.
+fn mirror_id(parent_id: ObjectId, source_id: ObjectId) -> ObjectId {
+    // splitmix64's finalizer, applied to each half in turn
+    let mix = |x: u64| {
+        let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    };
+    let half = |id: ObjectId| (u64::from(id.client_id()) << 32) | u64::from(id.sequence());
+    let hash = mix(mix(half(parent_id)) ^ half(source_id));
+    ObjectId::new((hash >> 32) as u32, hash as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button() -> (Document, ObjectId, ObjectId) {
+        let mut doc = Document::new("Components");
+        let component_id = doc.create_component(0.0, 0.0, 100.0, 40.0);
+        let label_id = doc.create_text(component_id, 10.0, 10.0, "Label");
+        (doc, component_id, label_id)
+    }
+
+    #[test]
+    fn test_instance_follows_component() {
+        let (mut doc, component_id, label_id) = button();
+        let page_id = doc.tree.first_page().unwrap();
+        let instance_id = doc.create_instance(component_id, page_id, 200.0, 0.0).unwrap();
+        let mirror_id = doc.get_children(instance_id)[0];
+
+        assert_eq!(doc.get_main_component(instance_id), Some(component_id));
+        assert_eq!(doc.get_node_x(instance_id), 200.0);
+
+        // Property edits flow through, except for overridden ones
+        doc.set_node_width(component_id, 120.0);
+        assert_eq!(doc.get_node_width(instance_id), 120.0);
+
+        doc.set_node_x(mirror_id, 30.0);
+        doc.set_node_x(label_id, 15.0);
+        doc.set_node_y(label_id, 12.0);
+        assert_eq!(doc.get_node_x(mirror_id), 30.0);
+        assert_eq!(doc.get_node_y(mirror_id), 12.0);
+
+        doc.reset_overrides(instance_id);
+        assert_eq!(doc.get_node_x(mirror_id), 15.0);
+
+        // Structural edits flow through too, and undo as one step
+        let icon_id = doc.create_rectangle(component_id, 0.0, 0.0, 8.0, 8.0);
+        assert_eq!(doc.get_children(instance_id).len(), 2);
+        doc.delete_node(icon_id);
+        assert_eq!(doc.get_children(instance_id).len(), 1);
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(instance_id).len(), 2);
+    }
+
+    #[test]
+    fn test_mirror_ids_match_across_clients() {
+        let (mut doc, component_id, _) = button();
+        let page_id = doc.tree.first_page().unwrap();
+        let instance_id = doc.create_instance(component_id, page_id, 200.0, 0.0).unwrap();
+        let mut other = Document::decode_binary(&doc.encode_binary().unwrap()).unwrap();
+
+        // The same component edit, applied on two clients, builds the same mirrors
+        let icon_id = ObjectId::new(7, 1);
+        for doc in [&mut doc, &mut other] {
+            doc.apply(Operation::Insert(Subtree::leaf(Node::new(icon_id, NodeType::Rectangle), Some(component_id))));
+        }
+        let mirrors = doc.get_children(instance_id);
+        assert_eq!(mirrors.len(), 2);
+        assert_eq!(mirrors, other.get_children(instance_id));
+        assert!(mirrors.iter().any(|&id| doc.get_node(id).unwrap().component_source() == Some(icon_id)));
+    }
+
+    #[test]
+    fn test_remote_edits_to_mirrors_are_not_overrides() {
+        let (mut doc, component_id, _) = button();
+        let page_id = doc.tree.first_page().unwrap();
+        let instance_id = doc.create_instance(component_id, page_id, 200.0, 0.0).unwrap();
+        let mirror_id = doc.get_children(instance_id)[0];
+
+        doc.apply_remote_property(mirror_id, Property::Width, PropertyValue::Float(80.0));
+        assert!(!doc.get_node(mirror_id).unwrap().is_overridden(Property::Width));
+    }
+
+    #[test]
+    fn test_detach_instance() {
+        let (mut doc, component_id, label_id) = button();
+        let page_id = doc.tree.first_page().unwrap();
+        let instance_id = doc.create_instance(component_id, page_id, 0.0, 100.0).unwrap();
+
+        assert!(doc.detach_instance(instance_id));
+        let frame = doc.get_node(instance_id).unwrap();
+        assert_eq!(frame.node_type(), NodeType::Frame);
+        assert_eq!(frame.main_component(), None);
+
+        doc.set_node_x(label_id, 50.0);
+        let mirror_id = doc.get_children(instance_id)[0];
+        assert_eq!(doc.get_node_x(mirror_id), 10.0);
+
+        // Instances can't be placed inside their own component
+        assert!(doc.create_instance(component_id, component_id, 0.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn test_rejects_components_nested_through_instances() {
+        let mut doc = Document::new("Components");
+        let page_id = doc.tree.first_page().unwrap();
+        let a = doc.create_component(0.0, 0.0, 100.0, 100.0);
+        let b = doc.create_component(200.0, 0.0, 100.0, 100.0);
+        let b_in_a = doc.create_instance(b, a, 0.0, 0.0).unwrap();
+
+        // A uses B, so B can't hold an instance of A, however it gets there
+        assert!(doc.create_instance(a, b, 0.0, 0.0).is_none());
+        let a_on_page = doc.create_instance(a, page_id, 0.0, 200.0).unwrap();
+        assert!(!doc.move_node(a_on_page, b, 0));
+        assert!(doc.paste(&doc.copy(vec![a_on_page]), b, 0).is_empty());
+        assert!(doc.create_instance(a, b_in_a, 0.0, 0.0).is_none());
+        assert!(doc.tree.validate().is_ok());
+
+        // Edits still reach the instances, including ones in a loaded copy
+        let rect_id = doc.create_rectangle(b, 0.0, 0.0, 10.0, 10.0);
+        assert_eq!(doc.get_children(b_in_a).len(), 1);
+        let mut loaded = Document::decode_json(&doc.to_json()).unwrap();
+        loaded.set_node_width(rect_id, 20.0);
+        let mirror_id = loaded.get_children(b_in_a)[0];
+        assert_eq!(loaded.get_node_width(mirror_id), 20.0);
+    }
+}
@@ -0,0 +1,193 @@
+//! Resize constraints
+//!
+//! When a frame is resized, each child decides how to follow it based on its
+//! `ConstraintHorizontal` / `ConstraintVertical` properties:
+//!
+//! ```text
+//! parent 200 wide → 300 wide, child at x = 150, width = 40
+//!
+//!   "left"        x = 150, width = 40    (default: keep distance to left edge)
+//!   "right"       x = 250, width = 40    (keep distance to right edge)
+//!   "left_right"  x = 150, width = 140   (keep both distances, stretch)
+//!   "center"      x = 200, width = 40    (keep offset from the center)
+//!   "scale"       x = 225, width = 60    (scale with the parent)
+//! ```
+//!
+//! Vertical constraints use `"top"`, `"bottom"`, `"top_bottom"`, `"center"`
+//! and `"scale"`. Children of auto layout frames are positioned by the layout
+//! instead and ignore their constraints, as do the operands of boolean nodes
+//! and the children of groups.
+//!
+//! Constraints run from `Document::apply()` whenever a node's Width or Height
+//! changes. Resized children go through `apply()` too, so constraints cascade
+//! down to grandchildren.
+
+use super::{Document, LayoutSettings, Node, NodeType, ObjectId, Operation, Property, PropertyValue};
+use wasm_bindgen::prelude::*;
+
+/// Size a node has when its Width/Height property is unset (see `Node::width()`)
+const DEFAULT_SIZE: f32 = 100.0;
+
+/// How a child follows its parent along one axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constraint {
+    /// Keep the distance to the left (or top) edge
+    Min,
+    /// Keep the distance to the right (or bottom) edge
+    Max,
+    /// Keep the distance to both edges
+    Stretch,
+    /// Keep the offset from the parent's center
+    Center,
+    /// Scale position and size with the parent
+    Scale,
+}
+
+impl Constraint {
+    /// Parse a constraint value. Both axis spellings are accepted.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "left" | "top" => Some(Constraint::Min),
+            "right" | "bottom" => Some(Constraint::Max),
+            "left_right" | "top_bottom" => Some(Constraint::Stretch),
+            "center" => Some(Constraint::Center),
+            "scale" => Some(Constraint::Scale),
+            _ => None,
+        }
+    }
+
+    /// A node's horizontal and vertical constraints
+    pub fn of(node: &Node) -> (Self, Self) {
+        let read = |property| match node.get_property(property) {
+            Some(PropertyValue::String(s)) => Constraint::parse(s).unwrap_or(Constraint::Min),
+            _ => Constraint::Min,
+        };
+        (read(Property::ConstraintHorizontal), read(Property::ConstraintVertical))
+    }
+
+    /// New `(position, size)` of a child along one axis when its parent's
+    /// size changes from `old_parent` to `new_parent`
+    pub fn resolve(self, position: f32, size: f32, old_parent: f32, new_parent: f32) -> (f32, f32) {
+        let delta = new_parent - old_parent;
+        match self {
+            Constraint::Min => (position, size),
+            Constraint::Max => (position + delta, size),
+            Constraint::Stretch => (position, (size + delta).max(0.0)),
+            Constraint::Center => (position + delta / 2.0, size),
+            Constraint::Scale if old_parent > 0.0 => {
+                let factor = new_parent / old_parent;
+                (position * factor, size * factor)
+            }
+            Constraint::Scale => (position, size),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Set how a node follows its parent when the parent is resized.
+    ///
+    /// `horizontal` is `"left"`, `"right"`, `"left_right"`, `"center"` or
+    /// `"scale"`; `vertical` is `"top"`, `"bottom"`, `"top_bottom"`,
+    /// `"center"` or `"scale"`.
+    pub fn set_constraints(&mut self, id: ObjectId, horizontal: &str, vertical: &str) {
+        self.history.begin_group();
+        self.set_node_property(id, Property::ConstraintHorizontal, PropertyValue::String(horizontal.into()));
+        self.set_node_property(id, Property::ConstraintVertical, PropertyValue::String(vertical.into()));
+        self.history.end_group();
+    }
+}
+
+impl Document {
+    /// Reposition the children of a node whose Width or Height just changed
+    pub(crate) fn apply_constraints(&mut self, inverse: &Operation) {
+        let Operation::SetProperty { id, property, value } = inverse else {
+            return;
+        };
+        let horizontal = match property {
+            Property::Width => true,
+            Property::Height => false,
+            _ => return,
+        };
+
+        let Some(parent) = self.tree.get(*id).map(|node| self.bound_node(node)) else { return };
+        // Boolean nodes and groups are sized to fit their children, not the
+        // other way round
+        let fits_children = matches!(parent.node_type(), NodeType::BooleanOperation | NodeType::Group);
+        if LayoutSettings::of(&parent).is_some() || fits_children {
+            return;
+        }
+        let old_value = match value {
+            Some(PropertyValue::Variable(variable_id)) => self.variable_value(*variable_id, *id),
+            _ => value.clone(),
+        };
+        let old_size = match old_value {
+            Some(PropertyValue::Float(v)) => v,
+            _ => DEFAULT_SIZE,
+        };
+        let new_size = if horizontal { parent.width() } else { parent.height() };
+        if old_size == new_size {
+            return;
+        }
+
+        for child_id in self.tree.children(*id) {
+            let Some(child) = self.tree.get(child_id).map(|node| self.bound_node(node)) else { continue };
+            let (constraint, position, size, position_property, size_property) = if horizontal {
+                (Constraint::of(&child).0, child.x(), child.width(), Property::X, Property::Width)
+            } else {
+                (Constraint::of(&child).1, child.y(), child.height(), Property::Y, Property::Height)
+            };
+
+            let (new_position, new_child_size) = constraint.resolve(position, size, old_size, new_size);
+            if new_position != position {
+                self.apply(Operation::SetProperty {
+                    id: child_id,
+                    property: position_property,
+                    value: Some(PropertyValue::Float(new_position)),
+                });
+            }
+            if new_child_size != size {
+                self.apply(Operation::SetProperty {
+                    id: child_id,
+                    property: size_property,
+                    value: Some(PropertyValue::Float(new_child_size)),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_constraints() {
+        assert_eq!(Constraint::Min.resolve(150.0, 40.0, 200.0, 300.0), (150.0, 40.0));
+        assert_eq!(Constraint::Max.resolve(150.0, 40.0, 200.0, 300.0), (250.0, 40.0));
+        assert_eq!(Constraint::Stretch.resolve(150.0, 40.0, 200.0, 300.0), (150.0, 140.0));
+        assert_eq!(Constraint::Center.resolve(150.0, 40.0, 200.0, 300.0), (200.0, 40.0));
+        assert_eq!(Constraint::Scale.resolve(150.0, 40.0, 200.0, 300.0), (225.0, 60.0));
+        assert_eq!(Constraint::Stretch.resolve(0.0, 40.0, 200.0, 100.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_resize_cascades_to_descendants() {
+        let mut doc = Document::new("Constraints");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 100.0);
+        let panel_id = doc.create_rectangle(frame_id, 10.0, 10.0, 180.0, 80.0);
+        let badge_id = doc.create_rectangle(panel_id, 150.0, 0.0, 20.0, 20.0);
+        doc.set_constraints(panel_id, "left_right", "top");
+        doc.set_constraints(badge_id, "right", "top");
+
+        doc.set_node_width(frame_id, 300.0);
+        assert_eq!(doc.get_node_x(panel_id), 10.0);
+        assert_eq!(doc.get_node_width(panel_id), 280.0);
+        assert_eq!(doc.get_node_x(badge_id), 250.0);
+
+        // One undo step reverts the resize and everything it caused
+        assert!(doc.undo());
+        assert_eq!(doc.get_node_width(panel_id), 180.0);
+        assert_eq!(doc.get_node_x(badge_id), 150.0);
+    }
+}
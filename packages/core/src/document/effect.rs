@@ -0,0 +1,230 @@
+//! Effects
+//!
+//! A node's effects are a list drawn around its content:
+//!
+//! ```text
+//! Effects = [ drop shadow #0004 (0, 4) blur 8,
+//!             drop shadow #0002 (0, 1) blur 2,
+//!             inner shadow #FFF8 (0, 1) blur 0,
+//!             background blur 20 ]
+//! ```
+//!
+//! Drop shadows are drawn under the node, bottom first, then background
+//! blurs blur whatever is already underneath its shape. Inner shadows go
+//! over the node's fills, under its children. A layer blur blurs the node and
+//! everything inside it.
+//!
+//! Nodes without an `Effects` list get at most one drop shadow (from
+//! `ShadowColor`, `ShadowOffsetX` / `ShadowOffsetY`, `ShadowBlur` and
+//! `ShadowSpread`) and one layer blur (from `BlurRadius`).
+//!
+//! ## Extents
+//!
+//! Blurs are Gaussian with a standard deviation of half the blur radius, and
+//! reach three deviations out. A node's render bounds (`get_render_bounds`)
+//! grow by that much, plus a drop shadow's offset and spread, so culling and
+//! anything cropping to a node see the whole effect.
+
+use super::{Color, Document, Node, NodeType, ObjectId, Property, PropertyValue};
+use crate::math::Rect;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// A drop or inner shadow
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Shadow {
+    pub color: Color,
+    #[serde(default)]
+    pub offset_x: f32,
+    #[serde(default)]
+    pub offset_y: f32,
+    #[serde(default)]
+    pub blur: f32,
+    /// Grows the shadow's shape (shrinks it for inner shadows)
+    #[serde(default)]
+    pub spread: f32,
+}
+
+/// What an effect does
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EffectKind {
+    DropShadow(Shadow),
+    InnerShadow(Shadow),
+    LayerBlur { radius: f32 },
+    BackgroundBlur { radius: f32 },
+}
+
+/// One entry of a node's effects
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Effect {
+    #[serde(flatten)]
+    pub kind: EffectKind,
+    #[serde(default = "visible")]
+    pub visible: bool,
+}
+
+impl Effect {
+    pub fn new(kind: EffectKind) -> Self {
+        Self { kind, visible: true }
+    }
+
+    /// The part of a node's local space this effect draws to, given the
+    /// node's bounds. Inner shadows and background blurs stay inside.
+    pub fn extent(&self, bounds: Rect) -> Rect {
+        match self.kind {
+            EffectKind::DropShadow(shadow) => {
+                let shifted = Rect::new(bounds.x + shadow.offset_x, bounds.y + shadow.offset_y, bounds.width, bounds.height);
+                shifted.expand(shadow.spread + blur_reach(shadow.blur))
+            }
+            EffectKind::LayerBlur { radius } => bounds.expand(blur_reach(radius)),
+            EffectKind::InnerShadow(_) | EffectKind::BackgroundBlur { .. } => bounds,
+        }
+    }
+}
+
+fn visible() -> bool {
+    true
+}
+
+/// The standard deviation of the Gaussian for a blur radius
+pub fn blur_sigma(radius: f32) -> f32 {
+    radius.max(0.0) / 2.0
+}
+
+/// How far a blur of this radius spreads
+fn blur_reach(radius: f32) -> f32 {
+    blur_sigma(radius) * 3.0
+}
+
+/// The bounds plus everything the visible effects draw outside them
+pub fn effect_bounds(effects: &[Effect], bounds: Rect) -> Rect {
+    effects
+        .iter()
+        .filter(|effect| effect.visible)
+        .fold(bounds, |rect, effect| rect.union(&effect.extent(bounds)))
+}
+
+/// How far a node's stroke reaches outside its bounds. Strokes are centered
+/// on the outline, and the corners of vector paths are mitered out to at
+/// most 4 half widths (lyon's default limit).
+fn stroke_extent(node: &Node) -> f32 {
+    let width = node.stroke_width();
+    match node.node_type() {
+        NodeType::Line => width.max(1.0) / 2.0,
+        _ if width <= 0.0 => 0.0,
+        NodeType::Vector | NodeType::BooleanOperation => width * 2.0,
+        _ => width / 2.0,
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// A node's effects as a JSON array (see `Effect`)
+    pub fn get_effects(&self, id: ObjectId) -> String {
+        let effects = self.tree.get(id).map(|node| self.bound_node(node).effects()).unwrap_or_default();
+        serde_json::to_string(&effects).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Replace a node's effects with a JSON array of effects.
+    ///
+    /// Returns `false` if the JSON isn't a list of effects.
+    pub fn set_effects(&mut self, id: ObjectId, json: &str) -> bool {
+        let Ok(effects) = serde_json::from_str::<Vec<Effect>>(json) else {
+            return false;
+        };
+        self.update_effects(id, |current| *current = effects)
+    }
+
+    pub fn add_drop_shadow(&mut self, id: ObjectId, color: Color, offset_x: f32, offset_y: f32, blur: f32, spread: f32) -> bool {
+        let shadow = Shadow { color, offset_x, offset_y, blur: blur.max(0.0), spread };
+        self.update_effects(id, |effects| effects.push(Effect::new(EffectKind::DropShadow(shadow))))
+    }
+
+    pub fn add_inner_shadow(&mut self, id: ObjectId, color: Color, offset_x: f32, offset_y: f32, blur: f32, spread: f32) -> bool {
+        let shadow = Shadow { color, offset_x, offset_y, blur: blur.max(0.0), spread };
+        self.update_effects(id, |effects| effects.push(Effect::new(EffectKind::InnerShadow(shadow))))
+    }
+
+    pub fn add_layer_blur(&mut self, id: ObjectId, radius: f32) -> bool {
+        let radius = radius.max(0.0);
+        self.update_effects(id, |effects| effects.push(Effect::new(EffectKind::LayerBlur { radius })))
+    }
+
+    pub fn add_background_blur(&mut self, id: ObjectId, radius: f32) -> bool {
+        let radius = radius.max(0.0);
+        self.update_effects(id, |effects| effects.push(Effect::new(EffectKind::BackgroundBlur { radius })))
+    }
+
+    pub fn remove_effect(&mut self, id: ObjectId, index: usize) -> bool {
+        index < self.effect_count(id)
+            && self.update_effects(id, |effects| {
+                effects.remove(index);
+            })
+    }
+
+    pub fn set_effect_visible(&mut self, id: ObjectId, index: usize, visible: bool) -> bool {
+        index < self.effect_count(id) && self.update_effects(id, |effects| effects[index].visible = visible)
+    }
+
+    /// A node's axis-aligned bounds in canvas coordinates including what its
+    /// stroke and its effects (its effect style's, if it has one) draw
+    /// outside them (its children aren't included)
+    pub fn get_render_bounds(&self, id: ObjectId) -> Option<Rect> {
+        let node = self.resolved_node(id)?;
+        let bounds = Rect::new(0.0, 0.0, node.width(), node.height());
+        let local = effect_bounds(&node.effects(), bounds).union(&bounds.expand(stroke_extent(&node)));
+        Some(self.world_transform(id).transform_rect(&local))
+    }
+}
+
+impl Document {
+    /// Edit a node's effects as a list, turning the single shadow and blur
+    /// properties into effects the first time
+    fn update_effects(&mut self, id: ObjectId, update: impl FnOnce(&mut Vec<Effect>)) -> bool {
+        let Some(mut effects) = self.tree.get(id).map(|node| self.bound_node(node).effects()) else {
+            return false;
+        };
+        update(&mut effects);
+        self.set_node_property(id, Property::Effects, PropertyValue::Effects(effects));
+        true
+    }
+
+    fn effect_count(&self, id: ObjectId) -> usize {
+        self.tree.get(id).map_or(0, |node| self.bound_node(node).effects().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effects_and_render_bounds() {
+        let mut doc = Document::new("Effects");
+        let page_id = doc.get_active_page().unwrap();
+        let rect_id = doc.create_rectangle(page_id, 100.0, 100.0, 50.0, 50.0);
+        assert_eq!(doc.get_effects(rect_id), "[]");
+
+        // The single shadow properties become the first entry of the list
+        doc.set_node_property(rect_id, Property::ShadowColor, PropertyValue::Color(Color::black()));
+        doc.set_node_property(rect_id, Property::ShadowOffsetY, PropertyValue::Float(4.0));
+        doc.set_node_property(rect_id, Property::ShadowBlur, PropertyValue::Float(8.0));
+        assert!(doc.add_inner_shadow(rect_id, Color::white(), 0.0, 1.0, 0.0, 0.0));
+        let effects = doc.tree.get(rect_id).unwrap().effects();
+        assert_eq!(effects.len(), 2);
+        assert!(matches!(effects[0].kind, EffectKind::DropShadow(Shadow { offset_y: 4.0, blur: 8.0, .. })));
+
+        // Blur 8 reaches 12 out, around a shadow moved 4 down
+        let bounds = doc.get_render_bounds(rect_id).unwrap();
+        assert_eq!((bounds.x, bounds.y, bounds.width, bounds.height), (88.0, 92.0, 74.0, 74.0));
+        assert!(doc.nodes_in_rect(155.0, 155.0, 5.0, 5.0).is_empty());
+        assert_eq!(doc.nodes_drawn_in_rect(155.0, 155.0, 5.0, 5.0), vec![rect_id]);
+
+        // Hidden effects don't count
+        assert!(doc.set_effect_visible(rect_id, 0, false));
+        assert_eq!(doc.get_render_bounds(rect_id).unwrap().width, 50.0);
+        assert!(doc.nodes_drawn_in_rect(155.0, 155.0, 5.0, 5.0).is_empty());
+        assert!(!doc.remove_effect(rect_id, 2));
+    }
+}
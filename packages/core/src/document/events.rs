@@ -0,0 +1,257 @@
+//! Change events
+//!
+//! The UI panels mirror parts of the document (the layer list, the selected
+//! node's properties). Instead of re-reading everything after each edit,
+//! they can ask the document what changed:
+//!
+//! ```text
+//! doc.set_change_events_enabled(true)
+//!
+//! every frame:
+//!   for event in JSON.parse(doc.take_change_events()):
+//!     node_created      → add a row to the layer list
+//!     node_deleted      → drop it
+//!     node_moved        → re-slot it under its new parent
+//!     property_changed  → patch the inspector if the node is selected
+//!     resolved_changed  → re-read the node's resolved values if selected
+//! ```
+//!
+//! Every change is reported, whatever caused it: user edits, undo and redo,
+//! remote changes, and follow-up changes such as auto layout or instance
+//! updates. Loading a document reports nothing; read it once after loading.
+//!
+//! A node's own properties aren't all it shows: editing a style, a
+//! variable's values or the variable modes of a frame changes the resolved
+//! values of every node using them. The edited node gets `property_changed`
+//! and each of those nodes gets `resolved_changed`.
+//!
+//! Events queue up until taken, so recording is off until a consumer turns
+//! it on.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue};
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// One change to the document, as seen after it happened
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChangeEvent {
+    /// A node was added. Nodes added together (a pasted frame and its
+    /// contents) are reported parents first.
+    NodeCreated {
+        id: ObjectId,
+        parent: Option<ObjectId>,
+        node_type: NodeType,
+    },
+    /// A node was removed. Removing a node removes its descendants too; each
+    /// is reported, parents first.
+    NodeDeleted { id: ObjectId, parent: Option<ObjectId> },
+    /// A node changed parent or position among its siblings
+    NodeMoved {
+        id: ObjectId,
+        old_parent: ObjectId,
+        new_parent: ObjectId,
+        index: usize,
+    },
+    /// A property was set or cleared (`None` means unset)
+    PropertyChanged {
+        id: ObjectId,
+        property: Property,
+        old_value: Option<PropertyValue>,
+        new_value: Option<PropertyValue>,
+    },
+    /// A style, variable or variable mode the node uses changed, so its
+    /// resolved values (see `Document::resolved_node`) may have too
+    ResolvedChanged { id: ObjectId },
+}
+
+/// Events waiting to be taken by the UI
+#[derive(Debug, Default)]
+pub(crate) struct ChangeLog {
+    enabled: bool,
+    events: Vec<ChangeEvent>,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Start or stop recording change events. Stopping drops pending events.
+    pub fn set_change_events_enabled(&mut self, enabled: bool) {
+        self.changes.enabled = enabled;
+        if !enabled {
+            self.changes.events.clear();
+        }
+    }
+
+    /// Take every event recorded since the last call, oldest first, as a JSON
+    /// array (see `ChangeEvent`)
+    pub fn take_change_events(&mut self) -> String {
+        serde_json::to_string(&self.drain_changes()).unwrap_or_else(|_| "[]".into())
+    }
+
+    pub fn pending_change_count(&self) -> usize {
+        self.changes.events.len()
+    }
+}
+
+impl Document {
+    /// Take every recorded event, oldest first
+    pub(crate) fn drain_changes(&mut self) -> Vec<ChangeEvent> {
+        std::mem::take(&mut self.changes.events)
+    }
+
+    /// Record the events for an operation that was just applied, given the
+    /// operation that undoes it
+    pub(crate) fn record_changes(&mut self, inverse: &Operation) {
+        if !self.changes.enabled {
+            return;
+        }
+        match inverse {
+            Operation::SetProperty { id, property, value } => {
+                let new_value = self.tree.get(*id).and_then(|n| n.get_property(*property)).cloned();
+                self.changes.events.push(ChangeEvent::PropertyChanged {
+                    id: *id,
+                    property: *property,
+                    old_value: value.clone(),
+                    new_value,
+                });
+                let dependents = self.resolved_dependents(*id, *property);
+                self.changes.events.extend(dependents.into_iter().map(|id| ChangeEvent::ResolvedChanged { id }));
+            }
+            // A node was inserted
+            Operation::Remove { id } => {
+                let mut pending = vec![*id];
+                let mut index = 0;
+                while let Some(&node_id) = pending.get(index) {
+                    pending.extend(self.tree.children(node_id));
+                    index += 1;
+                }
+                for node_id in pending {
+                    let Some(node) = self.tree.get(node_id) else { continue };
+                    self.changes.events.push(ChangeEvent::NodeCreated {
+                        id: node_id,
+                        parent: self.tree.parent(node_id),
+                        node_type: node.node_type(),
+                    });
+                }
+            }
+            // A subtree was removed
+            Operation::Insert(subtree) => {
+                let events = subtree
+                    .entries()
+                    .map(|(node, parent)| ChangeEvent::NodeDeleted { id: node.id(), parent });
+                self.changes.events.extend(events);
+            }
+            Operation::Move { id, parent: old_parent, .. } => {
+                let (Some(new_parent), Some(index)) = (self.tree.parent(*id), self.tree.index_in_parent(*id)) else {
+                    return;
+                };
+                self.changes.events.push(ChangeEvent::NodeMoved {
+                    id: *id,
+                    old_parent: *old_parent,
+                    new_parent,
+                    index,
+                });
+            }
+            // Overrides don't change what's drawn
+            Operation::SetOverride { .. } => {}
+        }
+    }
+
+    /// The nodes whose resolved values can change with a property of `id`:
+    /// the consumers of a style or of a variable (and of the styles bound to
+    /// it), or the nodes under a change of variable modes
+    fn resolved_dependents(&self, id: ObjectId, property: Property) -> Vec<ObjectId> {
+        let Some(node) = self.tree.get(id) else { return Vec::new() };
+        match (node.node_type(), property) {
+            (NodeType::Style, _) => self.get_style_consumers(id),
+            (NodeType::Variable, Property::VariableValues) => {
+                let mut dependents = Vec::new();
+                for consumer in self.get_variable_consumers(id) {
+                    dependents.push(consumer);
+                    if self.tree.get(consumer).map(Node::node_type) == Some(NodeType::Style) {
+                        dependents.extend(self.get_style_consumers(consumer));
+                    }
+                }
+                dependents
+            }
+            (_, Property::VariableModes) => {
+                let mut dependents = Vec::new();
+                let mut stack = self.tree.children(id);
+                while let Some(node_id) = stack.pop() {
+                    dependents.push(node_id);
+                    stack.extend(self.tree.children(node_id));
+                }
+                dependents
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Color;
+
+    #[test]
+    fn test_change_events() {
+        let mut doc = Document::new("Events");
+        let page_id = doc.get_active_page().unwrap();
+        doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        assert_eq!(doc.pending_change_count(), 0);
+
+        doc.set_change_events_enabled(true);
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        doc.set_node_x(rect_id, 5.0);
+        doc.move_node(rect_id, page_id, 0);
+        doc.delete_node(frame_id);
+        assert!(doc.undo());
+
+        let events = doc.drain_changes();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::NodeCreated { id: frame_id, parent: Some(page_id), node_type: NodeType::Frame },
+                ChangeEvent::NodeCreated { id: rect_id, parent: Some(frame_id), node_type: NodeType::Rectangle },
+                ChangeEvent::PropertyChanged {
+                    id: rect_id,
+                    property: Property::X,
+                    old_value: Some(PropertyValue::Float(0.0)),
+                    new_value: Some(PropertyValue::Float(5.0)),
+                },
+                ChangeEvent::NodeMoved { id: rect_id, old_parent: frame_id, new_parent: page_id, index: 0 },
+                ChangeEvent::NodeDeleted { id: frame_id, parent: Some(page_id) },
+                ChangeEvent::NodeCreated { id: frame_id, parent: Some(page_id), node_type: NodeType::Frame },
+            ]
+        );
+        assert_eq!(doc.take_change_events(), "[]");
+    }
+
+    #[test]
+    fn test_style_and_variable_edits_reach_consumers() {
+        let mut doc = Document::new("Events");
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        let brand = doc.create_style("fill", "Brand").unwrap();
+        doc.apply_style(rect_id, brand);
+        let collection = doc.create_variable_collection("Sizes");
+        let mode = doc.get_variable_modes(collection)[0];
+        let size = doc.create_variable(collection, "number", "size").unwrap();
+        doc.bind_variable(rect_id, Property::Width, size);
+
+        doc.set_change_events_enabled(true);
+        doc.set_style_color(brand, Property::FillColor, Color::red());
+        doc.set_variable_number(size, mode, 40.0);
+        doc.set_variable_mode(frame_id, collection, mode);
+        let resolved: Vec<ObjectId> = doc
+            .drain_changes()
+            .into_iter()
+            .filter_map(|event| match event {
+                ChangeEvent::ResolvedChanged { id } => Some(id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(resolved, vec![rect_id, rect_id, rect_id]);
+    }
+}
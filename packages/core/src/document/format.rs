@@ -0,0 +1,484 @@
+//! Document file format
+//!
+//! Documents are saved as JSON with an explicit schema version:
+//!
+//! ```text
+//! {
+//!   "schema_version": 1,
+//!   "name": "My Design",
+//!   "version": 42,
+//!   "root": { "client_id": 1, "sequence": 0 },
+//!   "nodes": [
+//!     { "id": ..., "node_type": "Document", "properties": {}, "order_index": "" },
+//!     { "id": ..., "node_type": "Page", "order_index": "V", ..., "parent": { ... } },
+//!     ...
+//!   ],
+//!   "images": [ "iVBORw0KGgo...", ... ]
+//! }
+//! ```
+//!
+//! Nodes are listed parents-first in sibling order, each with its parent id,
+//! so loading is a single pass and the `children_map` / `parent_map` indexes
+//! don't need to be stored. `images` holds the base64 bytes of every image
+//! file a node uses (see `image.rs`), and is left out when there are none.
+//!
+//! ## Migrations
+//!
+//! When the shape of the saved data changes (a property is renamed, a node
+//! type is split, a value changes representation), bump [`SCHEMA_VERSION`]
+//! and append a migration to [`MIGRATIONS`]. Files are upgraded one version
+//! at a time on the raw JSON before being deserialized, so each migration only
+//! needs to know about its own step.
+//!
+//! Files without a `schema_version` are version 0: the bare `DocumentTree`
+//! that `to_json()` wrote before this format existed.
+
+use super::{spread_keys, Document, DocumentTree, Node, ObjectId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::JsValue;
+
+/// The schema version written by this build
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Upgrades a raw document file by exactly one schema version
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// `MIGRATIONS[i]` upgrades a file from schema version `i` to `i + 1`.
+///
+/// The length of this list must always be `SCHEMA_VERSION`.
+const MIGRATIONS: &[Migration] = &[wrap_bare_tree, base62_order_indices];
+
+/// Name given to version 0 files, which didn't store one
+const UNTITLED: &str = "Untitled";
+
+/// Errors that can occur while loading a document file
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatError {
+    /// The input isn't valid JSON
+    Syntax(String),
+    /// The `schema_version` field isn't a 32-bit unsigned integer
+    InvalidSchemaVersion(String),
+    /// The file was written by a newer (or unknown) version of Anatsui
+    UnsupportedVersion { found: u32, supported: u32 },
+    /// A migration step failed
+    Migration { from: u32, message: String },
+    /// The JSON is well-formed but doesn't describe a valid document
+    Invalid(String),
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Syntax(message) => write!(f, "Invalid JSON: {}", message),
+            FormatError::InvalidSchemaVersion(found) => write!(f, "Invalid schema_version {}", found),
+            FormatError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "Unsupported schema version {} (this build supports up to {})",
+                found, supported
+            ),
+            FormatError::Migration { from, message } => write!(
+                f,
+                "Failed to migrate from schema version {}: {}",
+                from, message
+            ),
+            FormatError::Invalid(message) => write!(f, "Invalid document: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<FormatError> for JsValue {
+    fn from(error: FormatError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+/// The on-disk representation of a document
+#[derive(Debug, Serialize, Deserialize)]
+struct DocumentFile {
+    schema_version: u32,
+    name: String,
+    version: u32,
+    root: ObjectId,
+    nodes: Vec<NodeRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+}
+
+/// A node together with its parent
+#[derive(Debug, Serialize, Deserialize)]
+struct NodeRecord {
+    #[serde(flatten)]
+    node: Node,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<ObjectId>,
+}
+
+impl Document {
+    /// Serialize the document to the current JSON file format
+    pub(crate) fn encode_json(&self) -> Result<String, FormatError> {
+        let root = self.tree.root_id();
+        let mut nodes = Vec::with_capacity(self.tree.len());
+        let mut visited = HashSet::new();
+        // Nodes that aren't attached under the root are left out: the file
+        // couldn't be loaded with them (see `check_tree`)
+        collect_records(&self.tree, root, &mut nodes, &mut visited);
+        let images = self.encode_images(nodes.iter().map(|record| &record.node));
+
+        let file = DocumentFile {
+            schema_version: SCHEMA_VERSION,
+            name: self.name.clone(),
+            version: self.version,
+            root,
+            nodes,
+            images,
+        };
+        serde_json::to_string(&file).map_err(|e| FormatError::Invalid(e.to_string()))
+    }
+
+    /// Load a document from JSON, migrating older files to the current schema
+    pub(crate) fn decode_json(json: &str) -> Result<Document, FormatError> {
+        let mut value: Value =
+            serde_json::from_str(json).map_err(|e| FormatError::Syntax(e.to_string()))?;
+        migrate(&mut value, MIGRATIONS)?;
+
+        let file: DocumentFile =
+            serde_json::from_value(value).map_err(|e| FormatError::Invalid(e.to_string()))?;
+        build_document(file)
+    }
+}
+
+/// Push a node and its descendants parents-first
+fn collect_records(
+    tree: &DocumentTree,
+    id: ObjectId,
+    out: &mut Vec<NodeRecord>,
+    visited: &mut HashSet<ObjectId>,
+) {
+    if !visited.insert(id) {
+        return;
+    }
+    if let Some(node) = tree.get(id) {
+        out.push(NodeRecord {
+            node: node.clone(),
+            parent: tree.parent(id),
+        });
+        for child_id in tree.children(id) {
+            collect_records(tree, child_id, out, visited);
+        }
+    }
+}
+
+/// Run every migration needed to bring `value` up to the latest schema
+fn migrate(value: &mut Value, migrations: &[Migration]) -> Result<(), FormatError> {
+    let supported = migrations.len() as u32;
+    let found = match value.get("schema_version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| FormatError::InvalidSchemaVersion(version.to_string()))?,
+    };
+
+    if found > supported {
+        return Err(FormatError::UnsupportedVersion { found, supported });
+    }
+
+    for (step, migration) in migrations.iter().enumerate().skip(found as usize) {
+        let from = step as u32;
+        migration(value).map_err(|message| FormatError::Migration { from, message })?;
+        value["schema_version"] = Value::from(from + 1);
+    }
+
+    Ok(())
+}
+
+/// 0 → 1: wrap a bare `DocumentTree` with a name and version.
+///
+/// The tree stored its nodes, and the parent → children lists, in maps keyed
+/// by node id. The keys are only used to match each list to its parent node,
+/// so it doesn't matter how they were stringified. Nodes are written
+/// parents-first from the root; any the root doesn't reach are kept at the
+/// end without a parent.
+fn wrap_bare_tree(value: &mut Value) -> Result<(), String> {
+    let nodes = value.get("nodes").and_then(Value::as_object).ok_or("missing nodes")?;
+    let root = value.get("root_id").cloned().ok_or("missing root_id")?;
+    let empty = serde_json::Map::new();
+    let children_map = value.get("children_map").and_then(Value::as_object).unwrap_or(&empty);
+
+    let by_id: HashMap<String, &Value> = nodes
+        .values()
+        .filter_map(|node| Some((node.get("id")?.to_string(), node)))
+        .collect();
+    let mut children: HashMap<String, &Vec<Value>> = HashMap::new();
+    for (key, list) in children_map {
+        let parent = nodes.get(key).and_then(|node| node.get("id")).ok_or("children of unknown node")?;
+        children.insert(parent.to_string(), list.as_array().ok_or("children must be a list")?);
+    }
+
+    let mut records = Vec::with_capacity(by_id.len());
+    let mut visited = HashSet::new();
+    let mut stack = vec![(root.clone(), None::<Value>)];
+    while let Some((id, parent)) = stack.pop() {
+        let key = id.to_string();
+        let Some(node) = by_id.get(&key) else { continue };
+        if !visited.insert(key.clone()) {
+            continue;
+        }
+        let mut record = (*node).clone();
+        if let Some(parent) = parent {
+            record["parent"] = parent;
+        }
+        records.push(record);
+        for child in children.get(&key).into_iter().flat_map(|list| list.iter().rev()) {
+            stack.push((child.clone(), Some(id.clone())));
+        }
+    }
+    if records.is_empty() {
+        return Err("root node not found".into());
+    }
+    for (key, node) in &by_id {
+        if !visited.contains(key) {
+            records.push((*node).clone());
+        }
+    }
+
+    *value = serde_json::json!({
+        "name": UNTITLED,
+        "version": 1,
+        "root": root,
+        "nodes": records,
+    });
+    Ok(())
+}
+
+/// 1 → 2: order indices are base 62 keys (see `fractional.rs`).
+///
+/// Version 1 files used decimal strings such as "0.5", often the same one for
+/// every sibling. Nodes are listed in sibling order, so each list of siblings
+/// gets fresh keys in that order.
+fn base62_order_indices(value: &mut Value) -> Result<(), String> {
+    let nodes = value
+        .get_mut("nodes")
+        .and_then(Value::as_array_mut)
+        .ok_or("missing nodes")?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for parent in nodes.iter().filter_map(|node| node.get("parent")) {
+        *counts.entry(parent.to_string()).or_default() += 1;
+    }
+    let mut keys: HashMap<String, std::vec::IntoIter<String>> = counts
+        .into_iter()
+        .map(|(parent, count)| (parent, spread_keys(count).into_iter()))
+        .collect();
+
+    for node in nodes.iter_mut() {
+        let Some(parent) = node.get("parent").map(Value::to_string) else {
+            continue;
+        };
+        if let Some(key) = keys.get_mut(&parent).and_then(Iterator::next) {
+            node["order_index"] = Value::from(key);
+        }
+    }
+    Ok(())
+}
+
+/// Rebuild the tree from a deserialized file, checking it is well-formed
+fn build_document(file: DocumentFile) -> Result<Document, FormatError> {
+    let mut records = file.nodes.into_iter();
+
+    let root = records
+        .next()
+        .ok_or_else(|| FormatError::Invalid("document has no nodes".into()))?;
+    if root.node.id() != file.root || root.parent.is_some() {
+        return Err(FormatError::Invalid("first node must be the root".into()));
+    }
+
+    let mut tree = DocumentTree::with_root(root.node);
+
+    for record in records {
+        let id = record.node.id();
+        if tree.get(id).is_some() {
+            return Err(FormatError::Invalid(format!("duplicate node {}", id)));
+        }
+        if let Some(parent_id) = record.parent {
+            // Parents are written before their children, so a missing parent
+            // means the file is truncated or was edited by hand
+            if tree.get(parent_id).is_none() {
+                return Err(FormatError::Invalid(format!(
+                    "node {} references unknown parent {}",
+                    id, parent_id
+                )));
+            }
+        }
+        tree.insert(record.node);
+        if let Some(parent_id) = record.parent {
+            tree.push_child(id, parent_id);
+        }
+    }
+
+    check_tree(&tree)?;
+    let mut document = Document::from_tree(tree, file.name, file.version);
+    for image in &file.images {
+        document
+            .insert_encoded_image(image)
+            .map_err(|e| FormatError::Invalid(e.to_string()))?;
+    }
+    Ok(document)
+}
+
+/// Reject a loaded tree that `DocumentTree::validate()` finds problems with,
+/// including nodes that aren't attached anywhere: nothing draws, saves or
+/// edits them, so a file that has any is damaged.
+pub(crate) fn check_tree(tree: &DocumentTree) -> Result<(), FormatError> {
+    match tree.validate() {
+        Err(issues) => Err(FormatError::Invalid(issues[0].to_string())),
+        Ok(()) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{NodeType, Property, PropertyValue};
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut doc = Document::new("Round Trip");
+        let frame_id = doc.create_frame(10.0, 20.0, 300.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 5.0, 5.0, 40.0, 40.0);
+        let text_id = doc.create_text(frame_id, 0.0, 60.0, "Hello");
+
+        let json = doc.encode_json().unwrap();
+        let loaded = Document::decode_json(&json).unwrap();
+
+        assert_eq!(loaded.name(), "Round Trip");
+        assert_eq!(loaded.version(), doc.version());
+        assert_eq!(loaded.root_id(), doc.root_id());
+        assert_eq!(loaded.get_children(frame_id), vec![rect_id, text_id]);
+        assert_eq!(loaded.get_node_x(frame_id), 10.0);
+        assert!(matches!(
+            loaded.tree.get(text_id).unwrap().get_property(Property::Text),
+            Some(PropertyValue::String(s)) if s == "Hello"
+        ));
+    }
+
+    #[test]
+    fn test_saves_only_attached_nodes() {
+        let mut doc = Document::new("Detached");
+        let orphan_id = ObjectId::new(7, 1);
+        doc.tree.insert(Node::new(orphan_id, NodeType::Rectangle));
+
+        let loaded = Document::decode_json(&doc.encode_json().unwrap()).unwrap();
+        assert!(!loaded.has_node(orphan_id));
+        assert_eq!(loaded.tree.len(), doc.tree.len() - 1);
+    }
+
+    #[test]
+    fn test_rejects_malformed_files() {
+        assert!(matches!(Document::decode_json("{"), Err(FormatError::Syntax(_))));
+        assert!(matches!(
+            Document::decode_json(r#"{"name": "x"}"#),
+            Err(FormatError::Migration { from: 0, .. })
+        ));
+        assert_eq!(
+            Document::decode_json(r#"{"schema_version": 4294967297}"#).err(),
+            Some(FormatError::InvalidSchemaVersion("4294967297".into()))
+        );
+        assert!(matches!(
+            Document::decode_json(r#"{"schema_version": 999}"#),
+            Err(FormatError::UnsupportedVersion { found: 999, .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_orphans() {
+        let mut doc = Document::new("Orphans");
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+
+        let mut value: Value = serde_json::from_str(&doc.encode_json().unwrap()).unwrap();
+        for node in value["nodes"].as_array_mut().unwrap() {
+            if node["id"] == serde_json::to_value(rect_id).unwrap() {
+                node["parent"] = Value::Null;
+            }
+        }
+        assert_eq!(
+            Document::decode_json(&value.to_string()).err(),
+            Some(FormatError::Invalid(format!("Node {} has no parent", rect_id)))
+        );
+    }
+
+    #[test]
+    fn test_migrates_decimal_order_indices() {
+        let mut doc = Document::new("Legacy");
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let ids: Vec<ObjectId> = (0..3).map(|_| doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0)).collect();
+
+        // Rewrite as a version 1 file where every sibling shares "0.5"
+        let mut value: Value = serde_json::from_str(&doc.encode_json().unwrap()).unwrap();
+        value["schema_version"] = Value::from(1);
+        for node in value["nodes"].as_array_mut().unwrap() {
+            node["order_index"] = Value::from("0.5");
+        }
+
+        let mut loaded = Document::decode_json(&value.to_string()).unwrap();
+        assert_eq!(loaded.get_children(frame_id), ids);
+        loaded.tree.move_before(ids[2], ids[1]).unwrap();
+        assert_eq!(loaded.get_children(frame_id), vec![ids[0], ids[2], ids[1]]);
+    }
+
+    #[test]
+    fn test_migrates_bare_tree() {
+        let node = |sequence: u32, node_type: &str| {
+            serde_json::json!({
+                "id": { "client_id": 1, "sequence": sequence },
+                "node_type": node_type,
+                "properties": {},
+                "order_index": "0.5",
+            })
+        };
+        let id = |sequence: u32| serde_json::json!({ "client_id": 1, "sequence": sequence });
+        let json = serde_json::json!({
+            "nodes": {
+                "1:2": node(2, "Rectangle"),
+                "1:0": node(0, "Document"),
+                "1:3": node(3, "Ellipse"),
+                "1:1": node(1, "Page"),
+            },
+            "root_id": id(0),
+            "children_map": { "1:0": [id(1)], "1:1": [id(3), id(2)] },
+            "parent_map": { "1:1": id(0), "1:2": id(1), "1:3": id(1) },
+        });
+
+        let loaded = Document::decode_json(&json.to_string()).unwrap();
+        assert_eq!(loaded.name(), UNTITLED);
+        assert_eq!(loaded.root_id(), ObjectId::new(1, 0));
+        assert_eq!(loaded.get_children(ObjectId::new(1, 1)), vec![ObjectId::new(1, 3), ObjectId::new(1, 2)]);
+    }
+
+    #[test]
+    fn test_migrations_run_in_order() {
+        fn add_name(value: &mut Value) -> Result<(), String> {
+            value["name"] = Value::from("migrated");
+            Ok(())
+        }
+        fn upper_name(value: &mut Value) -> Result<(), String> {
+            let name = value["name"].as_str().ok_or("missing name")?.to_uppercase();
+            value["name"] = Value::from(name);
+            Ok(())
+        }
+
+        let mut value = serde_json::json!({});
+        migrate(&mut value, &[add_name, upper_name]).unwrap();
+        assert_eq!(value["schema_version"], 2);
+        assert_eq!(value["name"], "MIGRATED");
+
+        let mut value = serde_json::json!({ "schema_version": 1 });
+        let error = migrate(&mut value, &[add_name, upper_name]).unwrap_err();
+        assert_eq!(error, FormatError::Migration { from: 1, message: "missing name".into() });
+    }
+}
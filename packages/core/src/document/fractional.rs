@@ -0,0 +1,221 @@
+//! Fractional index keys for sibling order
+//!
+//! Every node stores an `order_index` string and siblings are drawn in the
+//! order of those strings. To move a node between two siblings only its own
+//! key changes: we generate a new key that sorts between its neighbours.
+//!
+//! A key is a fraction between 0 and 1 written in base 62, digits only (the
+//! leading "0." is implied):
+//!
+//! ```text
+//! digits:  0-9 A-Z a-z          (ASCII order = numeric order)
+//!
+//! "V"   = 31/62                 first key in an empty parent
+//! "W"   after "V"               appending steps up one digit
+//! "VV"  between "V" and "W"     inserting adds a digit when there's no room
+//! "0z"  before "1"
+//! ```
+//!
+//! Keys never end in "0", so two different keys are never numerically equal
+//! and plain string comparison gives the right order at any length. There's
+//! no precision limit: keys just get longer.
+//!
+//! ## Concurrent inserts
+//!
+//! Two collaborators inserting between the same neighbours at the same time
+//! would compute the same key, and the order of the two nodes would depend on
+//! which operation each client received first. [`jittered_key_between`] adds
+//! a few random digits so concurrent keys differ and every client sorts them
+//! the same way. Local edits always use it.
+
+use uuid::Uuid;
+
+/// Key digits in ascending order
+const DIGITS: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const BASE: u8 = 62;
+
+/// Random digits appended by `jittered_key_between`
+const JITTER_DIGITS: usize = 3;
+
+/// Check that `key` is a well-formed key: digits only, not ending in "0".
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && !key.ends_with('0') && key.bytes().all(|c| digit(c).is_some())
+}
+
+/// A key that sorts strictly between `a` and `b`.
+///
+/// `None` means "no bound" (before the first or after the last sibling).
+/// Bounds that aren't valid keys are treated as missing, and if `a` isn't
+/// below `b` the upper bound is ignored.
+pub fn key_between(a: Option<&str>, b: Option<&str>) -> String {
+    let a = a.filter(|key| is_valid_key(key)).map(digits);
+    let b = b.filter(|key| is_valid_key(key)).map(digits);
+    let b = b.filter(|b| a.as_ref().is_none_or(|a| a < b));
+
+    let mut out = Vec::new();
+    match (&a, &b) {
+        (None, None) => out.push(BASE / 2),
+        (Some(a), None) => after(a, &mut out),
+        (None, Some(b)) => before(b, &mut out),
+        (Some(a), Some(b)) => midpoint(a, b, &mut out),
+    }
+    to_key(&out)
+}
+
+/// Like [`key_between`], with random digits appended so that two clients
+/// inserting at the same place at the same time get different keys.
+pub fn jittered_key_between(a: Option<&str>, b: Option<&str>) -> String {
+    // Every key produced by `key_between` already differs from its bounds
+    // before its last digit ends, so anything appended keeps it in range
+    let mut key = key_between(a, b);
+    let random = Uuid::new_v4();
+    for (i, &byte) in random.as_bytes().iter().take(JITTER_DIGITS).enumerate() {
+        let mut value = byte % BASE;
+        if i == JITTER_DIGITS - 1 && value == 0 {
+            value = 1;
+        }
+        key.push(DIGITS[value as usize] as char);
+    }
+    key
+}
+
+/// `count` evenly spaced keys in ascending order, as short as possible.
+///
+/// Used to give a whole list of siblings fresh keys, e.g. when loading files
+/// written before keys were base 62.
+pub fn spread_keys(count: usize) -> Vec<String> {
+    let slots = count as u128 + 1;
+    let mut width = 1;
+    let mut range = BASE as u128;
+    while range < slots {
+        width += 1;
+        range *= BASE as u128;
+    }
+
+    (1..slots)
+        .map(|i| {
+            let mut value = i * range / slots;
+            let mut out = vec![0; width];
+            for slot in out.iter_mut().rev() {
+                *slot = (value % BASE as u128) as u8;
+                value /= BASE as u128;
+            }
+            to_key(&out)
+        })
+        .collect()
+}
+
+/// Push digits for a key above `a` (no upper bound): bump the first digit
+/// that has room, so appending many siblings keeps keys short
+fn after(a: &[u8], out: &mut Vec<u8>) {
+    match a.first() {
+        Some(&d) if d < BASE - 1 => out.push(d + 1),
+        Some(&d) => {
+            out.push(d);
+            after(&a[1..], out);
+        }
+        None => out.push(1),
+    }
+}
+
+/// Push digits for a key below `b` (no lower bound)
+fn before(b: &[u8], out: &mut Vec<u8>) {
+    match b {
+        [d, ..] if *d > 1 => out.push(d - 1),
+        // "1" is the smallest one-digit key; go one level deeper under it
+        [1] => out.extend([0, BASE - 1]),
+        [d, rest @ ..] => {
+            out.push(*d);
+            before(rest, out);
+        }
+        [] => out.push(BASE / 2),
+    }
+}
+
+/// Push digits for a key halfway between `a` and `b` (`a < b`)
+fn midpoint(a: &[u8], b: &[u8], out: &mut Vec<u8>) {
+    // Copy the shared prefix (a missing digit of `a` counts as 0)
+    let shared = b.iter().enumerate().take_while(|&(i, &d)| a.get(i).copied().unwrap_or(0) == d).count();
+    out.extend_from_slice(&b[..shared]);
+    let a = a.get(shared..).unwrap_or(&[]);
+    let b = &b[shared..];
+
+    let low = a.first().copied().unwrap_or(0);
+    let high = b.first().copied().unwrap_or(BASE);
+    if high - low > 1 {
+        out.push((low + high) / 2);
+    } else {
+        // Adjacent digits: keep `a`'s digit and go above the rest of `a`
+        out.push(low);
+        let rest = a.get(1..).unwrap_or(&[]);
+        if rest.is_empty() {
+            out.push(BASE / 2);
+        } else {
+            after(rest, out);
+        }
+    }
+}
+
+fn digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'A'..=b'Z' => Some(c - b'A' + 10),
+        b'a'..=b'z' => Some(c - b'a' + 36),
+        _ => None,
+    }
+}
+
+fn digits(key: &str) -> Vec<u8> {
+    key.bytes().filter_map(digit).collect()
+}
+
+/// Encode digit values, dropping trailing zeros
+fn to_key(values: &[u8]) -> String {
+    let end = values.iter().rposition(|&d| d != 0).map_or(0, |i| i + 1);
+    values[..end].iter().map(|&d| DIGITS[d as usize] as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_stay_ordered_under_repeated_inserts() {
+        // Squeeze between the same two neighbours over and over: an f64
+        // midpoint runs out of precision after about 50 steps
+        let (low, mut high) = ("V".to_string(), "W".to_string());
+        for _ in 0..500 {
+            let key = key_between(Some(&low), Some(&high));
+            assert!(is_valid_key(&key));
+            assert!(low < key && key < high, "{} < {} < {}", low, key, high);
+            high = key;
+        }
+
+        // Appending and prepending step one digit at a time
+        let mut keys = vec![key_between(None, None)];
+        for _ in 0..200 {
+            keys.push(key_between(keys.last().map(String::as_str), None));
+            keys.insert(0, key_between(None, keys.first().map(String::as_str)));
+        }
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(keys.iter().all(|key| is_valid_key(key) && key.len() <= 5));
+
+        let spread = spread_keys(1000);
+        assert!(spread.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(spread.iter().all(|key| is_valid_key(key)));
+    }
+
+    #[test]
+    fn test_jitter_keeps_keys_in_range() {
+        let bounds = [(None, None), (Some("1"), None), (None, Some("1")), (Some("V"), Some("W")), (Some("Vz"), Some("W1"))];
+        for (a, b) in bounds {
+            for _ in 0..50 {
+                let key = jittered_key_between(a, b);
+                assert!(is_valid_key(&key));
+                assert!(a.is_none_or(|a| a < key.as_str()) && b.is_none_or(|b| key.as_str() < b), "{}", key);
+            }
+        }
+        assert_ne!(jittered_key_between(Some("V"), Some("W")), jittered_key_between(Some("V"), Some("W")));
+        assert!(!is_valid_key("0.5") && !is_valid_key("V0") && !is_valid_key(""));
+    }
+}
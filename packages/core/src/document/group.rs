@@ -0,0 +1,317 @@
+//! Groups
+//!
+//! A group has no look of its own; it holds its children together so they
+//! can be selected, moved and ordered as one. Its bounds are derived from
+//! the children:
+//!
+//! ```text
+//! before                       group([a, b])
+//!
+//!  ┌──┐ a                       ┌ Group ─────────┐  X/Y/Width/Height =
+//!  └──┘      ┌────┐ b           │┌──┐            │  union of the children's
+//!            └────┘             │└──┘      ┌────┐│  bounds, children
+//!                               │          └────┘│  relative to it
+//!                               └────────────────┘
+//! ```
+//!
+//! `group()` and `ungroup()` keep every child where it is on the canvas
+//! (rotation included) and in the same paint order.
+//!
+//! ## Keeping The Bounds Up To Date
+//!
+//! `Document::apply()` queues a group whenever one of its children is added,
+//! removed, moved or resized, or when the group itself is resized. Refitting
+//! moves the group's origin to the children's top-left corner, shifts the
+//! children back by the same amount and sets its size, so nothing moves on
+//! the canvas. A group's size always follows its children: resizing a group
+//! directly is undone by the next refit, and resize constraints don't apply
+//! to its children. A group whose last child is removed is deleted.
+
+use super::{
+    local_transform, placement_for, Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree,
+};
+use crate::math::{Rect, Vec2};
+use wasm_bindgen::prelude::*;
+
+/// Upper bound on refits per change, in case bounds never settle
+const MAX_GROUP_PASSES: usize = 10_000;
+
+/// Offsets smaller than this are not worth moving the children for
+const FIT_EPSILON: f32 = 1e-3;
+
+/// Groups waiting to be refitted after a change
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GroupQueue {
+    pending: Vec<ObjectId>,
+    running: bool,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Wrap nodes in a new group.
+    ///
+    /// The group takes the place of the topmost node; the nodes may come
+    /// from different parents. Nodes inside other listed nodes are moved
+    /// along with their ancestor. Returns `None` if nothing can be grouped.
+    pub fn group(&mut self, ids: Vec<ObjectId>) -> Option<ObjectId> {
+        let mut ids: Vec<ObjectId> = ids.into_iter().filter(|&id| self.is_groupable(id)).collect();
+        ids.sort_by(|a, b| self.compare_z_order(*a, *b));
+        ids.dedup();
+        let members = ids.clone();
+        ids.retain(|&id| !members.iter().any(|&other| other != id && self.is_in_subtree(id, other)));
+
+        let topmost = *ids.last()?;
+        let parent_id = self.tree.parent(topmost)?;
+        let (position, order_index) = self.placement(topmost);
+
+        // Bounds of the nodes in the new parent's space
+        let to_parent = self.world_transform(parent_id).inverse();
+        let bounds = ids
+            .iter()
+            .filter_map(|&id| {
+                let node = self.bound_node(self.tree.get(id)?);
+                let local = to_parent.multiply(&self.world_transform(id));
+                Some(local.transform_rect(&Rect::new(0.0, 0.0, node.width(), node.height())))
+            })
+            .reduce(|a, b| a.union(&b))?;
+
+        let group_id = ObjectId::random();
+        let mut group = Node::new(group_id, NodeType::Group);
+        group.set_property(Property::X, PropertyValue::Float(bounds.x));
+        group.set_property(Property::Y, PropertyValue::Float(bounds.y));
+        group.set_property(Property::Width, PropertyValue::Float(bounds.width));
+        group.set_property(Property::Height, PropertyValue::Float(bounds.height));
+        group.set_property(Property::Name, PropertyValue::String("Group".into()));
+        group.set_order_index(order_index);
+
+        // Hold off refitting until every child has been moved in
+        self.history.begin_group();
+        self.group_queue.running = true;
+        self.apply(Operation::Insert(Subtree::from_nodes(vec![(group, Some(parent_id))], position)?));
+        for (index, &id) in ids.iter().enumerate() {
+            let order_index = self.tree.order_index_at(group_id, index, id);
+            self.reparent_in_place(id, group_id, index, order_index);
+        }
+        self.group_queue.running = false;
+        self.run_groups();
+        self.history.end_group();
+        Some(group_id)
+    }
+
+    /// Replace a group with its children, keeping them where they are on
+    /// the canvas and in the group's place in the paint order. Returns the
+    /// former children.
+    pub fn ungroup(&mut self, id: ObjectId) -> Vec<ObjectId> {
+        let is_group = self.tree.get(id).is_some_and(|n| n.node_type() == NodeType::Group);
+        let Some(parent_id) = self.tree.parent(id).filter(|_| is_group) else {
+            return Vec::new();
+        };
+        let children = self.tree.children(id);
+        let position = self.tree.index_in_parent(id).unwrap_or(usize::MAX);
+
+        self.history.begin_group();
+        self.group_queue.running = true;
+        for (offset, &child_id) in children.iter().enumerate() {
+            // Right after the group, in order
+            let target = position.saturating_add(1 + offset);
+            let order_index = self.tree.order_index_at(parent_id, target, child_id);
+            self.reparent_in_place(child_id, parent_id, target, order_index);
+        }
+        self.apply(Operation::Remove { id });
+        self.group_queue.running = false;
+        self.run_groups();
+        self.history.end_group();
+        children
+    }
+}
+
+impl Document {
+    /// Queue the groups an applied operation may have changed
+    pub(crate) fn invalidate_groups(&mut self, inverse: &Operation) {
+        match inverse {
+            Operation::SetProperty { id, property, .. } => {
+                if matches!(property, Property::Width | Property::Height) {
+                    self.queue_group(Some(*id));
+                }
+                if affects_group_bounds(*property) {
+                    self.queue_group(self.tree.parent(*id));
+                }
+            }
+            // Inserted
+            Operation::Remove { id } => self.queue_group(self.tree.parent(*id)),
+            // Removed
+            Operation::Insert(subtree) => self.queue_group(subtree.parent()),
+            Operation::Move { id, parent, .. } => {
+                self.queue_group(Some(*parent));
+                self.queue_group(self.tree.parent(*id));
+            }
+            Operation::SetOverride { .. } => {}
+        }
+    }
+
+    /// Refit every queued group until the queue is empty
+    pub(crate) fn run_groups(&mut self) {
+        if self.group_queue.running {
+            return;
+        }
+        self.group_queue.running = true;
+
+        let mut passes = 0;
+        while let Some(id) = self.group_queue.pending.pop() {
+            passes += 1;
+            if passes > MAX_GROUP_PASSES {
+                self.group_queue.pending.clear();
+                break;
+            }
+            self.fit_group(id);
+        }
+
+        self.group_queue.running = false;
+    }
+
+    fn queue_group(&mut self, id: Option<ObjectId>) {
+        let Some(id) = id else { return };
+        let is_group = self.tree.get(id).is_some_and(|n| n.node_type() == NodeType::Group);
+        if is_group && !self.group_queue.pending.contains(&id) {
+            self.group_queue.pending.push(id);
+        }
+    }
+
+    /// Fit a group to the union of its children's bounds
+    fn fit_group(&mut self, id: ObjectId) {
+        let children = self.tree.children(id);
+        let bounds = children
+            .iter()
+            .filter_map(|&child_id| {
+                let child = self.bound_node(self.tree.get(child_id)?);
+                Some(local_transform(&child).transform_rect(&Rect::new(0.0, 0.0, child.width(), child.height())))
+            })
+            .reduce(|a, b| a.union(&b));
+        let Some(bounds) = bounds else {
+            self.apply(Operation::Remove { id });
+            return;
+        };
+
+        if bounds.x.abs() > FIT_EPSILON || bounds.y.abs() > FIT_EPSILON {
+            self.shift_origin(id, &bounds, &children);
+        }
+        self.set_if_changed(id, Property::Width, bounds.width);
+        self.set_if_changed(id, Property::Height, bounds.height);
+    }
+
+    /// Move a node under a new parent without moving it on the canvas
+    fn reparent_in_place(&mut self, id: ObjectId, parent_id: ObjectId, position: usize, order_index: String) {
+        let Some(node) = self.tree.get(id) else { return };
+        let size = Vec2::new(node.width(), node.height());
+        let world = self.world_transform(id);
+
+        self.apply(Operation::Move { id, parent: parent_id, position, order_index });
+        let local = self.world_transform(parent_id).inverse().multiply(&world);
+        let (x, y, rotation) = placement_for(&local, size);
+        self.set_if_changed(id, Property::X, x);
+        self.set_if_changed(id, Property::Y, y);
+        self.set_if_changed(id, Property::Rotation, rotation);
+    }
+
+    fn is_groupable(&self, id: ObjectId) -> bool {
+        let on_canvas = self.tree.get(id).is_some_and(|n| {
+            !matches!(
+                n.node_type(),
+                NodeType::Document
+                    | NodeType::Page
+                    | NodeType::Style
+                    | NodeType::VariableCollection
+                    | NodeType::VariableMode
+                    | NodeType::Variable
+            )
+        });
+        on_canvas && self.get_node_page(id).is_some()
+    }
+}
+
+/// Properties of a child that change its bounds in the group's space
+fn affects_group_bounds(property: Property) -> bool {
+    matches!(
+        property,
+        Property::X | Property::Y | Property::Width | Property::Height | Property::Rotation
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Rect, expected: Rect) {
+        let close = (actual.x - expected.x).abs() < 1e-3
+            && (actual.y - expected.y).abs() < 1e-3
+            && (actual.width - expected.width).abs() < 1e-3
+            && (actual.height - expected.height).abs() < 1e-3;
+        assert!(close, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_group_and_ungroup_keep_placement() {
+        let mut doc = Document::new("Groups");
+        let frame_id = doc.create_frame(100.0, 100.0, 400.0, 400.0);
+        let a = doc.create_rectangle(frame_id, 10.0, 10.0, 20.0, 20.0);
+        let b = doc.create_ellipse(frame_id, 50.0, 40.0, 30.0, 30.0);
+        let c = doc.create_rectangle(frame_id, 200.0, 200.0, 10.0, 10.0);
+        doc.set_node_property(b, Property::Rotation, PropertyValue::Float(45.0));
+        let before: Vec<Rect> = [a, b].iter().map(|&id| doc.get_absolute_bounds(id).unwrap()).collect();
+
+        let group_id = doc.group(vec![b, a]).unwrap();
+        assert_eq!(doc.get_children(frame_id), vec![group_id, c]);
+        assert_eq!(doc.get_children(group_id), vec![a, b]);
+        for (&id, &expected) in [a, b].iter().zip(&before) {
+            assert_near(doc.get_absolute_bounds(id).unwrap(), expected);
+        }
+        // The group hugs its children
+        let rotated = before[1];
+        let expected = Rect::new(110.0, 110.0, rotated.right() - 110.0, rotated.bottom() - 110.0);
+        assert_near(doc.get_absolute_bounds(group_id).unwrap(), expected);
+
+        let children = doc.ungroup(group_id);
+        assert_eq!(children, vec![a, b]);
+        assert!(!doc.has_node(group_id));
+        assert_eq!(doc.get_children(frame_id), vec![a, b, c]);
+        for (&id, &expected) in [a, b].iter().zip(&before) {
+            assert_near(doc.get_absolute_bounds(id).unwrap(), expected);
+        }
+
+        // Grouping and ungrouping are single undo steps
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(group_id), vec![a, b]);
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(frame_id), vec![a, b, c]);
+        assert_eq!(doc.get_node_x(a), 10.0);
+    }
+
+    #[test]
+    fn test_group_bounds_follow_children() {
+        let mut doc = Document::new("Groups");
+        let page_id = doc.get_active_page().unwrap();
+        let a = doc.create_rectangle(page_id, 0.0, 0.0, 10.0, 10.0);
+        let b = doc.create_rectangle(page_id, 20.0, 0.0, 10.0, 10.0);
+        let group_id = doc.group(vec![a, b]).unwrap();
+        assert_near(doc.get_absolute_bounds(group_id).unwrap(), Rect::new(0.0, 0.0, 30.0, 10.0));
+
+        // Moving a child up and to the left moves the group's origin
+        doc.set_node_x(a, -20.0);
+        doc.set_node_y(a, -5.0);
+        assert_near(doc.get_absolute_bounds(group_id).unwrap(), Rect::new(-20.0, -5.0, 50.0, 15.0));
+        assert_near(doc.get_absolute_bounds(b).unwrap(), Rect::new(20.0, 0.0, 10.0, 10.0));
+
+        // Resizing the group directly snaps back to the children
+        doc.set_node_width(group_id, 500.0);
+        assert_eq!(doc.get_node_width(group_id), 50.0);
+
+        // Moving the group moves the children
+        doc.set_node_x(group_id, 0.0);
+        assert_near(doc.get_absolute_bounds(b).unwrap(), Rect::new(40.0, 0.0, 10.0, 10.0));
+
+        // Removing the last child removes the group
+        doc.delete_node(a);
+        doc.delete_node(b);
+        assert!(!doc.has_node(group_id));
+    }
+}
@@ -0,0 +1,375 @@
+//! Undo/redo history for document mutations
+//!
+//! Every change to a `Document` is expressed as an [`Operation`]. Applying an
+//! operation to the tree returns its inverse, and the history keeps those
+//! inverses around so the change can be reverted later.
+//!
+//! ## How It Works
+//!
+//! ```text
+//! set_node_x(rect, 50)
+//!   → Operation::SetProperty { X = 50 }   applied to the tree
+//!   ← Operation::SetProperty { X = 10 }   recorded in the undo stack
+//!
+//! undo()
+//!   → Operation::SetProperty { X = 10 }   applied to the tree
+//!   ← Operation::SetProperty { X = 50 }   recorded in the redo stack
+//! ```
+//!
+//! Because undoing is just "apply the inverse", undo and redo are the same
+//! code path running against two different stacks.
+//!
+//! ## Grouping
+//!
+//! A drag or a multi-node edit produces many operations that the user thinks
+//! of as one action. Wrapping them in `begin_group()` / `end_group()` makes
+//! them a single undo step. Groups can nest; only the outermost one counts.
+//!
+//! ## Untracked Changes
+//!
+//! Edits made by collaborators, and sizes re-measured after a font loads,
+//! aren't the local user's actions. They are applied between `pause()` and
+//! `resume()`, so they neither become undo steps nor clear the redo stack.
+
+use super::{DocumentTree, ObjectId, Property, PropertyValue, Subtree};
+
+/// Maximum number of undo steps kept before the oldest are dropped
+const DEFAULT_HISTORY_LIMIT: usize = 200;
+
+/// A single reversible change to the document tree
+#[derive(Debug, Clone)]
+pub enum Operation {
+    /// Set a property, or clear it when `value` is `None`
+    SetProperty {
+        id: ObjectId,
+        property: Property,
+        value: Option<PropertyValue>,
+    },
+    /// Attach a subtree (a new node, or one restored by undo)
+    Insert(Subtree),
+    /// Detach a node and all of its descendants
+    Remove { id: ObjectId },
+    /// Reparent a node and give it a new order index
+    Move {
+        id: ObjectId,
+        parent: ObjectId,
+        position: usize,
+        order_index: String,
+    },
+    /// Mark or unmark an instance property as overridden
+    SetOverride {
+        id: ObjectId,
+        property: Property,
+        overridden: bool,
+    },
+}
+
+impl Operation {
+    /// Apply this operation to the tree and return the operation that undoes it.
+    ///
+    /// Returns `None` when the operation had nothing to act on (for example,
+    /// the target node no longer exists, or a property is set to the value it
+    /// already has) or would break the tree (a move into the node's own
+    /// subtree, a shape inserted under the Document). Nothing is changed in
+    /// that case.
+    pub fn apply(self, tree: &mut DocumentTree) -> Option<Operation> {
+        match self {
+            Operation::SetProperty { id, property, value } => {
+                let node = tree.get_mut(id)?;
+                let old = node.get_property(property).cloned();
+                if old == value {
+                    return None;
+                }
+                match value {
+                    Some(value) => node.set_property(property, value),
+                    None => node.remove_property(property),
+                }
+                Some(Operation::SetProperty { id, property, value: old })
+            }
+            Operation::Insert(subtree) => {
+                let id = subtree.root();
+                if tree.get(id).is_some() {
+                    return None;
+                }
+                tree.restore_subtree(subtree).ok()?;
+                Some(Operation::Remove { id })
+            }
+            Operation::Remove { id } => {
+                let subtree = tree.take_subtree(id)?;
+                Some(Operation::Insert(subtree))
+            }
+            Operation::Move { id, parent, position, order_index } => {
+                tree.check_reparent(id, parent).ok()?;
+                let old_parent = tree.parent(id)?;
+                let old_position = tree.index_in_parent(id).unwrap_or(0);
+                let node = tree.get_mut(id)?;
+                let old_index = node.order_index().to_string();
+                node.set_order_index(order_index);
+                tree.attach_at(id, parent, position).ok()?;
+                Some(Operation::Move {
+                    id,
+                    parent: old_parent,
+                    position: old_position,
+                    order_index: old_index,
+                })
+            }
+            Operation::SetOverride { id, property, overridden } => {
+                let node = tree.get_mut(id)?;
+                let was_overridden = node.is_overridden(property);
+                node.set_overridden(property, overridden);
+                Some(Operation::SetOverride { id, property, overridden: was_overridden })
+            }
+        }
+    }
+
+    /// The node this operation acts on
+    pub fn target(&self) -> ObjectId {
+        match self {
+            Operation::SetProperty { id, .. }
+            | Operation::Remove { id }
+            | Operation::Move { id, .. }
+            | Operation::SetOverride { id, .. } => *id,
+            Operation::Insert(subtree) => subtree.root(),
+        }
+    }
+}
+
+/// One undo step: the inverse operations of everything done in that step.
+///
+/// Inverses are stored in the order they were recorded and must be applied
+/// in reverse to walk the document back.
+#[derive(Debug, Clone, Default)]
+struct Transaction {
+    inverses: Vec<Operation>,
+}
+
+/// Undo and redo stacks for a document
+#[derive(Debug, Clone)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    /// The step currently being built inside `begin_group()` / `end_group()`
+    pending: Transaction,
+    /// Nesting depth of open groups
+    group_depth: u32,
+    /// Nesting depth of `pause()` calls; nothing is recorded while non-zero
+    paused: u32,
+    limit: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            pending: Transaction::default(),
+            group_depth: 0,
+            paused: 0,
+            limit: DEFAULT_HISTORY_LIMIT,
+        }
+    }
+
+    /// Record the inverse of an operation that was just applied.
+    ///
+    /// Any new change invalidates the redo stack. Ignored while paused.
+    pub fn record(&mut self, inverse: Operation) {
+        if self.paused > 0 {
+            return;
+        }
+        self.redo_stack.clear();
+        self.pending.inverses.push(inverse);
+        if self.group_depth == 0 {
+            self.commit_pending();
+        }
+    }
+
+    /// Start grouping subsequent changes into a single undo step
+    pub fn begin_group(&mut self) {
+        self.group_depth += 1;
+    }
+
+    /// Close a group. When the outermost group closes, it becomes one undo step.
+    pub fn end_group(&mut self) {
+        if self.group_depth == 0 {
+            return;
+        }
+        self.group_depth -= 1;
+        if self.group_depth == 0 {
+            self.commit_pending();
+        }
+    }
+
+    /// Stop recording until the matching `resume()`. Pauses can nest.
+    pub fn pause(&mut self) {
+        self.paused += 1;
+    }
+
+    /// Undo one `pause()`
+    pub fn resume(&mut self) {
+        self.paused = self.paused.saturating_sub(1);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty() || !self.pending.inverses.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Revert the most recent step. Returns `false` if there was nothing to undo.
+    ///
+    /// `apply` performs each inverse operation and returns its own inverse;
+    /// the document passes its mutation path here so caches stay in sync.
+    /// An open group is closed first, so undoing mid-drag reverts the drag so far.
+    pub fn undo(&mut self, apply: impl FnMut(Operation) -> Option<Operation>) -> bool {
+        self.close_groups();
+        match self.undo_stack.pop() {
+            Some(transaction) => {
+                let redo = Self::replay(transaction, apply);
+                self.redo_stack.push(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-apply the most recently undone step. Returns `false` if there was nothing to redo.
+    pub fn redo(&mut self, apply: impl FnMut(Operation) -> Option<Operation>) -> bool {
+        self.close_groups();
+        match self.redo_stack.pop() {
+            Some(transaction) => {
+                let undo = Self::replay(transaction, apply);
+                self.undo_stack.push(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drop all undo and redo steps
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.pending = Transaction::default();
+        self.group_depth = 0;
+    }
+
+    /// Apply a step's inverses (newest first) and collect their own inverses
+    fn replay(
+        transaction: Transaction,
+        apply: impl FnMut(Operation) -> Option<Operation>,
+    ) -> Transaction {
+        let inverses = transaction.inverses.into_iter().rev().filter_map(apply).collect();
+        Transaction { inverses }
+    }
+
+    fn close_groups(&mut self) {
+        self.group_depth = 0;
+        self.commit_pending();
+    }
+
+    fn commit_pending(&mut self) {
+        if self.pending.inverses.is_empty() {
+            return;
+        }
+        let transaction = std::mem::take(&mut self.pending);
+        self.undo_stack.push(transaction);
+        if self.undo_stack.len() > self.limit {
+            let excess = self.undo_stack.len() - self.limit;
+            self.undo_stack.drain(..excess);
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Node, NodeType};
+
+    fn tree_with_rect() -> (DocumentTree, ObjectId, ObjectId) {
+        let mut tree = DocumentTree::new();
+        let page_id = ObjectId::random();
+        tree.insert(Node::new(page_id, NodeType::Page));
+        let rect_id = ObjectId::random();
+        tree.insert(Node::new(rect_id, NodeType::Rectangle));
+        tree.set_parent(rect_id, page_id).unwrap();
+        (tree, page_id, rect_id)
+    }
+
+    fn record(history: &mut History, tree: &mut DocumentTree, op: Operation) {
+        let inverse = op.apply(tree).unwrap();
+        history.record(inverse);
+    }
+
+    #[test]
+    fn test_undo_redo_property() {
+        let (mut tree, _, rect_id) = tree_with_rect();
+        let mut history = History::new();
+
+        let op = Operation::SetProperty {
+            id: rect_id,
+            property: Property::X,
+            value: Some(PropertyValue::Float(42.0)),
+        };
+        record(&mut history, &mut tree, op);
+        assert_eq!(tree.get(rect_id).unwrap().x(), 42.0);
+
+        assert!(history.undo(|op| op.apply(&mut tree)));
+        assert_eq!(tree.get(rect_id).unwrap().x(), 0.0);
+        assert!(tree.get(rect_id).unwrap().get_property(Property::X).is_none());
+
+        assert!(history.redo(|op| op.apply(&mut tree)));
+        assert_eq!(tree.get(rect_id).unwrap().x(), 42.0);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_grouped_remove_restores_subtree() {
+        let (mut tree, page_id, rect_id) = tree_with_rect();
+        let mut history = History::new();
+
+        history.begin_group();
+        let op = Operation::SetProperty {
+            id: rect_id,
+            property: Property::Width,
+            value: Some(PropertyValue::Float(10.0)),
+        };
+        record(&mut history, &mut tree, op);
+        record(&mut history, &mut tree, Operation::Remove { id: page_id });
+        history.end_group();
+
+        assert!(tree.is_empty());
+        assert!(history.undo(|op| op.apply(&mut tree)));
+        assert_eq!(tree.children(page_id), vec![rect_id]);
+        assert_eq!(tree.get(rect_id).unwrap().width(), 100.0);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_no_op_and_paused_changes_are_not_recorded() {
+        let (mut tree, _, rect_id) = tree_with_rect();
+        let mut history = History::new();
+
+        let set_x = |x| Operation::SetProperty {
+            id: rect_id,
+            property: Property::X,
+            value: Some(PropertyValue::Float(x)),
+        };
+        record(&mut history, &mut tree, set_x(5.0));
+        assert!(set_x(5.0).apply(&mut tree).is_none());
+
+        assert!(history.undo(|op| op.apply(&mut tree)));
+        history.pause();
+        record(&mut history, &mut tree, set_x(7.0));
+        history.resume();
+        assert!(history.can_redo());
+        assert!(!history.can_undo());
+    }
+}
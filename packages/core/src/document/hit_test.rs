@@ -0,0 +1,392 @@
+//! Hit testing
+//!
+//! Answers "what is under the cursor" for the select tool:
+//!
+//! 1. Handles and edges of the current selection, so a selected node can be
+//!    resized even where it overlaps other nodes.
+//! 2. Otherwise the topmost visible, unlocked node on the active page whose
+//!    shape contains the point.
+//!
+//! Candidates come from the spatial index (by render bounds, which reach
+//! over strokes drawn outside a node's box), then each one is tested against
+//! its real shape in its own local space (the point is mapped through the
+//! inverse world transform, so rotation is handled for free): rounded
+//! rectangles, ellipses, lines, vector paths and strokes. The children of a
+//! boolean node are never hit directly; the boolean's result is. Nothing is
+//! hit outside the frames that clip it or outside the outline of its mask.
+//!
+//! `tolerance` is in screen pixels and is divided by `zoom`, so the slop
+//! around thin shapes feels the same at every zoom level. `zoom` is clamped
+//! to the renderer's range first, so a zero or garbage zoom from JS can't
+//! turn the tolerance into infinity or NaN.
+//!
+//! ## Handle Indices
+//!
+//! ```text
+//!  0 ──── 4 ──── 1        Edges: 0 = top, 1 = bottom, 2 = left, 3 = right
+//!  │             │
+//!  6             7
+//!  │             │
+//!  2 ──── 5 ──── 3
+//! ```
+//!
+//! The order matches `Renderer::draw_selection`.
+
+use super::{Document, Node, NodeType, ObjectId, Paint, Property};
+use crate::geometry::Contour;
+use crate::math::Vec2;
+use crate::tools::{HitResult, ToolState};
+use std::cmp::Ordering;
+use wasm_bindgen::prelude::*;
+
+/// Size of a selection handle in screen pixels (see `Renderer::draw_selection`)
+const HANDLE_SIZE: f32 = 8.0;
+
+/// Zoom range of the renderer's viewport (see `Renderer::set_viewport_zoom`)
+const MIN_ZOOM: f32 = 0.01;
+const MAX_ZOOM: f32 = 256.0;
+
+#[wasm_bindgen]
+impl Document {
+    /// Find the topmost node under a canvas point
+    pub fn hit_test(&self, x: f32, y: f32, tolerance: f32, zoom: f32) -> HitResult {
+        let tolerance = tolerance / clamp_zoom(zoom);
+        let point = Vec2::new(x, y);
+
+        // Strokes and effects can be hit outside a node's bounds
+        let mut candidates = self.nodes_drawn_in_rect(
+            x - tolerance,
+            y - tolerance,
+            tolerance * 2.0,
+            tolerance * 2.0,
+        );
+        candidates.sort_by(|a, b| self.compare_z_order(*b, *a));
+
+        candidates
+            .into_iter()
+            .find(|&id| self.is_hittable(id) && self.shape_contains(id, point, tolerance) && !self.is_clipped_at(id, point))
+            .map(HitResult::object)
+            .unwrap_or_else(HitResult::none)
+    }
+
+    /// Hit test that checks the handles and edges of the selection first
+    pub fn hit_test_with_selection(
+        &self,
+        x: f32,
+        y: f32,
+        tolerance: f32,
+        zoom: f32,
+        tools: &ToolState,
+    ) -> HitResult {
+        let point = Vec2::new(x, y);
+        let zoom = clamp_zoom(zoom);
+        for &id in tools.selection().iter().rev() {
+            if let Some(hit) = self.hit_selection_chrome(id, point, tolerance / zoom, zoom) {
+                return hit;
+            }
+        }
+        self.hit_test(x, y, tolerance, zoom)
+    }
+}
+
+impl Document {
+    /// Order two nodes by paint order (later-painted nodes compare greater)
+    pub(crate) fn compare_z_order(&self, a: ObjectId, b: ObjectId) -> Ordering {
+        self.z_path(a).cmp(&self.z_path(b))
+    }
+
+    /// Sibling indices from the root down to a node
+    fn z_path(&self, id: ObjectId) -> Vec<usize> {
+        let mut path = Vec::new();
+        let mut current = id;
+        while let Some(index) = self.tree.index_in_parent(current) {
+            path.push(index);
+            match self.tree.parent(current) {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    /// A node can be hit if it is on the active page and it and all of its
+    /// ancestors are visible and unlocked. The operands of a boolean are hit
+    /// through its result instead.
+    fn is_hittable(&self, id: ObjectId) -> bool {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            match self.tree.get(node_id) {
+                Some(node) if node.node_type() == NodeType::BooleanOperation && node_id != id => return false,
+                Some(node) if node.node_type() == NodeType::Page => return self.get_active_page() == Some(node_id),
+                Some(node) if node.visible() && !node.locked() => {}
+                _ => return false,
+            }
+            current = self.tree.parent(node_id);
+        }
+        true
+    }
+
+    /// Whether a canvas point lies on a node's shape (fill or stroke)
+    fn shape_contains(&self, id: ObjectId, point: Vec2, tolerance: f32) -> bool {
+        let Some(node) = self.resolved_node(id) else { return false };
+        let local = self.world_transform(id).inverse().apply(point);
+        shape_distance(&node, local) <= tolerance
+    }
+
+    /// Whether a canvas point is cut off from a node by its mask or by a
+    /// mask or clipping frame around one of its ancestors (see the mask
+    /// module docs). Alpha masks cut by their outline too.
+    fn is_clipped_at(&self, id: ObjectId, point: Vec2) -> bool {
+        let outside = |clip: ObjectId| {
+            self.resolved_node(clip).is_some_and(|node| {
+                let local = self.world_transform(clip).inverse().apply(point);
+                node.node_type() != NodeType::Group && shape_distance(&node, local) > 0.0
+            })
+        };
+        let mut current = id;
+        loop {
+            if self.get_mask(current).is_some_and(outside) {
+                return true;
+            }
+            let Some(parent) = self.tree.parent(current) else {
+                return false;
+            };
+            if self.get_clip_content(parent) && outside(parent) {
+                return true;
+            }
+            current = parent;
+        }
+    }
+
+    /// Check a selected node's handles, then its edges
+    fn hit_selection_chrome(&self, id: ObjectId, point: Vec2, tolerance: f32, zoom: f32) -> Option<HitResult> {
+        let node = self.bound_node(self.tree.get(id)?);
+        let local = self.world_transform(id).inverse().apply(point);
+        let (w, h) = (node.width(), node.height());
+
+        let handles = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(w, 0.0),
+            Vec2::new(0.0, h),
+            Vec2::new(w, h),
+            Vec2::new(w / 2.0, 0.0),
+            Vec2::new(w / 2.0, h),
+            Vec2::new(0.0, h / 2.0),
+            Vec2::new(w, h / 2.0),
+        ];
+        let reach = HANDLE_SIZE / 2.0 / zoom + tolerance;
+        for (index, handle) in handles.iter().enumerate() {
+            let offset = (local - *handle).abs();
+            if offset.x <= reach && offset.y <= reach {
+                return Some(HitResult::handle(id, index as u32));
+            }
+        }
+
+        let edges = [
+            (Vec2::new(0.0, 0.0), Vec2::new(w, 0.0)),
+            (Vec2::new(0.0, h), Vec2::new(w, h)),
+            (Vec2::new(0.0, 0.0), Vec2::new(0.0, h)),
+            (Vec2::new(w, 0.0), Vec2::new(w, h)),
+        ];
+        for (index, (start, end)) in edges.iter().enumerate() {
+            if segment_distance(local, *start, *end) <= tolerance {
+                return Some(HitResult::edge(id, index as u32));
+            }
+        }
+
+        None
+    }
+}
+
+/// Distance from a local-space point to a node's painted shape
+/// (0 inside a filled shape)
+fn shape_distance(node: &Node, p: Vec2) -> f32 {
+    let size = Vec2::new(node.width(), node.height());
+    let stroke = node.stroke_width();
+
+    match node.node_type() {
+        // Groups have no shape of their own; only their children are hit
+        NodeType::Document | NodeType::Page | NodeType::Style | NodeType::Group => f32::INFINITY,
+        NodeType::Line => segment_distance(p, Vec2::ZERO, size) - stroke.max(1.0) / 2.0,
+        NodeType::Vector | NodeType::BooleanOperation if node.get_property(Property::VectorData).is_some() => {
+            let contours = node.vector_network().map(|n| n.to_contours()).unwrap_or_default();
+            let edge = contours_distance(&contours, p);
+            let distance = if contours_contain(&contours, p) { 0.0 } else { edge };
+            if stroke > 0.0 {
+                distance.min(edge - stroke / 2.0).max(0.0)
+            } else {
+                distance
+            }
+        }
+        node_type => {
+            let edge = match node_type {
+                NodeType::Ellipse => ellipse_sdf(p, size),
+                _ => rounded_rect_sdf(p, size, node.corner_radius()),
+            };
+            // Shapes without a visible fill can only be hit on their stroke
+            let filled = node.fills().iter().any(Paint::is_drawn)
+                || !matches!(node_type, NodeType::Rectangle | NodeType::Ellipse | NodeType::Vector);
+            let distance = if filled { edge.max(0.0) } else { edge.abs() };
+            if stroke > 0.0 {
+                distance.min(edge.abs() - stroke / 2.0).max(0.0)
+            } else {
+                distance
+            }
+        }
+    }
+}
+
+/// Signed distance to a rounded rectangle at the origin (negative inside)
+fn rounded_rect_sdf(p: Vec2, size: Vec2, radius: f32) -> f32 {
+    let half = size / 2.0;
+    let radius = radius.clamp(0.0, half.x.min(half.y));
+    let q = (p - half).abs() - half + Vec2::splat(radius);
+    q.max(Vec2::ZERO).length() + q.x.max(q.y).min(0.0) - radius
+}
+
+/// Approximate signed distance to the ellipse inscribed in `size`
+fn ellipse_sdf(p: Vec2, size: Vec2) -> f32 {
+    let radii = size / 2.0;
+    if radii.x <= 0.0 || radii.y <= 0.0 {
+        return segment_distance(p, Vec2::ZERO, size);
+    }
+    let normalized = (p - radii) / radii;
+    (normalized.length() - 1.0) * radii.x.min(radii.y)
+}
+
+/// Even-odd point in polygon test over a set of closed contours
+fn contours_contain(contours: &[Contour], p: Vec2) -> bool {
+    let mut inside = false;
+    for contour in contours {
+        let n = contour.len();
+        for i in 0..n {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            if (a[1] > p.y) != (b[1] > p.y) && p.x < a[0] + (p.y - a[1]) / (b[1] - a[1]) * (b[0] - a[0]) {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Distance to the nearest edge of a set of closed contours
+fn contours_distance(contours: &[Contour], p: Vec2) -> f32 {
+    contours
+        .iter()
+        .flat_map(|contour| {
+            let n = contour.len();
+            (0..n).map(move |i| segment_distance(p, Vec2::from(contour[i]), Vec2::from(contour[(i + 1) % n])))
+        })
+        .fold(f32::INFINITY, f32::min)
+}
+
+fn segment_distance(p: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let direction = end - start;
+    let length_squared = direction.length_squared();
+    if length_squared == 0.0 {
+        return p.distance(start);
+    }
+    let t = ((p - start).dot(direction) / length_squared).clamp(0.0, 1.0);
+    p.distance(start + direction * t)
+}
+
+/// Keep a zoom from JS in the renderer's range; NaN counts as 100%
+fn clamp_zoom(zoom: f32) -> f32 {
+    if zoom.is_nan() {
+        1.0
+    } else {
+        zoom.clamp(MIN_ZOOM, MAX_ZOOM)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::PropertyValue;
+    use crate::tools::HitType;
+
+    #[test]
+    fn test_hit_test_shapes_and_z_order() {
+        let mut doc = Document::new("Hits");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 10.0, 50.0, 50.0);
+        let ellipse_id = doc.create_ellipse(frame_id, 40.0, 40.0, 60.0, 60.0);
+        doc.set_node_property(rect_id, Property::CornerRadius, PropertyValue::Float(20.0));
+
+        // The ellipse is on top where they overlap
+        assert_eq!(doc.hit_test(50.0, 50.0, 0.0, 1.0).object_id(), Some(ellipse_id));
+        assert_eq!(doc.hit_test(20.0, 30.0, 0.0, 1.0).object_id(), Some(rect_id));
+        // Rounded corner of the rect and corner of the ellipse's box fall through
+        assert_eq!(doc.hit_test(12.0, 12.0, 0.0, 1.0).object_id(), Some(frame_id));
+        assert_eq!(doc.hit_test(95.0, 95.0, 0.0, 1.0).object_id(), Some(frame_id));
+
+        doc.set_node_property(ellipse_id, Property::Visible, PropertyValue::Bool(false));
+        assert_eq!(doc.hit_test(50.0, 50.0, 0.0, 1.0).object_id(), Some(rect_id));
+
+        // A zero or NaN zoom still gives a finite tolerance
+        assert_eq!(doc.hit_test(150.0, 150.0, 1.0, 0.0).object_id(), Some(frame_id));
+        assert_eq!(doc.hit_test(150.0, 150.0, 1.0, f32::NAN).object_id(), Some(frame_id));
+
+        doc.set_node_property(frame_id, Property::Locked, PropertyValue::Bool(true));
+        assert!(matches!(doc.hit_test(50.0, 50.0, 0.0, 1.0).hit_type(), HitType::None));
+    }
+
+    #[test]
+    fn test_hit_thick_strokes_outside_bounds() {
+        let mut doc = Document::new("Strokes");
+        let page_id = doc.get_active_page().unwrap();
+        let rect_id = doc.create_rectangle(page_id, 10.0, 10.0, 50.0, 50.0);
+        assert!(doc.hit_test(5.0, 30.0, 0.0, 1.0).object_id().is_none());
+
+        // A 20 wide stroke is centered on the edge, reaching 10 out
+        doc.set_node_property(rect_id, Property::StrokeWidth, PropertyValue::Float(20.0));
+        assert_eq!(doc.hit_test(5.0, 30.0, 0.0, 1.0).object_id(), Some(rect_id));
+        assert_eq!(doc.nodes_drawn_in_rect(0.0, 30.0, 1.0, 1.0), vec![rect_id]);
+        assert!(doc.nodes_in_rect(0.0, 30.0, 1.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_hit_rotated_selection_handles() {
+        let mut doc = Document::new("Handles");
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 50.0);
+        doc.set_node_property(frame_id, Property::Rotation, PropertyValue::Float(90.0));
+        let mut tools = ToolState::new();
+        tools.set_selection(frame_id);
+
+        // Rotated 90° about (50, 25): the top-left corner lands at (75, -25)
+        let hit = doc.hit_test_with_selection(76.0, -24.0, 2.0, 1.0, &tools);
+        assert!(matches!(hit.hit_type(), HitType::Handle));
+        assert_eq!(hit.handle_index(), Some(0));
+
+        // The top edge now runs vertically along x = 75
+        let hit = doc.hit_test_with_selection(75.5, 0.0, 2.0, 1.0, &tools);
+        assert!(matches!(hit.hit_type(), HitType::Edge));
+        assert_eq!(hit.handle_index(), Some(0));
+
+        // Inside the rotated frame, away from the chrome
+        assert!(matches!(doc.hit_test_with_selection(50.0, 25.0, 2.0, 1.0, &tools).hit_type(), HitType::Object));
+    }
+
+    #[test]
+    fn test_hit_clipped_content_and_masks() {
+        let mut doc = Document::new("Clips");
+        let outer = doc.create_frame(0.0, 0.0, 300.0, 300.0);
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        doc.move_node(frame_id, outer, 0);
+        let rect_id = doc.create_rectangle(frame_id, 50.0, 50.0, 100.0, 100.0);
+
+        // Overflowing children are hit until the frame clips them
+        assert_eq!(doc.hit_test(120.0, 120.0, 0.0, 1.0).object_id(), Some(rect_id));
+        doc.set_clip_content(frame_id, true);
+        assert_eq!(doc.hit_test(120.0, 120.0, 0.0, 1.0).object_id(), Some(outer));
+        assert_eq!(doc.hit_test(90.0, 90.0, 0.0, 1.0).object_id(), Some(rect_id));
+
+        // A circle masking the rectangle cuts off its corner
+        let circle = doc.create_ellipse(frame_id, 50.0, 50.0, 40.0, 40.0);
+        doc.move_node(circle, frame_id, 0);
+        doc.set_mask(circle, true);
+        assert_eq!(doc.hit_test(70.0, 70.0, 0.0, 1.0).object_id(), Some(rect_id));
+        assert_eq!(doc.hit_test(52.0, 52.0, 0.0, 1.0).object_id(), Some(frame_id));
+    }
+}
@@ -0,0 +1,593 @@
+//! Images
+//!
+//! Image files live in the document's asset store, keyed by the SHA-256 of
+//! their bytes. Nodes never hold image data, only an [`ImagePaint`] naming an
+//! asset by hash, so the same photo used a hundred times is stored once and
+//! copies of a node share it:
+//!
+//! ```text
+//! let hash = doc.add_image(bytes)          PNG or JPEG, as read from disk
+//! doc.create_image(page, x, y, hash)       an Image node at the natural size
+//! doc.set_image_fill(rect, hash, "tile")   or fill any shape with it
+//! ```
+//!
+//! The store only grows while editing (so undo can always bring back a node
+//! that used an image). Saving writes just the images some node still uses.
+//!
+//! ## Scale modes
+//!
+//! An image paint places the image relative to the node's bounds:
+//!
+//! ```text
+//! fill   scaled to cover the bounds, centred, overflow cut off
+//! fit    scaled to fit inside the bounds, centred, gaps left empty
+//! crop   the `crop` rectangle of the image stretched over the bounds
+//! tile   repeated at `scale` × its natural size from the top-left corner
+//! ```
+//!
+//! Files are kept as encoded PNG/JPEG bytes; the renderer decodes them to
+//! RGBA with [`ImageAsset::decode`] when it first needs a texture. Files can
+//! come from pasted or loaded documents, so images over [`MAX_PIXELS`] are
+//! rejected before any pixels are decoded.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Paint, PaintKind, Property, PropertyValue, Subtree};
+use crate::math::{Rect, Vec2};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use wasm_bindgen::prelude::*;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest image accepted, in pixels (a 8192 × 4096 image, 128 MiB as RGBA)
+pub const MAX_PIXELS: u64 = 8192 * 4096;
+
+/// Most the decoders may allocate: the largest image at 4 bytes per pixel
+const MAX_DECODE_BYTES: usize = MAX_PIXELS as usize * 4;
+const JPEG_SIGNATURE: &[u8] = &[0xFF, 0xD8, 0xFF];
+
+/// Image file formats the store accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+/// Errors from reading an image file
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImageError {
+    /// Not a PNG or JPEG file
+    Unsupported,
+    /// The file is damaged or uses a feature the decoder doesn't support
+    Decode(String),
+    /// The image has more than [`MAX_PIXELS`] pixels
+    TooLarge { width: u32, height: u32 },
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::Unsupported => write!(f, "Unsupported image format"),
+            ImageError::Decode(message) => write!(f, "Failed to decode image: {}", message),
+            ImageError::TooLarge { width, height } => {
+                write!(f, "Image is too large ({} × {}, at most {} pixels)", width, height, MAX_PIXELS)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// An image file in the asset store
+#[derive(Debug, Clone)]
+pub struct ImageAsset {
+    bytes: Vec<u8>,
+    format: ImageFormat,
+    width: u32,
+    height: u32,
+}
+
+impl ImageAsset {
+    /// Check an image file and read its size (without decoding the pixels)
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, ImageError> {
+        let format = sniff_format(&bytes).ok_or(ImageError::Unsupported)?;
+        let (width, height) = match format {
+            ImageFormat::Png => {
+                let reader = png_decoder(&bytes).read_info().map_err(decode_error)?;
+                let info = reader.info();
+                (info.width, info.height)
+            }
+            ImageFormat::Jpeg => {
+                let mut decoder = jpeg_decoder::Decoder::new(bytes.as_slice());
+                decoder.read_info().map_err(decode_error)?;
+                jpeg_size(&decoder)?
+            }
+        };
+        check_size(width, height)?;
+        Ok(Self { bytes, format, width, height })
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn format(&self) -> ImageFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Decode the file to 8-bit RGBA pixels
+    pub fn decode(&self) -> Result<DecodedImage, ImageError> {
+        match self.format {
+            ImageFormat::Png => decode_png(&self.bytes),
+            ImageFormat::Jpeg => decode_jpeg(&self.bytes),
+        }
+    }
+}
+
+/// Decoded pixels, 8-bit RGBA, rows top to bottom, not premultiplied
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl DecodedImage {
+    /// Halve the image (averaging 2×2 blocks) until both sides are at most
+    /// `max_size`, e.g. the GPU's texture size limit
+    pub fn fit_within(self, max_size: u32) -> Self {
+        let mut image = self;
+        while image.width.max(image.height) > max_size.max(1) {
+            image = image.half_size();
+        }
+        image
+    }
+
+    fn half_size(&self) -> Self {
+        let width = self.width.div_ceil(2);
+        let height = self.height.div_ceil(2);
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                // Odd edges reuse the last row or column
+                let xs = [2 * x, (2 * x + 1).min(self.width - 1)];
+                let ys = [2 * y, (2 * y + 1).min(self.height - 1)];
+                for channel in 0..4 {
+                    let sum: u32 = ys
+                        .iter()
+                        .flat_map(|&sy| xs.iter().map(move |&sx| (sy * self.width + sx) as usize * 4 + channel))
+                        .map(|i| self.pixels[i] as u32)
+                        .sum();
+                    pixels.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+        Self { width, height, pixels }
+    }
+}
+
+/// How an image paint places its image in the node's bounds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    #[default]
+    Fill,
+    Fit,
+    Crop,
+    Tile,
+}
+
+impl ScaleMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "fill" => Some(ScaleMode::Fill),
+            "fit" => Some(ScaleMode::Fit),
+            "crop" => Some(ScaleMode::Crop),
+            "tile" => Some(ScaleMode::Tile),
+            _ => None,
+        }
+    }
+}
+
+/// A fill that draws an image from the asset store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImagePaint {
+    /// Hash of the image in the asset store
+    pub image: String,
+    pub scale_mode: ScaleMode,
+    /// `crop` mode: the part of the image shown, as fractions of its size
+    /// (x, y, width, height)
+    pub crop: [f32; 4],
+    /// `tile` mode: tile size as a multiple of the image's natural size
+    pub scale: f32,
+}
+
+impl ImagePaint {
+    pub fn new(image: &str, scale_mode: ScaleMode) -> Self {
+        Self {
+            image: image.into(),
+            scale_mode,
+            crop: [0.0, 0.0, 1.0, 1.0],
+            scale: 1.0,
+        }
+    }
+
+    /// Where the whole image (one tile, in `tile` mode) goes, in the local
+    /// space of a node of `size`, for an image of `image_width` × `image_height`.
+    ///
+    /// Parts of the rectangle outside the node are cut off; in `fit` mode
+    /// parts of the node outside the rectangle stay empty.
+    pub fn image_rect(&self, size: Vec2, image_width: u32, image_height: u32) -> Rect {
+        let image = Vec2::new(image_width.max(1) as f32, image_height.max(1) as f32);
+        let centred = |scale: f32| {
+            let scaled = image * scale;
+            Rect::new((size.x - scaled.x) / 2.0, (size.y - scaled.y) / 2.0, scaled.x, scaled.y)
+        };
+        match self.scale_mode {
+            ScaleMode::Fill => centred((size.x / image.x).max(size.y / image.y)),
+            ScaleMode::Fit => centred((size.x / image.x).min(size.y / image.y)),
+            ScaleMode::Crop => {
+                let [x, y, width, height] = self.crop;
+                let width = size.x / width.max(f32::EPSILON);
+                let height = size.y / height.max(f32::EPSILON);
+                Rect::new(-x * width, -y * height, width, height)
+            }
+            ScaleMode::Tile => Rect::new(0.0, 0.0, image.x * self.scale, image.y * self.scale),
+        }
+    }
+}
+
+/// The document's images, by content hash
+#[derive(Debug, Default)]
+pub(crate) struct ImageStore {
+    assets: HashMap<String, ImageAsset>,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Add a PNG or JPEG file to the asset store and return its hash.
+    ///
+    /// Adding the same bytes twice returns the same hash. Returns `None` if
+    /// the file isn't a readable PNG or JPEG.
+    pub fn add_image(&mut self, bytes: &[u8]) -> Option<String> {
+        self.insert_image(bytes.to_vec()).ok()
+    }
+
+    pub fn has_image(&self, hash: &str) -> bool {
+        self.images.assets.contains_key(hash)
+    }
+
+    /// Natural size of a stored image as `[width, height]` (empty if unknown)
+    pub fn get_image_size(&self, hash: &str) -> Vec<u32> {
+        self.image(hash).map(|asset| vec![asset.width, asset.height]).unwrap_or_default()
+    }
+
+    /// Create an image node at the image's natural size, filled with the image
+    pub fn create_image(&mut self, parent_id: ObjectId, x: f32, y: f32, hash: &str) -> Option<ObjectId> {
+        let asset = self.image(hash)?;
+        let (width, height) = (asset.width as f32, asset.height as f32);
+
+        let image_id = ObjectId::random();
+        let mut image = Node::new(image_id, NodeType::Image);
+        image.set_property(Property::X, PropertyValue::Float(x));
+        image.set_property(Property::Y, PropertyValue::Float(y));
+        image.set_property(Property::Width, PropertyValue::Float(width));
+        image.set_property(Property::Height, PropertyValue::Float(height));
+        image.set_property(Property::Name, PropertyValue::String("Image".into()));
+        let fill = Paint::image(ImagePaint::new(hash, ScaleMode::Fill));
+        image.set_property(Property::Fills, PropertyValue::Paints(vec![fill]));
+
+        self.apply(Operation::Insert(Subtree::leaf(image, Some(parent_id)))).then_some(image_id)
+    }
+
+    /// Fill a node with a stored image, on top of its other fills and in
+    /// place of any image it had. `scale_mode` is "fill", "fit", "crop" or
+    /// "tile"; crop and tile start from the whole image at natural size.
+    pub fn set_image_fill(&mut self, id: ObjectId, hash: &str, scale_mode: &str) -> bool {
+        let Some(scale_mode) = ScaleMode::parse(scale_mode) else {
+            return false;
+        };
+        if !self.has_image(hash) {
+            return false;
+        }
+        self.update_fills(id, |fills| {
+            fills.retain(|paint| !matches!(paint.kind, PaintKind::Image(_)));
+            fills.push(Paint::image(ImagePaint::new(hash, scale_mode)));
+        })
+    }
+
+    /// Show only part of a node's (topmost) image fill (fractions of the image size),
+    /// switching it to `crop` mode
+    pub fn set_image_crop(&mut self, id: ObjectId, x: f32, y: f32, width: f32, height: f32) -> bool {
+        if width <= 0.0 || height <= 0.0 {
+            return false;
+        }
+        self.update_image_fill(id, |paint| {
+            paint.scale_mode = ScaleMode::Crop;
+            paint.crop = [x, y, width, height];
+        })
+    }
+
+    /// Repeat a node's (topmost) image fill at `scale` × its natural size, switching it
+    /// to `tile` mode
+    pub fn set_image_tile_scale(&mut self, id: ObjectId, scale: f32) -> bool {
+        if scale <= 0.0 {
+            return false;
+        }
+        self.update_image_fill(id, |paint| {
+            paint.scale_mode = ScaleMode::Tile;
+            paint.scale = scale;
+        })
+    }
+}
+
+impl Document {
+    /// A stored image by hash
+    pub fn image(&self, hash: &str) -> Option<&ImageAsset> {
+        self.images.assets.get(hash)
+    }
+
+    /// Check an image file and add it to the store, returning its hash
+    pub(crate) fn insert_image(&mut self, bytes: Vec<u8>) -> Result<String, ImageError> {
+        let hash = image_hash(&bytes);
+        if !self.images.assets.contains_key(&hash) {
+            let asset = ImageAsset::from_bytes(bytes)?;
+            self.images.assets.insert(hash.clone(), asset);
+        }
+        Ok(hash)
+    }
+
+    /// Add an image saved with `encode_images()`
+    pub(crate) fn insert_encoded_image(&mut self, data: &str) -> Result<String, ImageError> {
+        let bytes = BASE64.decode(data).map_err(decode_error)?;
+        self.insert_image(bytes)
+    }
+
+    /// The stored images used by `nodes`, base64 encoded, in hash order
+    pub(crate) fn encode_images<'a>(&self, nodes: impl IntoIterator<Item = &'a Node>) -> Vec<String> {
+        self.images_used_by(nodes).iter().map(|asset| BASE64.encode(&asset.bytes)).collect()
+    }
+
+    /// The stored images used by `nodes`, in hash order
+    pub(crate) fn images_used_by<'a>(&self, nodes: impl IntoIterator<Item = &'a Node>) -> Vec<&ImageAsset> {
+        let hashes: BTreeSet<&str> = nodes
+            .into_iter()
+            .flat_map(|node| node.properties().values())
+            .flat_map(|value| match value {
+                PropertyValue::Image(paint) => vec![paint.image.as_str()],
+                PropertyValue::Paints(paints) => paints
+                    .iter()
+                    .filter_map(|paint| match &paint.kind {
+                        PaintKind::Image(image) => Some(image.image.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        hashes.into_iter().filter_map(|hash| self.image(hash)).collect()
+    }
+
+    fn update_image_fill(&mut self, id: ObjectId, update: impl FnOnce(&mut ImagePaint)) -> bool {
+        let has_image = |node: &Node| node.fills().iter().any(|paint| matches!(paint.kind, PaintKind::Image(_)));
+        if !self.tree.get(id).is_some_and(has_image) {
+            return false;
+        }
+        self.update_fills(id, |fills| {
+            let topmost = fills.iter_mut().rev().find_map(|paint| match &mut paint.kind {
+                PaintKind::Image(image) => Some(image),
+                _ => None,
+            });
+            if let Some(image) = topmost {
+                update(image);
+            }
+        })
+    }
+}
+
+/// The content hash an image is stored under: lowercase hex SHA-256
+pub fn image_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn sniff_format(bytes: &[u8]) -> Option<ImageFormat> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        Some(ImageFormat::Png)
+    } else if bytes.starts_with(JPEG_SIGNATURE) {
+        Some(ImageFormat::Jpeg)
+    } else {
+        None
+    }
+}
+
+fn decode_error(error: impl std::fmt::Display) -> ImageError {
+    ImageError::Decode(error.to_string())
+}
+
+/// Reject empty images and ones over [`MAX_PIXELS`]
+fn check_size(width: u32, height: u32) -> Result<(), ImageError> {
+    if width == 0 || height == 0 {
+        Err(ImageError::Decode("image is empty".into()))
+    } else if width as u64 * height as u64 > MAX_PIXELS {
+        Err(ImageError::TooLarge { width, height })
+    } else {
+        Ok(())
+    }
+}
+
+/// A PNG decoder that won't allocate more than [`MAX_DECODE_BYTES`]
+fn png_decoder(bytes: &[u8]) -> png::Decoder<&[u8]> {
+    png::Decoder::new_with_limits(bytes, png::Limits { bytes: MAX_DECODE_BYTES })
+}
+
+/// The size from a JPEG header read with `read_info()`
+fn jpeg_size(decoder: &jpeg_decoder::Decoder<&[u8]>) -> Result<(u32, u32), ImageError> {
+    let info = decoder.info().ok_or_else(|| ImageError::Decode("missing header".into()))?;
+    Ok((info.width as u32, info.height as u32))
+}
+
+fn decode_png(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    let mut decoder = png_decoder(bytes);
+    // Expand palettes and low bit depths, drop 16-bit precision
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(decode_error)?;
+    check_size(reader.info().width, reader.info().height)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(decode_error)?;
+    buffer.truncate(frame.buffer_size());
+
+    let pixels = match frame.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        png::ColorType::Indexed => return Err(ImageError::Decode("unexpanded palette".into())),
+    };
+    Ok(DecodedImage { width: frame.width, height: frame.height, pixels })
+}
+
+fn decode_jpeg(bytes: &[u8]) -> Result<DecodedImage, ImageError> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    decoder.read_info().map_err(decode_error)?;
+    let (width, height) = jpeg_size(&decoder)?;
+    check_size(width, height)?;
+    decoder.set_max_decoding_buffer_size(MAX_DECODE_BYTES);
+    let buffer = decoder.decode().map_err(decode_error)?;
+    let info = decoder.info().ok_or_else(|| ImageError::Decode("missing header".into()))?;
+
+    let pixels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => buffer.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        jpeg_decoder::PixelFormat::L8 => buffer.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+        jpeg_decoder::PixelFormat::L16 => buffer
+            .chunks_exact(2)
+            .map(|p| (u16::from_ne_bytes([p[0], p[1]]) >> 8) as u8)
+            .flat_map(|v| [v, v, v, 255])
+            .collect(),
+        // Adobe CMYK JPEGs store inverted values
+        jpeg_decoder::PixelFormat::CMYK32 => buffer
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let k = p[3] as u32;
+                let channel = |c: u8| (c as u32 * k / 255) as u8;
+                [channel(p[0]), channel(p[1]), channel(p[2]), 255]
+            })
+            .collect(),
+    };
+    Ok(DecodedImage { width: info.width as u32, height: info.height as u32, pixels })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA PNG with a gradient
+    fn png_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        let pixels: Vec<u8> = (0..(width * height) as u8).flat_map(|i| [i * 40, 0, 255 - i * 40, 255]).collect();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_asset_store_and_image_nodes() {
+        let mut doc = Document::new("Images");
+        let page_id = doc.get_active_page().unwrap();
+        let bytes = png_bytes(3, 2);
+
+        let hash = doc.add_image(&bytes).unwrap();
+        assert_eq!(hash, image_hash(&bytes));
+        assert_eq!(doc.add_image(&bytes), Some(hash.clone()));
+        assert_eq!(doc.add_image(b"GIF89a"), None);
+        assert_eq!(doc.get_image_size(&hash), vec![3, 2]);
+
+        let decoded = doc.image(&hash).unwrap().decode().unwrap();
+        assert_eq!((decoded.width, decoded.height, decoded.pixels.len()), (3, 2, 24));
+        assert_eq!(&decoded.pixels[4..8], &[40, 0, 215, 255]);
+        let small = decoded.fit_within(1);
+        assert_eq!((small.width, small.height, small.pixels.len()), (1, 1, 4));
+
+        let image_id = doc.create_image(page_id, 10.0, 10.0, &hash).unwrap();
+        assert_eq!((doc.get_node_width(image_id), doc.get_node_height(image_id)), (3.0, 2.0));
+        let rect_id = doc.create_rectangle(page_id, 0.0, 0.0, 50.0, 50.0);
+        assert!(doc.set_image_fill(rect_id, &hash, "tile"));
+        assert!(doc.set_image_tile_scale(rect_id, 2.0));
+        assert!(!doc.set_image_fill(rect_id, "missing", "fill"));
+
+        // Only images still in use are saved, and both formats keep them
+        let unused = doc.add_image(&png_bytes(1, 1)).unwrap();
+        for loaded in [
+            Document::decode_json(&doc.encode_json().unwrap()).unwrap(),
+            Document::decode_binary(&doc.encode_binary().unwrap()).unwrap(),
+        ] {
+            assert!(loaded.has_image(&hash) && !loaded.has_image(&unused));
+            let fills = loaded.tree.get(rect_id).unwrap().fills();
+            assert!(matches!(&fills[1].kind, PaintKind::Image(paint) if paint.scale_mode == ScaleMode::Tile && paint.scale == 2.0));
+        }
+        doc.delete_node(image_id);
+        doc.delete_node(rect_id);
+        assert!(!Document::decode_json(&doc.encode_json().unwrap()).unwrap().has_image(&hash));
+
+        // Pasting into another document brings the image along
+        doc.undo();
+        let payload = doc.copy(vec![rect_id]);
+        let mut other = Document::new("Other");
+        let other_page = other.get_active_page().unwrap();
+        assert_eq!(other.paste(&payload, other_page, 0).len(), 1);
+        assert!(other.has_image(&hash));
+    }
+
+    #[test]
+    fn test_rejects_oversized_images() {
+        // Just the headers: the size is known before any pixel data
+        let mut png = Vec::new();
+        let mut writer = png::Encoder::new(&mut png, 40_000, 40_000).write_header().unwrap();
+        writer.write_chunk(png::chunk::IDAT, &[]).unwrap();
+        drop(writer);
+        let jpeg = [
+            &[0xFF, 0xD8][..],
+            &[0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x9C, 0x40, 0x9C, 0x40, 0x01, 0x01, 0x11, 0x00],
+            &[0xFF, 0xDA, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00],
+        ]
+        .concat();
+        let too_large = Err(ImageError::TooLarge { width: 40_000, height: 40_000 });
+        assert_eq!(ImageAsset::from_bytes(png.clone()).map(|_| ()), too_large);
+        assert_eq!(decode_png(&png).map(|_| ()), too_large);
+        assert_eq!(ImageAsset::from_bytes(jpeg.clone()).map(|_| ()), too_large);
+        assert_eq!(decode_jpeg(&jpeg).map(|_| ()), too_large);
+    }
+
+    #[test]
+    fn test_image_rect_scale_modes() {
+        let size = Vec2::new(200.0, 100.0);
+        let rect = |paint: &ImagePaint| {
+            let r = paint.image_rect(size, 100, 100);
+            (r.x, r.y, r.width, r.height)
+        };
+
+        assert_eq!(rect(&ImagePaint::new("", ScaleMode::Fill)), (0.0, -50.0, 200.0, 200.0));
+        assert_eq!(rect(&ImagePaint::new("", ScaleMode::Fit)), (50.0, 0.0, 100.0, 100.0));
+
+        let mut crop = ImagePaint::new("", ScaleMode::Crop);
+        crop.crop = [0.5, 0.0, 0.5, 0.5];
+        assert_eq!(rect(&crop), (-200.0, 0.0, 400.0, 200.0));
+
+        let mut tile = ImagePaint::new("", ScaleMode::Tile);
+        tile.scale = 0.5;
+        assert_eq!(rect(&tile), (0.0, 0.0, 50.0, 50.0));
+    }
+}
@@ -0,0 +1,562 @@
+//! Auto layout
+//!
+//! Frames with `LayoutMode = "auto"` position their children in a stack,
+//! like CSS flexbox:
+//!
+//! ```text
+//! LayoutDirection = "horizontal", LayoutGap = 8, LayoutPadding = 12
+//! ┌──────────────────────────────────────────┐
+//! │ 12                                       │
+//! │   ┌──────┐ 8 ┌──────────┐ 8 ┌───┐        │
+//! │   │  A   │   │    B     │   │ C │        │
+//! │   └──────┘   └──────────┘   └───┘        │
+//! │                                          │
+//! └──────────────────────────────────────────┘
+//! ```
+//!
+//! ## Properties
+//!
+//! | Property                 | Values                                      |
+//! |--------------------------|---------------------------------------------|
+//! | `LayoutMode`             | `"none"` (default) or `"auto"`              |
+//! | `LayoutDirection`        | `"horizontal"` (default) or `"vertical"`    |
+//! | `LayoutGap`              | spacing between children                    |
+//! | `LayoutPadding`          | `Float` (all sides) or `Vec4` (t, r, b, l)  |
+//! | `LayoutAlign`            | cross axis: `"start"`, `"center"`, `"end"`, `"stretch"` |
+//! | `LayoutSizingHorizontal` | `"fixed"` (default), `"hug"` or `"fill"`    |
+//! | `LayoutSizingVertical`   | `"fixed"` (default), `"hug"` or `"fill"`    |
+//!
+//! `"hug"` on a frame sizes it to its content. `"fill"` on a child stretches it
+//! across the free space of its auto layout parent. Hidden children are skipped.
+//! Child positions are relative to the frame.
+//!
+//! ## Incremental Updates
+//!
+//! `Document::apply()` marks frames dirty when a change can affect them (a
+//! layout property, a child's size, a child added or removed) and re-runs
+//! layout on just those frames. Layout results are written with normal
+//! operations, so hug sizes ripple up to parent frames and fill sizes ripple
+//! down into nested frames until nothing changes.
+//!
+//! Layout reads values with variable bindings resolved (see `variable.rs`),
+//! so a frame whose Width or LayoutPadding is bound lays out with the value
+//! it is drawn with. Editing a variable's values re-runs layout around its
+//! consumers, and switching a node's variable mode re-runs it for the whole
+//! subtree. A computed position or size that differs from a bound value
+//! replaces the binding, since layout decides that value.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue};
+use wasm_bindgen::prelude::*;
+
+/// Upper bound on frames laid out per change, in case sizes never settle
+const MAX_LAYOUT_PASSES: usize = 10_000;
+
+/// Main axis of an auto layout frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// Placement of children on the cross axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutAlign {
+    Start,
+    Center,
+    End,
+    Stretch,
+}
+
+/// How a node is sized along one axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutSizing {
+    /// Keep the node's own Width/Height
+    Fixed,
+    /// Shrink-wrap the content (auto layout frames only)
+    Hug,
+    /// Take up the free space in the auto layout parent
+    Fill,
+}
+
+/// Resolved auto layout settings of a frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutSettings {
+    pub direction: LayoutDirection,
+    pub gap: f32,
+    /// Top, right, bottom, left
+    pub padding: [f32; 4],
+    pub align: LayoutAlign,
+}
+
+impl LayoutSettings {
+    /// Read the layout settings of a node, or `None` if it doesn't use auto layout
+    pub fn of(node: &Node) -> Option<Self> {
+        if string_property(node, Property::LayoutMode) != Some("auto") {
+            return None;
+        }
+
+        let direction = match string_property(node, Property::LayoutDirection) {
+            Some("vertical") => LayoutDirection::Vertical,
+            _ => LayoutDirection::Horizontal,
+        };
+        let gap = match node.get_property(Property::LayoutGap) {
+            Some(PropertyValue::Float(v)) => *v,
+            _ => 0.0,
+        };
+        let padding = match node.get_property(Property::LayoutPadding) {
+            Some(PropertyValue::Float(v)) => [*v; 4],
+            Some(PropertyValue::Vec2(vertical, horizontal)) => [*vertical, *horizontal, *vertical, *horizontal],
+            Some(PropertyValue::Vec4(top, right, bottom, left)) => [*top, *right, *bottom, *left],
+            _ => [0.0; 4],
+        };
+        let align = match string_property(node, Property::LayoutAlign) {
+            Some("center") => LayoutAlign::Center,
+            Some("end") => LayoutAlign::End,
+            Some("stretch") => LayoutAlign::Stretch,
+            _ => LayoutAlign::Start,
+        };
+
+        Some(Self { direction, gap, padding, align })
+    }
+}
+
+/// How a node is sized horizontally and vertically
+pub fn layout_sizing(node: &Node) -> (LayoutSizing, LayoutSizing) {
+    let parse = |property| match string_property(node, property) {
+        Some("hug") => LayoutSizing::Hug,
+        Some("fill") => LayoutSizing::Fill,
+        _ => LayoutSizing::Fixed,
+    };
+    (parse(Property::LayoutSizingHorizontal), parse(Property::LayoutSizingVertical))
+}
+
+/// A child's size and sizing modes, as seen by the layout pass
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutItem {
+    pub width: f32,
+    pub height: f32,
+    pub sizing: (LayoutSizing, LayoutSizing),
+}
+
+/// Output of a layout pass: frame size and child rectangles (x, y, width, height)
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutResult {
+    pub width: f32,
+    pub height: f32,
+    pub children: Vec<[f32; 4]>,
+}
+
+/// Lay out `items` inside a frame.
+///
+/// `size` is the frame's current size and `sizing` its own sizing modes;
+/// hugging axes are replaced by the content size.
+pub fn compute_layout(
+    settings: &LayoutSettings,
+    size: (f32, f32),
+    sizing: (LayoutSizing, LayoutSizing),
+    items: &[LayoutItem],
+) -> LayoutResult {
+    let horizontal = settings.direction == LayoutDirection::Horizontal;
+    let [top, right, bottom, left] = settings.padding;
+
+    // Work in main/cross coordinates so both directions share one code path
+    let (main_size, cross_size) = if horizontal { size } else { (size.1, size.0) };
+    let (main_sizing, cross_sizing) = if horizontal { sizing } else { (sizing.1, sizing.0) };
+    let (pad_main_start, pad_main_end, pad_cross_start, pad_cross_end) = if horizontal {
+        (left, right, top, bottom)
+    } else {
+        (top, bottom, left, right)
+    };
+    let axes = |item: &LayoutItem| {
+        if horizontal {
+            (item.width, item.height, item.sizing.0, item.sizing.1)
+        } else {
+            (item.height, item.width, item.sizing.1, item.sizing.0)
+        }
+    };
+
+    let gaps = settings.gap * items.len().saturating_sub(1) as f32;
+    let main_hugs = main_sizing == LayoutSizing::Hug;
+    let cross_hugs = cross_sizing == LayoutSizing::Hug;
+
+    // Fill children only make sense when the frame has a size to share out;
+    // in a hugging frame they keep their current size
+    let fixed_main: f32 = items
+        .iter()
+        .map(axes)
+        .filter(|&(_, _, main, _)| main_hugs || main != LayoutSizing::Fill)
+        .map(|(m, ..)| m)
+        .sum();
+    let fill_count = if main_hugs {
+        0
+    } else {
+        items.iter().map(axes).filter(|&(_, _, main, _)| main == LayoutSizing::Fill).count()
+    };
+
+    let main_size = if main_hugs {
+        pad_main_start + fixed_main + gaps + pad_main_end
+    } else {
+        main_size
+    };
+    let inner_main = main_size - pad_main_start - pad_main_end;
+    let fill_size = if fill_count > 0 {
+        ((inner_main - fixed_main - gaps) / fill_count as f32).max(0.0)
+    } else {
+        0.0
+    };
+
+    let content_cross = items
+        .iter()
+        .map(axes)
+        .filter(|&(_, _, _, cross)| cross_hugs || cross != LayoutSizing::Fill)
+        .map(|(_, c, ..)| c)
+        .fold(0.0, f32::max);
+    let cross_size = if cross_hugs {
+        pad_cross_start + content_cross + pad_cross_end
+    } else {
+        cross_size
+    };
+    let inner_cross = cross_size - pad_cross_start - pad_cross_end;
+
+    let mut cursor = pad_main_start;
+    let children = items
+        .iter()
+        .map(|item| {
+            let (main, cross, main_mode, cross_mode) = axes(item);
+            let main = if fill_count > 0 && main_mode == LayoutSizing::Fill { fill_size } else { main };
+            let stretch = settings.align == LayoutAlign::Stretch || cross_mode == LayoutSizing::Fill;
+            let cross = if stretch { inner_cross.max(0.0) } else { cross };
+
+            let cross_offset = match settings.align {
+                LayoutAlign::Start | LayoutAlign::Stretch => 0.0,
+                LayoutAlign::Center => (inner_cross - cross) / 2.0,
+                LayoutAlign::End => inner_cross - cross,
+            };
+            let main_pos = cursor;
+            let cross_pos = pad_cross_start + cross_offset;
+            cursor += main + settings.gap;
+
+            if horizontal {
+                [main_pos, cross_pos, main, cross]
+            } else {
+                [cross_pos, main_pos, cross, main]
+            }
+        })
+        .collect();
+
+    let (width, height) = if horizontal { (main_size, cross_size) } else { (cross_size, main_size) };
+    LayoutResult { width, height, children }
+}
+
+/// Frames waiting to be laid out after a change
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LayoutQueue {
+    pending: Vec<ObjectId>,
+    running: bool,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Turn on auto layout for a frame.
+    ///
+    /// `direction` is `"horizontal"` or `"vertical"`; `padding` applies to all sides.
+    pub fn set_auto_layout(&mut self, id: ObjectId, direction: &str, gap: f32, padding: f32) {
+        self.history.begin_group();
+        self.set_node_property(id, Property::LayoutDirection, PropertyValue::String(direction.into()));
+        self.set_node_property(id, Property::LayoutGap, PropertyValue::Float(gap));
+        self.set_node_property(id, Property::LayoutPadding, PropertyValue::Float(padding));
+        self.set_node_property(id, Property::LayoutMode, PropertyValue::String("auto".into()));
+        self.history.end_group();
+    }
+
+    /// Turn off auto layout. Children keep their current positions.
+    pub fn remove_auto_layout(&mut self, id: ObjectId) {
+        self.set_node_property(id, Property::LayoutMode, PropertyValue::String("none".into()));
+    }
+
+    /// Set cross axis alignment: `"start"`, `"center"`, `"end"` or `"stretch"`
+    pub fn set_layout_align(&mut self, id: ObjectId, align: &str) {
+        self.set_node_property(id, Property::LayoutAlign, PropertyValue::String(align.into()));
+    }
+
+    /// Set how a node is sized on each axis: `"fixed"`, `"hug"` or `"fill"`
+    pub fn set_layout_sizing(&mut self, id: ObjectId, horizontal: &str, vertical: &str) {
+        self.history.begin_group();
+        self.set_node_property(id, Property::LayoutSizingHorizontal, PropertyValue::String(horizontal.into()));
+        self.set_node_property(id, Property::LayoutSizingVertical, PropertyValue::String(vertical.into()));
+        self.history.end_group();
+    }
+
+    /// Re-run auto layout on a frame and everything it affects
+    pub fn relayout(&mut self, id: ObjectId) {
+        self.layout_queue.pending.push(id);
+        self.history.begin_group();
+        self.run_layout();
+        self.history.end_group();
+    }
+}
+
+impl Document {
+    /// Queue the frames affected by a change made through `apply()`
+    pub(crate) fn invalidate_layout(&mut self, inverse: &Operation) {
+        match inverse {
+            Operation::SetProperty { id, property, .. } => {
+                if is_layout_property(*property) {
+                    self.queue_layout(Some(*id));
+                }
+                if affects_parent_layout(*property) {
+                    self.queue_layout(self.tree.parent(*id));
+                }
+                match property {
+                    Property::VariableValues => self.queue_consumer_layout(*id),
+                    Property::VariableModes => self.queue_subtree_layout(*id),
+                    _ => {}
+                }
+            }
+            // Inserted: lay out the new parent and the node itself
+            Operation::Remove { id } => {
+                self.queue_layout(self.tree.parent(*id));
+                self.queue_layout(Some(*id));
+            }
+            // Removed: lay out the former parent
+            Operation::Insert(subtree) => self.queue_layout(subtree.parent()),
+            Operation::Move { id, parent, .. } => {
+                self.queue_layout(Some(*parent));
+                self.queue_layout(self.tree.parent(*id));
+            }
+            Operation::SetOverride { .. } => {}
+        }
+    }
+
+    /// Lay out every queued frame until the queue is empty
+    pub(crate) fn run_layout(&mut self) {
+        if self.layout_queue.running {
+            return;
+        }
+        self.layout_queue.running = true;
+
+        let mut passes = 0;
+        while let Some(id) = self.layout_queue.pending.pop() {
+            passes += 1;
+            if passes > MAX_LAYOUT_PASSES {
+                self.layout_queue.pending.clear();
+                break;
+            }
+            self.layout_frame(id);
+        }
+
+        self.layout_queue.running = false;
+    }
+
+    fn queue_layout(&mut self, id: Option<ObjectId>) {
+        let Some(id) = id else { return };
+        let uses_layout = self.tree.get(id).and_then(|node| LayoutSettings::of(&self.bound_node(node))).is_some();
+        if uses_layout && !self.layout_queue.pending.contains(&id) {
+            self.layout_queue.pending.push(id);
+        }
+    }
+
+    /// Queue the frames around every node bound to a variable whose values
+    /// just changed
+    fn queue_consumer_layout(&mut self, variable_id: ObjectId) {
+        if self.tree.get(variable_id).map(Node::node_type) != Some(NodeType::Variable) {
+            return;
+        }
+        for consumer in self.get_variable_consumers(variable_id) {
+            self.queue_layout(Some(consumer));
+            self.queue_layout(self.tree.parent(consumer));
+        }
+    }
+
+    /// Queue every frame in a subtree whose variable mode just changed, and
+    /// the parent of its root
+    fn queue_subtree_layout(&mut self, id: ObjectId) {
+        self.queue_layout(self.tree.parent(id));
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            self.queue_layout(Some(node_id));
+            stack.extend(self.tree.children(node_id));
+        }
+    }
+
+    /// Apply auto layout to one frame's children (and its own hug size)
+    fn layout_frame(&mut self, id: ObjectId) {
+        let Some(frame) = self.tree.get(id).map(|node| self.bound_node(node)) else { return };
+        let Some(settings) = LayoutSettings::of(&frame) else { return };
+        let size = (frame.width(), frame.height());
+        let sizing = layout_sizing(&frame);
+
+        let children: Vec<ObjectId> = self
+            .tree
+            .children(id)
+            .into_iter()
+            .filter(|&child| self.tree.get(child).is_some_and(|n| self.bound_node(n).visible()))
+            .collect();
+        let items: Vec<LayoutItem> = children
+            .iter()
+            .filter_map(|&child| self.tree.get(child))
+            .map(|node| {
+                let node = self.bound_node(node);
+                LayoutItem {
+                    width: node.width(),
+                    height: node.height(),
+                    sizing: layout_sizing(&node),
+                }
+            })
+            .collect();
+
+        let result = compute_layout(&settings, size, sizing, &items);
+
+        self.set_if_changed(id, Property::Width, result.width);
+        self.set_if_changed(id, Property::Height, result.height);
+        for (child, [x, y, width, height]) in children.into_iter().zip(result.children) {
+            self.set_if_changed(child, Property::X, x);
+            self.set_if_changed(child, Property::Y, y);
+            self.set_if_changed(child, Property::Width, width);
+            self.set_if_changed(child, Property::Height, height);
+        }
+    }
+
+    /// Write a float property only if it differs from the (resolved) current
+    /// value, so repeated passes settle and matching bindings are kept
+    pub(crate) fn set_if_changed(&mut self, id: ObjectId, property: Property, value: f32) {
+        let value = PropertyValue::Float(value);
+        let current = self.tree.get(id).and_then(|n| self.bound_node(n).get_property(property).cloned());
+        if current.as_ref() != Some(&value) {
+            self.apply(Operation::SetProperty { id, property, value: Some(value) });
+        }
+    }
+}
+
+fn string_property(node: &Node, property: Property) -> Option<&str> {
+    match node.get_property(property) {
+        Some(PropertyValue::String(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+/// Properties of a frame that change how its children are laid out
+fn is_layout_property(property: Property) -> bool {
+    matches!(
+        property,
+        Property::LayoutMode
+            | Property::LayoutDirection
+            | Property::LayoutGap
+            | Property::LayoutPadding
+            | Property::LayoutAlign
+            | Property::LayoutSizingHorizontal
+            | Property::LayoutSizingVertical
+            | Property::Width
+            | Property::Height
+    )
+}
+
+/// Properties of a child that change its parent's layout
+fn affects_parent_layout(property: Property) -> bool {
+    matches!(
+        property,
+        Property::X
+            | Property::Y
+            | Property::Width
+            | Property::Height
+            | Property::Visible
+            | Property::LayoutSizingHorizontal
+            | Property::LayoutSizingVertical
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(width: f32, height: f32, sizing: (LayoutSizing, LayoutSizing)) -> LayoutItem {
+        LayoutItem { width, height, sizing }
+    }
+
+    #[test]
+    fn test_horizontal_hug_layout() {
+        let settings = LayoutSettings {
+            direction: LayoutDirection::Horizontal,
+            gap: 8.0,
+            padding: [10.0, 20.0, 10.0, 20.0],
+            align: LayoutAlign::Center,
+        };
+        let fixed = (LayoutSizing::Fixed, LayoutSizing::Fixed);
+        let items = [item(50.0, 20.0, fixed), item(30.0, 40.0, fixed)];
+
+        let result = compute_layout(&settings, (0.0, 0.0), (LayoutSizing::Hug, LayoutSizing::Hug), &items);
+        assert_eq!(result.width, 20.0 + 50.0 + 8.0 + 30.0 + 20.0);
+        assert_eq!(result.height, 10.0 + 40.0 + 10.0);
+        assert_eq!(result.children[0], [20.0, 20.0, 50.0, 20.0]);
+        assert_eq!(result.children[1], [78.0, 10.0, 30.0, 40.0]);
+    }
+
+    #[test]
+    fn test_vertical_fill_layout() {
+        let settings = LayoutSettings {
+            direction: LayoutDirection::Vertical,
+            gap: 10.0,
+            padding: [0.0; 4],
+            align: LayoutAlign::Stretch,
+        };
+        let fixed = (LayoutSizing::Fixed, LayoutSizing::Fixed);
+        let fill = (LayoutSizing::Fixed, LayoutSizing::Fill);
+        let items = [item(10.0, 20.0, fixed), item(10.0, 0.0, fill), item(10.0, 0.0, fill)];
+
+        let result = compute_layout(&settings, (100.0, 200.0), fixed, &items);
+        assert_eq!(result.children[0], [0.0, 0.0, 100.0, 20.0]);
+        assert_eq!(result.children[1], [0.0, 30.0, 100.0, 80.0]);
+        assert_eq!(result.children[2], [0.0, 120.0, 100.0, 80.0]);
+    }
+
+    #[test]
+    fn test_document_relayout_on_change() {
+        let mut doc = Document::new("Layout");
+        let frame_id = doc.create_frame(0.0, 0.0, 10.0, 10.0);
+        doc.set_auto_layout(frame_id, "horizontal", 5.0, 0.0);
+        doc.set_layout_sizing(frame_id, "hug", "fixed");
+
+        let a = doc.create_rectangle(frame_id, 0.0, 0.0, 40.0, 10.0);
+        let b = doc.create_rectangle(frame_id, 0.0, 0.0, 60.0, 10.0);
+        assert_eq!(doc.get_node_x(b), 45.0);
+        assert_eq!(doc.get_node_width(frame_id), 105.0);
+
+        doc.set_node_width(a, 20.0);
+        assert_eq!(doc.get_node_x(b), 25.0);
+        assert_eq!(doc.get_node_width(frame_id), 85.0);
+
+        // The resize and the relayout it caused undo together
+        assert!(doc.undo());
+        assert_eq!(doc.get_node_x(b), 45.0);
+        assert_eq!(doc.get_node_width(frame_id), 105.0);
+    }
+
+    #[test]
+    fn test_layout_uses_bound_values() {
+        let mut doc = Document::new("Layout");
+        let collection = doc.create_variable_collection("Spacing");
+        let compact = doc.get_variable_modes(collection)[0];
+        let roomy = doc.add_variable_mode(collection, "Roomy").unwrap();
+        let size = doc.create_variable(collection, "number", "size").unwrap();
+        doc.set_variable_number(size, compact, 40.0);
+        doc.set_variable_number(size, roomy, 80.0);
+
+        let frame_id = doc.create_frame(0.0, 0.0, 10.0, 10.0);
+        doc.set_auto_layout(frame_id, "horizontal", 5.0, 0.0);
+        doc.set_layout_sizing(frame_id, "hug", "fixed");
+        let a = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        let b = doc.create_rectangle(frame_id, 0.0, 0.0, 60.0, 10.0);
+        assert!(doc.bind_variable(a, Property::Width, size));
+        assert!(doc.bind_variable(frame_id, Property::LayoutGap, size));
+        assert_eq!(doc.get_node_x(b), 80.0);
+        assert_eq!(doc.get_node_width(frame_id), 140.0);
+
+        // Switching mode and editing values re-run layout; the binding stays
+        assert!(doc.set_variable_mode(frame_id, collection, roomy));
+        assert_eq!(doc.get_node_x(b), 160.0);
+        doc.set_variable_number(size, roomy, 20.0);
+        assert_eq!(doc.get_node_x(b), 40.0);
+        assert_eq!(doc.get_node_width(frame_id), 100.0);
+        assert_eq!(doc.get_bound_variable(a, Property::Width), Some(size));
+    }
+}
@@ -0,0 +1,149 @@
+//! Clipping and masks
+//!
+//! Two things limit where a node's content shows:
+//!
+//! - A frame (or component or instance) with `ClipContent` set clips its
+//!   children to its bounds, rounded by its corner radius and turned with it.
+//! - A node with `IsMask` set isn't drawn. Instead it masks the siblings
+//!   drawn after it, up to the next mask in the same parent:
+//!
+//! ```text
+//! Group
+//! ├── Photo          not masked
+//! ├── Circle (mask)  ┐
+//! ├── Photo          │ masked by Circle
+//! ├── Label          ┘
+//! ├── Star (mask)    ┐ masked by Star
+//! └── Badge          ┘
+//! ```
+//!
+//! An alpha mask shows its siblings where its own pixels (fills, children,
+//! opacity) are opaque, fading them where it is translucent. A vector mask
+//! only uses its outline, as a hard clip, whatever its fills are. Hiding a
+//! mask shows the siblings it masks unmasked.
+//!
+//! Hit testing follows the same rules, so clipped away parts of a node
+//! can't be clicked.
+
+use super::{Document, NodeType, ObjectId, Property, PropertyValue};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// How a mask clips the siblings after it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaskType {
+    /// By the opacity of the mask's pixels
+    #[default]
+    Alpha,
+    /// By the mask's outline
+    Vector,
+}
+
+impl MaskType {
+    pub fn parse(mask_type: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::from(mask_type)).ok()
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    pub fn get_clip_content(&self, id: ObjectId) -> bool {
+        self.tree.get(id).is_some_and(|node| self.bound_node(node).clips_content())
+    }
+
+    /// Clip a frame's children to its bounds. Returns `false` for nodes
+    /// other than frames, components and instances.
+    pub fn set_clip_content(&mut self, id: ObjectId, clip: bool) -> bool {
+        let frame_like = self
+            .tree
+            .get(id)
+            .is_some_and(|node| matches!(node.node_type(), NodeType::Frame | NodeType::Component | NodeType::Instance));
+        if !frame_like {
+            return false;
+        }
+        self.set_node_property(id, Property::ClipContent, PropertyValue::Bool(clip));
+        true
+    }
+
+    pub fn is_mask(&self, id: ObjectId) -> bool {
+        self.tree.get(id).is_some_and(|node| self.bound_node(node).is_mask())
+    }
+
+    /// Turn a node into a mask for the siblings after it, or back
+    pub fn set_mask(&mut self, id: ObjectId, is_mask: bool) -> bool {
+        let canvas = self.tree.get(id).is_some_and(|node| node.node_type().is_canvas());
+        if !canvas {
+            return false;
+        }
+        self.set_node_property(id, Property::IsMask, PropertyValue::Bool(is_mask));
+        true
+    }
+
+    /// "alpha" or "vector"
+    pub fn get_mask_type(&self, id: ObjectId) -> String {
+        let mask_type = self.tree.get(id).map(|node| self.bound_node(node).mask_type()).unwrap_or_default();
+        serde_json::to_value(mask_type).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default()
+    }
+
+    pub fn set_mask_type(&mut self, id: ObjectId, mask_type: &str) -> bool {
+        if MaskType::parse(mask_type).is_none() || !self.has_node(id) {
+            return false;
+        }
+        self.set_node_property(id, Property::MaskType, PropertyValue::String(mask_type.into()));
+        true
+    }
+
+    /// The mask that masks a node: the nearest mask among the siblings
+    /// before it, unless that mask is hidden. Masks themselves aren't
+    /// masked.
+    pub fn get_mask(&self, id: ObjectId) -> Option<ObjectId> {
+        if self.is_mask(id) {
+            return None;
+        }
+        let siblings = self.tree.children(self.tree.parent(id)?);
+        let index = siblings.iter().position(|&sibling| sibling == id)?;
+        let mask = siblings[..index].iter().rev().copied().find(|&sibling| self.is_mask(sibling))?;
+        self.tree.get(mask).is_some_and(|node| self.bound_node(node).visible()).then_some(mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_apply_to_following_siblings() {
+        let mut doc = Document::new("Masks");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let below = doc.create_rectangle(frame_id, 0.0, 0.0, 50.0, 50.0);
+        let circle = doc.create_ellipse(frame_id, 0.0, 0.0, 50.0, 50.0);
+        let photo = doc.create_rectangle(frame_id, 0.0, 0.0, 50.0, 50.0);
+        let star = doc.create_rectangle(frame_id, 0.0, 0.0, 50.0, 50.0);
+        let badge = doc.create_rectangle(frame_id, 0.0, 0.0, 50.0, 50.0);
+
+        assert!(doc.set_mask(circle, true));
+        assert!(doc.set_mask(star, true));
+        assert_eq!(doc.get_mask(below), None);
+        assert_eq!(doc.get_mask(photo), Some(circle));
+        assert_eq!(doc.get_mask(star), None);
+        assert_eq!(doc.get_mask(badge), Some(star));
+
+        // Hiding a mask unmasks its siblings, without handing them to the
+        // mask before it
+        doc.set_node_property(star, Property::Visible, PropertyValue::Bool(false));
+        assert_eq!(doc.get_mask(badge), None);
+        assert_eq!(doc.get_mask(photo), Some(circle));
+
+        assert_eq!(doc.get_mask_type(circle), "alpha");
+        assert!(doc.set_mask_type(circle, "vector"));
+        assert!(!doc.set_mask_type(circle, "luminance"));
+        assert_eq!(doc.get_mask_type(circle), "vector");
+
+        // Only frame-like nodes clip their content
+        assert!(!doc.get_clip_content(frame_id));
+        assert!(doc.set_clip_content(frame_id, true));
+        assert!(doc.get_clip_content(frame_id));
+        assert!(!doc.set_clip_content(photo, true));
+    }
+}
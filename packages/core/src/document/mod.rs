@@ -3,21 +3,72 @@
 //! Represents the document as a tree of nodes with properties.
 //! Inspired by Figma's approach: Map<ObjectID, Map<Property, Value>>
 
+mod binary;
+mod boolean;
+mod clipboard;
+mod component;
+mod constraints;
+mod effect;
+mod events;
+mod format;
+mod fractional;
+mod group;
+mod history;
+mod hit_test;
+mod image;
+mod layout;
+mod mask;
 mod node;
+mod page;
+mod paint;
 mod properties;
+mod query;
+mod spatial;
+mod style;
+mod text;
+mod transform;
 mod tree;
-
+mod variable;
+
+pub use binary::*;
+pub use constraints::*;
+pub use effect::*;
+pub use events::*;
+pub use format::*;
+pub use fractional::*;
+pub use history::*;
+pub use image::*;
+pub use layout::*;
+pub use mask::*;
 pub use node::*;
+pub use paint::*;
 pub use properties::*;
+pub use query::*;
+pub use spatial::*;
+pub use style::*;
+pub use text::*;
+pub use transform::*;
 pub use tree::*;
+pub use variable::*;
+
+use boolean::BooleanQueue;
+use component::InstanceIndex;
+use events::ChangeLog;
+use group::GroupQueue;
+use image::ImageStore;
+use text::{TextLayoutCache, TextQueue};
 
+use crate::text::FontLibrary;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use wasm_bindgen::prelude::*;
 
-/// Unique identifier for objects in the document
+/// Unique identifier for objects in the document.
+///
+/// IDs are ordered by client and then sequence; siblings that share an order
+/// index are drawn in ID order so every client agrees on the result.
 #[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ObjectId {
     client_id: u32,
     sequence: u32,
@@ -48,6 +99,7 @@ impl ObjectId {
         self.sequence
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         format!("{}:{}", self.client_id, self.sequence)
     }
@@ -65,6 +117,24 @@ pub struct Document {
     tree: DocumentTree,
     name: String,
     version: u32,
+    history: History,
+    /// The instances of each component
+    instances: InstanceIndex,
+    layout_queue: LayoutQueue,
+    boolean_queue: BooleanQueue,
+    group_queue: GroupQueue,
+    transforms: TransformCache,
+    spatial: SpatialCache,
+    /// The page being edited (see `get_active_page`)
+    active_page: Option<ObjectId>,
+    /// Change events waiting for the UI (see `take_change_events`)
+    changes: ChangeLog,
+    /// Image files used by image paints, by content hash
+    images: ImageStore,
+    /// Fonts added at runtime for laying out text (not saved)
+    fonts: FontLibrary,
+    text_queue: TextQueue,
+    text_layouts: TextLayoutCache,
 }
 
 #[wasm_bindgen]
@@ -72,25 +142,19 @@ impl Document {
     /// Create a new empty document
     #[wasm_bindgen(constructor)]
     pub fn new(name: &str) -> Self {
-        let mut tree = DocumentTree::new();
-        
         // Create root node
         let root_id = ObjectId::random();
-        let root = Node::new(root_id, NodeType::Document);
-        tree.insert(root);
+        let mut tree = DocumentTree::with_root(Node::new(root_id, NodeType::Document));
         
         // Create first page
         let page_id = ObjectId::random();
         let mut page = Node::new(page_id, NodeType::Page);
         page.set_property(Property::Name, PropertyValue::String("Page 1".into()));
+        page.set_order_index(key_between(None, None));
         tree.insert(page);
-        tree.set_parent(page_id, root_id);
+        tree.set_parent(page_id, root_id).expect("pages go under the document");
         
-        Self {
-            tree,
-            name: name.to_string(),
-            version: 1,
-        }
+        Self::from_tree(tree, name.to_string(), 1)
     }
 
     pub fn name(&self) -> String {
@@ -110,26 +174,10 @@ impl Document {
         self.tree.root_id()
     }
 
-    /// Create a new frame on the first page
+    /// Create a new frame on the active page
     pub fn create_frame(&mut self, x: f32, y: f32, width: f32, height: f32) -> ObjectId {
-        let frame_id = ObjectId::random();
-        let mut frame = Node::new(frame_id, NodeType::Frame);
-        
-        frame.set_property(Property::X, PropertyValue::Float(x));
-        frame.set_property(Property::Y, PropertyValue::Float(y));
-        frame.set_property(Property::Width, PropertyValue::Float(width));
-        frame.set_property(Property::Height, PropertyValue::Float(height));
-        frame.set_property(Property::Name, PropertyValue::String("Frame".into()));
-        
-        self.tree.insert(frame);
-        
-        // Get first page and parent to it
-        if let Some(first_page) = self.tree.first_page() {
-            self.tree.set_parent(frame_id, first_page);
-        }
-        
-        self.version += 1;
-        frame_id
+        let page_id = self.get_active_page();
+        self.insert_frame(page_id, x, y, width, height)
     }
 
     /// Create a rectangle
@@ -143,10 +191,7 @@ impl Document {
         rect.set_property(Property::Height, PropertyValue::Float(height));
         rect.set_property(Property::FillColor, PropertyValue::Color(Color::new(0.8, 0.8, 0.8, 1.0)));
         
-        self.tree.insert(rect);
-        self.tree.set_parent(rect_id, parent_id);
-        
-        self.version += 1;
+        self.apply(Operation::Insert(Subtree::leaf(rect, Some(parent_id))));
         rect_id
     }
 
@@ -161,10 +206,7 @@ impl Document {
         ellipse.set_property(Property::Height, PropertyValue::Float(height));
         ellipse.set_property(Property::FillColor, PropertyValue::Color(Color::new(0.6, 0.6, 0.9, 1.0)));
         
-        self.tree.insert(ellipse);
-        self.tree.set_parent(ellipse_id, parent_id);
-        
-        self.version += 1;
+        self.apply(Operation::Insert(Subtree::leaf(ellipse, Some(parent_id))));
         ellipse_id
     }
 
@@ -179,10 +221,7 @@ impl Document {
         text.set_property(Property::FontSize, PropertyValue::Float(16.0));
         text.set_property(Property::FillColor, PropertyValue::Color(Color::new(0.0, 0.0, 0.0, 1.0)));
         
-        self.tree.insert(text);
-        self.tree.set_parent(text_id, parent_id);
-        
-        self.version += 1;
+        self.apply(Operation::Insert(Subtree::leaf(text, Some(parent_id))));
         text_id
     }
 
@@ -198,68 +237,70 @@ impl Document {
 
     /// Get node X position
     pub fn get_node_x(&self, id: ObjectId) -> f32 {
-        self.tree.get(id).map(|n| n.x()).unwrap_or(0.0)
+        self.tree.get(id).map(|n| self.bound_node(n).x()).unwrap_or(0.0)
     }
 
     /// Get node Y position
     pub fn get_node_y(&self, id: ObjectId) -> f32 {
-        self.tree.get(id).map(|n| n.y()).unwrap_or(0.0)
+        self.tree.get(id).map(|n| self.bound_node(n).y()).unwrap_or(0.0)
     }
 
     /// Get node width
     pub fn get_node_width(&self, id: ObjectId) -> f32 {
-        self.tree.get(id).map(|n| n.width()).unwrap_or(0.0)
+        self.tree.get(id).map(|n| self.bound_node(n).width()).unwrap_or(0.0)
     }
 
     /// Get node height
     pub fn get_node_height(&self, id: ObjectId) -> f32 {
-        self.tree.get(id).map(|n| n.height()).unwrap_or(0.0)
+        self.tree.get(id).map(|n| self.bound_node(n).height()).unwrap_or(0.0)
+    }
+
+    /// Get node opacity
+    pub fn get_node_opacity(&self, id: ObjectId) -> f32 {
+        self.tree.get(id).map(|n| self.bound_node(n).opacity()).unwrap_or(1.0)
     }
 
     /// Set node X position
     pub fn set_node_x(&mut self, id: ObjectId, x: f32) {
-        if let Some(node) = self.tree.get_mut(id) {
-            node.set_property(Property::X, PropertyValue::Float(x));
-            self.version += 1;
-        }
+        self.set_node_property(id, Property::X, PropertyValue::Float(x));
     }
 
     /// Set node Y position
     pub fn set_node_y(&mut self, id: ObjectId, y: f32) {
-        if let Some(node) = self.tree.get_mut(id) {
-            node.set_property(Property::Y, PropertyValue::Float(y));
-            self.version += 1;
-        }
+        self.set_node_property(id, Property::Y, PropertyValue::Float(y));
     }
 
     /// Set node width
     pub fn set_node_width(&mut self, id: ObjectId, width: f32) {
-        if let Some(node) = self.tree.get_mut(id) {
-            node.set_property(Property::Width, PropertyValue::Float(width));
-            self.version += 1;
-        }
+        self.set_node_property(id, Property::Width, PropertyValue::Float(width));
     }
 
     /// Set node height
     pub fn set_node_height(&mut self, id: ObjectId, height: f32) {
-        if let Some(node) = self.tree.get_mut(id) {
-            node.set_property(Property::Height, PropertyValue::Float(height));
-            self.version += 1;
-        }
+        self.set_node_property(id, Property::Height, PropertyValue::Float(height));
     }
 
-    /// Update a node property (internal use)
+    /// Set node opacity (clamped to 0..1)
+    pub fn set_node_opacity(&mut self, id: ObjectId, opacity: f32) {
+        self.set_node_property(id, Property::Opacity, PropertyValue::Float(opacity.clamp(0.0, 1.0)));
+    }
+
+    /// Update a node property (internal use).
+    ///
+    /// Setting a property that comes from a shared style detaches the node
+    /// from that style first.
     pub(crate) fn set_node_property(&mut self, id: ObjectId, property: Property, value: PropertyValue) {
-        if let Some(node) = self.tree.get_mut(id) {
-            node.set_property(property, value);
-            self.version += 1;
+        self.history.begin_group();
+        self.detach_style_for(id, property);
+        if self.apply(Operation::SetProperty { id, property, value: Some(value) }) {
+            self.mark_override(id, property);
         }
+        self.history.end_group();
     }
 
     /// Delete a node
     pub fn delete_node(&mut self, id: ObjectId) {
-        self.tree.remove(id);
-        self.version += 1;
+        self.apply(Operation::Remove { id });
     }
 
     /// Get children of a node
@@ -267,8 +308,342 @@ impl Document {
         self.tree.children(parent_id)
     }
 
-    /// Serialize document to JSON
+    /// Get the parent of a node
+    pub fn get_parent(&self, id: ObjectId) -> Option<ObjectId> {
+        self.tree.parent(id)
+    }
+
+    /// Move a node under `parent_id`, at `index` among its children (clamped
+    /// to the end).
+    ///
+    /// Returns `false` if either node is missing, `parent_id` is inside the
+    /// moved node, or `parent_id` can't hold it (see `NodeType::can_contain`).
+    pub fn move_node(&mut self, id: ObjectId, parent_id: ObjectId, index: usize) -> bool {
+        if self.tree.check_reparent(id, parent_id).is_err() {
+            return false;
+        }
+        let order_index = self.tree.order_index_at(parent_id, index, id);
+        self.apply(Operation::Move { id, parent: parent_id, position: index, order_index })
+    }
+
+    /// Serialize document to JSON.
+    ///
+    /// The output includes the document name, version and schema version and
+    /// can be loaded back with `Document.from_json()`.
     pub fn to_json(&self) -> String {
-        serde_json::to_string(&self.tree).unwrap_or_default()
+        self.encode_json().unwrap_or_default()
+    }
+
+    /// Load a document saved with `to_json()`.
+    ///
+    /// Files from older schema versions are migrated automatically. Malformed
+    /// files throw an error describing what is wrong.
+    pub fn from_json(json: &str) -> Result<Document, JsValue> {
+        Ok(Self::decode_json(json)?)
+    }
+
+    /// Serialize document to the compact binary format.
+    ///
+    /// Much smaller and faster to load than `to_json()` for large files.
+    /// Returned to JS as a `Uint8Array`. Throws if the document can't be
+    /// stored in this format (nodes nested too deeply).
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        Ok(self.encode_binary()?)
+    }
+
+    /// Load a document saved with `to_bytes()`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Document, JsValue> {
+        Ok(Self::decode_binary(bytes)?)
+    }
+
+    /// Undo the last change (or group of changes).
+    ///
+    /// Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let mut history = std::mem::take(&mut self.history);
+        let undone = history.undo(|op| self.apply_untracked(op));
+        self.history = history;
+        undone
+    }
+
+    /// Redo the last undone change.
+    ///
+    /// Returns `false` if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let mut history = std::mem::take(&mut self.history);
+        let redone = history.redo(|op| self.apply_untracked(op));
+        self.history = history;
+        redone
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Start grouping changes into a single undo step.
+    ///
+    /// Call this on pointer down and `end_undo_group()` on pointer up so a
+    /// whole drag undoes in one step. Groups may nest.
+    pub fn begin_undo_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    /// Finish the undo group started by `begin_undo_group()`
+    pub fn end_undo_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Forget all undo and redo steps
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+}
+
+impl Document {
+    /// Wrap an already-built tree (used by the file loaders)
+    pub(crate) fn from_tree(tree: DocumentTree, name: String, version: u32) -> Self {
+        let instances = InstanceIndex::build(&tree);
+        Self {
+            tree,
+            name,
+            version,
+            history: History::new(),
+            instances,
+            layout_queue: LayoutQueue::default(),
+            boolean_queue: BooleanQueue::default(),
+            group_queue: GroupQueue::default(),
+            transforms: TransformCache::default(),
+            spatial: SpatialCache::default(),
+            active_page: None,
+            changes: ChangeLog::default(),
+            images: ImageStore::default(),
+            fonts: FontLibrary::default(),
+            text_queue: TextQueue::default(),
+            text_layouts: TextLayoutCache::default(),
+        }
+    }
+
+    /// Create a frame under `parent` (normally a page)
+    pub(crate) fn insert_frame(
+        &mut self,
+        parent: Option<ObjectId>,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> ObjectId {
+        let frame_id = ObjectId::random();
+        let mut frame = Node::new(frame_id, NodeType::Frame);
+        
+        frame.set_property(Property::X, PropertyValue::Float(x));
+        frame.set_property(Property::Y, PropertyValue::Float(y));
+        frame.set_property(Property::Width, PropertyValue::Float(width));
+        frame.set_property(Property::Height, PropertyValue::Float(height));
+        frame.set_property(Property::Name, PropertyValue::String("Frame".into()));
+        
+        self.apply(Operation::Insert(Subtree::leaf(frame, parent)));
+        frame_id
+    }
+
+    /// Apply an operation and record its inverse in the undo history.
+    ///
+    /// This is the single entry point for document mutations. Changes that
+    /// follow from the operation (updating component instances, text sizes,
+    /// resize constraints, auto layout, boolean results, group bounds) are
+    /// applied here too and recorded in the same undo step. Returns `false` (and
+    /// records nothing) if the operation had no effect.
+    ///
+    /// New nodes that don't have an order index yet get one here, so the
+    /// recorded operation (and anything sent to collaborators) carries it.
+    pub(crate) fn apply(&mut self, mut op: Operation) -> bool {
+        if let Operation::Insert(subtree) = &mut op {
+            self.tree.place_subtree(subtree);
+        }
+        let Some(inverse) = self.apply_untracked(op) else {
+            return false;
+        };
+
+        self.history.begin_group();
+        self.history.record(inverse.clone());
+        self.sync_instances(&inverse);
+        self.invalidate_text(&inverse);
+        self.run_text();
+        self.apply_constraints(&inverse);
+        self.invalidate_layout(&inverse);
+        self.run_layout();
+        self.invalidate_booleans(&inverse);
+        self.run_booleans();
+        self.invalidate_groups(&inverse);
+        self.run_groups();
+        self.history.end_group();
+        true
+    }
+
+    /// Apply a move made by a collaborator.
+    ///
+    /// The node goes wherever `order_index` sorts among the new siblings, so
+    /// every client ends up with the same order whatever order moves arrive in.
+    pub(crate) fn apply_remote_move(
+        &mut self,
+        id: ObjectId,
+        parent_id: ObjectId,
+        order_index: String,
+    ) -> Result<(), TreeError> {
+        if !is_valid_key(&order_index) {
+            return Err(TreeError::InvalidOrderIndex(id));
+        }
+        self.tree.check_reparent(id, parent_id)?;
+        self.untracked(|doc| doc.apply(Operation::Move { id, parent: parent_id, position: usize::MAX, order_index }));
+        Ok(())
+    }
+
+    /// Apply a property change made by a collaborator.
+    ///
+    /// The value is written as it is: detaching styles and marking overrides
+    /// are the local user's edits, and arrive as changes of their own.
+    pub(crate) fn apply_remote_property(&mut self, id: ObjectId, property: Property, value: PropertyValue) {
+        self.untracked(|doc| doc.apply(Operation::SetProperty { id, property, value: Some(value) }));
+    }
+
+    /// Apply a node created by a collaborator. The node starts out with no
+    /// properties; they follow as property changes.
+    pub(crate) fn apply_remote_create(
+        &mut self,
+        id: ObjectId,
+        node_type: NodeType,
+        parent_id: ObjectId,
+        order_index: String,
+    ) -> Result<(), TreeError> {
+        if !is_valid_key(&order_index) {
+            return Err(TreeError::InvalidOrderIndex(id));
+        }
+        let mut node = Node::new(id, node_type);
+        node.set_order_index(order_index);
+        let subtree = Subtree::leaf(node, Some(parent_id));
+        self.tree.check_subtree(&subtree)?;
+        self.untracked(|doc| doc.apply(Operation::Insert(subtree)));
+        Ok(())
+    }
+
+    /// Apply a deletion made by a collaborator. The root can't be deleted.
+    pub(crate) fn apply_remote_delete(&mut self, id: ObjectId) -> Result<(), TreeError> {
+        if id == self.tree.root_id() {
+            return Err(TreeError::RemoveRoot);
+        }
+        if !self.untracked(|doc| doc.apply(Operation::Remove { id })) {
+            return Err(TreeError::MissingNode(id));
+        }
+        Ok(())
+    }
+
+    /// Run changes that aren't the local user's actions (remote edits,
+    /// re-measuring after a font loads) without recording them for undo.
+    /// Follow-up changes still run, they just aren't recorded either.
+    pub(crate) fn untracked<R>(&mut self, change: impl FnOnce(&mut Self) -> R) -> R {
+        self.history.pause();
+        let result = change(self);
+        self.history.resume();
+        result
+    }
+
+    /// Apply an operation without touching the history, returning its inverse
+    fn apply_untracked(&mut self, op: Operation) -> Option<Operation> {
+        let inverse = op.apply(&mut self.tree)?;
+        self.version += 1;
+        self.invalidate_transforms(&inverse);
+        self.invalidate_spatial(&inverse);
+        self.invalidate_text_layouts(&inverse);
+        self.index_instances(&inverse);
+        self.record_changes(&inverse);
+        Some(inverse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_create_and_delete() {
+        let mut doc = Document::new("Test");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 10.0, 50.0, 50.0);
+
+        doc.begin_undo_group();
+        doc.set_node_x(rect_id, 20.0);
+        doc.set_node_x(rect_id, 30.0);
+        doc.end_undo_group();
+
+        doc.delete_node(frame_id);
+        assert!(!doc.has_node(rect_id));
+
+        assert!(doc.undo());
+        assert_eq!(doc.get_children(frame_id), vec![rect_id]);
+        assert_eq!(doc.get_node_x(rect_id), 30.0);
+
+        assert!(doc.undo());
+        assert_eq!(doc.get_node_x(rect_id), 10.0);
+
+        assert!(doc.redo());
+        assert_eq!(doc.get_node_x(rect_id), 30.0);
+
+        assert!(doc.undo());
+        assert!(doc.undo());
+        assert!(doc.undo());
+        assert!(!doc.has_node(frame_id));
+        assert!(!doc.can_undo());
+    }
+
+    #[test]
+    fn test_remote_changes_are_not_undoable() {
+        let mut doc = Document::new("Test");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 10.0, 50.0, 50.0);
+        doc.clear_history();
+
+        doc.set_node_x(rect_id, 10.0);
+        assert!(!doc.can_undo());
+
+        doc.set_node_x(rect_id, 20.0);
+        doc.apply_remote_property(rect_id, Property::Y, PropertyValue::Float(40.0));
+        let page_id = doc.get_parent(frame_id).unwrap();
+        assert!(doc.apply_remote_move(rect_id, page_id, "V".into()).is_ok());
+
+        assert!(doc.undo());
+        assert!(!doc.can_undo());
+        assert_eq!(doc.get_node_x(rect_id), 10.0);
+        assert_eq!(doc.get_node_y(rect_id), 40.0);
+        assert_eq!(doc.get_parent(rect_id), Some(page_id));
+    }
+
+    #[test]
+    fn test_remote_create_and_delete() {
+        let mut doc = Document::new("Test");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        doc.clear_history();
+
+        let rect_id = ObjectId::new(7, 1);
+        let root_id = doc.root_id();
+        let create = |doc: &mut Document, node_type, parent_id, key: &str| {
+            doc.apply_remote_create(rect_id, node_type, parent_id, key.into())
+        };
+        let invalid_parent = |parent| Err(TreeError::InvalidParent { node: rect_id, parent });
+        assert_eq!(create(&mut doc, NodeType::Rectangle, root_id, "V"), invalid_parent(root_id));
+        assert_eq!(create(&mut doc, NodeType::Page, frame_id, "V"), invalid_parent(frame_id));
+        assert_eq!(create(&mut doc, NodeType::Rectangle, frame_id, "a0"), Err(TreeError::InvalidOrderIndex(rect_id)));
+        assert_eq!(create(&mut doc, NodeType::Rectangle, frame_id, "V"), Ok(()));
+        assert_eq!(create(&mut doc, NodeType::Rectangle, frame_id, "W"), Err(TreeError::DuplicateNode(rect_id)));
+        assert_eq!(doc.get_parent(rect_id), Some(frame_id));
+        assert!(doc.tree.validate().is_ok());
+
+        assert_eq!(doc.apply_remote_delete(root_id), Err(TreeError::RemoveRoot));
+        assert_eq!(doc.apply_remote_delete(rect_id), Ok(()));
+        assert_eq!(doc.apply_remote_delete(rect_id), Err(TreeError::MissingNode(rect_id)));
+        assert!(!doc.has_node(rect_id));
+        assert!(!doc.can_undo());
     }
 }
@@ -1,7 +1,10 @@
 //! Node types and node structure
 
-use super::{Color, Property, PropertyValue};
+use super::{
+    BlendMode, Color, Effect, EffectKind, ImagePaint, MaskType, Paint, Property, PropertyValue, Shadow, PASS_THROUGH,
+};
 use crate::document::ObjectId;
+use crate::geometry::VectorNetwork;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
@@ -22,6 +25,46 @@ pub enum NodeType {
     Image,
     Component,
     Instance,
+    BooleanOperation,
+    Style,
+    VariableCollection,
+    VariableMode,
+    Variable,
+}
+
+impl NodeType {
+    /// Whether nodes of this type are drawn on a page (as opposed to the
+    /// document, pages themselves, styles and variables)
+    pub fn is_canvas(self) -> bool {
+        !matches!(
+            self,
+            NodeType::Document
+                | NodeType::Page
+                | NodeType::Style
+                | NodeType::VariableCollection
+                | NodeType::VariableMode
+                | NodeType::Variable
+        )
+    }
+
+    /// Whether a node of this type may have a `child` type node as a child.
+    ///
+    /// ```text
+    /// Document            → Page, Style, VariableCollection
+    /// VariableCollection  → VariableMode, Variable
+    /// Page, canvas nodes  → canvas nodes
+    /// Style, VariableMode,
+    /// Variable            → nothing
+    /// ```
+    pub fn can_contain(self, child: NodeType) -> bool {
+        match self {
+            NodeType::Document => matches!(child, NodeType::Page | NodeType::Style | NodeType::VariableCollection),
+            NodeType::VariableCollection => matches!(child, NodeType::VariableMode | NodeType::Variable),
+            NodeType::Style | NodeType::VariableMode | NodeType::Variable => false,
+            NodeType::Page => child.is_canvas(),
+            _ => self.is_canvas() && child.is_canvas(),
+        }
+    }
 }
 
 /// A node in the document tree
@@ -30,8 +73,13 @@ pub struct Node {
     id: ObjectId,
     node_type: NodeType,
     properties: HashMap<Property, PropertyValue>,
-    /// Fractional index for ordering among siblings
+    /// Fractional index key for ordering among siblings. Empty until the
+    /// node is first inserted into a document.
     order_index: String,
+    /// Properties set locally on an instance node that should not be
+    /// overwritten when the main component changes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    overrides: Vec<Property>,
 }
 
 impl Node {
@@ -41,7 +89,16 @@ impl Node {
             id,
             node_type,
             properties: HashMap::new(),
-            order_index: "0.5".to_string(),
+            order_index: String::new(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Copy this node under a new ID
+    pub fn duplicate(&self, id: ObjectId) -> Self {
+        Self {
+            id,
+            ..self.clone()
         }
     }
 
@@ -53,6 +110,10 @@ impl Node {
         self.node_type
     }
 
+    pub(crate) fn set_node_type(&mut self, node_type: NodeType) {
+        self.node_type = node_type;
+    }
+
     pub fn order_index(&self) -> &str {
         &self.order_index
     }
@@ -81,6 +142,27 @@ impl Node {
         &self.properties
     }
 
+    /// Check if a property is overridden on this instance node
+    pub fn is_overridden(&self, property: Property) -> bool {
+        self.overrides.contains(&property)
+    }
+
+    /// Mark or unmark a property as overridden
+    pub fn set_overridden(&mut self, property: Property, overridden: bool) {
+        if overridden {
+            if !self.overrides.contains(&property) {
+                self.overrides.push(property);
+            }
+        } else {
+            self.overrides.retain(|&p| p != property);
+        }
+    }
+
+    /// Get all overridden properties
+    pub fn overrides(&self) -> &[Property] {
+        &self.overrides
+    }
+
     // Convenience getters for common properties
     
     pub fn x(&self) -> f32 {
@@ -125,6 +207,43 @@ impl Node {
         }
     }
 
+    /// How the node is composited onto what's below it; `None` passes
+    /// through, compositing its children directly onto what's below
+    pub fn blend_mode(&self) -> Option<BlendMode> {
+        match self.get_property(Property::BlendMode) {
+            Some(PropertyValue::String(mode)) if mode == PASS_THROUGH => None,
+            Some(PropertyValue::String(mode)) => Some(BlendMode::parse(mode).unwrap_or_default()),
+            _ => match self.node_type {
+                NodeType::Frame | NodeType::Group | NodeType::Component | NodeType::Instance => None,
+                _ => Some(BlendMode::Normal),
+            },
+        }
+    }
+
+    /// Whether the node's children are clipped to its bounds (see the mask
+    /// module docs)
+    pub fn clips_content(&self) -> bool {
+        match self.get_property(Property::ClipContent) {
+            Some(PropertyValue::Bool(v)) => *v,
+            _ => false,
+        }
+    }
+
+    /// Whether the node masks the siblings after it
+    pub fn is_mask(&self) -> bool {
+        match self.get_property(Property::IsMask) {
+            Some(PropertyValue::Bool(v)) => *v,
+            _ => false,
+        }
+    }
+
+    pub fn mask_type(&self) -> MaskType {
+        match self.get_property(Property::MaskType) {
+            Some(PropertyValue::String(mask_type)) => MaskType::parse(mask_type).unwrap_or_default(),
+            _ => MaskType::default(),
+        }
+    }
+
     pub fn fill_color(&self) -> Color {
         match self.get_property(Property::FillColor) {
             Some(PropertyValue::Color(c)) => *c,
@@ -173,4 +292,119 @@ impl Node {
             _ => false,
         }
     }
+
+    pub fn fill_opacity(&self) -> f32 {
+        match self.get_property(Property::FillOpacity) {
+            Some(PropertyValue::Float(v)) => *v,
+            _ => 1.0,
+        }
+    }
+
+    /// The image drawn over the node's fill color
+    pub fn image_fill(&self) -> Option<ImagePaint> {
+        match self.get_property(Property::ImageFill) {
+            Some(PropertyValue::Image(paint)) => Some(paint.clone()),
+            _ => None,
+        }
+    }
+
+    /// The node's fill paints, bottom first: its `Fills` if set, otherwise
+    /// its fill color and image fill
+    pub fn fills(&self) -> Vec<Paint> {
+        if let Some(PropertyValue::Paints(paints)) = self.get_property(Property::Fills) {
+            return paints.clone();
+        }
+        let mut fills = Vec::new();
+        if let Some(PropertyValue::Color(color)) = self.get_property(Property::FillColor) {
+            let mut paint = Paint::solid(*color);
+            paint.opacity = self.fill_opacity();
+            fills.push(paint);
+        }
+        fills.extend(self.image_fill().map(Paint::image));
+        fills
+    }
+
+    /// The node's stroke paints, bottom first: its `Strokes` if set,
+    /// otherwise its stroke color
+    pub fn strokes(&self) -> Vec<Paint> {
+        if let Some(PropertyValue::Paints(paints)) = self.get_property(Property::Strokes) {
+            return paints.clone();
+        }
+        let Some(PropertyValue::Color(color)) = self.get_property(Property::StrokeColor) else {
+            return Vec::new();
+        };
+        let mut paint = Paint::solid(*color);
+        if let Some(PropertyValue::Float(opacity)) = self.get_property(Property::StrokeOpacity) {
+            paint.opacity = *opacity;
+        }
+        vec![paint]
+    }
+
+    /// The node's effects: its `Effects` if set, otherwise a drop shadow if
+    /// it has a shadow color and a layer blur if it has a blur radius
+    pub fn effects(&self) -> Vec<Effect> {
+        if let Some(PropertyValue::Effects(effects)) = self.get_property(Property::Effects) {
+            return effects.clone();
+        }
+        let mut effects = Vec::new();
+        if let Some(PropertyValue::Color(color)) = self.get_property(Property::ShadowColor) {
+            effects.push(Effect::new(EffectKind::DropShadow(Shadow {
+                color: *color,
+                offset_x: self.float(Property::ShadowOffsetX),
+                offset_y: self.float(Property::ShadowOffsetY),
+                blur: self.float(Property::ShadowBlur),
+                spread: self.float(Property::ShadowSpread),
+            })));
+        }
+        let radius = self.float(Property::BlurRadius);
+        if radius > 0.0 {
+            effects.push(Effect::new(EffectKind::LayerBlur { radius }));
+        }
+        effects
+    }
+
+    /// A float property, or 0 if it isn't set
+    fn float(&self, property: Property) -> f32 {
+        match self.get_property(property) {
+            Some(PropertyValue::Float(v)) => *v,
+            _ => 0.0,
+        }
+    }
+
+    /// The node's path geometry (for vectors and boolean results)
+    pub fn vector_network(&self) -> Option<VectorNetwork> {
+        match self.get_property(Property::VectorData) {
+            Some(PropertyValue::String(json)) => serde_json::from_str(json).ok(),
+            _ => None,
+        }
+    }
+
+    /// The variable a property is bound to
+    pub fn bound_variable(&self, property: Property) -> Option<ObjectId> {
+        match self.get_property(property) {
+            Some(PropertyValue::Variable(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Whether any property is bound to a variable
+    pub fn has_bindings(&self) -> bool {
+        self.properties.values().any(|value| matches!(value, PropertyValue::Variable(_)))
+    }
+
+    /// The component this instance was created from
+    pub fn main_component(&self) -> Option<ObjectId> {
+        match self.get_property(Property::MainComponent) {
+            Some(PropertyValue::Ref(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The component node this instance node mirrors
+    pub fn component_source(&self) -> Option<ObjectId> {
+        match self.get_property(Property::ComponentSource) {
+            Some(PropertyValue::Ref(id)) => Some(*id),
+            _ => None,
+        }
+    }
 }
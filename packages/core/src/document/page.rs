@@ -0,0 +1,236 @@
+//! Pages
+//!
+//! A document has one or more pages, the `Page` children of the root. Each
+//! page is a separate canvas: only the active page is rendered, hit tested
+//! and marquee selected, and new frames go onto it.
+//!
+//! ```text
+//! Document
+//!   ├─ Page "Designs"      ← active
+//!   ├─ Page "Components"
+//!   └─ Style "Brand"       (styles and variables are not pages)
+//! ```
+//!
+//! Which page is active is part of the editing session, not of the document:
+//! it isn't saved, synced or undone. If the active page goes away (deleted
+//! here, by a collaborator, or by undo), the first page takes over.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+impl Document {
+    /// All pages, in order
+    pub fn get_pages(&self) -> Vec<ObjectId> {
+        self.tree.pages()
+    }
+
+    /// Add a page after the last one
+    pub fn create_page(&mut self, name: &str) -> ObjectId {
+        let root_id = self.tree.root_id();
+        let position = self
+            .tree
+            .pages()
+            .last()
+            .and_then(|&last| self.tree.index_in_parent(last))
+            .map_or(0, |index| index + 1);
+
+        let page_id = ObjectId::random();
+        let mut page = Node::new(page_id, NodeType::Page);
+        page.set_property(Property::Name, PropertyValue::String(name.into()));
+        page.set_order_index(self.tree.order_index_at(root_id, position, page_id));
+
+        let subtree = Subtree::from_nodes(vec![(page, Some(root_id))], position);
+        self.apply(Operation::Insert(subtree.expect("one node")));
+        page_id
+    }
+
+    pub fn rename_page(&mut self, page_id: ObjectId, name: &str) -> bool {
+        if !self.is_page(page_id) {
+            return false;
+        }
+        self.set_node_property(page_id, Property::Name, PropertyValue::String(name.into()));
+        true
+    }
+
+    /// Copy a page and everything on it, right after the original.
+    ///
+    /// Instances on the copy that use a component from the same page use the
+    /// copied component.
+    pub fn duplicate_page(&mut self, page_id: ObjectId) -> Option<ObjectId> {
+        if !self.is_page(page_id) {
+            return None;
+        }
+        let copy = self.tree.copy_subtree(page_id)?;
+        let (copy_id, position) = (copy.root(), copy.position());
+
+        let mut nodes = copy.into_nodes();
+        let (page, _) = nodes.first_mut()?;
+        let name = format!("{} copy", self.tree.get(page_id)?.name());
+        page.set_property(Property::Name, PropertyValue::String(name));
+        page.set_order_index(self.tree.order_index_at(self.tree.root_id(), position, copy_id));
+
+        let subtree = Subtree::from_nodes(nodes, position)?;
+        self.apply(Operation::Insert(subtree));
+        Some(copy_id)
+    }
+
+    /// Move a page to `index` in the page list (clamped to the end)
+    pub fn move_page(&mut self, page_id: ObjectId, index: usize) -> bool {
+        if !self.is_page(page_id) {
+            return false;
+        }
+        let root_id = self.tree.root_id();
+        let others: Vec<ObjectId> = self.tree.children(root_id).into_iter().filter(|&id| id != page_id).collect();
+        let other_pages: Vec<ObjectId> = self.tree.pages().into_iter().filter(|&id| id != page_id).collect();
+
+        // Land right before the page currently at `index`, or after the last page
+        let position_of = |id: ObjectId| others.iter().position(|&other| other == id).unwrap_or(others.len());
+        let position = match other_pages.get(index) {
+            Some(&next) => position_of(next),
+            None => other_pages.last().map_or(0, |&last| position_of(last) + 1),
+        };
+
+        let order_index = self.tree.order_index_at(root_id, position, page_id);
+        self.apply(Operation::Move { id: page_id, parent: root_id, position, order_index })
+    }
+
+    /// Delete a page and everything on it. The last page can't be deleted.
+    ///
+    /// If the page was active, the page after it (or before it, for the last
+    /// page) becomes active.
+    pub fn delete_page(&mut self, page_id: ObjectId) -> bool {
+        let pages = self.tree.pages();
+        let Some(index) = pages.iter().position(|&id| id == page_id) else {
+            return false;
+        };
+        if pages.len() < 2 {
+            return false;
+        }
+
+        if self.get_active_page() == Some(page_id) {
+            let neighbour = pages.get(index + 1).or_else(|| pages.get(index - 1));
+            self.active_page = neighbour.copied();
+        }
+        self.apply(Operation::Remove { id: page_id })
+    }
+
+    /// The page being edited
+    pub fn get_active_page(&self) -> Option<ObjectId> {
+        self.active_page
+            .filter(|&id| self.is_page(id))
+            .or_else(|| self.tree.first_page())
+    }
+
+    pub fn set_active_page(&mut self, page_id: ObjectId) -> bool {
+        if !self.is_page(page_id) {
+            return false;
+        }
+        self.active_page = Some(page_id);
+        true
+    }
+
+    /// The page a node is on (so the UI can switch to it when the node is
+    /// picked from a list)
+    pub fn get_node_page(&self, id: ObjectId) -> Option<ObjectId> {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            if self.is_page(node_id) {
+                return Some(node_id);
+            }
+            current = self.tree.parent(node_id);
+        }
+        None
+    }
+
+    /// Create a new frame on a page. Returns `None` if `page_id` isn't a page.
+    ///
+    /// Shapes can be created directly on a page by passing the page as the
+    /// parent of `create_rectangle()` and friends.
+    pub fn create_frame_on_page(
+        &mut self,
+        page_id: ObjectId,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) -> Option<ObjectId> {
+        if !self.is_page(page_id) {
+            return None;
+        }
+        Some(self.insert_frame(Some(page_id), x, y, width, height))
+    }
+}
+
+impl Document {
+    fn is_page(&self, id: ObjectId) -> bool {
+        self.tree.get(id).is_some_and(|n| n.node_type() == NodeType::Page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(doc: &Document) -> Vec<String> {
+        doc.get_pages().iter().map(|&id| doc.tree.get(id).unwrap().name()).collect()
+    }
+
+    #[test]
+    fn test_page_lifecycle() {
+        let mut doc = Document::new("Pages");
+        let first = doc.get_pages()[0];
+        let second = doc.create_page("Page 2");
+        doc.create_style("fill", "Brand");
+        let third = doc.create_page("Page 3");
+        assert_eq!(names(&doc), ["Page 1", "Page 2", "Page 3"]);
+
+        assert!(doc.rename_page(second, "Components"));
+        assert!(doc.move_page(third, 0));
+        assert_eq!(names(&doc), ["Page 3", "Page 1", "Components"]);
+        assert!(doc.move_page(third, 99));
+        assert_eq!(names(&doc), ["Page 1", "Components", "Page 3"]);
+        assert!(doc.move_page(third, 1));
+        assert_eq!(names(&doc), ["Page 1", "Page 3", "Components"]);
+
+        // New frames go onto the active page
+        assert_eq!(doc.get_active_page(), Some(first));
+        assert!(doc.set_active_page(third));
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        assert_eq!(doc.get_node_page(frame_id), Some(third));
+
+        // Deleting the active page activates its neighbour; the last page stays
+        assert!(doc.delete_page(third));
+        assert_eq!(doc.get_active_page(), Some(second));
+        assert!(doc.delete_page(second));
+        assert!(!doc.delete_page(first));
+        assert_eq!(doc.get_active_page(), Some(first));
+
+        assert!(doc.undo());
+        assert_eq!(names(&doc), ["Page 1", "Components"]);
+    }
+
+    #[test]
+    fn test_duplicate_page_and_active_page_hits() {
+        let mut doc = Document::new("Pages");
+        let first = doc.get_pages()[0];
+        let component_id = doc.create_component(0.0, 0.0, 50.0, 50.0);
+        doc.create_rectangle(component_id, 0.0, 0.0, 50.0, 50.0);
+        let instance_id = doc.create_instance(component_id, first, 100.0, 0.0).unwrap();
+
+        let copy = doc.duplicate_page(first).unwrap();
+        assert_eq!(names(&doc), ["Page 1", "Page 1 copy"]);
+        let copied: Vec<ObjectId> = doc.get_children(copy);
+        assert_eq!(copied.len(), 2);
+        assert!(!copied.contains(&component_id) && !copied.contains(&instance_id));
+        // The copied instance follows the copied component
+        assert_eq!(doc.get_main_component(copied[1]), Some(copied[0]));
+
+        // Only the active page can be hit
+        let hit_page = |doc: &Document| doc.get_node_page(doc.hit_test(110.0, 10.0, 0.0, 1.0).object_id().unwrap());
+        assert_eq!(hit_page(&doc), Some(first));
+        doc.set_active_page(copy);
+        assert_eq!(hit_page(&doc), Some(copy));
+        assert_eq!(doc.nodes_in_marquee(-10.0, -10.0, 200.0, 100.0).len(), 2);
+    }
+}
@@ -0,0 +1,588 @@
+//! Paints
+//!
+//! A node's fill is a stack of paints, drawn bottom first:
+//!
+//! ```text
+//! Fills = [ solid #FFFFFF,                       ← bottom
+//!           linear gradient red → transparent,
+//!           image "3fa1…" (fit, 50% opacity) ]   ← top
+//! ```
+//!
+//! Each paint has its own opacity, visibility and blend mode. Nodes without
+//! a `Fills` list are filled from `FillColor` / `FillOpacity` (and an old
+//! single `ImageFill`), so simple shapes keep using those.
+//!
+//! Strokes work the same way: a `Strokes` list drawn along the outline,
+//! `StrokeWidth` wide, with `StrokeColor` / `StrokeOpacity` as the fallback.
+//! Every fill method below has a stroke twin (`set_strokes`,
+//! `add_solid_stroke`, ...).
+//!
+//! ## Gradients
+//!
+//! Gradient stops are positions from 0 to 1. Where a point of the node falls
+//! on the gradient is decided in *gradient space*: the gradient's transform
+//! maps the node's bounds, scaled to the unit square, into it.
+//!
+//! ```text
+//! linear    t = x                     (0, 0.5) → (1, 0.5)
+//! radial    t = distance from (0.5, 0.5) × 2
+//! angular   t = angle around (0.5, 0.5), clockwise from +x, / 360°
+//! diamond   t = (|x - 0.5| + |y - 0.5|) × 2
+//! ```
+//!
+//! With the identity transform a linear gradient runs left to right across
+//! the node and the others are centred in it. [`Gradient::from_handles`]
+//! builds the transform from the two handles the editor shows.
+//!
+//! ## Blend modes
+//!
+//! A paint's blend mode combines it with the paints below it and whatever
+//! the node is drawn over. Nodes have a blend mode of their own
+//! (`Property::BlendMode`), which decides how the node as a whole is
+//! composited:
+//!
+//! ```text
+//! pass_through   children blend straight onto what's below the node
+//! normal, ...    the node and its children are drawn into a layer of their
+//!                own (isolated), then the layer is blended onto what's below
+//! ```
+//!
+//! Leaf nodes are normal by default; frames, groups, components and
+//! instances pass through. A node with an opacity below 1 is always
+//! isolated, so overlapping children fade together instead of one by one.
+
+use super::{Color, Document, ImagePaint, Node, ObjectId, Property, PropertyValue};
+use crate::math::{Mat3, Transform2D, Vec2};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+/// The `BlendMode` property value for nodes that pass their children's
+/// blending through
+pub const PASS_THROUGH: &str = "pass_through";
+
+/// How a paint or layer combines with what's underneath
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    Hue,
+    Saturation,
+    Color,
+    Luminosity,
+}
+
+impl BlendMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::from(mode)).ok()
+    }
+}
+
+/// The shape of a gradient (see the module docs)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GradientType {
+    #[default]
+    Linear,
+    Radial,
+    Angular,
+    Diamond,
+}
+
+impl GradientType {
+    pub fn parse(gradient_type: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::from(gradient_type)).ok()
+    }
+}
+
+/// A color at a position (0 to 1) along a gradient
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ColorStop {
+    pub position: f32,
+    pub color: Color,
+}
+
+/// A gradient fill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Gradient {
+    pub gradient_type: GradientType,
+    pub stops: Vec<ColorStop>,
+    /// Maps the node's unit square into gradient space, as `[a, b, c, d, e, f]`:
+    /// `x' = a·x + c·y + e`, `y' = b·x + d·y + f`
+    #[serde(default = "identity")]
+    pub transform: [f32; 6],
+}
+
+impl Gradient {
+    /// A gradient with the identity transform
+    pub fn new(gradient_type: GradientType, stops: Vec<ColorStop>) -> Self {
+        Self { gradient_type, stops, transform: identity() }
+    }
+
+    /// A gradient placed by two handles in the node's unit square: `start`
+    /// is where a linear gradient begins (the centre of the others) and `end`
+    /// is where it reaches its last stop.
+    pub fn from_handles(gradient_type: GradientType, start: Vec2, end: Vec2, stops: Vec<ColorStop>) -> Self {
+        let direction = end - start;
+        let length = direction.length().max(f32::EPSILON);
+        let (origin, scale) = match gradient_type {
+            GradientType::Linear => (Vec2::new(0.0, 0.5), 1.0 / length),
+            _ => (Vec2::new(0.5, 0.5), 0.5 / length),
+        };
+        let transform = Transform2D::translate(origin.x, origin.y)
+            .multiply(&Transform2D::scale(scale, scale))
+            .multiply(&Transform2D::rotate(-direction.y.atan2(direction.x)))
+            .multiply(&Transform2D::translate(-start.x, -start.y));
+        let m = transform.matrix();
+        Self {
+            gradient_type,
+            stops,
+            transform: [m.x_axis.x, m.x_axis.y, m.y_axis.x, m.y_axis.y, m.z_axis.x, m.z_axis.y],
+        }
+    }
+
+    /// The transform as a matrix
+    pub fn matrix(&self) -> Mat3 {
+        let [a, b, c, d, e, f] = self.transform;
+        Mat3::from_cols_array(&[a, b, 0.0, c, d, 0.0, e, f, 1.0])
+    }
+
+    /// Where a point of the unit square falls on the gradient, from 0 to 1
+    pub fn position_at(&self, point: Vec2) -> f32 {
+        let g = self.matrix().transform_point2(point);
+        let centred = g - Vec2::splat(0.5);
+        let t = match self.gradient_type {
+            GradientType::Linear => g.x,
+            GradientType::Radial => centred.length() * 2.0,
+            GradientType::Angular => centred.y.atan2(centred.x).rem_euclid(std::f32::consts::TAU) / std::f32::consts::TAU,
+            GradientType::Diamond => (centred.x.abs() + centred.y.abs()) * 2.0,
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    /// The color at `t`, interpolating between stops. Before the first stop
+    /// and after the last, the end colors continue.
+    pub fn color_at(&self, t: f32) -> Color {
+        let stops = self.sorted_stops();
+        let Some(first) = stops.first() else {
+            return Color::transparent();
+        };
+        let mut color = first.color;
+        for pair in stops.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            if t >= b.position {
+                color = b.color;
+            } else if t > a.position {
+                color = a.color.lerp(&b.color, (t - a.position) / (b.position - a.position));
+                break;
+            }
+        }
+        color
+    }
+
+    /// The stops in position order
+    pub fn sorted_stops(&self) -> Vec<ColorStop> {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        stops
+    }
+}
+
+fn identity() -> [f32; 6] {
+    [1.0, 0.0, 0.0, 1.0, 0.0, 0.0]
+}
+
+/// What a paint draws
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PaintKind {
+    Solid { color: Color },
+    Gradient(Gradient),
+    Image(ImagePaint),
+}
+
+/// One layer of a node's fill
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Paint {
+    #[serde(flatten)]
+    pub kind: PaintKind,
+    #[serde(default = "full_opacity")]
+    pub opacity: f32,
+    #[serde(default = "visible")]
+    pub visible: bool,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+}
+
+impl Paint {
+    pub fn new(kind: PaintKind) -> Self {
+        Self {
+            kind,
+            opacity: 1.0,
+            visible: true,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+
+    pub fn solid(color: Color) -> Self {
+        Self::new(PaintKind::Solid { color })
+    }
+
+    pub fn gradient(gradient: Gradient) -> Self {
+        Self::new(PaintKind::Gradient(gradient))
+    }
+
+    pub fn image(image: ImagePaint) -> Self {
+        Self::new(PaintKind::Image(image))
+    }
+
+    /// Whether drawing this paint changes anything
+    pub fn is_drawn(&self) -> bool {
+        let transparent = match &self.kind {
+            PaintKind::Solid { color } => color.a <= 0.0,
+            PaintKind::Gradient(gradient) => gradient.stops.iter().all(|stop| stop.color.a <= 0.0),
+            PaintKind::Image(_) => false,
+        };
+        self.visible && self.opacity > 0.0 && !transparent
+    }
+}
+
+fn full_opacity() -> f32 {
+    1.0
+}
+
+fn visible() -> bool {
+    true
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// A node's fills as a JSON array of paints, bottom first (see `Paint`)
+    pub fn get_fills(&self, id: ObjectId) -> String {
+        let fills = self.tree.get(id).map(|node| self.bound_node(node).fills()).unwrap_or_default();
+        serde_json::to_string(&fills).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Replace a node's fills with a JSON array of paints, bottom first.
+    ///
+    /// Returns `false` if the JSON isn't a list of paints.
+    pub fn set_fills(&mut self, id: ObjectId, json: &str) -> bool {
+        let Ok(fills) = serde_json::from_str::<Vec<Paint>>(json) else {
+            return false;
+        };
+        self.update_fills(id, |current| *current = fills)
+    }
+
+    /// Add a solid paint on top of a node's fills
+    pub fn add_solid_fill(&mut self, id: ObjectId, color: Color) -> bool {
+        self.update_fills(id, |fills| fills.push(Paint::solid(color)))
+    }
+
+    /// Add a two-stop gradient on top of a node's fills, running left to
+    /// right (or centred). `gradient_type` is "linear", "radial", "angular"
+    /// or "diamond".
+    pub fn add_gradient_fill(&mut self, id: ObjectId, gradient_type: &str, from: Color, to: Color) -> bool {
+        let Some(gradient_type) = GradientType::parse(gradient_type) else {
+            return false;
+        };
+        let stops = vec![ColorStop { position: 0.0, color: from }, ColorStop { position: 1.0, color: to }];
+        self.update_fills(id, |fills| fills.push(Paint::gradient(Gradient::new(gradient_type, stops))))
+    }
+
+    pub fn remove_fill(&mut self, id: ObjectId, index: usize) -> bool {
+        index < self.fill_count(id)
+            && self.update_fills(id, |fills| {
+                fills.remove(index);
+            })
+    }
+
+    pub fn set_fill_visible(&mut self, id: ObjectId, index: usize, visible: bool) -> bool {
+        self.update_fill(id, index, |paint| paint.visible = visible)
+    }
+
+    pub fn set_fill_opacity(&mut self, id: ObjectId, index: usize, opacity: f32) -> bool {
+        self.update_fill(id, index, |paint| paint.opacity = opacity.clamp(0.0, 1.0))
+    }
+
+    /// `mode` is "normal", "multiply", "screen", "overlay", "darken", "lighten",
+    /// "color_dodge", "color_burn", "hard_light", "soft_light", "difference",
+    /// "exclusion", "hue", "saturation", "color" or "luminosity"
+    pub fn set_fill_blend_mode(&mut self, id: ObjectId, index: usize, mode: &str) -> bool {
+        let Some(mode) = BlendMode::parse(mode) else {
+            return false;
+        };
+        self.update_fill(id, index, |paint| paint.blend_mode = mode)
+    }
+
+    /// A node's own blend mode: "pass_through" or one of the paint modes
+    pub fn get_node_blend_mode(&self, id: ObjectId) -> String {
+        let mode = self.tree.get(id).and_then(|node| self.bound_node(node).blend_mode());
+        match mode {
+            Some(mode) => serde_json::to_value(mode).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default(),
+            None => PASS_THROUGH.into(),
+        }
+    }
+
+    /// Set a node's own blend mode: "pass_through" or one of the modes
+    /// `set_fill_blend_mode` takes. Returns `false` for unknown modes.
+    pub fn set_node_blend_mode(&mut self, id: ObjectId, mode: &str) -> bool {
+        let known = mode == PASS_THROUGH || BlendMode::parse(mode).is_some();
+        if !known || !self.has_node(id) {
+            return false;
+        }
+        self.set_node_property(id, Property::BlendMode, PropertyValue::String(mode.into()));
+        true
+    }
+
+    /// A node's strokes as a JSON array of paints, bottom first
+    pub fn get_strokes(&self, id: ObjectId) -> String {
+        let strokes = self.tree.get(id).map(|node| self.bound_node(node).strokes()).unwrap_or_default();
+        serde_json::to_string(&strokes).unwrap_or_else(|_| "[]".into())
+    }
+
+    /// Replace a node's strokes with a JSON array of paints, bottom first.
+    ///
+    /// Returns `false` if the JSON isn't a list of paints.
+    pub fn set_strokes(&mut self, id: ObjectId, json: &str) -> bool {
+        let Ok(strokes) = serde_json::from_str::<Vec<Paint>>(json) else {
+            return false;
+        };
+        self.update_paints(id, Property::Strokes, |current| *current = strokes)
+    }
+
+    /// Add a solid paint on top of a node's strokes
+    pub fn add_solid_stroke(&mut self, id: ObjectId, color: Color) -> bool {
+        self.update_paints(id, Property::Strokes, |strokes| strokes.push(Paint::solid(color)))
+    }
+
+    /// Add a two-stop gradient on top of a node's strokes (see `add_gradient_fill`)
+    pub fn add_gradient_stroke(&mut self, id: ObjectId, gradient_type: &str, from: Color, to: Color) -> bool {
+        let Some(gradient_type) = GradientType::parse(gradient_type) else {
+            return false;
+        };
+        let stops = vec![ColorStop { position: 0.0, color: from }, ColorStop { position: 1.0, color: to }];
+        self.update_paints(id, Property::Strokes, |strokes| {
+            strokes.push(Paint::gradient(Gradient::new(gradient_type, stops)))
+        })
+    }
+
+    pub fn remove_stroke(&mut self, id: ObjectId, index: usize) -> bool {
+        index < self.paint_count(id, Property::Strokes)
+            && self.update_paints(id, Property::Strokes, |strokes| {
+                strokes.remove(index);
+            })
+    }
+
+    pub fn set_stroke_visible(&mut self, id: ObjectId, index: usize, visible: bool) -> bool {
+        self.update_paint(id, Property::Strokes, index, |paint| paint.visible = visible)
+    }
+
+    pub fn set_stroke_opacity(&mut self, id: ObjectId, index: usize, opacity: f32) -> bool {
+        self.update_paint(id, Property::Strokes, index, |paint| paint.opacity = opacity.clamp(0.0, 1.0))
+    }
+
+    /// `mode` is one of the modes `set_fill_blend_mode` takes
+    pub fn set_stroke_blend_mode(&mut self, id: ObjectId, index: usize, mode: &str) -> bool {
+        let Some(mode) = BlendMode::parse(mode) else {
+            return false;
+        };
+        self.update_paint(id, Property::Strokes, index, |paint| paint.blend_mode = mode)
+    }
+}
+
+impl Document {
+    /// Edit a node's fills as a list, turning `FillColor` / `ImageFill` into
+    /// paints the first time
+    pub(crate) fn update_fills(&mut self, id: ObjectId, update: impl FnOnce(&mut Vec<Paint>)) -> bool {
+        self.update_paints(id, Property::Fills, update)
+    }
+
+    /// Edit a node's `Fills` or `Strokes` as a list, starting from the paints
+    /// it is drawn with
+    fn update_paints(&mut self, id: ObjectId, property: Property, update: impl FnOnce(&mut Vec<Paint>)) -> bool {
+        let Some(mut paints) = self.tree.get(id).map(|node| paints_of(&self.bound_node(node), property)) else {
+            return false;
+        };
+        update(&mut paints);
+        self.set_node_property(id, property, PropertyValue::Paints(paints));
+        true
+    }
+
+    /// Edit one of a node's paints. Returns `false` if there's no paint at `index`.
+    fn update_paint(
+        &mut self,
+        id: ObjectId,
+        property: Property,
+        index: usize,
+        update: impl FnOnce(&mut Paint),
+    ) -> bool {
+        index < self.paint_count(id, property) && self.update_paints(id, property, |paints| update(&mut paints[index]))
+    }
+
+    fn update_fill(&mut self, id: ObjectId, index: usize, update: impl FnOnce(&mut Paint)) -> bool {
+        self.update_paint(id, Property::Fills, index, update)
+    }
+
+    fn fill_count(&self, id: ObjectId) -> usize {
+        self.paint_count(id, Property::Fills)
+    }
+
+    fn paint_count(&self, id: ObjectId, property: Property) -> usize {
+        self.tree.get(id).map_or(0, |node| paints_of(&self.bound_node(node), property).len())
+    }
+}
+
+/// A node's fills or strokes
+fn paints_of(node: &Node, property: Property) -> Vec<Paint> {
+    match property {
+        Property::Strokes => node.strokes(),
+        _ => node.fills(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_positions_and_colors() {
+        let stops = vec![
+            ColorStop { position: 1.0, color: Color::blue() },
+            ColorStop { position: 0.0, color: Color::red() },
+        ];
+        let linear = Gradient::new(GradientType::Linear, stops.clone());
+        assert_eq!(linear.position_at(Vec2::new(0.25, 0.9)), 0.25);
+        assert_eq!(linear.color_at(0.5), Color::new(0.5, 0.0, 0.5, 1.0));
+        assert_eq!(linear.color_at(-1.0), Color::red());
+
+        let radial = Gradient::new(GradientType::Radial, stops.clone());
+        assert_eq!(radial.position_at(Vec2::new(0.5, 0.5)), 0.0);
+        assert_eq!(radial.position_at(Vec2::new(0.5, 0.25)), 0.5);
+        let diamond = Gradient::new(GradientType::Diamond, stops.clone());
+        assert_eq!(diamond.position_at(Vec2::new(0.75, 0.25)), 1.0);
+        let angular = Gradient::new(GradientType::Angular, stops.clone());
+        assert_eq!(angular.position_at(Vec2::new(0.5, 1.0)), 0.25);
+
+        // Handles: top to bottom instead of left to right
+        let vertical = Gradient::from_handles(GradientType::Linear, Vec2::new(0.5, 0.0), Vec2::new(0.5, 1.0), stops);
+        assert!((vertical.position_at(Vec2::new(0.0, 0.75)) - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_fill_stack() {
+        let mut doc = Document::new("Paints");
+        let page_id = doc.get_active_page().unwrap();
+        let rect_id = doc.create_rectangle(page_id, 0.0, 0.0, 100.0, 100.0);
+
+        // The fill color becomes the first paint
+        assert!(doc.add_gradient_fill(rect_id, "radial", Color::white(), Color::transparent()));
+        assert!(!doc.add_gradient_fill(rect_id, "conic", Color::white(), Color::black()));
+        assert!(doc.set_fill_blend_mode(rect_id, 1, "multiply"));
+        assert!(doc.set_fill_opacity(rect_id, 0, 0.5));
+        assert!(!doc.set_fill_visible(rect_id, 2, false));
+
+        let fills = doc.tree.get(rect_id).unwrap().fills();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].kind, PaintKind::Solid { color: Color::new(0.8, 0.8, 0.8, 1.0) });
+        assert_eq!(fills[0].opacity, 0.5);
+        assert!(matches!(&fills[1].kind, PaintKind::Gradient(g) if g.gradient_type == GradientType::Radial));
+        assert_eq!(fills[1].blend_mode, BlendMode::Multiply);
+
+        // JSON in and out, with defaults for missing fields
+        let json = r#"[{"type": "solid", "color": {"r": 1, "g": 0, "b": 0, "a": 1}}]"#;
+        assert!(doc.set_fills(rect_id, json));
+        assert!(!doc.set_fills(rect_id, r#"[{"type": "noise"}]"#));
+        let fills: Vec<Paint> = serde_json::from_str(&doc.get_fills(rect_id)).unwrap();
+        assert_eq!(fills, vec![Paint::solid(Color::red())]);
+
+        assert!(doc.remove_fill(rect_id, 0));
+        assert!(doc.tree.get(rect_id).unwrap().fills().is_empty());
+        assert!(doc.undo());
+        assert_eq!(doc.tree.get(rect_id).unwrap().fills().len(), 1);
+    }
+
+    #[test]
+    fn test_stroke_stack() {
+        let mut doc = Document::new("Paints");
+        let page_id = doc.get_active_page().unwrap();
+        let ellipse_id = doc.create_ellipse(page_id, 0.0, 0.0, 100.0, 100.0);
+        assert_eq!(doc.get_strokes(ellipse_id), "[]");
+
+        // The stroke color becomes the first paint
+        doc.set_node_property(ellipse_id, Property::StrokeColor, PropertyValue::Color(Color::black()));
+        doc.set_node_property(ellipse_id, Property::StrokeOpacity, PropertyValue::Float(0.5));
+        assert!(doc.add_gradient_stroke(ellipse_id, "linear", Color::red(), Color::blue()));
+        assert!(doc.set_stroke_blend_mode(ellipse_id, 1, "screen"));
+        assert!(!doc.set_stroke_visible(ellipse_id, 2, false));
+        let strokes = doc.tree.get(ellipse_id).unwrap().strokes();
+        assert_eq!(strokes.len(), 2);
+        assert_eq!(strokes[0].kind, PaintKind::Solid { color: Color::black() });
+        assert_eq!(strokes[0].opacity, 0.5);
+        assert_eq!(strokes[1].blend_mode, BlendMode::Screen);
+        assert!(doc.tree.get(ellipse_id).unwrap().fills().len() == 1);
+
+        // Saved in both formats
+        let binary = Document::decode_binary(&doc.encode_binary().unwrap()).unwrap();
+        let json = Document::decode_json(&doc.encode_json().unwrap()).unwrap();
+        assert_eq!(binary.get_strokes(ellipse_id), doc.get_strokes(ellipse_id));
+        assert_eq!(json.get_strokes(ellipse_id), doc.get_strokes(ellipse_id));
+
+        assert!(doc.set_strokes(ellipse_id, r#"[{"type": "solid", "color": {"r": 0, "g": 0, "b": 1, "a": 1}}]"#));
+        assert!(doc.remove_stroke(ellipse_id, 0));
+        assert!(!doc.remove_stroke(ellipse_id, 0));
+        assert!(doc.undo());
+        assert_eq!(doc.tree.get(ellipse_id).unwrap().strokes(), vec![Paint::solid(Color::blue())]);
+    }
+
+    #[test]
+    fn test_fills_decide_hits_and_boolean_paints() {
+        let mut doc = Document::new("Paints");
+        let page_id = doc.get_active_page().unwrap();
+        let rect_id = doc.create_rectangle(page_id, 0.0, 0.0, 100.0, 100.0);
+        let other_id = doc.create_rectangle(page_id, 50.0, 0.0, 100.0, 100.0);
+
+        // Only a drawn fill makes the inside hittable
+        assert!(doc.set_fills(rect_id, "[]"));
+        assert!(doc.hit_test(20.0, 50.0, 0.0, 1.0).object_id().is_none());
+        assert!(doc.add_gradient_fill(rect_id, "linear", Color::red(), Color::blue()));
+        assert_eq!(doc.hit_test(20.0, 50.0, 0.0, 1.0).object_id(), Some(rect_id));
+
+        // A boolean is painted like its subject
+        assert!(doc.add_gradient_stroke(rect_id, "radial", Color::white(), Color::black()));
+        let boolean_id = doc.create_boolean_operation(vec![rect_id, other_id], "union").unwrap();
+        assert_eq!(doc.get_fills(boolean_id), doc.get_fills(rect_id));
+        assert_eq!(doc.get_strokes(boolean_id), doc.get_strokes(rect_id));
+    }
+
+    #[test]
+    fn test_node_blend_modes() {
+        let mut doc = Document::new("Blending");
+        let page_id = doc.get_active_page().unwrap();
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(page_id, 0.0, 0.0, 10.0, 10.0);
+
+        // Containers pass through, everything else is normal
+        assert_eq!(doc.get_node_blend_mode(frame_id), PASS_THROUGH);
+        assert_eq!(doc.get_node_blend_mode(rect_id), "normal");
+
+        assert!(doc.set_node_blend_mode(frame_id, "normal"));
+        assert!(doc.set_node_blend_mode(rect_id, "color_dodge"));
+        assert!(!doc.set_node_blend_mode(rect_id, "dissolve"));
+        assert_eq!(doc.tree.get(frame_id).unwrap().blend_mode(), Some(BlendMode::Normal));
+        assert_eq!(doc.get_node_blend_mode(rect_id), "color_dodge");
+
+        doc.set_node_opacity(rect_id, 1.5);
+        assert_eq!(doc.get_node_opacity(rect_id), 1.0);
+    }
+}
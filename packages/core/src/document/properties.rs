@@ -1,9 +1,13 @@
 //! Property types and values
 
+use crate::document::{Effect, ImagePaint, ObjectId, Paint};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
-/// Properties that can be set on nodes
+/// Properties that can be set on nodes.
+///
+/// JavaScript sees these as numbers in declaration order, so new properties
+/// are added at the end.
 #[wasm_bindgen]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Property {
@@ -38,10 +42,15 @@ pub enum Property {
     Text,
     FontFamily,
     FontSize,
+    /// 100 (thin) to 900 (black), 400 by default
     FontWeight,
+    /// "normal" or "italic"
     FontStyle,
+    /// "left", "center" or "right"
     TextAlign,
+    /// Distance from one line to the next; the font's own when unset
     LineHeight,
+    /// Extra space after each character
     LetterSpacing,
     
     // Effects
@@ -65,10 +74,96 @@ pub enum Property {
     
     // Parent relationship (for tree structure)
     ParentId,
+    
+    // Components
+    /// On an instance: the component it was created from
+    MainComponent,
+    /// On a node inside an instance: the component node it mirrors
+    ComponentSource,
+    
+    // Layout sizing
+    /// How a node is sized horizontally: "fixed", "hug" or "fill"
+    LayoutSizingHorizontal,
+    /// How a node is sized vertically: "fixed", "hug" or "fill"
+    LayoutSizingVertical,
+    
+    // Constraints
+    /// How a node follows its parent's width: "left", "right", "left_right", "center" or "scale"
+    ConstraintHorizontal,
+    /// How a node follows its parent's height: "top", "bottom", "top_bottom", "center" or "scale"
+    ConstraintVertical,
+    
+    // Vector
+    /// On a boolean node: "union", "subtract", "intersect" or "exclude"
+    BooleanOperation,
+    /// Path geometry as vector network JSON, in the node's local space.
+    /// On a boolean node this is the computed result.
+    VectorData,
+    
+    // Styles
+    /// On a style node: "fill", "stroke", "text" or "effect"
+    StyleType,
+    /// Fill style applied to a node
+    FillStyle,
+    /// Stroke style applied to a node
+    StrokeStyle,
+    /// Text style applied to a node
+    TextStyle,
+    /// Effect style applied to a node
+    EffectStyle,
+    
+    // Variables
+    /// On a variable: "color", "number", "string" or "boolean"
+    VariableType,
+    /// On a variable: its value in each mode of its collection (a `Map` keyed by mode)
+    VariableValues,
+    /// The mode this node's subtree uses for each variable collection (a `Map`
+    /// keyed by collection)
+    VariableModes,
+    
+    // Image and paint fills
+    /// An image drawn over the fill color (an `Image` value). Ignored when
+    /// `Fills` is set.
+    ImageFill,
+    /// The node's fill as a stack of paints (a `Paints` value, bottom
+    /// first). Replaces `FillColor`, `FillOpacity` and `ImageFill` when set.
+    Fills,
+    
+    // Stroke paints
+    /// The node's stroke as a stack of paints (a `Paints` value, bottom
+    /// first), drawn `StrokeWidth` wide. Replaces `StrokeColor` and
+    /// `StrokeOpacity` when set.
+    Strokes,
+    
+    // Effect lists
+    /// The node's effects (an `Effects` value). Replaces the single shadow
+    /// and blur properties above when set.
+    Effects,
+    
+    // Compositing
+    /// How the node is composited onto what's below it: "pass_through" or a
+    /// blend mode ("normal", "multiply", ...). Frames, groups, components and
+    /// instances pass through by default, other nodes are normal.
+    BlendMode,
+    
+    // Clipping and masks
+    /// On a frame, component or instance: whether children are clipped to
+    /// its bounds (with its corner radius)
+    ClipContent,
+    /// Whether the node masks the siblings after it instead of being drawn
+    IsMask,
+    /// How a mask clips: "alpha" (by its pixels' opacity, the default) or
+    /// "vector" (by its outline)
+    MaskType,
+    
+    // Text sizing
+    /// How a text node's size follows its content: "auto_width" (the
+    /// default), "auto_height" or "fixed"
+    TextAutoResize,
 }
 
 /// Values that properties can hold
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PropertyValue {
     Float(f32),
     Int(i32),
@@ -77,11 +172,22 @@ pub enum PropertyValue {
     Color(Color),
     Vec2(f32, f32),
     Vec4(f32, f32, f32, f32),
+    /// Reference to another node
+    Ref(ObjectId),
+    /// Bound to a variable; read as the variable's value in the active mode
+    Variable(ObjectId),
+    /// Values keyed by node id
+    Map(Vec<(ObjectId, PropertyValue)>),
+    /// An image from the document's asset store
+    Image(ImagePaint),
+    /// A stack of paints, bottom first
+    Paints(Vec<Paint>),
+    Effects(Vec<Effect>),
 }
 
 /// RGBA color
 #[wasm_bindgen]
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
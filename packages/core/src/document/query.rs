@@ -0,0 +1,371 @@
+//! Node queries
+//!
+//! Find nodes by type, name and property values, optionally scoped to part
+//! of the tree. Queries are built up from filters and every filter must
+//! match:
+//!
+//! ```javascript
+//! const buttons = doc.query(
+//!   new NodeQuery()
+//!     .on_page(pageId)
+//!     .of_type(NodeType.Frame)
+//!     .name_glob("Button/*")
+//! );
+//!
+//! const headings = doc.query(
+//!   new NodeQuery().of_type(NodeType.Text).where_float(Property.FontSize, ">", 24)
+//! );
+//! ```
+//!
+//! Results are returned in document order (a depth-first walk that visits
+//! parents before children and siblings back to front), so they are stable
+//! from one call to the next.
+
+use super::{Color, Document, DocumentTree, Node, NodeType, ObjectId, Property, PropertyValue};
+use regex::Regex;
+use wasm_bindgen::prelude::*;
+
+/// How a property value is compared in a [`Filter::Property`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Parse `=`, `==`, `!=`, `<`, `<=`, `>` or `>=`
+    pub fn parse(op: &str) -> Option<Self> {
+        match op {
+            "=" | "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn test(self, value: &PropertyValue, expected: &PropertyValue) -> bool {
+        if let (Some(a), Some(b)) = (as_number(value), as_number(expected)) {
+            return match self {
+                Comparison::Equal => a == b,
+                Comparison::NotEqual => a != b,
+                Comparison::Less => a < b,
+                Comparison::LessOrEqual => a <= b,
+                Comparison::Greater => a > b,
+                Comparison::GreaterOrEqual => a >= b,
+            };
+        }
+
+        // Everything else only supports equality
+        let equal = match (value, expected) {
+            (PropertyValue::Color(a), PropertyValue::Color(b)) => colors_match(a, b),
+            _ => value == expected,
+        };
+        match self {
+            Comparison::Equal => equal,
+            Comparison::NotEqual => !equal,
+            _ => false,
+        }
+    }
+}
+
+/// A single condition a node must satisfy
+#[derive(Debug, Clone)]
+pub enum Filter {
+    /// Node has one of these types
+    Type(Vec<NodeType>),
+    /// Name matches a glob (`*` matches any run of characters, `?` one character)
+    NameGlob(String),
+    /// Name matches a regular expression
+    NameRegex(Regex),
+    /// Property is set and compares true against a value
+    Property {
+        property: Property,
+        comparison: Comparison,
+        value: PropertyValue,
+    },
+    /// Node is a descendant of this node
+    DescendantOf(ObjectId),
+    /// Node is an ancestor of this node
+    AncestorOf(ObjectId),
+}
+
+impl Filter {
+    fn matches(&self, tree: &DocumentTree, node: &Node) -> bool {
+        match self {
+            Filter::Type(types) => types.contains(&node.node_type()),
+            Filter::NameGlob(pattern) => glob_match(pattern, &node.name()),
+            Filter::NameRegex(regex) => regex.is_match(&node.name()),
+            Filter::Property { property, comparison, value } => node
+                .get_property(*property)
+                .is_some_and(|actual| comparison.test(actual, value)),
+            Filter::DescendantOf(ancestor) => is_ancestor(tree, *ancestor, node.id()),
+            Filter::AncestorOf(descendant) => is_ancestor(tree, node.id(), *descendant),
+        }
+    }
+}
+
+/// A set of filters plus an optional result limit
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct NodeQuery {
+    filters: Vec<Filter>,
+    limit: Option<usize>,
+}
+
+#[wasm_bindgen]
+impl NodeQuery {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only nodes of this type. Calling it again allows more types.
+    pub fn of_type(mut self, node_type: NodeType) -> NodeQuery {
+        for filter in &mut self.filters {
+            if let Filter::Type(types) = filter {
+                types.push(node_type);
+                return self;
+            }
+        }
+        self.filter(Filter::Type(vec![node_type]))
+    }
+
+    /// Only nodes whose name matches a glob such as `"Button/*"`
+    pub fn name_glob(self, pattern: &str) -> NodeQuery {
+        self.filter(Filter::NameGlob(pattern.to_string()))
+    }
+
+    /// Only nodes whose name matches a regular expression
+    pub fn name_regex(self, pattern: &str) -> Result<NodeQuery, JsValue> {
+        let regex = Regex::new(pattern).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(self.filter(Filter::NameRegex(regex)))
+    }
+
+    /// Compare a numeric property, e.g. `where_float(Property.FontSize, ">", 24)`
+    pub fn where_float(self, property: Property, op: &str, value: f32) -> Result<NodeQuery, JsValue> {
+        let comparison = parse_comparison(op)?;
+        Ok(self.filter(Filter::Property { property, comparison, value: PropertyValue::Float(value) }))
+    }
+
+    /// Compare a string property with `=` or `!=`
+    pub fn where_string(self, property: Property, op: &str, value: &str) -> Result<NodeQuery, JsValue> {
+        let comparison = parse_comparison(op)?;
+        Ok(self.filter(Filter::Property {
+            property,
+            comparison,
+            value: PropertyValue::String(value.to_string()),
+        }))
+    }
+
+    /// Match a boolean property
+    pub fn where_bool(self, property: Property, value: bool) -> NodeQuery {
+        self.filter(Filter::Property {
+            property,
+            comparison: Comparison::Equal,
+            value: PropertyValue::Bool(value),
+        })
+    }
+
+    /// Match a color property against a hex color such as `"#FF0000"`
+    pub fn where_color(self, property: Property, hex: &str) -> NodeQuery {
+        self.filter(Filter::Property {
+            property,
+            comparison: Comparison::Equal,
+            value: PropertyValue::Color(Color::from_hex(hex)),
+        })
+    }
+
+    /// Only nodes inside this node (not the node itself)
+    pub fn inside(self, ancestor: ObjectId) -> NodeQuery {
+        self.filter(Filter::DescendantOf(ancestor))
+    }
+
+    /// Only nodes on this page
+    pub fn on_page(self, page: ObjectId) -> NodeQuery {
+        self.inside(page)
+    }
+
+    /// Only nodes that contain this node (its parent, grandparent, ...)
+    pub fn containing(self, descendant: ObjectId) -> NodeQuery {
+        self.filter(Filter::AncestorOf(descendant))
+    }
+
+    /// Stop after this many results
+    pub fn limit(mut self, count: usize) -> NodeQuery {
+        self.limit = Some(count);
+        self
+    }
+}
+
+impl NodeQuery {
+    /// Add a filter
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Run the query, returning matching ids in document order
+    pub fn run(&self, tree: &DocumentTree) -> Vec<ObjectId> {
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let mut results = Vec::new();
+        if limit == 0 {
+            return results;
+        }
+
+        // Descendant scoping lets us start the walk at the scope root
+        let start = self
+            .filters
+            .iter()
+            .find_map(|filter| match filter {
+                Filter::DescendantOf(ancestor) => Some(*ancestor),
+                _ => None,
+            })
+            .unwrap_or_else(|| tree.root_id());
+
+        let mut stack = vec![start];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = tree.get(id) {
+                if self.filters.iter().all(|filter| filter.matches(tree, node)) {
+                    results.push(id);
+                    if results.len() >= limit {
+                        break;
+                    }
+                }
+            }
+            stack.extend(tree.children(id).into_iter().rev());
+        }
+        results
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Find the nodes matching a query, in document order
+    pub fn query(&self, query: &NodeQuery) -> Vec<ObjectId> {
+        query.run(&self.tree)
+    }
+
+    /// Find nodes whose name matches a glob such as `"Icon/*"`
+    pub fn find_by_name(&self, pattern: &str) -> Vec<ObjectId> {
+        NodeQuery::new().name_glob(pattern).run(&self.tree)
+    }
+}
+
+fn parse_comparison(op: &str) -> Result<Comparison, JsValue> {
+    Comparison::parse(op).ok_or_else(|| JsValue::from_str(&format!("Unknown comparison '{}'", op)))
+}
+
+fn as_number(value: &PropertyValue) -> Option<f64> {
+    match value {
+        PropertyValue::Float(v) => Some(*v as f64),
+        PropertyValue::Int(v) => Some(*v as f64),
+        _ => None,
+    }
+}
+
+/// Colors are equal if they agree at 8 bits per channel
+fn colors_match(a: &Color, b: &Color) -> bool {
+    let quantize = |c: &Color| [c.r, c.g, c.b, c.a].map(|v| (v * 255.0).round() as i32);
+    quantize(a) == quantize(b)
+}
+
+/// Whether `ancestor` is a strict ancestor of `id`
+fn is_ancestor(tree: &DocumentTree, ancestor: ObjectId, id: ObjectId) -> bool {
+    let mut current = tree.parent(id);
+    while let Some(parent) = current {
+        if parent == ancestor {
+            return true;
+        }
+        current = tree.parent(parent);
+    }
+    false
+}
+
+/// Match `text` against a glob pattern with `*` and `?` wildcards
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Greedy matching with backtracking to the most recent `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("Button/*", "Button/Primary"));
+        assert!(glob_match("*Icon?", "Small Icon1"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("Button/*", "Card/Button"));
+        assert!(!glob_match("Icon?", "Icon"));
+    }
+
+    #[test]
+    fn test_query_filters_and_scoping() {
+        let mut doc = Document::new("Query");
+        let page_id = doc.tree.first_page().unwrap();
+        let card_id = doc.create_frame(0.0, 0.0, 300.0, 200.0);
+        let title_id = doc.create_text(card_id, 10.0, 10.0, "Title");
+        let body_id = doc.create_text(card_id, 10.0, 40.0, "Body");
+        let other_id = doc.create_frame(400.0, 0.0, 100.0, 100.0);
+        let red_id = doc.create_rectangle(other_id, 0.0, 0.0, 10.0, 10.0);
+        doc.set_node_property(title_id, Property::FontSize, PropertyValue::Float(32.0));
+        doc.set_node_property(red_id, Property::FillColor, PropertyValue::Color(Color::from_hex("#FF0000")));
+        doc.set_node_property(red_id, Property::Name, PropertyValue::String("Swatch/Red".into()));
+
+        let large_text = NodeQuery::new()
+            .of_type(NodeType::Text)
+            .filter(Filter::Property {
+                property: Property::FontSize,
+                comparison: Comparison::Greater,
+                value: PropertyValue::Float(24.0),
+            });
+        assert_eq!(doc.query(&large_text), vec![title_id]);
+
+        let text_in_card = NodeQuery::new().inside(card_id).of_type(NodeType::Text);
+        assert_eq!(doc.query(&text_in_card), vec![title_id, body_id]);
+        assert_eq!(doc.query(&text_in_card.limit(1)), vec![title_id]);
+
+        let red = NodeQuery::new().on_page(page_id).where_color(Property::FillColor, "#ff0000");
+        assert_eq!(doc.query(&red), vec![red_id]);
+        assert_eq!(doc.find_by_name("Swatch/*"), vec![red_id]);
+
+        let regex = NodeQuery::new().filter(Filter::NameRegex(Regex::new("^Swatch/(Red|Blue)$").unwrap()));
+        assert_eq!(doc.query(&regex), vec![red_id]);
+
+        let frames_around = NodeQuery::new().containing(red_id).of_type(NodeType::Frame);
+        assert_eq!(doc.query(&frames_around), vec![other_id]);
+    }
+}
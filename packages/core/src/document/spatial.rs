@@ -0,0 +1,375 @@
+//! Spatial index
+//!
+//! An R-tree of every node's absolute bounds (see `get_absolute_bounds`), so
+//! "what is under the cursor", "what is inside the marquee" and "what is on
+//! screen" don't have to scan the whole document.
+//!
+//! Entries are filed under their render bounds (`get_render_bounds`), which
+//! also cover strokes and effects like drop shadows, so culling finds a node
+//! whose shadow is on screen. Every other query answers for the absolute bounds.
+//!
+//! ## Keeping It Up To Date
+//!
+//! The index is maintained lazily. Any operation that can move a node (a
+//! position, size or rotation change, an insert, remove or reparent) marks
+//! the node and its descendants as dirty; the next query re-indexes just the
+//! dirty nodes. The first query after loading builds the whole index in one
+//! bulk load.
+//!
+//! Pages, styles, variables and the document root are not indexed.
+
+use super::{Document, DocumentTree, NodeType, ObjectId, Operation, Property};
+use crate::math::Rect;
+use rstar::{Envelope, PointDistance, RTree, RTreeObject, AABB};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+
+/// A node's absolute bounds as stored in the R-tree, filed under its render
+/// bounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct IndexEntry {
+    id: ObjectId,
+    bounds: AABB<[f32; 2]>,
+    envelope: AABB<[f32; 2]>,
+}
+
+impl IndexEntry {
+    fn new(id: ObjectId, bounds: &Rect, render_bounds: &Rect) -> Self {
+        Self {
+            id,
+            bounds: aabb(bounds),
+            envelope: aabb(&bounds.union(render_bounds)),
+        }
+    }
+}
+
+fn aabb(rect: &Rect) -> AABB<[f32; 2]> {
+    AABB::from_corners([rect.x, rect.y], [rect.right(), rect.bottom()])
+}
+
+impl RTreeObject for IndexEntry {
+    type Envelope = AABB<[f32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl PointDistance for IndexEntry {
+    // Never closer than the envelope, as the R-tree requires
+    fn distance_2(&self, point: &[f32; 2]) -> f32 {
+        self.bounds.distance_2(point)
+    }
+}
+
+/// R-tree of node bounds, keyed by node id
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    tree: RTree<IndexEntry>,
+    entries: HashMap<ObjectId, IndexEntry>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an index from scratch in one pass, from each node's bounds and
+    /// render bounds
+    pub fn bulk_load(items: impl IntoIterator<Item = (ObjectId, Rect, Rect)>) -> Self {
+        let entries: HashMap<ObjectId, IndexEntry> = items
+            .into_iter()
+            .map(|(id, bounds, render_bounds)| (id, IndexEntry::new(id, &bounds, &render_bounds)))
+            .collect();
+        let tree = RTree::bulk_load(entries.values().copied().collect());
+        Self { tree, entries }
+    }
+
+    /// Add a node, or update its bounds if it is already indexed
+    pub fn insert(&mut self, id: ObjectId, bounds: Rect) {
+        self.insert_with_render_bounds(id, bounds, bounds);
+    }
+
+    /// Add a node that draws outside its bounds, or update it
+    pub fn insert_with_render_bounds(&mut self, id: ObjectId, bounds: Rect, render_bounds: Rect) {
+        self.remove(id);
+        let entry = IndexEntry::new(id, &bounds, &render_bounds);
+        self.tree.insert(entry);
+        self.entries.insert(id, entry);
+    }
+
+    pub fn remove(&mut self, id: ObjectId) {
+        if let Some(entry) = self.entries.remove(&id) {
+            self.tree.remove(&entry);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Nodes whose bounds contain the point (edges included)
+    pub fn query_point(&self, x: f32, y: f32) -> Vec<ObjectId> {
+        self.tree.locate_all_at_point(&[x, y]).map(|e| e.id).collect()
+    }
+
+    /// Nodes whose bounds intersect the rectangle
+    pub fn query_rect(&self, rect: &Rect) -> Vec<ObjectId> {
+        let envelope = aabb(rect);
+        self.tree
+            .locate_in_envelope_intersecting(&envelope)
+            .filter(|e| e.bounds.intersects(&envelope))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    /// Nodes whose render bounds intersect the rectangle
+    pub fn query_render_rect(&self, rect: &Rect) -> Vec<ObjectId> {
+        self.tree
+            .locate_in_envelope_intersecting(&aabb(rect))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    /// Nodes whose bounds lie entirely inside the rectangle
+    pub fn query_contained(&self, rect: &Rect) -> Vec<ObjectId> {
+        let envelope = aabb(rect);
+        self.tree
+            .locate_in_envelope_intersecting(&envelope)
+            .filter(|e| envelope.contains_envelope(&e.bounds))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    /// Up to `count` nodes ordered by the distance from the point to their bounds
+    pub fn nearest(&self, x: f32, y: f32, count: usize) -> Vec<ObjectId> {
+        self.tree
+            .nearest_neighbor_iter(&[x, y])
+            .take(count)
+            .map(|e| e.id)
+            .collect()
+    }
+}
+
+/// The document's index plus the nodes that need re-indexing
+#[derive(Debug, Default)]
+pub(crate) struct SpatialCache {
+    state: RefCell<SpatialState>,
+}
+
+#[derive(Debug, Default)]
+struct SpatialState {
+    index: SpatialIndex,
+    dirty: HashSet<ObjectId>,
+    built: bool,
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Nodes whose absolute bounds contain a canvas point
+    pub fn nodes_at_point(&self, x: f32, y: f32) -> Vec<ObjectId> {
+        self.with_spatial_index(|index| index.query_point(x, y))
+    }
+
+    /// Nodes whose absolute bounds intersect a canvas rectangle
+    pub fn nodes_in_rect(&self, x: f32, y: f32, width: f32, height: f32) -> Vec<ObjectId> {
+        self.with_spatial_index(|index| index.query_rect(&Rect::new(x, y, width, height)))
+    }
+
+    /// Nodes that draw into a canvas rectangle: those whose render bounds
+    /// (strokes and effects included) intersect it
+    pub fn nodes_drawn_in_rect(&self, x: f32, y: f32, width: f32, height: f32) -> Vec<ObjectId> {
+        self.with_spatial_index(|index| index.query_render_rect(&Rect::new(x, y, width, height)))
+    }
+
+    /// Nodes a marquee selection over a canvas rectangle should pick: visible,
+    /// unlocked nodes sitting directly on the active page that the rectangle
+    /// touches
+    pub fn nodes_in_marquee(&self, x: f32, y: f32, width: f32, height: f32) -> Vec<ObjectId> {
+        let active_page = self.get_active_page();
+        self.nodes_in_rect(x, y, width, height)
+            .into_iter()
+            .filter(|&id| {
+                let on_page = active_page.is_some() && self.tree.parent(id) == active_page;
+                let selectable = self.tree.get(id).is_some_and(|n| n.visible() && !n.locked());
+                on_page && selectable
+            })
+            .collect()
+    }
+
+    /// The `count` nodes closest to a canvas point, nearest first
+    pub fn nearest_nodes(&self, x: f32, y: f32, count: usize) -> Vec<ObjectId> {
+        self.with_spatial_index(|index| index.nearest(x, y, count))
+    }
+}
+
+impl Document {
+    /// Run a query against the spatial index, bringing it up to date first
+    pub(crate) fn with_spatial_index<R>(&self, query: impl FnOnce(&SpatialIndex) -> R) -> R {
+        let mut state = self.spatial.state.borrow_mut();
+
+        if !state.built {
+            let items: Vec<(ObjectId, Rect, Rect)> = self
+                .tree
+                .iter()
+                .filter(|node| is_indexed(node.node_type()))
+                .filter_map(|node| {
+                    let id = node.id();
+                    Some((id, self.get_absolute_bounds(id)?, self.get_render_bounds(id)?))
+                })
+                .collect();
+            state.index = SpatialIndex::bulk_load(items);
+            state.dirty.clear();
+            state.built = true;
+        }
+
+        let dirty: Vec<ObjectId> = state.dirty.drain().collect();
+        for id in dirty {
+            match self.tree.get(id) {
+                Some(node) if is_indexed(node.node_type()) => {
+                    let bounds = self.get_absolute_bounds(id).unwrap_or_default();
+                    let render_bounds = self.get_render_bounds(id).unwrap_or(bounds);
+                    state.index.insert_with_render_bounds(id, bounds, render_bounds);
+                }
+                _ => state.index.remove(id),
+            }
+        }
+
+        query(&state.index)
+    }
+
+    /// Mark the nodes an applied operation may have moved
+    pub(crate) fn invalidate_spatial(&mut self, inverse: &Operation) {
+        let state = self.spatial.state.get_mut();
+        if !state.built {
+            return;
+        }
+        match inverse {
+            // Any node may be bound to the variable
+            Operation::SetProperty { property: Property::VariableValues, .. } => state.built = false,
+            // Any node may use the style
+            Operation::SetProperty { id, .. }
+                if self.tree.get(*id).is_some_and(|node| node.node_type() == NodeType::Style) =>
+            {
+                state.built = false
+            }
+            Operation::SetProperty { property, .. } if !affects_bounds(*property) => {}
+            Operation::SetOverride { .. } => {}
+            // The subtree was just removed
+            Operation::Insert(subtree) => {
+                state.dirty.extend(subtree.nodes().map(|node| node.id()));
+            }
+            _ => mark_subtree(&self.tree, inverse.target(), &mut state.dirty),
+        }
+    }
+}
+
+fn mark_subtree(tree: &DocumentTree, id: ObjectId, dirty: &mut HashSet<ObjectId>) {
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        dirty.insert(id);
+        stack.extend(tree.children(id));
+    }
+}
+
+fn is_indexed(node_type: NodeType) -> bool {
+    !matches!(
+        node_type,
+        NodeType::Document
+            | NodeType::Page
+            | NodeType::Style
+            | NodeType::VariableCollection
+            | NodeType::VariableMode
+            | NodeType::Variable
+    )
+}
+
+/// Properties that can change a node's absolute or render bounds
+fn affects_bounds(property: Property) -> bool {
+    matches!(
+        property,
+        Property::X
+            | Property::Y
+            | Property::Width
+            | Property::Height
+            | Property::Rotation
+            | Property::VariableModes
+            | Property::ShadowColor
+            | Property::ShadowOffsetX
+            | Property::ShadowOffsetY
+            | Property::ShadowBlur
+            | Property::ShadowSpread
+            | Property::BlurRadius
+            | Property::Effects
+            | Property::EffectStyle
+            | Property::StrokeWidth
+            | Property::StrokeStyle
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Color;
+
+    #[test]
+    fn test_index_queries() {
+        let mut index = SpatialIndex::new();
+        let a = ObjectId::new(1, 1);
+        let b = ObjectId::new(1, 2);
+        index.insert(a, Rect::new(0.0, 0.0, 10.0, 10.0));
+        index.insert(b, Rect::new(20.0, 0.0, 10.0, 10.0));
+
+        assert_eq!(index.query_point(5.0, 5.0), vec![a]);
+        assert!(index.query_point(15.0, 5.0).is_empty());
+        assert_eq!(index.query_rect(&Rect::new(8.0, 0.0, 4.0, 4.0)), vec![a]);
+        assert_eq!(index.query_contained(&Rect::new(15.0, -5.0, 20.0, 20.0)), vec![b]);
+        assert_eq!(index.nearest(19.0, 5.0, 2), vec![b, a]);
+
+        // Re-inserting moves the entry instead of duplicating it
+        index.insert(a, Rect::new(100.0, 100.0, 10.0, 10.0));
+        assert_eq!(index.len(), 2);
+        assert!(index.query_point(5.0, 5.0).is_empty());
+    }
+
+    #[test]
+    fn test_document_index_tracks_changes() {
+        let mut doc = Document::new("Spatial");
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 10.0, 20.0, 20.0);
+        assert_eq!(doc.nodes_at_point(15.0, 15.0).len(), 2);
+
+        // Moving the frame moves its child in the index too
+        doc.set_node_x(frame_id, 500.0);
+        assert!(doc.nodes_at_point(15.0, 15.0).is_empty());
+        assert!(doc.nodes_at_point(515.0, 15.0).contains(&rect_id));
+
+        doc.delete_node(rect_id);
+        assert_eq!(doc.nodes_at_point(515.0, 15.0), vec![frame_id]);
+
+        assert!(doc.undo());
+        assert!(doc.nodes_at_point(515.0, 15.0).contains(&rect_id));
+    }
+
+    #[test]
+    fn test_document_index_tracks_effect_styles() {
+        let mut doc = Document::new("Spatial");
+        let page_id = doc.get_active_page().unwrap();
+        let rect_id = doc.create_rectangle(page_id, 0.0, 0.0, 20.0, 20.0);
+        let style_id = doc.create_style("effect", "Shadow").unwrap();
+        assert!(doc.set_style_color(style_id, Property::ShadowColor, Color::black()));
+        assert!(doc.set_style_float(style_id, Property::ShadowOffsetX, 100.0));
+        assert!(doc.nodes_drawn_in_rect(105.0, 5.0, 1.0, 1.0).is_empty());
+
+        // Applying the style, then editing it, moves the shadow in the index
+        assert!(doc.apply_style(rect_id, style_id));
+        assert_eq!(doc.nodes_drawn_in_rect(105.0, 5.0, 1.0, 1.0), vec![rect_id]);
+        assert!(doc.set_style_float(style_id, Property::ShadowOffsetX, 200.0));
+        assert_eq!(doc.nodes_drawn_in_rect(205.0, 5.0, 1.0, 1.0), vec![rect_id]);
+    }
+}
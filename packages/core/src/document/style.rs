@@ -0,0 +1,408 @@
+//! Shared styles
+//!
+//! A style is a named set of property values that any number of nodes can
+//! reference, so changing a brand color means editing one style instead of
+//! every node that uses it. Styles are `Style` nodes stored directly under
+//! the document root (next to the pages), which means they are saved, synced
+//! and undone like everything else.
+//!
+//! ```text
+//! Document
+//!   ├─ Page 1
+//!   │    └─ Rectangle  FillStyle = Ref(brand)   FillColor = #000000 (ignored)
+//!   └─ Style "Brand"   StyleType = "fill"       FillColor = #0D99FF
+//! ```
+//!
+//! | Kind       | Reference     | Properties                                          |
+//! |------------|---------------|-----------------------------------------------------|
+//! | `"fill"`   | `FillStyle`   | fill color, opacity                                 |
+//! | `"stroke"` | `StrokeStyle` | stroke color, width, opacity, align, cap, join      |
+//! | `"text"`   | `TextStyle`   | font family, size, weight, style, line height, letter spacing |
+//! | `"effect"` | `EffectStyle` | shadow color, offsets, blur, spread; layer blur     |
+//!
+//! ## Resolution
+//!
+//! Node values are resolved when they are read (see
+//! `Document::resolved_node`): a property the node's style defines wins over
+//! the node's own value. Editing a style therefore updates every consumer
+//! without touching them.
+//!
+//! Setting one of a style's properties directly on a node detaches the node
+//! from that style first. Detaching copies the resolved values onto the node
+//! and drops the reference, so nothing changes visually. Deleting a style
+//! detaches all of its consumers.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree};
+use crate::document::Color;
+use wasm_bindgen::prelude::*;
+
+/// What a style applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleKind {
+    Fill,
+    Stroke,
+    Text,
+    Effect,
+}
+
+impl StyleKind {
+    pub const ALL: [StyleKind; 4] = [StyleKind::Fill, StyleKind::Stroke, StyleKind::Text, StyleKind::Effect];
+
+    /// Parse `"fill"`, `"stroke"`, `"text"` or `"effect"`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "fill" => Some(StyleKind::Fill),
+            "stroke" => Some(StyleKind::Stroke),
+            "text" => Some(StyleKind::Text),
+            "effect" => Some(StyleKind::Effect),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StyleKind::Fill => "fill",
+            StyleKind::Stroke => "stroke",
+            StyleKind::Text => "text",
+            StyleKind::Effect => "effect",
+        }
+    }
+
+    /// The kind of a style node
+    pub fn of(node: &Node) -> Option<Self> {
+        if node.node_type() != NodeType::Style {
+            return None;
+        }
+        match node.get_property(Property::StyleType) {
+            Some(PropertyValue::String(s)) => StyleKind::parse(s),
+            _ => None,
+        }
+    }
+
+    /// The property a node uses to reference a style of this kind
+    pub fn reference(&self) -> Property {
+        match self {
+            StyleKind::Fill => Property::FillStyle,
+            StyleKind::Stroke => Property::StrokeStyle,
+            StyleKind::Text => Property::TextStyle,
+            StyleKind::Effect => Property::EffectStyle,
+        }
+    }
+
+    /// The properties a style of this kind defines
+    pub fn properties(&self) -> &'static [Property] {
+        match self {
+            StyleKind::Fill => &[Property::FillColor, Property::FillOpacity, Property::ImageFill, Property::Fills],
+            StyleKind::Stroke => &[
+                Property::StrokeColor,
+                Property::StrokeWidth,
+                Property::StrokeOpacity,
+                Property::StrokeAlign,
+                Property::StrokeCap,
+                Property::StrokeJoin,
+                Property::Strokes,
+            ],
+            StyleKind::Text => &[
+                Property::FontFamily,
+                Property::FontSize,
+                Property::FontWeight,
+                Property::FontStyle,
+                Property::LineHeight,
+                Property::LetterSpacing,
+            ],
+            StyleKind::Effect => &[
+                Property::ShadowColor,
+                Property::ShadowOffsetX,
+                Property::ShadowOffsetY,
+                Property::ShadowBlur,
+                Property::ShadowSpread,
+                Property::BlurRadius,
+                Property::Effects,
+            ],
+        }
+    }
+
+    /// The kind of style that defines a property, if any
+    pub fn of_property(property: Property) -> Option<Self> {
+        StyleKind::ALL.into_iter().find(|kind| kind.properties().contains(&property))
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Create an empty style of a kind (`"fill"`, `"stroke"`, `"text"` or
+    /// `"effect"`). Returns `None` for an unknown kind.
+    pub fn create_style(&mut self, kind: &str, name: &str) -> Option<ObjectId> {
+        let kind = StyleKind::parse(kind)?;
+        Some(self.insert_style(kind, name))
+    }
+
+    /// Create a style from a node's current values and apply it to the node
+    pub fn create_style_from_node(&mut self, node_id: ObjectId, kind: &str, name: &str) -> Option<ObjectId> {
+        let node = self.resolved_node(node_id)?;
+        let kind = StyleKind::parse(kind)?;
+
+        self.history.begin_group();
+        let style_id = self.insert_style(kind, name);
+        for &property in kind.properties() {
+            if let Some(value) = node.get_property(property) {
+                self.set_node_property(style_id, property, value.clone());
+            }
+        }
+        self.apply_style(node_id, style_id);
+        self.history.end_group();
+        Some(style_id)
+    }
+
+    /// All styles of a kind, in creation order
+    pub fn get_styles(&self, kind: &str) -> Vec<ObjectId> {
+        let Some(kind) = StyleKind::parse(kind) else {
+            return Vec::new();
+        };
+        self.tree
+            .children(self.tree.root_id())
+            .into_iter()
+            .filter(|&id| self.tree.get(id).and_then(StyleKind::of) == Some(kind))
+            .collect()
+    }
+
+    pub fn rename_style(&mut self, style_id: ObjectId, name: &str) -> bool {
+        if self.style_kind(style_id).is_none() {
+            return false;
+        }
+        self.set_node_property(style_id, Property::Name, PropertyValue::String(name.into()));
+        true
+    }
+
+    /// Set a number on a style. Returns `false` if the property doesn't
+    /// belong to the style's kind.
+    pub fn set_style_float(&mut self, style_id: ObjectId, property: Property, value: f32) -> bool {
+        self.set_style_value(style_id, property, PropertyValue::Float(value))
+    }
+
+    /// Set a color on a style. Returns `false` if the property doesn't
+    /// belong to the style's kind.
+    pub fn set_style_color(&mut self, style_id: ObjectId, property: Property, color: Color) -> bool {
+        self.set_style_value(style_id, property, PropertyValue::Color(color))
+    }
+
+    /// Set a string on a style. Returns `false` if the property doesn't
+    /// belong to the style's kind.
+    pub fn set_style_string(&mut self, style_id: ObjectId, property: Property, value: &str) -> bool {
+        self.set_style_value(style_id, property, PropertyValue::String(value.into()))
+    }
+
+    /// Make a node use a style, replacing any other style of the same kind
+    pub fn apply_style(&mut self, node_id: ObjectId, style_id: ObjectId) -> bool {
+        let Some(kind) = self.style_kind(style_id) else {
+            return false;
+        };
+        let styleable = self
+            .tree
+            .get(node_id)
+            .is_some_and(|n| !matches!(n.node_type(), NodeType::Document | NodeType::Page | NodeType::Style));
+        if !styleable {
+            return false;
+        }
+        self.set_node_property(node_id, kind.reference(), PropertyValue::Ref(style_id));
+        true
+    }
+
+    /// The style of a kind applied to a node
+    pub fn get_applied_style(&self, node_id: ObjectId, kind: &str) -> Option<ObjectId> {
+        let kind = StyleKind::parse(kind)?;
+        match self.tree.get(node_id)?.get_property(kind.reference()) {
+            Some(PropertyValue::Ref(id)) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Stop a node from using its style of a kind, keeping its current look.
+    ///
+    /// Returns `false` if no such style is applied.
+    pub fn detach_style(&mut self, node_id: ObjectId, kind: &str) -> bool {
+        let Some(kind) = StyleKind::parse(kind) else {
+            return false;
+        };
+        self.detach(node_id, kind)
+    }
+
+    /// Nodes that reference a style
+    pub fn get_style_consumers(&self, style_id: ObjectId) -> Vec<ObjectId> {
+        let Some(kind) = self.style_kind(style_id) else {
+            return Vec::new();
+        };
+        let reference = PropertyValue::Ref(style_id);
+        self.tree
+            .iter()
+            .filter(|node| node.get_property(kind.reference()) == Some(&reference))
+            .map(|node| node.id())
+            .collect()
+    }
+
+    /// Delete a style. Its consumers are detached first, so they keep their
+    /// appearance.
+    pub fn delete_style(&mut self, style_id: ObjectId) -> bool {
+        let Some(kind) = self.style_kind(style_id) else {
+            return false;
+        };
+        self.history.begin_group();
+        for consumer in self.get_style_consumers(style_id) {
+            self.detach(consumer, kind);
+        }
+        self.apply(Operation::Remove { id: style_id });
+        self.history.end_group();
+        true
+    }
+}
+
+impl Document {
+    /// A copy of a node with the values of its styles and the variables it
+    /// is bound to filled in
+    pub(crate) fn resolved_node(&self, id: ObjectId) -> Option<Node> {
+        let mut node = self.get_node(id)?;
+        for kind in StyleKind::ALL {
+            let Some(style) = self.applied_style(&node, kind) else { continue };
+            for &property in kind.properties() {
+                if let Some(value) = style.get_property(property) {
+                    node.set_property(property, value.clone());
+                }
+            }
+        }
+        // After the styles, so style values bound to variables resolve too
+        self.resolve_bindings(&mut node);
+        Some(node)
+    }
+
+    /// Detach a node from the style that defines `property`, if it has one,
+    /// so that a local edit of the property sticks
+    pub(crate) fn detach_style_for(&mut self, id: ObjectId, property: Property) {
+        if let Some(kind) = StyleKind::of_property(property) {
+            self.detach(id, kind);
+        }
+    }
+
+    fn insert_style(&mut self, kind: StyleKind, name: &str) -> ObjectId {
+        let style_id = ObjectId::random();
+        let mut style = Node::new(style_id, NodeType::Style);
+        style.set_property(Property::StyleType, PropertyValue::String(kind.as_str().into()));
+        style.set_property(Property::Name, PropertyValue::String(name.into()));
+
+        let root_id = self.tree.root_id();
+        self.apply(Operation::Insert(Subtree::leaf(style, Some(root_id))));
+        style_id
+    }
+
+    fn detach(&mut self, node_id: ObjectId, kind: StyleKind) -> bool {
+        let Some(node) = self.tree.get(node_id) else { return false };
+        let Some(style) = self.applied_style(node, kind) else {
+            return false;
+        };
+        let values: Vec<(Property, PropertyValue)> = kind
+            .properties()
+            .iter()
+            .filter_map(|&property| Some((property, style.get_property(property)?.clone())))
+            .collect();
+
+        self.history.begin_group();
+        let reference = kind.reference();
+        if self.apply(Operation::SetProperty { id: node_id, property: reference, value: None }) {
+            self.mark_override(node_id, reference);
+        }
+        for (property, value) in values {
+            self.set_node_property(node_id, property, value);
+        }
+        self.history.end_group();
+        true
+    }
+
+    /// The style node a node references for a kind, if it still exists
+    fn applied_style(&self, node: &Node, kind: StyleKind) -> Option<&Node> {
+        let Some(PropertyValue::Ref(style_id)) = node.get_property(kind.reference()) else {
+            return None;
+        };
+        self.tree.get(*style_id).filter(|style| StyleKind::of(style) == Some(kind))
+    }
+
+    fn style_kind(&self, style_id: ObjectId) -> Option<StyleKind> {
+        self.tree.get(style_id).and_then(StyleKind::of)
+    }
+
+    fn set_style_value(&mut self, style_id: ObjectId, property: Property, value: PropertyValue) -> bool {
+        match self.style_kind(style_id) {
+            Some(kind) if kind.properties().contains(&property) => {
+                self.set_node_property(style_id, property, value);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill(doc: &Document, id: ObjectId) -> Color {
+        doc.resolved_node(id).unwrap().fill_color()
+    }
+
+    #[test]
+    fn test_style_edits_reach_consumers() {
+        let mut doc = Document::new("Styles");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let a = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        let b = doc.create_rectangle(frame_id, 20.0, 0.0, 10.0, 10.0);
+
+        let brand = doc.create_style("fill", "Brand").unwrap();
+        assert!(doc.set_style_color(brand, Property::FillColor, Color::red()));
+        assert!(!doc.set_style_float(brand, Property::FontSize, 12.0));
+        assert!(doc.apply_style(a, brand));
+        assert!(doc.apply_style(b, brand));
+        assert_eq!(doc.get_styles("fill"), vec![brand]);
+        assert_eq!(doc.get_style_consumers(brand).len(), 2);
+
+        doc.set_style_color(brand, Property::FillColor, Color::blue());
+        assert_eq!(fill(&doc, a), Color::blue());
+        assert_eq!(fill(&doc, b), Color::blue());
+
+        // Detaching keeps the look but stops following the style
+        assert!(doc.detach_style(a, "fill"));
+        assert_eq!(doc.get_applied_style(a, "fill"), None);
+        doc.set_style_color(brand, Property::FillColor, Color::green());
+        assert_eq!(fill(&doc, a), Color::blue());
+        assert_eq!(fill(&doc, b), Color::green());
+
+        // A collaborator's value doesn't; their detach arrives by itself
+        doc.apply_remote_property(b, Property::FillColor, PropertyValue::Color(Color::black()));
+        assert_eq!(doc.get_applied_style(b, "fill"), Some(brand));
+        assert_eq!(fill(&doc, b), Color::green());
+
+        // A local edit detaches too
+        doc.set_node_property(b, Property::FillColor, PropertyValue::Color(Color::black()));
+        assert_eq!(doc.get_applied_style(b, "fill"), None);
+        assert_eq!(fill(&doc, b), Color::black());
+    }
+
+    #[test]
+    fn test_delete_style_detaches_consumers() {
+        let mut doc = Document::new("Styles");
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let text_id = doc.create_text(frame_id, 0.0, 0.0, "Hello");
+        doc.set_node_property(text_id, Property::FontSize, PropertyValue::Float(24.0));
+
+        let heading = doc.create_style_from_node(text_id, "text", "Heading").unwrap();
+        assert_eq!(doc.get_applied_style(text_id, "text"), Some(heading));
+        doc.set_style_float(heading, Property::FontSize, 32.0);
+
+        assert!(doc.delete_style(heading));
+        assert!(!doc.has_node(heading));
+        let text = doc.resolved_node(text_id).unwrap();
+        assert_eq!(text.get_property(Property::FontSize), Some(&PropertyValue::Float(32.0)));
+        assert_eq!(doc.get_applied_style(text_id, "text"), None);
+
+        // Deleting the style and detaching is one undo step
+        assert!(doc.undo());
+        assert_eq!(doc.get_applied_style(text_id, "text"), Some(heading));
+    }
+}
@@ -0,0 +1,430 @@
+//! Text nodes
+//!
+//! A text node's content is its `Text`, laid out by [`crate::text`] with
+//! the font matched from `FontFamily`, `FontWeight` and `FontStyle`. Its
+//! size follows the content according to `TextAutoResize`:
+//!
+//! ```text
+//! auto_width   Width and Height fit the text; lines only break at \n (default)
+//! auto_height  Width stays; lines wrap to it and Height fits them
+//! fixed        Width and Height stay; lines wrap to Width and may overflow
+//! ```
+//!
+//! `Document::apply()` re-measures a text node when it is inserted, when its
+//! text or a property its layout depends on changes (directly, through its
+//! text style or through a bound variable's value or mode) and, for auto
+//! height, when its width changes. The new size is
+//! written with normal operations, so it is undone with the edit and auto
+//! layout frames around the text follow it. The layouts drawn are cached
+//! per node and forgotten on the same changes, including undo.
+//!
+//! Fonts are registered at runtime with `add_font()` and aren't saved with
+//! the document. Until a font is loaded, texts are measured with fallback
+//! metrics; adding a font re-measures every text.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue};
+use crate::text::{layout_text, FontFace, TextAlign, TextFont, TextLayout, TextStyle};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+/// Font size of text nodes that don't set one
+const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+/// Font family of text nodes that don't set one
+const DEFAULT_FONT_FAMILY: &str = "Inter";
+
+/// Font weight of text nodes that don't set one (regular)
+const DEFAULT_FONT_WEIGHT: f32 = 400.0;
+
+/// How a text node's size follows its content
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAutoResize {
+    Fixed,
+    AutoHeight,
+    #[default]
+    AutoWidth,
+}
+
+impl TextAutoResize {
+    pub fn parse(mode: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::from(mode)).ok()
+    }
+}
+
+/// Text nodes waiting to be measured after a change
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextQueue {
+    pending: Vec<ObjectId>,
+    running: bool,
+}
+
+/// Layouts of text nodes as last drawn (see `Document::text_layout`)
+#[derive(Debug, Default)]
+pub(crate) struct TextLayoutCache {
+    layouts: RefCell<HashMap<ObjectId, Rc<TextLayout>>>,
+}
+
+/// The layout settings of a text node
+pub fn text_style(node: &Node) -> TextStyle {
+    TextStyle {
+        font_size: float_property(node, Property::FontSize).unwrap_or(DEFAULT_FONT_SIZE),
+        line_height: float_property(node, Property::LineHeight),
+        letter_spacing: float_property(node, Property::LetterSpacing).unwrap_or(0.0),
+        align: string_property(node, Property::TextAlign).and_then(TextAlign::parse).unwrap_or_default(),
+    }
+}
+
+impl Document {
+    /// Fit every text to its content again, without recording history
+    fn remeasure_texts(&mut self) {
+        self.text_layouts.layouts.get_mut().clear();
+        let texts: Vec<ObjectId> =
+            self.tree.iter().filter(|node| node.node_type() == NodeType::Text).map(|node| node.id()).collect();
+        self.untracked(|doc| {
+            for id in texts {
+                doc.queue_text(id);
+            }
+            doc.run_text();
+        });
+    }
+}
+
+/// How a text node is sized
+pub fn text_auto_resize(node: &Node) -> TextAutoResize {
+    string_property(node, Property::TextAutoResize).and_then(TextAutoResize::parse).unwrap_or_default()
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Register a TrueType or OpenType font under a family name, weight
+    /// (100 to 900) and style, then re-measure every text. The new sizes
+    /// aren't an edit, so they stay out of the undo history. Returns `false`
+    /// if the file can't be read.
+    pub fn add_font(&mut self, family: &str, weight: f32, italic: bool, bytes: &[u8]) -> bool {
+        if self.fonts.add(family, weight, italic, bytes).is_err() {
+            return false;
+        }
+        self.remeasure_texts();
+        true
+    }
+
+    /// Whether a font of this family has been added
+    pub fn has_font(&self, family: &str) -> bool {
+        self.fonts.has_family(family)
+    }
+
+    /// Replace the content of a text node
+    pub fn set_text(&mut self, id: ObjectId, text: &str) -> bool {
+        if !self.is_text(id) {
+            return false;
+        }
+        self.set_node_property(id, Property::Text, PropertyValue::String(text.into()));
+        true
+    }
+
+    /// "auto_width", "auto_height" or "fixed"
+    pub fn get_text_auto_resize(&self, id: ObjectId) -> String {
+        let mode = self.tree.get(id).map(|node| text_auto_resize(&self.bound_node(node))).unwrap_or_default();
+        serde_json::to_value(mode).ok().and_then(|v| v.as_str().map(String::from)).unwrap_or_default()
+    }
+
+    pub fn set_text_auto_resize(&mut self, id: ObjectId, mode: &str) -> bool {
+        if TextAutoResize::parse(mode).is_none() || !self.is_text(id) {
+            return false;
+        }
+        self.set_node_property(id, Property::TextAutoResize, PropertyValue::String(mode.into()));
+        true
+    }
+
+    /// A text node's glyph positions, lines and measured size as JSON (see
+    /// `TextLayout`), in its local space
+    pub fn get_text_layout(&self, id: ObjectId) -> String {
+        let layout = self.resolved_node(id).filter(|node| node.node_type() == NodeType::Text);
+        let layout = layout.map(|node| self.layout_text_node(&node)).unwrap_or_default();
+        serde_json::to_string(&layout).unwrap_or_default()
+    }
+}
+
+impl Document {
+    /// The font a (resolved) text node is drawn with, if any font is loaded
+    pub fn font_for(&self, node: &Node) -> Option<&FontFace> {
+        let family = string_property(node, Property::FontFamily).unwrap_or(DEFAULT_FONT_FAMILY);
+        let weight = float_property(node, Property::FontWeight).unwrap_or(DEFAULT_FONT_WEIGHT);
+        let italic = string_property(node, Property::FontStyle) == Some("italic");
+        self.fonts.find(family, weight, italic)
+    }
+
+    /// Lay out a (resolved) text node, wrapped to its width unless it is
+    /// auto width
+    pub fn layout_text_node(&self, node: &Node) -> TextLayout {
+        let font = self.font_for(node).map_or(TextFont::Fallback, |face| TextFont::Font(face.font()));
+        let max_width = match text_auto_resize(node) {
+            TextAutoResize::AutoWidth => None,
+            TextAutoResize::AutoHeight | TextAutoResize::Fixed => Some(node.width()),
+        };
+        let text = string_property(node, Property::Text).unwrap_or_default();
+        layout_text(text, font, &text_style(node), max_width)
+    }
+
+    /// Queue the text nodes an applied operation may have resized
+    pub(crate) fn invalidate_text(&mut self, inverse: &Operation) {
+        if let Operation::SetProperty { id, property: Property::Width, .. } = inverse {
+            let auto_height = self
+                .tree
+                .get(*id)
+                .is_some_and(|node| text_auto_resize(&self.bound_node(node)) == TextAutoResize::AutoHeight);
+            if auto_height {
+                self.queue_text(*id);
+            }
+            return;
+        }
+        for id in self.changed_texts(inverse) {
+            self.queue_text(id);
+        }
+    }
+
+    /// Forget the cached layouts an applied (or undone) operation may have
+    /// changed
+    pub(crate) fn invalidate_text_layouts(&mut self, inverse: &Operation) {
+        let changed = match inverse {
+            // Wrapped lines follow the width
+            Operation::SetProperty { id, property: Property::Width, .. } => vec![*id],
+            // Removed: its ids may come back with an undo
+            Operation::Insert(subtree) => subtree.nodes().map(|node| node.id()).collect(),
+            _ => self.changed_texts(inverse),
+        };
+        let layouts = self.text_layouts.layouts.get_mut();
+        for id in changed {
+            layouts.remove(&id);
+        }
+    }
+
+    /// The text nodes whose content or layout settings an applied operation
+    /// may have changed
+    fn changed_texts(&self, inverse: &Operation) -> Vec<ObjectId> {
+        let mut texts = Vec::new();
+        match inverse {
+            // Texts (or text styles) bound to the variable take its new value
+            Operation::SetProperty { id, property: Property::VariableValues, .. }
+                if self.tree.get(*id).map(Node::node_type) == Some(NodeType::Variable) =>
+            {
+                for consumer in self.get_variable_consumers(*id) {
+                    self.collect_text_or_consumers(consumer, &mut texts);
+                }
+            }
+            // Bound values in the subtree may resolve differently
+            Operation::SetProperty { id, property: Property::VariableModes, .. } => {
+                self.collect_subtree_texts(*id, &mut texts)
+            }
+            Operation::SetProperty { id, property, .. } if affects_text_layout(*property) => {
+                self.collect_text_or_consumers(*id, &mut texts);
+            }
+            // Inserted: any text in the new subtree
+            Operation::Remove { id } => self.collect_subtree_texts(*id, &mut texts),
+            _ => {}
+        }
+        texts.retain(|&id| self.is_text(id));
+        texts
+    }
+
+    /// A text node, or every consumer of a text style
+    fn collect_text_or_consumers(&self, id: ObjectId, texts: &mut Vec<ObjectId>) {
+        // A text style's consumers take its values when read
+        let is_style = self.tree.get(id).is_some_and(|node| node.node_type() == NodeType::Style);
+        if !is_style {
+            texts.push(id);
+            return;
+        }
+        let style = PropertyValue::Ref(id);
+        let consumers = self.tree.iter().filter(|node| node.get_property(Property::TextStyle) == Some(&style));
+        texts.extend(consumers.map(|node| node.id()));
+    }
+
+    fn collect_subtree_texts(&self, id: ObjectId, texts: &mut Vec<ObjectId>) {
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            texts.push(node_id);
+            stack.extend(self.tree.children(node_id));
+        }
+    }
+
+    /// The layout of a (resolved) text node as drawn, computed once until
+    /// the node or a font changes
+    pub fn text_layout(&self, node: &Node) -> Rc<TextLayout> {
+        if let Some(layout) = self.text_layouts.layouts.borrow().get(&node.id()) {
+            return Rc::clone(layout);
+        }
+        let layout = Rc::new(self.layout_text_node(node));
+        self.text_layouts.layouts.borrow_mut().insert(node.id(), Rc::clone(&layout));
+        layout
+    }
+
+    /// Measure every queued text node until the queue is empty
+    pub(crate) fn run_text(&mut self) {
+        if self.text_queue.running {
+            return;
+        }
+        self.text_queue.running = true;
+        while let Some(id) = self.text_queue.pending.pop() {
+            self.fit_text(id);
+        }
+        self.text_queue.running = false;
+    }
+
+    fn queue_text(&mut self, id: ObjectId) {
+        if self.is_text(id) && !self.text_queue.pending.contains(&id) {
+            self.text_queue.pending.push(id);
+        }
+    }
+
+    fn is_text(&self, id: ObjectId) -> bool {
+        self.tree.get(id).is_some_and(|node| node.node_type() == NodeType::Text)
+    }
+
+    /// Size a text node to its content, as far as its resize mode allows
+    fn fit_text(&mut self, id: ObjectId) {
+        let Some(node) = self.resolved_node(id) else {
+            return;
+        };
+        let layout = self.layout_text_node(&node);
+        match text_auto_resize(&node) {
+            TextAutoResize::AutoWidth => {
+                self.set_if_changed(id, Property::Width, layout.width);
+                self.set_if_changed(id, Property::Height, layout.height);
+            }
+            TextAutoResize::AutoHeight => self.set_if_changed(id, Property::Height, layout.height),
+            TextAutoResize::Fixed => {}
+        }
+    }
+}
+
+/// Properties that change how a text is laid out (or, on a style, how its
+/// consumers are)
+fn affects_text_layout(property: Property) -> bool {
+    matches!(
+        property,
+        Property::Text
+            | Property::FontFamily
+            | Property::FontSize
+            | Property::FontWeight
+            | Property::FontStyle
+            | Property::LineHeight
+            | Property::LetterSpacing
+            | Property::TextAlign
+            | Property::TextAutoResize
+            | Property::TextStyle
+    )
+}
+
+fn float_property(node: &Node, property: Property) -> Option<f32> {
+    match node.get_property(property) {
+        Some(PropertyValue::Float(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+fn string_property(node: &Node, property: Property) -> Option<&str> {
+    match node.get_property(property) {
+        Some(PropertyValue::String(s)) => Some(s.as_str()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_size_follows_content() {
+        let mut doc = Document::new("Text");
+        let page_id = doc.get_active_page().unwrap();
+        // Fallback metrics: 8 wide per character and 19.2 per line at 16
+        let text_id = doc.create_text(page_id, 0.0, 0.0, "Hello");
+        assert_eq!((doc.get_node_width(text_id), doc.get_node_height(text_id)), (40.0, 19.2));
+
+        assert!(doc.set_text(text_id, "Hello\nworld!"));
+        assert_eq!((doc.get_node_width(text_id), doc.get_node_height(text_id)), (48.0, 38.4));
+
+        // Auto height wraps to the width
+        assert!(doc.set_text_auto_resize(text_id, "auto_height"));
+        doc.set_node_width(text_id, 30.0);
+        assert_eq!(doc.get_node_height(text_id), 19.2 * 4.0);
+        assert_eq!(doc.get_node_width(text_id), 30.0);
+
+        // Fixed keeps its size
+        assert!(doc.set_text_auto_resize(text_id, "fixed"));
+        doc.set_node_property(text_id, Property::FontSize, PropertyValue::Float(32.0));
+        assert_eq!(doc.get_node_height(text_id), 19.2 * 4.0);
+
+        // The edit and the resize undo together
+        assert!(doc.set_text_auto_resize(text_id, "auto_width"));
+        assert_eq!(doc.get_node_width(text_id), 96.0);
+        assert!(doc.undo());
+        assert_eq!(doc.get_node_width(text_id), 30.0);
+    }
+
+    #[test]
+    fn test_remeasure_stays_out_of_history() {
+        let mut doc = Document::new("Text");
+        let page_id = doc.get_active_page().unwrap();
+        let text_id = doc.create_text(page_id, 0.0, 0.0, "Hi");
+        doc.clear_history();
+        assert!(doc.set_text(text_id, "Hello"));
+        assert!(doc.undo());
+
+        // As if a font had just been added: the measured size is stale
+        doc.tree.get_mut(text_id).unwrap().set_property(Property::Width, PropertyValue::Float(100.0));
+        doc.remeasure_texts();
+        assert_eq!(doc.get_node_width(text_id), 16.0);
+        assert!(!doc.can_undo());
+        assert!(doc.redo());
+        assert_eq!(doc.get_node_width(text_id), 40.0);
+    }
+
+    #[test]
+    fn test_bound_font_size_remeasures() {
+        let mut doc = Document::new("Text");
+        let collection = doc.create_variable_collection("Type");
+        let light = doc.get_variable_modes(collection)[0];
+        let large = doc.add_variable_mode(collection, "Large").unwrap();
+        let size = doc.create_variable(collection, "number", "body").unwrap();
+        doc.set_variable_number(size, light, 16.0);
+        doc.set_variable_number(size, large, 32.0);
+
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let text_id = doc.create_text(frame_id, 0.0, 0.0, "Hello");
+        assert!(doc.bind_variable(text_id, Property::FontSize, size));
+        assert_eq!(doc.get_node_height(text_id), 19.2);
+
+        doc.set_variable_number(size, light, 8.0);
+        assert_eq!(doc.get_node_height(text_id), 9.6);
+        assert!(doc.set_variable_mode(frame_id, collection, large));
+        assert_eq!(doc.get_node_height(text_id), 38.4);
+    }
+
+    #[test]
+    fn test_cached_layout_follows_edits() {
+        let mut doc = Document::new("Text");
+        let page_id = doc.get_active_page().unwrap();
+        let text_id = doc.create_text(page_id, 0.0, 0.0, "Hello");
+        let layout = |doc: &Document| doc.text_layout(&doc.resolved_node(text_id).unwrap());
+        assert_eq!(layout(&doc).lines.len(), 1);
+        assert!(Rc::ptr_eq(&layout(&doc), &layout(&doc)));
+
+        assert!(doc.set_text(text_id, "Hello\nworld"));
+        assert_eq!(layout(&doc).lines.len(), 2);
+        assert!(doc.undo());
+        assert_eq!(layout(&doc).lines.len(), 1);
+
+        // Fixed text wraps to its width
+        assert!(doc.set_text_auto_resize(text_id, "fixed"));
+        doc.set_node_width(text_id, 30.0);
+        assert_eq!(layout(&doc).lines.len(), 2);
+        doc.set_node_property(text_id, Property::TextAlign, PropertyValue::String("right".into()));
+        assert_eq!(layout(&doc).lines[0].x, 30.0 - 24.0);
+    }
+}
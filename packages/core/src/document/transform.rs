@@ -0,0 +1,197 @@
+//! World transforms
+//!
+//! Each node's `X`, `Y` and `Rotation` are relative to its parent. The local
+//! transform places the node's unrotated box at (X, Y) and rotates it about
+//! its center by `Rotation` degrees:
+//!
+//! ```text
+//! local = translate(x, y) · translate(w/2, h/2) · rotate(θ) · translate(-w/2, -h/2)
+//! world = world(parent) · local
+//! ```
+//!
+//! Pages and the document root have the identity transform, so a frame's X/Y
+//! are canvas coordinates.
+//!
+//! World transforms are computed lazily and cached. `Document::apply()` and
+//! undo/redo drop the cached entries of every node whose placement may have
+//! changed (the node itself and all of its descendants).
+
+use super::{Document, DocumentTree, Node, NodeType, ObjectId, Operation, Property};
+use crate::math::{Mat3, Rect, Transform2D, Vec2};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+/// Cache of computed world transforms
+#[derive(Debug, Default)]
+pub(crate) struct TransformCache {
+    world: RefCell<HashMap<ObjectId, Transform2D>>,
+}
+
+impl TransformCache {
+    fn get(&self, id: ObjectId) -> Option<Transform2D> {
+        self.world.borrow().get(&id).copied()
+    }
+
+    fn insert(&self, id: ObjectId, transform: Transform2D) {
+        self.world.borrow_mut().insert(id, transform);
+    }
+
+    /// Forget the cached transforms of a node and its descendants
+    fn invalidate_subtree(&mut self, tree: &DocumentTree, id: ObjectId) {
+        let world = self.world.get_mut();
+        if world.is_empty() {
+            return;
+        }
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            world.remove(&id);
+            stack.extend(tree.children(id));
+        }
+    }
+}
+
+/// The transform from a node's local space into its parent's space
+pub fn local_transform(node: &Node) -> Transform2D {
+    if matches!(node.node_type(), NodeType::Document | NodeType::Page) {
+        return Transform2D::new();
+    }
+
+    let translation = Mat3::from_translation(Vec2::new(node.x(), node.y()));
+    let rotation = node.rotation();
+    if rotation == 0.0 {
+        return Transform2D::from_matrix(translation);
+    }
+
+    let center = Vec2::new(node.width(), node.height()) / 2.0;
+    Transform2D::from_matrix(
+        translation
+            * Mat3::from_translation(center)
+            * Mat3::from_angle(rotation.to_radians())
+            * Mat3::from_translation(-center),
+    )
+}
+
+/// The X, Y and rotation (in degrees) that make `local_transform` of a node
+/// of `size` equal `transform`, which must be a rotation and a translation
+pub fn placement_for(transform: &Transform2D, size: Vec2) -> (f32, f32, f32) {
+    let matrix = transform.matrix();
+    let angle = matrix.x_axis.y.atan2(matrix.x_axis.x);
+    let center = size / 2.0;
+    // translation = (x, y) + c - R·c
+    let origin = matrix.z_axis.truncate() - center + Mat3::from_angle(angle).transform_vector2(center);
+    (origin.x, origin.y, angle.to_degrees())
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// The transform from a node's local space to canvas coordinates
+    pub fn get_world_transform(&self, id: ObjectId) -> Option<Transform2D> {
+        self.tree.get(id)?;
+        Some(self.world_transform(id))
+    }
+
+    /// A node's axis-aligned bounding box in canvas coordinates, taking
+    /// parent offsets and rotation into account
+    pub fn get_absolute_bounds(&self, id: ObjectId) -> Option<Rect> {
+        let node = self.bound_node(self.tree.get(id)?);
+        let local = Rect::new(0.0, 0.0, node.width(), node.height());
+        Some(self.world_transform(id).transform_rect(&local))
+    }
+}
+
+impl Document {
+    /// World transform of a node, using the cache where possible.
+    ///
+    /// Returns the identity for unknown nodes.
+    pub(crate) fn world_transform(&self, id: ObjectId) -> Transform2D {
+        if let Some(cached) = self.transforms.get(id) {
+            return cached;
+        }
+
+        // Walk up to the nearest cached ancestor (or the root), then compose
+        // back down so every node on the way gets cached too
+        let mut chain = Vec::new();
+        let mut current = Some(id);
+        let mut base = Transform2D::new();
+        while let Some(node_id) = current {
+            if let Some(cached) = self.transforms.get(node_id) {
+                base = cached;
+                break;
+            }
+            chain.push(node_id);
+            current = self.tree.parent(node_id);
+        }
+
+        for node_id in chain.into_iter().rev() {
+            if let Some(node) = self.tree.get(node_id) {
+                base = base.multiply(&local_transform(&self.bound_node(node)));
+            }
+            self.transforms.insert(node_id, base);
+        }
+        base
+    }
+
+    /// Drop cached transforms affected by an applied operation
+    pub(crate) fn invalidate_transforms(&mut self, inverse: &Operation) {
+        match inverse {
+            // Any node may be bound to the variable
+            Operation::SetProperty { property: Property::VariableValues, .. } => {
+                self.transforms.world.get_mut().clear();
+            }
+            Operation::SetProperty { property, .. } if !affects_transform(*property) => {}
+            Operation::SetOverride { .. } => {}
+            // The subtree was just removed; its nodes are no longer in the tree
+            Operation::Insert(subtree) => {
+                let world = self.transforms.world.get_mut();
+                for node in subtree.nodes() {
+                    world.remove(&node.id());
+                }
+            }
+            _ => self.transforms.invalidate_subtree(&self.tree, inverse.target()),
+        }
+    }
+}
+
+/// Properties that feed into `local_transform` (a change of variable modes
+/// can re-resolve bound values in the subtree)
+fn affects_transform(property: Property) -> bool {
+    matches!(
+        property,
+        Property::X | Property::Y | Property::Width | Property::Height | Property::Rotation | Property::VariableModes
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::PropertyValue;
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!((actual - expected).length() < 1e-3, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn test_world_transform_composes_parents() {
+        let mut doc = Document::new("Transforms");
+        let frame_id = doc.create_frame(100.0, 50.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 20.0, 40.0, 20.0);
+
+        let world = doc.get_world_transform(rect_id).unwrap();
+        assert_near(world.apply(Vec2::ZERO), Vec2::new(110.0, 70.0));
+
+        // Rotating the frame 90° about its center swings the child around it
+        doc.set_node_property(frame_id, Property::Rotation, PropertyValue::Float(90.0));
+        let world = doc.get_world_transform(rect_id).unwrap();
+        assert_near(world.apply(Vec2::ZERO), Vec2::new(280.0, 60.0));
+
+        let bounds = doc.get_absolute_bounds(rect_id).unwrap();
+        assert_near(Vec2::new(bounds.x, bounds.y), Vec2::new(260.0, 60.0));
+        assert_near(Vec2::new(bounds.width, bounds.height), Vec2::new(20.0, 40.0));
+
+        // Undo bypasses apply(), but must still invalidate the cache
+        assert!(doc.undo());
+        let world = doc.get_world_transform(rect_id).unwrap();
+        assert_near(world.apply(Vec2::ZERO), Vec2::new(110.0, 70.0));
+    }
+}
@@ -31,10 +31,10 @@
 //! - Bidirectional for efficient traversal in both directions
 //!
 //! ### Fractional Indexing (Z-Order)
-//! - Nodes have a fractional "order_index" (e.g., "0.5", "0.75", "0.625")
+//! - Nodes have a fractional "order_index" key in base 62 (e.g., "V", "W", "VV")
 //! - This allows inserting nodes *between* existing nodes without reordering everything
-//! - Example: To insert between "0.5" and "1.0", use "0.75"
-//! - To insert between "0.75" and "1.0", use "0.875"
+//! - Example: To insert between "V" and "W", use "VV"
+//! - Keys compare as plain strings; see `fractional.rs` for how they're generated
 //!
 //! ## Why This Design?
 //!
@@ -54,9 +54,9 @@
 //! - Better for serialization (can send over network)
 //! - Cache-friendly: nodes stored contiguously in memory
 
-use super::{Node, ObjectId, Property, PropertyValue};
+use super::{jittered_key_between, key_between, spread_keys, Node, NodeType, ObjectId, PropertyValue};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// The document tree holding all nodes in the canvas.
 ///
@@ -95,7 +95,8 @@ pub struct DocumentTree {
     root_id: ObjectId,
     
     /// Maps parent ID to ordered list of children IDs.
-    /// Children are sorted by their order_index for consistent rendering order.
+    /// Children are sorted by their order_index (then by ID, for ties) for
+    /// consistent rendering order.
     /// Example: {"frame-1" => ["shape-a", "shape-b", "shape-c"]}
     children_map: HashMap<ObjectId, Vec<ObjectId>>,
     
@@ -105,9 +106,17 @@ pub struct DocumentTree {
 }
 
 impl DocumentTree {
+    /// Create a tree with `root` (normally a `Document` node) as its root.
+    pub fn with_root(root: Node) -> Self {
+        let mut tree = Self::new();
+        tree.root_id = root.id();
+        tree.insert(root);
+        tree
+    }
+
     /// Create a new empty document tree with a random root ID.
     ///
-    /// The root node is not automatically created - you need to insert it.
+    /// No node has that ID; use `with_root()` to build a tree with a real root.
     pub fn new() -> Self {
         let root_id = ObjectId::random();
         Self {
@@ -132,6 +141,10 @@ impl DocumentTree {
     /// # Example
     ///
     /// ```rust
+    /// # use anatsui_core::document::{DocumentTree, Node, NodeType, ObjectId};
+    /// # let mut tree = DocumentTree::new();
+    /// # let node_id = ObjectId::random();
+    /// # tree.insert(Node::new(node_id, NodeType::Rectangle));
     /// if let Some(node) = tree.get(node_id) {
     ///     println!("Node name: {}", node.name());
     /// }
@@ -147,8 +160,12 @@ impl DocumentTree {
     /// # Example
     ///
     /// ```rust
+    /// # use anatsui_core::document::{DocumentTree, Node, NodeType, ObjectId, Property, PropertyValue};
+    /// # let mut tree = DocumentTree::new();
+    /// # let node_id = ObjectId::random();
+    /// # tree.insert(Node::new(node_id, NodeType::Rectangle));
     /// if let Some(node) = tree.get_mut(node_id) {
-    ///     node.set_name("New Name".to_string());
+    ///     node.set_property(Property::Name, PropertyValue::String("New Name".to_string()));
     /// }
     /// ```
     pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut Node> {
@@ -157,25 +174,22 @@ impl DocumentTree {
 
     /// Insert a node into the tree.
     ///
-    /// If this is the first node, it becomes the root.
-    /// Otherwise, you need to call `set_parent()` to establish relationships.
+    /// The node starts out detached; call `set_parent()` to attach it.
     ///
     /// # Example
     ///
     /// ```rust
-    /// let mut node = Node::new_rectangle();
+    /// # use anatsui_core::document::{DocumentTree, Node, NodeType, ObjectId};
+    /// # let mut tree = DocumentTree::new();
+    /// # let parent_id = ObjectId::random();
+    /// # tree.insert(Node::new(parent_id, NodeType::Frame));
+    /// let node = Node::new(ObjectId::random(), NodeType::Rectangle);
     /// tree.insert(node.clone());
-    /// tree.set_parent(node.id(), parent_id);
+    /// tree.set_parent(node.id(), parent_id)?;
+    /// # Ok::<(), anatsui_core::document::TreeError>(())
     /// ```
     pub fn insert(&mut self, node: Node) {
-        let id = node.id();
-        
-        // If tree is empty, this becomes the root
-        if self.nodes.is_empty() {
-            self.root_id = id;
-        }
-        
-        self.nodes.insert(id, node);
+        self.nodes.insert(node.id(), node);
     }
 
     /// Remove a node and all its descendants from the tree.
@@ -188,26 +202,175 @@ impl DocumentTree {
     /// # Example
     ///
     /// ```rust
+    /// # use anatsui_core::document::{DocumentTree, Node, NodeType, ObjectId};
+    /// # let mut tree = DocumentTree::new();
+    /// # let frame_id = ObjectId::random();
+    /// # tree.insert(Node::new(frame_id, NodeType::Frame));
     /// // Delete a frame and everything inside it
     /// tree.remove(frame_id);
     /// ```
     pub fn remove(&mut self, id: ObjectId) {
+        self.take_subtree(id);
+    }
+
+    /// Remove a node and all its descendants, returning them as a [`Subtree`].
+    ///
+    /// The subtree remembers where it was attached (parent and sibling
+    /// position), so handing it back to `restore_subtree()` puts every
+    /// node exactly where it was. This is what makes deletes undoable.
+    ///
+    /// Returns `None` if the node doesn't exist.
+    pub fn take_subtree(&mut self, id: ObjectId) -> Option<Subtree> {
+        if !self.nodes.contains_key(&id) {
+            return None;
+        }
+
+        let parent = self.parent_map.get(&id).cloned();
+        let position = self.index_in_parent(id).unwrap_or(0);
+
+        // Collect nodes parents-first so they can be re-attached in order
+        let mut nodes = Vec::new();
+        self.collect_subtree(id, &mut nodes);
+
         // Step 1: Remove from parent's children list
         if let Some(parent_id) = self.parent_map.remove(&id) {
             if let Some(children) = self.children_map.get_mut(&parent_id) {
                 children.retain(|&child_id| child_id != id);
             }
         }
-        
-        // Step 2: Recursively remove all descendants
-        if let Some(children) = self.children_map.remove(&id) {
-            for child_id in children {
-                self.remove(child_id); // Recursive call
+
+        // Step 2: Remove every node of the subtree and its relationships
+        for (node, _) in &nodes {
+            let node_id = node.id();
+            self.children_map.remove(&node_id);
+            self.parent_map.remove(&node_id);
+            self.nodes.remove(&node_id);
+        }
+
+        Some(Subtree {
+            root: id,
+            parent,
+            position,
+            nodes,
+        })
+    }
+
+    /// Copy a node and all its descendants without removing them.
+    ///
+    /// Returns `None` if the node doesn't exist.
+    pub fn clone_subtree(&self, id: ObjectId) -> Option<Subtree> {
+        self.nodes.get(&id)?;
+        let mut nodes = Vec::new();
+        self.collect_subtree(id, &mut nodes);
+        Some(Subtree {
+            root: id,
+            parent: self.parent_map.get(&id).cloned(),
+            position: self.index_in_parent(id).unwrap_or(0),
+            nodes,
+        })
+    }
+
+    /// Copy a node and all its descendants under fresh IDs.
+    ///
+    /// References between copied nodes (an instance and its main component,
+    /// say) are pointed at the copies; references to nodes outside the
+    /// subtree are kept. The copy is placed right after the original.
+    ///
+    /// Returns `None` if the node doesn't exist.
+    pub fn copy_subtree(&self, id: ObjectId) -> Option<Subtree> {
+        let original = self.clone_subtree(id)?;
+        let new_ids: HashMap<ObjectId, ObjectId> =
+            original.nodes().map(|node| (node.id(), ObjectId::random())).collect();
+        let remap = |id: ObjectId| new_ids.get(&id).copied().unwrap_or(id);
+
+        let nodes = original
+            .nodes
+            .into_iter()
+            .map(|(node, parent)| (remap_node(&node, &remap), parent.map(remap)))
+            .collect();
+
+        Some(Subtree {
+            root: remap(id),
+            parent: original.parent,
+            position: original.position + 1,
+            nodes,
+        })
+    }
+
+    /// Re-attach a subtree previously returned by `take_subtree()`.
+    ///
+    /// The root goes back to its recorded parent at its recorded position;
+    /// descendants keep their original parents and sibling order. Nothing is
+    /// inserted if the subtree is malformed (see `check_subtree()`), or if
+    /// its instances would nest a component inside itself.
+    pub fn restore_subtree(&mut self, subtree: Subtree) -> Result<(), TreeError> {
+        self.check_subtree(&subtree)?;
+        let Subtree { root, parent, position, nodes } = subtree;
+
+        for (node, node_parent) in nodes {
+            let id = node.id();
+            self.insert(node);
+
+            if id == root {
+                if let Some(parent_id) = parent {
+                    self.link(id, parent_id, position);
+                }
+            } else if let Some(parent_id) = node_parent {
+                self.link(id, parent_id, usize::MAX);
+            }
+        }
+
+        // Checked once everything is in place, so components inside the
+        // subtree are followed too
+        if let Some(parent_id) = parent {
+            if let Err(error) = self.check_component_use(root, parent_id) {
+                self.take_subtree(root);
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a subtree can be inserted as it is.
+    ///
+    /// Subtrees also come from clipboard payloads, so nothing about them is
+    /// trusted: every id must be new to the tree and listed once, every node
+    /// but the root must name a parent listed before it, and every parent
+    /// (the root's included) must be able to hold its child.
+    pub(crate) fn check_subtree(&self, subtree: &Subtree) -> Result<(), TreeError> {
+        let mut types: HashMap<ObjectId, NodeType> = HashMap::new();
+        for (index, (node, node_parent)) in subtree.nodes.iter().enumerate() {
+            let id = node.id();
+            if self.nodes.contains_key(&id) || types.contains_key(&id) {
+                return Err(TreeError::DuplicateNode(id));
+            }
+            if index == 0 {
+                if let Some(parent_id) = subtree.parent {
+                    self.check_parent_type(node, parent_id)?;
+                }
+            } else {
+                let Some((parent_id, parent_type)) =
+                    node_parent.and_then(|parent_id| Some((parent_id, *types.get(&parent_id)?)))
+                else {
+                    return Err(TreeError::OutsideSubtree(id));
+                };
+                if !parent_type.can_contain(node.node_type()) {
+                    return Err(TreeError::InvalidParent { node: id, parent: parent_id });
+                }
+            }
+            types.insert(id, node.node_type());
+        }
+        Ok(())
+    }
+
+    /// Walk a subtree depth-first, pushing `(node, parent)` pairs parents-first.
+    fn collect_subtree(&self, id: ObjectId, out: &mut Vec<(Node, Option<ObjectId>)>) {
+        if let Some(node) = self.nodes.get(&id) {
+            out.push((node.clone(), self.parent_map.get(&id).cloned()));
+            for child_id in self.children(id) {
+                self.collect_subtree(child_id, out);
             }
         }
-        
-        // Step 3: Remove the node itself
-        self.nodes.remove(&id);
     }
 
     /// Set the parent of a node, moving it to a new location in the tree.
@@ -217,13 +380,64 @@ impl DocumentTree {
     /// 2. Adding to new parent
     /// 3. Sorting children by order_index (z-order)
     ///
+    /// Fails, leaving the tree unchanged, if either node is missing, if the
+    /// parent is the node itself or one of its descendants, or if the
+    /// parent's type can't hold the node (see `NodeType::can_contain`).
+    ///
     /// # Example
     ///
     /// ```rust
+    /// # use anatsui_core::document::{DocumentTree, Node, NodeType, ObjectId};
+    /// # let mut tree = DocumentTree::new();
+    /// # let (shape_id, frame_id) = (ObjectId::random(), ObjectId::random());
+    /// # tree.insert(Node::new(shape_id, NodeType::Rectangle));
+    /// # tree.insert(Node::new(frame_id, NodeType::Frame));
     /// // Move a shape into a frame
-    /// tree.set_parent(shape_id, frame_id);
+    /// tree.set_parent(shape_id, frame_id)?;
+    /// # Ok::<(), anatsui_core::document::TreeError>(())
     /// ```
-    pub fn set_parent(&mut self, child_id: ObjectId, parent_id: ObjectId) {
+    pub fn set_parent(&mut self, child_id: ObjectId, parent_id: ObjectId) -> Result<(), TreeError> {
+        self.attach_at(child_id, parent_id, usize::MAX)
+    }
+
+    /// Set the parent of a node, placing it at `position` among its new siblings.
+    ///
+    /// The position is clamped to the end of the list. Children are still
+    /// sorted by order_index afterwards (siblings that share an index by ID),
+    /// so the node's index decides where it lands. Fails like `set_parent()`.
+    pub fn attach_at(&mut self, child_id: ObjectId, parent_id: ObjectId, position: usize) -> Result<(), TreeError> {
+        self.check_reparent(child_id, parent_id)?;
+        self.link(child_id, parent_id, position);
+        Ok(())
+    }
+
+    /// Check that `child_id` can be moved under `parent_id` (see `set_parent()`)
+    pub fn check_reparent(&self, child_id: ObjectId, parent_id: ObjectId) -> Result<(), TreeError> {
+        let child = self.nodes.get(&child_id).ok_or(TreeError::MissingNode(child_id))?;
+        self.check_parent_type(child, parent_id)?;
+
+        let mut current = Some(parent_id);
+        while let Some(id) = current {
+            if id == child_id {
+                return Err(TreeError::Cycle { node: child_id, parent: parent_id });
+            }
+            current = self.parent_map.get(&id).cloned();
+        }
+        self.check_component_use(child_id, parent_id)
+    }
+
+    /// Check that `parent_id` exists and its type can hold `child`
+    fn check_parent_type(&self, child: &Node, parent_id: ObjectId) -> Result<(), TreeError> {
+        let parent = self.nodes.get(&parent_id).ok_or(TreeError::MissingNode(parent_id))?;
+        if parent.node_type().can_contain(child.node_type()) {
+            Ok(())
+        } else {
+            Err(TreeError::InvalidParent { node: child.id(), parent: parent_id })
+        }
+    }
+
+    /// Attach a node without any checks
+    fn link(&mut self, child_id: ObjectId, parent_id: ObjectId, position: usize) {
         // Remove from old parent's children list
         if let Some(old_parent_id) = self.parent_map.get(&child_id).cloned() {
             if let Some(children) = self.children_map.get_mut(&old_parent_id) {
@@ -235,21 +449,87 @@ impl DocumentTree {
         self.parent_map.insert(child_id, parent_id);
         
         // Add to new parent's children list
-        self.children_map
-            .entry(parent_id)
-            .or_insert_with(Vec::new)
-            .push(child_id);
+        let children = self.children_map.entry(parent_id).or_default();
+        let position = position.min(children.len());
+        children.insert(position, child_id);
         
-        // Sort children by fractional index (determines draw order)
+        // Sort children by fractional index (determines draw order). Ties
+        // break by ID rather than arrival order, so clients that received
+        // the same keys in a different order still agree.
         if let Some(children) = self.children_map.get_mut(&parent_id) {
             children.sort_by(|a, b| {
-                let a_index = self.nodes.get(a).map(|n| n.order_index()).unwrap_or("0.5");
-                let b_index = self.nodes.get(b).map(|n| n.order_index()).unwrap_or("0.5");
-                a_index.cmp(b_index)
+                let a_index = self.nodes.get(a).map(|n| n.order_index()).unwrap_or_default();
+                let b_index = self.nodes.get(b).map(|n| n.order_index()).unwrap_or_default();
+                a_index.cmp(b_index).then(a.cmp(b))
             });
         }
     }
 
+    /// Check that placing `id` under `parent_id` doesn't make a component
+    /// contain an instance of itself.
+    ///
+    /// The components `id`'s subtree has instances of, and the components
+    /// those use in turn (following `MainComponent` links), must not include
+    /// a component containing `parent_id` or the main component of an
+    /// instance containing it. Instance propagation would otherwise keep
+    /// expanding A → B → A forever.
+    pub fn check_component_use(&self, id: ObjectId, parent_id: ObjectId) -> Result<(), TreeError> {
+        let mut holders = HashSet::new();
+        let mut current = Some(parent_id);
+        while let Some(ancestor_id) = current {
+            if let Some(ancestor) = self.nodes.get(&ancestor_id) {
+                if ancestor.node_type() == NodeType::Component {
+                    holders.insert(ancestor_id);
+                }
+                holders.extend(ancestor.main_component());
+            }
+            current = self.parent_map.get(&ancestor_id).cloned();
+        }
+        if holders.is_empty() {
+            return Ok(());
+        }
+
+        let mut pending = self.used_components(id);
+        let mut seen = HashSet::new();
+        while let Some(component) = pending.pop() {
+            if holders.contains(&component) {
+                return Err(TreeError::ComponentCycle { node: id, component });
+            }
+            if seen.insert(component) {
+                pending.extend(self.used_components(component));
+            }
+        }
+        Ok(())
+    }
+
+    /// The main components of every instance in a subtree
+    fn used_components(&self, id: ObjectId) -> Vec<ObjectId> {
+        let mut used = Vec::new();
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            used.extend(self.nodes.get(&node_id).and_then(Node::main_component));
+            stack.extend(self.children_map.get(&node_id).into_iter().flatten());
+        }
+        used
+    }
+
+    /// Append a child to a parent without re-sorting the siblings.
+    ///
+    /// Loaders use this because saved files already list children in order;
+    /// sorting after every insert would make loading wide frames quadratic.
+    pub(crate) fn push_child(&mut self, child_id: ObjectId, parent_id: ObjectId) {
+        self.parent_map.insert(child_id, parent_id);
+        self.children_map.entry(parent_id).or_default().push(child_id);
+    }
+
+    /// Get the position of a node among its siblings.
+    ///
+    /// Returns `None` for the root or for nodes that aren't in the tree.
+    pub fn index_in_parent(&self, id: ObjectId) -> Option<usize> {
+        let parent_id = self.parent_map.get(&id)?;
+        self.children_map.get(parent_id)?.iter().position(|&child| child == id)
+    }
+
     /// Get the parent ID of a node.
     ///
     /// Returns `None` if the node is the root or doesn't exist.
@@ -270,82 +550,131 @@ impl DocumentTree {
 
     /// Get the first page in the document.
     ///
-    /// This is a convenience method that returns the first child of the root.
     /// In most documents, this is "Page 1".
     pub fn first_page(&self) -> Option<ObjectId> {
-        self.children(self.root_id).first().cloned()
+        self.pages().first().cloned()
+    }
+
+    /// The pages of the document, in order.
+    ///
+    /// Pages are the `Page` children of the root; styles and variables live
+    /// next to them and are skipped.
+    pub fn pages(&self) -> Vec<ObjectId> {
+        self.children(self.root_id)
+            .into_iter()
+            .filter(|id| self.nodes.get(id).is_some_and(|n| n.node_type() == NodeType::Page))
+            .collect()
+    }
+
+    /// An order index that places a node at `position` among the other
+    /// children of `parent_id` (`node_id` itself is left out of the count).
+    ///
+    /// The key is jittered (see `fractional`), so it is meant for local edits
+    /// that may race with a collaborator's.
+    pub(crate) fn order_index_at(&self, parent_id: ObjectId, position: usize, node_id: ObjectId) -> String {
+        let siblings: Vec<&str> = self
+            .children(parent_id)
+            .iter()
+            .filter(|&&id| id != node_id)
+            .filter_map(|id| self.nodes.get(id).map(|n| n.order_index()))
+            .collect();
+        let position = position.min(siblings.len());
+        let prev = position.checked_sub(1).and_then(|i| siblings.get(i)).copied();
+        // No key fits between siblings that share an index, so land after
+        // the whole run of them
+        let next = siblings[position..].iter().find(|&&key| prev.is_none_or(|prev| key > prev)).copied();
+        jittered_key_between(prev, next)
+    }
+
+    /// Give the unplaced nodes of a new subtree (those with an empty order
+    /// index) a key.
+    ///
+    /// The root is keyed for its position among the current children of its
+    /// parent; other nodes go after the siblings listed before them.
+    pub(crate) fn place_subtree(&self, subtree: &mut Subtree) {
+        let mut last_keys: HashMap<ObjectId, String> = HashMap::new();
+        for (node, parent) in &mut subtree.nodes {
+            let Some(parent_id) = *parent else {
+                continue;
+            };
+            if node.order_index().is_empty() {
+                let key = if node.id() == subtree.root {
+                    self.order_index_at(parent_id, subtree.position, node.id())
+                } else {
+                    key_between(last_keys.get(&parent_id).map(String::as_str), None)
+                };
+                node.set_order_index(key);
+            }
+            last_keys.insert(parent_id, node.order_index().to_string());
+        }
     }
 
-    /// Move a node before another sibling using fractional indexing.
+    /// Replace every order index with evenly spaced keys that keep the
+    /// current child order.
     ///
-    /// This calculates a new order_index that places `node_id` directly before `before_id`.
-    /// Both nodes must share the same parent.
+    /// Used when loading files whose indices predate base 62 keys.
+    pub(crate) fn reset_order_indices(&mut self) {
+        for children in self.children_map.values() {
+            for (id, key) in children.iter().zip(spread_keys(children.len())) {
+                if let Some(node) = self.nodes.get_mut(id) {
+                    node.set_order_index(key);
+                }
+            }
+        }
+    }
+
+    /// Move a node directly before another sibling using fractional indexing.
+    ///
+    /// Only the moved node gets a new order_index, between `before_id` and
+    /// the sibling currently in front of it. If the node was under another
+    /// parent it is moved to `before_id`'s parent, which can fail like
+    /// `set_parent()`. Does nothing if `before_id` is the root.
     ///
     /// # Example
     ///
     /// ```text
-    /// Before: [A(0.3), B(0.5), C(0.7)]
+    /// Before: [A("F"), B("V"), C("k")]
     /// tree.move_before(C, B)
-    /// After:  [A(0.3), C(0.4), B(0.5)]
+    /// After:  [A("F"), C("N..."), B("V")]
     /// ```
-    pub fn move_before(&mut self, node_id: ObjectId, before_id: ObjectId) {
-        if let Some(parent_id) = self.parent_map.get(&before_id).cloned() {
-            self.set_parent(node_id, parent_id);
-            
-            // Calculate new fractional index between previous sibling and before_id
-            if let Some(children) = self.children_map.get(&parent_id) {
-                if let Some(before_idx) = children.iter().position(|&id| id == before_id) {
-                    let before_index = self.nodes.get(&before_id)
-                        .map(|n| n.order_index().to_string())
-                        .unwrap_or_else(|| "0.5".to_string());
-                    
-                    let prev_index = if before_idx > 0 {
-                        children.get(before_idx - 1)
-                            .and_then(|&id| self.nodes.get(&id))
-                            .map(|n| n.order_index().to_string())
-                            .unwrap_or_else(|| "0".to_string())
-                    } else {
-                        "0".to_string()
-                    };
-                    
-                    // Midpoint between prev and before
-                    let new_index = fractional_midpoint(&prev_index, &before_index);
-                    
-                    if let Some(node) = self.nodes.get_mut(&node_id) {
-                        node.set_order_index(new_index);
-                    }
-                }
-            }
-        }
+    pub fn move_before(&mut self, node_id: ObjectId, before_id: ObjectId) -> Result<(), TreeError> {
+        self.move_next_to(node_id, before_id, 0)
     }
 
-    /// Move a node after another sibling using fractional indexing.
+    /// Move a node directly after another sibling using fractional indexing.
     ///
     /// This calculates a new order_index that places `node_id` directly after `after_id`.
-    pub fn move_after(&mut self, node_id: ObjectId, after_id: ObjectId) {
-        if let Some(parent_id) = self.parent_map.get(&after_id).cloned() {
-            self.set_parent(node_id, parent_id);
-            
-            if let Some(children) = self.children_map.get(&parent_id) {
-                if let Some(after_idx) = children.iter().position(|&id| id == after_id) {
-                    let after_index = self.nodes.get(&after_id)
-                        .map(|n| n.order_index().to_string())
-                        .unwrap_or_else(|| "0.5".to_string());
-                    
-                    let next_index = children.get(after_idx + 1)
-                        .and_then(|&id| self.nodes.get(&id))
-                        .map(|n| n.order_index().to_string())
-                        .unwrap_or_else(|| "1".to_string());
-                    
-                    // Midpoint between after and next
-                    let new_index = fractional_midpoint(&after_index, &next_index);
-                    
-                    if let Some(node) = self.nodes.get_mut(&node_id) {
-                        node.set_order_index(new_index);
-                    }
-                }
-            }
+    pub fn move_after(&mut self, node_id: ObjectId, after_id: ObjectId) -> Result<(), TreeError> {
+        self.move_next_to(node_id, after_id, 1)
+    }
+
+    /// Shared part of `move_before` / `move_after`: `offset` is 0 to land
+    /// before `sibling_id` and 1 to land after it
+    fn move_next_to(&mut self, node_id: ObjectId, sibling_id: ObjectId, offset: usize) -> Result<(), TreeError> {
+        if !self.nodes.contains_key(&sibling_id) {
+            return Err(TreeError::MissingNode(sibling_id));
+        }
+        let Some(parent_id) = self.parent_map.get(&sibling_id).cloned() else {
+            return Ok(());
+        };
+        self.check_reparent(node_id, parent_id)?;
+        if node_id == sibling_id {
+            return Ok(());
         }
+
+        let index = self
+            .children(parent_id)
+            .into_iter()
+            .filter(|&id| id != node_id)
+            .position(|id| id == sibling_id)
+            .unwrap_or(usize::MAX);
+        let position = index.saturating_add(offset);
+        let key = self.order_index_at(parent_id, position, node_id);
+        if let Some(node) = self.nodes.get_mut(&node_id) {
+            node.set_order_index(key);
+        }
+        self.link(node_id, parent_id, position);
+        Ok(())
     }
 
     /// Iterate over all nodes in the tree (unordered).
@@ -364,6 +693,64 @@ impl DocumentTree {
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
+
+    /// Check that the parent and children maps describe one consistent tree.
+    ///
+    /// Run after loading a file and after applying remote operations; local
+    /// edits go through the checked methods above and can't break the tree.
+    /// Returns every problem found.
+    pub fn validate(&self) -> Result<(), Vec<TreeIssue>> {
+        let mut issues = Vec::new();
+        if !self.nodes.contains_key(&self.root_id) {
+            issues.push(TreeIssue::MissingRoot);
+        }
+
+        for (&parent_id, children) in &self.children_map {
+            for &child_id in children {
+                if !self.nodes.contains_key(&parent_id) || !self.nodes.contains_key(&child_id) {
+                    issues.push(TreeIssue::Dangling { parent: parent_id, child: child_id });
+                } else if self.parent_map.get(&child_id) != Some(&parent_id) {
+                    issues.push(TreeIssue::Mismatch { parent: parent_id, child: child_id });
+                }
+            }
+        }
+
+        for (&child_id, &parent_id) in &self.parent_map {
+            let listed = self.children_map.get(&parent_id).is_some_and(|children| children.contains(&child_id));
+            if !self.nodes.contains_key(&parent_id) || !self.nodes.contains_key(&child_id) {
+                issues.push(TreeIssue::Dangling { parent: parent_id, child: child_id });
+            } else if !listed {
+                issues.push(TreeIssue::Mismatch { parent: parent_id, child: child_id });
+            } else if !self.nodes[&parent_id].node_type().can_contain(self.nodes[&child_id].node_type()) {
+                issues.push(TreeIssue::InvalidParent { parent: parent_id, child: child_id });
+            }
+        }
+
+        // Walk up from every node; nodes already walked through are known to
+        // end somewhere, so each node is visited once
+        let mut settled: HashSet<ObjectId> = HashSet::new();
+        for &id in self.nodes.keys() {
+            if id != self.root_id && !self.parent_map.contains_key(&id) {
+                issues.push(TreeIssue::Orphan(id));
+            }
+            let mut path = HashSet::new();
+            let mut current = Some(id);
+            while let Some(node_id) = current.filter(|node_id| !settled.contains(node_id)) {
+                if !path.insert(node_id) {
+                    issues.push(TreeIssue::Cycle(node_id));
+                    break;
+                }
+                current = self.parent_map.get(&node_id).copied();
+            }
+            settled.extend(path);
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
 }
 
 impl Default for DocumentTree {
@@ -372,51 +759,183 @@ impl Default for DocumentTree {
     }
 }
 
-/// Calculate the midpoint between two fractional indices for z-ordering.
-///
-/// ## How Fractional Indexing Works
-///
-/// Instead of using array indices (0, 1, 2, ...), we use fractional values:
-/// - Node A: "0.5"
-/// - Node B: "0.75"
-/// - Node C: "0.875"
-///
-/// To insert between A and B: `(0.5 + 0.75) / 2 = 0.625`
-///
-/// ## Why String-Based?
-///
-/// Using strings instead of f64 gives us:
-/// - Arbitrary precision (no floating-point rounding errors)
-/// - Consistent behavior across platforms
-/// - Better for CRDTs (conflict-free replicated data types)
-///
-/// ## Production Note
-///
-/// This is a simplified implementation. In production, you'd want:
-/// - Arbitrary-precision string arithmetic
-/// - Automatic rebalancing when indices get too long
-/// - Fractional-indexing library (e.g., `fractional-index` crate)
-///
-/// # Arguments
-///
-/// * `a` - The lower bound fractional index (e.g., "0.5")
-/// * `b` - The upper bound fractional index (e.g., "0.75")
-///
-/// # Returns
+/// Why a node couldn't be attached where it was asked to go
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeError {
+    /// The node or the new parent isn't in the tree
+    MissingNode(ObjectId),
+    /// The new parent is the node itself or one of its descendants
+    Cycle { node: ObjectId, parent: ObjectId },
+    /// The parent's type can't hold the node (a shape directly under the
+    /// Document, say)
+    InvalidParent { node: ObjectId, parent: ObjectId },
+    /// The node holds an instance of `component`, and the parent is inside
+    /// that component (or inside something it uses), so the component would
+    /// end up containing an instance of itself
+    ComponentCycle { node: ObjectId, component: ObjectId },
+    /// A node being inserted has the same id as a node in the tree or
+    /// another node being inserted with it
+    DuplicateNode(ObjectId),
+    /// A node being inserted as part of a subtree doesn't name a parent
+    /// listed before it in that subtree
+    OutsideSubtree(ObjectId),
+    /// The node's order index isn't a valid fractional key
+    InvalidOrderIndex(ObjectId),
+    /// The root node can't be removed
+    RemoveRoot,
+}
+
+impl std::fmt::Display for TreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeError::MissingNode(id) => write!(f, "Node {} doesn't exist", id),
+            TreeError::Cycle { node, parent } => {
+                write!(f, "Node {} can't move into its own descendant {}", node, parent)
+            }
+            TreeError::InvalidParent { node, parent } => write!(f, "Node {} can't be a child of {}", node, parent),
+            TreeError::ComponentCycle { node, component } => {
+                write!(f, "Node {} would make component {} contain an instance of itself", node, component)
+            }
+            TreeError::DuplicateNode(id) => write!(f, "Node {} already exists", id),
+            TreeError::OutsideSubtree(id) => write!(f, "Node {} has no parent earlier in its subtree", id),
+            TreeError::InvalidOrderIndex(id) => write!(f, "Node {} has an invalid order index", id),
+            TreeError::RemoveRoot => write!(f, "The root node can't be removed"),
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+/// A problem found by `DocumentTree::validate()`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeIssue {
+    /// There's no node with the root ID
+    MissingRoot,
+    /// A node other than the root has no parent
+    Orphan(ObjectId),
+    /// A parent or children map entry names a node that doesn't exist
+    Dangling { parent: ObjectId, child: ObjectId },
+    /// The parent map and the children map disagree about a child
+    Mismatch { parent: ObjectId, child: ObjectId },
+    /// The parent's type can't hold the child
+    InvalidParent { parent: ObjectId, child: ObjectId },
+    /// Following parents from this node never reaches a node without one
+    Cycle(ObjectId),
+}
+
+impl std::fmt::Display for TreeIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TreeIssue::MissingRoot => write!(f, "Root node is missing"),
+            TreeIssue::Orphan(id) => write!(f, "Node {} has no parent", id),
+            TreeIssue::Dangling { parent, child } => {
+                write!(f, "Link from {} to {} names a missing node", parent, child)
+            }
+            TreeIssue::Mismatch { parent, child } => {
+                write!(f, "Parent and children maps disagree about {} under {}", child, parent)
+            }
+            TreeIssue::InvalidParent { parent, child } => write!(f, "Node {} can't be a child of {}", child, parent),
+            TreeIssue::Cycle(id) => write!(f, "Node {} is in a parent cycle", id),
+        }
+    }
+}
+
+/// A node and all of its descendants, detached from a tree.
 ///
-/// A string representing the midpoint (e.g., "0.625")
-fn fractional_midpoint(a: &str, b: &str) -> String {
-    // Parse as floats (in production, use arbitrary-precision strings)
-    let a_val: f64 = a.parse().unwrap_or(0.0);
-    let b_val: f64 = b.parse().unwrap_or(1.0);
-    
-    // Calculate midpoint
-    let mid = (a_val + b_val) / 2.0;
-    
-    // Format with enough precision to avoid collisions
-    // 15 decimal places is usually sufficient
-    format!("{:.15}", mid)
+/// Produced by `DocumentTree::take_subtree()` and consumed by
+/// `DocumentTree::restore_subtree()`. Nodes are stored parents-first along
+/// with the parent each one had, so the exact structure can be rebuilt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtree {
+    /// The topmost node of the subtree
+    root: ObjectId,
+    /// Where the root was attached (`None` for a detached or root node)
+    parent: Option<ObjectId>,
+    /// Index of the root among its former siblings
+    position: usize,
+    /// Every node in the subtree with its parent, parents before children
+    nodes: Vec<(Node, Option<ObjectId>)>,
+}
 
+impl Subtree {
+    /// Wrap a single new node so it can be attached at the end of `parent`.
+    pub fn leaf(node: Node, parent: Option<ObjectId>) -> Self {
+        Self {
+            root: node.id(),
+            parent,
+            position: usize::MAX,
+            nodes: vec![(node, parent)],
+        }
+    }
+
+    /// Build a subtree from `(node, parent)` pairs listed parents-first.
+    ///
+    /// The first entry is the root; it is attached to its parent at `position`.
+    /// Returns `None` if `nodes` is empty.
+    pub fn from_nodes(nodes: Vec<(Node, Option<ObjectId>)>, position: usize) -> Option<Self> {
+        let (root, parent) = nodes.first().map(|(node, parent)| (node.id(), *parent))?;
+        Some(Self {
+            root,
+            parent,
+            position,
+            nodes,
+        })
+    }
+
+    pub fn root(&self) -> ObjectId {
+        self.root
+    }
+
+    pub fn parent(&self) -> Option<ObjectId> {
+        self.parent
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Iterate over the nodes of the subtree, parents before children.
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().map(|(node, _)| node)
+    }
+
+    /// Iterate over the `(node, parent)` pairs of the subtree, parents first
+    pub fn entries(&self) -> impl Iterator<Item = (&Node, Option<ObjectId>)> {
+        self.nodes.iter().map(|(node, parent)| (node, *parent))
+    }
+
+    /// Take the `(node, parent)` pairs out of the subtree, parents first
+    pub fn into_nodes(self) -> Vec<(Node, Option<ObjectId>)> {
+        self.nodes
+    }
+}
+
+/// A copy of `node` with its own id and every node reference in its
+/// properties passed through `remap`
+pub(crate) fn remap_node(node: &Node, remap: &impl Fn(ObjectId) -> ObjectId) -> Node {
+    let mut copy = node.duplicate(remap(node.id()));
+    for (&property, value) in node.properties() {
+        if let Some(value) = remap_value(value, remap) {
+            copy.set_property(property, value);
+        }
+    }
+    copy
+}
+
+/// A value with its node references passed through `remap`, or `None` if
+/// it holds no references
+fn remap_value(value: &PropertyValue, remap: &impl Fn(ObjectId) -> ObjectId) -> Option<PropertyValue> {
+    match value {
+        PropertyValue::Ref(id) => Some(PropertyValue::Ref(remap(*id))),
+        PropertyValue::Variable(id) => Some(PropertyValue::Variable(remap(*id))),
+        PropertyValue::Map(entries) => Some(PropertyValue::Map(
+            entries
+                .iter()
+                .map(|(key, value)| (remap(*key), remap_value(value, remap).unwrap_or_else(|| value.clone())))
+                .collect(),
+        )),
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -435,9 +954,132 @@ mod tests {
         let child_id = ObjectId::random();
         let child = Node::new(child_id, NodeType::Rectangle);
         tree.insert(child);
-        tree.set_parent(child_id, parent_id);
+        tree.set_parent(child_id, parent_id).unwrap();
         
         assert_eq!(tree.children(parent_id), vec![child_id]);
         assert_eq!(tree.parent(child_id), Some(parent_id));
     }
+
+    #[test]
+    fn test_take_and_restore_subtree() {
+        let mut tree = DocumentTree::new();
+
+        let root_id = ObjectId::random();
+        tree.insert(Node::new(root_id, NodeType::Page));
+
+        let ids: Vec<ObjectId> = (0..3).map(|_| ObjectId::random()).collect();
+        for (&id, key) in ids.iter().zip(spread_keys(3)) {
+            let mut node = Node::new(id, NodeType::Frame);
+            node.set_order_index(key);
+            tree.insert(node);
+            tree.set_parent(id, root_id).unwrap();
+        }
+
+        let nested_id = ObjectId::random();
+        tree.insert(Node::new(nested_id, NodeType::Rectangle));
+        tree.set_parent(nested_id, ids[1]).unwrap();
+
+        let subtree = tree.take_subtree(ids[1]).unwrap();
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.children(root_id), vec![ids[0], ids[2]]);
+        assert!(tree.parent(nested_id).is_none());
+
+        tree.restore_subtree(subtree).unwrap();
+        assert_eq!(tree.len(), 5);
+        assert_eq!(tree.children(root_id), ids);
+        assert_eq!(tree.children(ids[1]), vec![nested_id]);
+    }
+
+    #[test]
+    fn test_repeated_moves_keep_order() {
+        let mut tree = DocumentTree::new();
+        let root_id = ObjectId::random();
+        tree.insert(Node::new(root_id, NodeType::Page));
+
+        let ids: Vec<ObjectId> = (0..3).map(|_| ObjectId::random()).collect();
+        let mut last = None;
+        for &id in &ids {
+            let mut node = Node::new(id, NodeType::Rectangle);
+            let key = key_between(last.as_deref(), None);
+            node.set_order_index(key.clone());
+            last = Some(key);
+            tree.insert(node);
+            tree.set_parent(id, root_id).unwrap();
+        }
+
+        // Keep swapping the last two between the same neighbours
+        let (a, b, c) = (ids[0], ids[1], ids[2]);
+        for i in 0..200 {
+            let (moved, target) = if i % 2 == 0 { (c, b) } else { (b, c) };
+            tree.move_before(moved, target).unwrap();
+            assert_eq!(tree.children(root_id), vec![a, moved, target]);
+        }
+        tree.move_after(a, c).unwrap();
+        assert_eq!(tree.children(root_id), vec![b, c, a]);
+    }
+
+    #[test]
+    fn test_shared_keys_order_by_id() {
+        let mut tree = DocumentTree::new();
+        let root_id = ObjectId::random();
+        tree.insert(Node::new(root_id, NodeType::Page));
+
+        // Two clients inserted siblings with the same key; both orders of
+        // arrival end up the same
+        let (low, high) = (ObjectId::new(1, 5), ObjectId::new(2, 1));
+        for arrival in [[low, high], [high, low]] {
+            let mut tree = tree.clone();
+            for id in arrival {
+                let mut node = Node::new(id, NodeType::Rectangle);
+                node.set_order_index("V".into());
+                tree.insert(node);
+                tree.attach_at(id, root_id, 0).unwrap();
+            }
+            assert_eq!(tree.children(root_id), vec![low, high]);
+
+            // A node placed between them lands after the shared key
+            let other = ObjectId::new(3, 1);
+            let mut node = Node::new(other, NodeType::Rectangle);
+            node.set_order_index(tree.order_index_at(root_id, 1, other));
+            tree.insert(node);
+            tree.set_parent(other, root_id).unwrap();
+            assert_eq!(tree.children(root_id), vec![low, high, other]);
+        }
+    }
+
+    #[test]
+    fn test_reparent_errors_and_validate() {
+        let root_id = ObjectId::random();
+        let mut tree = DocumentTree::with_root(Node::new(root_id, NodeType::Document));
+        let page_id = ObjectId::random();
+        let frame_id = ObjectId::random();
+        let rect_id = ObjectId::random();
+        tree.insert(Node::new(page_id, NodeType::Page));
+        tree.insert(Node::new(frame_id, NodeType::Frame));
+        tree.insert(Node::new(rect_id, NodeType::Rectangle));
+        tree.set_parent(page_id, root_id).unwrap();
+        tree.set_parent(frame_id, page_id).unwrap();
+        tree.set_parent(rect_id, frame_id).unwrap();
+        assert_eq!(tree.validate(), Ok(()));
+
+        let missing = ObjectId::random();
+        assert_eq!(tree.set_parent(missing, frame_id), Err(TreeError::MissingNode(missing)));
+        assert_eq!(tree.set_parent(rect_id, missing), Err(TreeError::MissingNode(missing)));
+        assert_eq!(tree.set_parent(frame_id, rect_id), Err(TreeError::Cycle { node: frame_id, parent: rect_id }));
+        assert_eq!(tree.set_parent(frame_id, frame_id), Err(TreeError::Cycle { node: frame_id, parent: frame_id }));
+        assert_eq!(tree.set_parent(rect_id, root_id), Err(TreeError::InvalidParent { node: rect_id, parent: root_id }));
+        assert_eq!(tree.children(frame_id), vec![rect_id]);
+        assert_eq!(tree.validate(), Ok(()));
+
+        // Corrupt the maps the way a bad remote op or file could
+        let orphan_id = ObjectId::random();
+        tree.insert(Node::new(orphan_id, NodeType::Ellipse));
+        tree.children_map.get_mut(&page_id).unwrap().push(missing);
+        tree.parent_map.insert(page_id, rect_id);
+        let issues = tree.validate().unwrap_err();
+        assert!(issues.contains(&TreeIssue::Orphan(orphan_id)));
+        assert!(issues.contains(&TreeIssue::Dangling { parent: page_id, child: missing }));
+        assert!(issues.contains(&TreeIssue::Mismatch { parent: root_id, child: page_id }));
+        assert!(issues.iter().any(|issue| matches!(issue, TreeIssue::Cycle(_))));
+    }
 }
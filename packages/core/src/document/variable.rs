@@ -0,0 +1,701 @@
+//! Design variables
+//!
+//! Variables are named values (colors, numbers, strings, booleans) that node
+//! properties can be bound to instead of holding a literal. They are grouped
+//! into collections, and each collection has one or more modes ("Light" and
+//! "Dark", "Compact" and "Comfortable") with a value per variable per mode.
+//! Like styles, they are nodes under the document root, so they are saved,
+//! synced and undone like everything else:
+//!
+//! ```text
+//! Document
+//!   ├─ Page 1
+//!   │    └─ Frame           VariableModes = { Theme: Dark }
+//!   │         └─ Rectangle  FillColor = Variable(background)
+//!   └─ VariableCollection "Theme"
+//!        ├─ VariableMode "Light"      ← the first mode is the default
+//!        ├─ VariableMode "Dark"
+//!        └─ Variable "background"     VariableType = "color"
+//!                                     VariableValues = { Light: #FFFFFF, Dark: #1E1E1E }
+//! ```
+//!
+//! ## Resolution
+//!
+//! A bound property is resolved when the node is read (see
+//! `Document::resolved_node`). The mode comes from the nearest node, starting
+//! at the bound node itself and walking up, that picks one for the
+//! variable's collection; if none does, the collection's default mode is
+//! used. Switching a frame's mode therefore changes every bound value in its
+//! subtree for rendering, hit testing and export without touching the nodes.
+//!
+//! A variable without a value for the active mode falls back to its value in
+//! the default mode. A binding to a variable that no longer exists reads as
+//! the property's default.
+//!
+//! World transforms, bounds, auto layout and resize constraints all use
+//! resolved values. Constraints respond to edits of a frame's size, so a
+//! frame resized by switching modes doesn't move its children by constraint.
+//!
+//! Unbinding a property, or deleting the variable it is bound to, writes the
+//! resolved value back onto the node, so nothing changes visually.
+
+use super::{Document, Node, NodeType, ObjectId, Operation, Property, PropertyValue, Subtree};
+use crate::document::Color;
+use std::borrow::Cow;
+use wasm_bindgen::prelude::*;
+
+/// The type of value a variable holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableKind {
+    Color,
+    Number,
+    String,
+    Boolean,
+}
+
+impl VariableKind {
+    /// Parse `"color"`, `"number"`, `"string"` or `"boolean"`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "color" => Some(VariableKind::Color),
+            "number" => Some(VariableKind::Number),
+            "string" => Some(VariableKind::String),
+            "boolean" => Some(VariableKind::Boolean),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VariableKind::Color => "color",
+            VariableKind::Number => "number",
+            VariableKind::String => "string",
+            VariableKind::Boolean => "boolean",
+        }
+    }
+
+    /// The kind of a variable node
+    pub fn of(node: &Node) -> Option<Self> {
+        if node.node_type() != NodeType::Variable {
+            return None;
+        }
+        match node.get_property(Property::VariableType) {
+            Some(PropertyValue::String(s)) => VariableKind::parse(s),
+            _ => None,
+        }
+    }
+
+    /// Whether a value can be stored in a variable of this kind
+    pub fn accepts(&self, value: &PropertyValue) -> bool {
+        matches!(
+            (self, value),
+            (VariableKind::Color, PropertyValue::Color(_))
+                | (VariableKind::Number, PropertyValue::Float(_) | PropertyValue::Int(_))
+                | (VariableKind::String, PropertyValue::String(_))
+                | (VariableKind::Boolean, PropertyValue::Bool(_))
+        )
+    }
+
+    /// The value a new variable starts with in every mode
+    fn initial_value(&self) -> PropertyValue {
+        match self {
+            VariableKind::Color => PropertyValue::Color(Color::black()),
+            VariableKind::Number => PropertyValue::Float(0.0),
+            VariableKind::String => PropertyValue::String(String::new()),
+            VariableKind::Boolean => PropertyValue::Bool(false),
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Document {
+    /// Create a variable collection with a single mode
+    pub fn create_variable_collection(&mut self, name: &str) -> ObjectId {
+        let root_id = self.tree.root_id();
+        self.history.begin_group();
+        let collection_id = self.insert_named(NodeType::VariableCollection, root_id, name);
+        self.insert_named(NodeType::VariableMode, collection_id, "Mode 1");
+        self.history.end_group();
+        collection_id
+    }
+
+    /// All variable collections, in creation order
+    pub fn get_variable_collections(&self) -> Vec<ObjectId> {
+        self.children_of_type(self.tree.root_id(), NodeType::VariableCollection)
+    }
+
+    /// The modes of a collection; the first one is the default
+    pub fn get_variable_modes(&self, collection_id: ObjectId) -> Vec<ObjectId> {
+        self.children_of_type(collection_id, NodeType::VariableMode)
+    }
+
+    /// The variables of a collection
+    pub fn get_variables(&self, collection_id: ObjectId) -> Vec<ObjectId> {
+        self.children_of_type(collection_id, NodeType::Variable)
+    }
+
+    /// Add a mode to a collection. Every variable starts out with its
+    /// default-mode value in the new mode.
+    pub fn add_variable_mode(&mut self, collection_id: ObjectId, name: &str) -> Option<ObjectId> {
+        let default_mode = self.default_mode(collection_id)?;
+
+        self.history.begin_group();
+        let mode_id = self.insert_named(NodeType::VariableMode, collection_id, name);
+        for variable_id in self.get_variables(collection_id) {
+            let value = self.tree.get(variable_id).and_then(|v| mode_value(v, default_mode)).cloned();
+            if let Some(value) = value {
+                self.set_map_entry(variable_id, Property::VariableValues, mode_id, Some(value));
+            }
+        }
+        self.history.end_group();
+        Some(mode_id)
+    }
+
+    /// Delete a mode. Nodes that picked it go back to the default mode.
+    ///
+    /// Returns `false` for the last mode of a collection.
+    pub fn delete_variable_mode(&mut self, mode_id: ObjectId) -> bool {
+        let Some(collection_id) = self.parent_if(mode_id, NodeType::VariableMode) else {
+            return false;
+        };
+        if self.get_variable_modes(collection_id).len() < 2 {
+            return false;
+        }
+
+        self.history.begin_group();
+        for variable_id in self.get_variables(collection_id) {
+            self.set_map_entry(variable_id, Property::VariableValues, mode_id, None);
+        }
+        let pickers: Vec<ObjectId> = self
+            .tree
+            .iter()
+            .filter(|node| chosen_mode(node, collection_id) == Some(mode_id))
+            .map(|node| node.id())
+            .collect();
+        for node_id in pickers {
+            self.set_map_entry(node_id, Property::VariableModes, collection_id, None);
+        }
+        self.apply(Operation::Remove { id: mode_id });
+        self.history.end_group();
+        true
+    }
+
+    /// Create a variable of a kind (`"color"`, `"number"`, `"string"` or
+    /// `"boolean"`) in a collection
+    pub fn create_variable(&mut self, collection_id: ObjectId, kind: &str, name: &str) -> Option<ObjectId> {
+        let kind = VariableKind::parse(kind)?;
+        self.tree.get(collection_id).filter(|c| c.node_type() == NodeType::VariableCollection)?;
+
+        let variable_id = ObjectId::random();
+        let mut variable = Node::new(variable_id, NodeType::Variable);
+        variable.set_property(Property::Name, PropertyValue::String(name.into()));
+        variable.set_property(Property::VariableType, PropertyValue::String(kind.as_str().into()));
+        let values = self
+            .get_variable_modes(collection_id)
+            .into_iter()
+            .map(|mode_id| (mode_id, kind.initial_value()))
+            .collect();
+        variable.set_property(Property::VariableValues, PropertyValue::Map(values));
+
+        self.apply(Operation::Insert(Subtree::leaf(variable, Some(collection_id))));
+        Some(variable_id)
+    }
+
+    /// The kind of a variable (`"color"`, `"number"`, `"string"` or `"boolean"`)
+    pub fn get_variable_type(&self, variable_id: ObjectId) -> Option<String> {
+        self.variable_kind(variable_id).map(|kind| kind.as_str().to_string())
+    }
+
+    /// Rename a variable, a mode or a collection
+    pub fn rename_variable(&mut self, id: ObjectId, name: &str) -> bool {
+        let renamable = self.tree.get(id).is_some_and(|node| {
+            matches!(
+                node.node_type(),
+                NodeType::VariableCollection | NodeType::VariableMode | NodeType::Variable
+            )
+        });
+        if renamable {
+            self.set_node_property(id, Property::Name, PropertyValue::String(name.into()));
+        }
+        renamable
+    }
+
+    /// Set a color variable's value in a mode
+    pub fn set_variable_color(&mut self, variable_id: ObjectId, mode_id: ObjectId, color: Color) -> bool {
+        self.set_variable_value(variable_id, mode_id, PropertyValue::Color(color))
+    }
+
+    /// Set a number variable's value in a mode
+    pub fn set_variable_number(&mut self, variable_id: ObjectId, mode_id: ObjectId, value: f32) -> bool {
+        self.set_variable_value(variable_id, mode_id, PropertyValue::Float(value))
+    }
+
+    /// Set a string variable's value in a mode
+    pub fn set_variable_string(&mut self, variable_id: ObjectId, mode_id: ObjectId, value: &str) -> bool {
+        self.set_variable_value(variable_id, mode_id, PropertyValue::String(value.into()))
+    }
+
+    /// Set a boolean variable's value in a mode
+    pub fn set_variable_boolean(&mut self, variable_id: ObjectId, mode_id: ObjectId, value: bool) -> bool {
+        self.set_variable_value(variable_id, mode_id, PropertyValue::Bool(value))
+    }
+
+    pub fn get_variable_color(&self, variable_id: ObjectId, mode_id: ObjectId) -> Option<Color> {
+        match self.variable_value_in(variable_id, mode_id)? {
+            PropertyValue::Color(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    pub fn get_variable_number(&self, variable_id: ObjectId, mode_id: ObjectId) -> Option<f32> {
+        match self.variable_value_in(variable_id, mode_id)? {
+            PropertyValue::Float(v) => Some(*v),
+            PropertyValue::Int(v) => Some(*v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn get_variable_string(&self, variable_id: ObjectId, mode_id: ObjectId) -> Option<String> {
+        match self.variable_value_in(variable_id, mode_id)? {
+            PropertyValue::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_variable_boolean(&self, variable_id: ObjectId, mode_id: ObjectId) -> Option<bool> {
+        match self.variable_value_in(variable_id, mode_id)? {
+            PropertyValue::Bool(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// Bind a node's property to a variable. Returns `false` if the node or
+    /// variable doesn't exist, or the property can't be bound.
+    pub fn bind_variable(&mut self, node_id: ObjectId, property: Property, variable_id: ObjectId) -> bool {
+        if self.variable_kind(variable_id).is_none() || !is_bindable(property) {
+            return false;
+        }
+        if !self.tree.get(node_id).is_some_and(|n| is_bindable_node(n.node_type())) {
+            return false;
+        }
+        self.set_node_property(node_id, property, PropertyValue::Variable(variable_id));
+        true
+    }
+
+    /// Replace a binding with the value it currently resolves to.
+    ///
+    /// Returns `false` if the property isn't bound.
+    pub fn unbind_variable(&mut self, node_id: ObjectId, property: Property) -> bool {
+        let Some(variable_id) = self.tree.get(node_id).and_then(|n| n.bound_variable(property)) else {
+            return false;
+        };
+        match self.variable_value(variable_id, node_id) {
+            Some(value) => self.set_node_property(node_id, property, value),
+            None => {
+                if self.apply(Operation::SetProperty { id: node_id, property, value: None }) {
+                    self.mark_override(node_id, property);
+                }
+            }
+        }
+        true
+    }
+
+    /// The variable a node's property is bound to
+    pub fn get_bound_variable(&self, node_id: ObjectId, property: Property) -> Option<ObjectId> {
+        self.tree.get(node_id)?.bound_variable(property)
+    }
+
+    /// Nodes with at least one property bound to a variable
+    pub fn get_variable_consumers(&self, variable_id: ObjectId) -> Vec<ObjectId> {
+        let binding = PropertyValue::Variable(variable_id);
+        self.tree
+            .iter()
+            .filter(|node| node.properties().values().any(|value| *value == binding))
+            .map(|node| node.id())
+            .collect()
+    }
+
+    /// Delete a variable. Its consumers keep the values they resolve to now.
+    pub fn delete_variable(&mut self, variable_id: ObjectId) -> bool {
+        if self.variable_kind(variable_id).is_none() {
+            return false;
+        }
+        self.history.begin_group();
+        self.unbind_all(variable_id);
+        self.apply(Operation::Remove { id: variable_id });
+        self.history.end_group();
+        true
+    }
+
+    /// Delete a collection with its modes and variables. Consumers keep the
+    /// values they resolve to now.
+    pub fn delete_variable_collection(&mut self, collection_id: ObjectId) -> bool {
+        let is_collection = self
+            .tree
+            .get(collection_id)
+            .is_some_and(|c| c.node_type() == NodeType::VariableCollection);
+        if !is_collection {
+            return false;
+        }
+
+        self.history.begin_group();
+        for variable_id in self.get_variables(collection_id) {
+            self.unbind_all(variable_id);
+        }
+        let pickers: Vec<ObjectId> = self
+            .tree
+            .iter()
+            .filter(|node| chosen_mode(node, collection_id).is_some())
+            .map(|node| node.id())
+            .collect();
+        for node_id in pickers {
+            self.set_map_entry(node_id, Property::VariableModes, collection_id, None);
+        }
+        self.apply(Operation::Remove { id: collection_id });
+        self.history.end_group();
+        true
+    }
+
+    /// Use a mode of a collection for a node and everything inside it
+    pub fn set_variable_mode(&mut self, node_id: ObjectId, collection_id: ObjectId, mode_id: ObjectId) -> bool {
+        if self.parent_if(mode_id, NodeType::VariableMode) != Some(collection_id) {
+            return false;
+        }
+        let can_pick = self.tree.get(node_id).is_some_and(|n| match n.node_type() {
+            NodeType::Page => true,
+            NodeType::Style => false,
+            node_type => is_bindable_node(node_type),
+        });
+        if !can_pick {
+            return false;
+        }
+        self.set_map_entry(node_id, Property::VariableModes, collection_id, Some(PropertyValue::Ref(mode_id)));
+        true
+    }
+
+    /// Stop picking a mode of a collection on a node, so it inherits the
+    /// mode from its ancestors again
+    pub fn clear_variable_mode(&mut self, node_id: ObjectId, collection_id: ObjectId) -> bool {
+        match self.tree.get(node_id) {
+            Some(node) if chosen_mode(node, collection_id).is_some() => {
+                self.set_map_entry(node_id, Property::VariableModes, collection_id, None);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The mode of a collection that is in effect at a node
+    pub fn get_variable_mode(&self, node_id: ObjectId, collection_id: ObjectId) -> Option<ObjectId> {
+        self.active_mode(node_id, collection_id)
+    }
+
+    /// A node's properties as they are drawn (style and variable values
+    /// filled in for the modes in effect at the node), as JSON for export
+    pub fn get_resolved_node_json(&self, id: ObjectId) -> Option<String> {
+        serde_json::to_string(&self.resolved_node(id)?).ok()
+    }
+}
+
+impl Document {
+    /// Replace a node's variable bindings with their values
+    pub(crate) fn resolve_bindings(&self, node: &mut Node) {
+        let bindings: Vec<(Property, ObjectId)> = node
+            .properties()
+            .iter()
+            .filter_map(|(&property, value)| match value {
+                PropertyValue::Variable(variable_id) => Some((property, *variable_id)),
+                _ => None,
+            })
+            .collect();
+
+        for (property, variable_id) in bindings {
+            match self.variable_value(variable_id, node.id()) {
+                Some(value) => node.set_property(property, value),
+                None => node.remove_property(property),
+            }
+        }
+    }
+
+    /// A node with its variable bindings resolved, borrowed as-is when it
+    /// has none
+    pub(crate) fn bound_node<'a>(&self, node: &'a Node) -> Cow<'a, Node> {
+        if !node.has_bindings() {
+            return Cow::Borrowed(node);
+        }
+        let mut resolved = node.clone();
+        self.resolve_bindings(&mut resolved);
+        Cow::Owned(resolved)
+    }
+
+    /// The value of a variable as seen from a node
+    pub(crate) fn variable_value(&self, variable_id: ObjectId, node_id: ObjectId) -> Option<PropertyValue> {
+        let collection_id = self.parent_if(variable_id, NodeType::Variable)?;
+        let variable = self.tree.get(variable_id)?;
+        let mode_id = self.active_mode(node_id, collection_id)?;
+        mode_value(variable, mode_id)
+            .or_else(|| mode_value(variable, self.default_mode(collection_id)?))
+            .cloned()
+    }
+
+    /// A variable's stored value in a mode
+    fn variable_value_in(&self, variable_id: ObjectId, mode_id: ObjectId) -> Option<&PropertyValue> {
+        self.variable_kind(variable_id)?;
+        mode_value(self.tree.get(variable_id)?, mode_id)
+    }
+
+    /// The mode picked by the node or its nearest ancestor that picks one,
+    /// or else the collection's default
+    fn active_mode(&self, node_id: ObjectId, collection_id: ObjectId) -> Option<ObjectId> {
+        let mut current = Some(node_id);
+        while let Some(id) = current {
+            let chosen = self.tree.get(id).and_then(|node| chosen_mode(node, collection_id));
+            // Ignore picks of modes that have since been deleted
+            if let Some(mode_id) = chosen.filter(|&mode| self.tree.parent(mode) == Some(collection_id)) {
+                return Some(mode_id);
+            }
+            current = self.tree.parent(id);
+        }
+        self.default_mode(collection_id)
+    }
+
+    fn default_mode(&self, collection_id: ObjectId) -> Option<ObjectId> {
+        self.get_variable_modes(collection_id).into_iter().next()
+    }
+
+    fn variable_kind(&self, variable_id: ObjectId) -> Option<VariableKind> {
+        self.tree.get(variable_id).and_then(VariableKind::of)
+    }
+
+    fn set_variable_value(&mut self, variable_id: ObjectId, mode_id: ObjectId, value: PropertyValue) -> bool {
+        let Some(kind) = self.variable_kind(variable_id) else {
+            return false;
+        };
+        let same_collection = self.parent_if(mode_id, NodeType::VariableMode) == self.tree.parent(variable_id);
+        if !same_collection || !kind.accepts(&value) {
+            return false;
+        }
+        self.set_map_entry(variable_id, Property::VariableValues, mode_id, Some(value));
+        true
+    }
+
+    /// Write resolved values over every binding to a variable
+    fn unbind_all(&mut self, variable_id: ObjectId) {
+        for node_id in self.get_variable_consumers(variable_id) {
+            let bound: Vec<Property> = self
+                .tree
+                .get(node_id)
+                .map(|node| {
+                    node.properties()
+                        .keys()
+                        .copied()
+                        .filter(|&property| node.bound_variable(property) == Some(variable_id))
+                        .collect()
+                })
+                .unwrap_or_default();
+            for property in bound {
+                self.unbind_variable(node_id, property);
+            }
+        }
+    }
+
+    /// Set (or remove, for `None`) one entry of a `Map` property. The
+    /// property is removed entirely once the map is empty.
+    fn set_map_entry(&mut self, id: ObjectId, property: Property, key: ObjectId, value: Option<PropertyValue>) {
+        let Some(node) = self.tree.get(id) else { return };
+        let mut entries = match node.get_property(property) {
+            Some(PropertyValue::Map(entries)) => entries.clone(),
+            _ => Vec::new(),
+        };
+        let position = entries.iter().position(|(entry_key, _)| *entry_key == key);
+        match (position, value) {
+            (Some(index), Some(value)) => entries[index].1 = value,
+            (None, Some(value)) => entries.push((key, value)),
+            (Some(index), None) => {
+                entries.remove(index);
+            }
+            (None, None) => return,
+        }
+
+        if entries.is_empty() {
+            if self.apply(Operation::SetProperty { id, property, value: None }) {
+                self.mark_override(id, property);
+            }
+        } else {
+            self.set_node_property(id, property, PropertyValue::Map(entries));
+        }
+    }
+
+    fn insert_named(&mut self, node_type: NodeType, parent_id: ObjectId, name: &str) -> ObjectId {
+        let id = ObjectId::random();
+        let mut node = Node::new(id, node_type);
+        node.set_property(Property::Name, PropertyValue::String(name.into()));
+        self.apply(Operation::Insert(Subtree::leaf(node, Some(parent_id))));
+        id
+    }
+
+    fn children_of_type(&self, parent_id: ObjectId, node_type: NodeType) -> Vec<ObjectId> {
+        self.tree
+            .children(parent_id)
+            .into_iter()
+            .filter(|&id| self.tree.get(id).is_some_and(|node| node.node_type() == node_type))
+            .collect()
+    }
+
+    /// The parent of a node, if the node has the given type
+    fn parent_if(&self, id: ObjectId, node_type: NodeType) -> Option<ObjectId> {
+        self.tree.get(id).filter(|node| node.node_type() == node_type)?;
+        self.tree.parent(id)
+    }
+}
+
+/// The mode a node picks for a collection, if any
+fn chosen_mode(node: &Node, collection_id: ObjectId) -> Option<ObjectId> {
+    match map_get(node.get_property(Property::VariableModes), collection_id)? {
+        PropertyValue::Ref(mode_id) => Some(*mode_id),
+        _ => None,
+    }
+}
+
+fn mode_value(variable: &Node, mode_id: ObjectId) -> Option<&PropertyValue> {
+    map_get(variable.get_property(Property::VariableValues), mode_id)
+}
+
+fn map_get(map: Option<&PropertyValue>, key: ObjectId) -> Option<&PropertyValue> {
+    match map? {
+        PropertyValue::Map(entries) => entries.iter().find(|(id, _)| *id == key).map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+/// Properties that hold document structure rather than design values
+fn is_bindable(property: Property) -> bool {
+    !matches!(
+        property,
+        Property::ParentId
+            | Property::MainComponent
+            | Property::ComponentSource
+            | Property::BooleanOperation
+            | Property::VectorData
+            | Property::StyleType
+            | Property::FillStyle
+            | Property::StrokeStyle
+            | Property::TextStyle
+            | Property::EffectStyle
+            | Property::VariableType
+            | Property::VariableValues
+            | Property::VariableModes
+    )
+}
+
+/// Nodes on the canvas, plus styles (so a style's values can follow a theme)
+fn is_bindable_node(node_type: NodeType) -> bool {
+    !matches!(
+        node_type,
+        NodeType::Document | NodeType::Page | NodeType::VariableCollection | NodeType::VariableMode | NodeType::Variable
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec2;
+
+    fn theme(doc: &mut Document) -> (ObjectId, ObjectId, ObjectId) {
+        let collection = doc.create_variable_collection("Theme");
+        let light = doc.get_variable_modes(collection)[0];
+        doc.rename_variable(light, "Light");
+        let dark = doc.add_variable_mode(collection, "Dark").unwrap();
+        (collection, light, dark)
+    }
+
+    #[test]
+    fn test_frame_mode_switches_bound_values() {
+        let mut doc = Document::new("Variables");
+        let (collection, light, dark) = theme(&mut doc);
+        let background = doc.create_variable(collection, "color", "background").unwrap();
+        doc.set_variable_color(background, light, Color::white());
+        doc.set_variable_color(background, dark, Color::black());
+        let size = doc.create_variable(collection, "number", "size").unwrap();
+        doc.set_variable_number(size, light, 40.0);
+        doc.set_variable_number(size, dark, 80.0);
+        assert!(!doc.set_variable_number(background, dark, 1.0));
+
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 10.0, 10.0, 50.0, 50.0);
+        assert!(doc.bind_variable(rect_id, Property::FillColor, background));
+        assert!(doc.bind_variable(rect_id, Property::Width, size));
+        assert!(!doc.bind_variable(rect_id, Property::FillStyle, background));
+
+        let fill = |doc: &Document| doc.resolved_node(rect_id).unwrap().fill_color();
+        assert_eq!(fill(&doc), Color::white());
+        assert_eq!(doc.get_absolute_bounds(rect_id).unwrap().width, 40.0);
+
+        assert!(doc.set_variable_mode(frame_id, collection, dark));
+        assert_eq!(doc.get_variable_mode(rect_id, collection), Some(dark));
+        assert_eq!(fill(&doc), Color::black());
+        assert_eq!(doc.get_absolute_bounds(rect_id).unwrap().width, 80.0);
+        assert!(doc.nodes_at_point(85.0, 20.0).contains(&rect_id));
+
+        // Bindings and modes survive a save and load
+        let loaded = Document::decode_binary(&doc.encode_binary().unwrap()).unwrap();
+        assert_eq!(loaded.get_bound_variable(rect_id, Property::FillColor), Some(background));
+        assert_eq!(loaded.resolved_node(rect_id).unwrap().fill_color(), Color::black());
+
+        assert!(doc.clear_variable_mode(frame_id, collection));
+        assert_eq!(fill(&doc), Color::white());
+    }
+
+    #[test]
+    fn test_deleting_keeps_resolved_values() {
+        let mut doc = Document::new("Variables");
+        let (collection, light, dark) = theme(&mut doc);
+        let accent = doc.create_variable(collection, "color", "accent").unwrap();
+        doc.set_variable_color(accent, light, Color::red());
+        doc.set_variable_color(accent, dark, Color::blue());
+
+        let frame_id = doc.create_frame(0.0, 0.0, 200.0, 200.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 50.0, 50.0);
+        doc.bind_variable(rect_id, Property::FillColor, accent);
+        doc.set_variable_mode(frame_id, collection, dark);
+
+        // Deleting the picked mode falls back to the default
+        assert!(doc.delete_variable_mode(dark));
+        assert!(!doc.delete_variable_mode(light));
+        assert_eq!(doc.get_variable_mode(rect_id, collection), Some(light));
+        assert_eq!(doc.resolved_node(rect_id).unwrap().fill_color(), Color::red());
+
+        assert_eq!(doc.get_variable_consumers(accent), vec![rect_id]);
+        assert!(doc.delete_variable(accent));
+        assert_eq!(doc.get_bound_variable(rect_id, Property::FillColor), None);
+        assert_eq!(doc.resolved_node(rect_id).unwrap().fill_color(), Color::red());
+
+        // One undo step brings the variable and the binding back
+        assert!(doc.undo());
+        assert_eq!(doc.get_bound_variable(rect_id, Property::FillColor), Some(accent));
+        assert!(doc.undo());
+        assert_eq!(doc.get_variable_mode(rect_id, collection), Some(dark));
+        assert_eq!(doc.resolved_node(rect_id).unwrap().fill_color(), Color::blue());
+    }
+
+    #[test]
+    fn test_value_edits_move_bound_nodes() {
+        let mut doc = Document::new("Variables");
+        let (collection, light, _) = theme(&mut doc);
+        let offset = doc.create_variable(collection, "number", "offset").unwrap();
+        doc.set_variable_number(offset, light, 10.0);
+
+        let frame_id = doc.create_frame(0.0, 0.0, 100.0, 100.0);
+        let rect_id = doc.create_rectangle(frame_id, 0.0, 0.0, 10.0, 10.0);
+        assert!(doc.bind_variable(frame_id, Property::X, offset));
+        assert_eq!(doc.get_world_transform(rect_id).unwrap().apply(Vec2::ZERO), Vec2::new(10.0, 0.0));
+        assert!(doc.nodes_at_point(15.0, 5.0).contains(&rect_id));
+
+        // Cached transforms and the spatial index follow the new value
+        doc.set_variable_number(offset, light, 500.0);
+        assert_eq!(doc.get_world_transform(rect_id).unwrap().apply(Vec2::ZERO), Vec2::new(500.0, 0.0));
+        assert_eq!(doc.get_absolute_bounds(rect_id).unwrap().x, 500.0);
+        assert!(doc.nodes_at_point(505.0, 5.0).contains(&rect_id));
+        assert!(!doc.nodes_at_point(15.0, 5.0).contains(&rect_id));
+    }
+}
@@ -0,0 +1,270 @@
+//! Boolean operations on vector networks
+//!
+//! Networks are flattened into polygons (closed contours, with bezier
+//! segments sampled into short lines), combined with `i_overlay`, and the
+//! resulting polygons are turned back into a network:
+//!
+//! ```text
+//! VectorNetwork ──to_contours()──► contours ──boolean()──► shapes ──from_shapes()──► VectorNetwork
+//! ```
+//!
+//! Operands are folded in order, the first one being the subject:
+//! `subtract` removes every later operand from the first, `intersect` keeps
+//! what all of them share and `exclude` keeps areas covered an odd number of
+//! times.
+//!
+//! Contours are taken from the network's regions. A network without regions
+//! is read as one path through its segments in order, which is how networks
+//! built point by point with the pen tool are laid out.
+
+use super::{VectorNetwork, VectorPoint};
+use crate::math::Rect;
+use i_overlay::core::fill_rule::FillRule;
+use i_overlay::core::overlay_rule::OverlayRule;
+use i_overlay::float::single::SingleFloatOverlay;
+
+/// A closed polygon; the last point connects back to the first
+pub type Contour = Vec<[f32; 2]>;
+
+/// Length of the straight pieces curves are sampled into
+const FLATTEN_STEP: f32 = 2.0;
+
+/// Most pieces a single curved segment is sampled into
+const MAX_CURVE_STEPS: usize = 64;
+
+/// How the operands of a boolean are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BooleanOp {
+    Union,
+    Subtract,
+    Intersect,
+    Exclude,
+}
+
+impl BooleanOp {
+    /// Parse `"union"`, `"subtract"`, `"intersect"` or `"exclude"`
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "union" => Some(BooleanOp::Union),
+            "subtract" => Some(BooleanOp::Subtract),
+            "intersect" => Some(BooleanOp::Intersect),
+            "exclude" => Some(BooleanOp::Exclude),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BooleanOp::Union => "union",
+            BooleanOp::Subtract => "subtract",
+            BooleanOp::Intersect => "intersect",
+            BooleanOp::Exclude => "exclude",
+        }
+    }
+
+    fn overlay_rule(&self) -> OverlayRule {
+        match self {
+            BooleanOp::Union => OverlayRule::Union,
+            BooleanOp::Subtract => OverlayRule::Difference,
+            BooleanOp::Intersect => OverlayRule::Intersect,
+            BooleanOp::Exclude => OverlayRule::Xor,
+        }
+    }
+}
+
+/// Combine operands (each a set of contours) into shapes.
+///
+/// Each output shape is a list of contours: its outer boundary followed by
+/// its holes.
+pub fn boolean(op: BooleanOp, operands: &[Vec<Contour>]) -> Vec<Vec<Contour>> {
+    let mut operands = operands.iter().filter(|contours| !contours.is_empty());
+    let Some(first) = operands.next() else {
+        return Vec::new();
+    };
+
+    let rule = op.overlay_rule();
+    let mut result: Vec<Contour> = first.clone();
+    for operand in operands {
+        let shapes = result.overlay(operand, rule, FillRule::NonZero);
+        result = shapes.into_iter().flatten().collect();
+    }
+
+    // A single operand is still normalized, so overlapping loops merge
+    result.overlay(&Vec::<Contour>::new(), OverlayRule::Subject, FillRule::NonZero)
+}
+
+impl VectorNetwork {
+    /// Flatten the network's closed loops into polygons
+    pub fn to_contours(&self) -> Vec<Contour> {
+        if self.regions().is_empty() {
+            let all: Vec<u32> = (0..self.segment_count()).collect();
+            return self.trace_contours(&all);
+        }
+        self.regions()
+            .iter()
+            .flat_map(|region| self.trace_contours(&region.segments))
+            .collect()
+    }
+
+    /// Build a network from shapes, one filled region per shape
+    pub fn from_shapes(shapes: &[Vec<Contour>]) -> Self {
+        let mut network = VectorNetwork::new();
+        for shape in shapes {
+            let mut region = Vec::new();
+            for contour in shape {
+                let points: Vec<u32> = contour.iter().map(|p| network.add_point(p[0], p[1])).collect();
+                for i in 0..points.len() {
+                    if let Some(segment) = network.connect(points[i], points[(i + 1) % points.len()]) {
+                        region.push(segment);
+                    }
+                }
+            }
+            network.add_region(region, true);
+        }
+        network
+    }
+
+    /// Walk segments in order, starting a new loop whenever the next segment
+    /// doesn't continue from where the previous one ended
+    fn trace_contours(&self, segment_indices: &[u32]) -> Vec<Contour> {
+        let segments = self.segments();
+        let mut contours = Vec::new();
+        let mut current: Contour = Vec::new();
+        let mut cursor: Option<u32> = None;
+
+        for (i, &index) in segment_indices.iter().enumerate() {
+            let Some(segment) = segments.get(index as usize) else { continue };
+            let (from, to) = match cursor {
+                Some(point) if point == segment.start => (segment.start, segment.end),
+                Some(point) if point == segment.end => (segment.end, segment.start),
+                _ => {
+                    if current.len() >= 3 {
+                        contours.push(std::mem::take(&mut current));
+                    }
+                    current.clear();
+                    // Orient the first segment of a loop towards the next one
+                    let next = segment_indices.get(i + 1).and_then(|&n| segments.get(n as usize));
+                    let reversed = next.is_some_and(|next| next.connects_to(segment.start) && !next.connects_to(segment.end));
+                    let (from, to) = if reversed { (segment.end, segment.start) } else { (segment.start, segment.end) };
+                    if let Some(start) = self.get_point(from) {
+                        current.push([start.x, start.y]);
+                    }
+                    (from, to)
+                }
+            };
+
+            let (Some(a), Some(b)) = (self.get_point(from), self.get_point(to)) else { continue };
+            let forward = from == segment.start;
+            sample_segment(&a, &b, forward, &mut current);
+            cursor = Some(to);
+        }
+
+        if current.len() >= 3 {
+            contours.push(current);
+        }
+        contours
+    }
+}
+
+/// Append the points of the segment from `a` to `b`, excluding `a` itself.
+///
+/// Curves use the outgoing handle of the segment's start point and the
+/// incoming handle of its end point, whichever way it is walked.
+fn sample_segment(a: &VectorPoint, b: &VectorPoint, forward: bool, out: &mut Contour) {
+    let (start, end) = if forward { (a, b) } else { (b, a) };
+    let p0 = [start.x, start.y];
+    let p1 = [start.handle_out_absolute_x(), start.handle_out_absolute_y()];
+    let p2 = [end.handle_in_absolute_x(), end.handle_in_absolute_y()];
+    let p3 = [end.x, end.y];
+
+    if p1 == p0 && p2 == p3 {
+        out.push([b.x, b.y]);
+        return;
+    }
+
+    let control_length = distance(p0, p1) + distance(p1, p2) + distance(p2, p3);
+    let steps = ((control_length / FLATTEN_STEP).ceil() as usize).clamp(4, MAX_CURVE_STEPS);
+    let samples = (1..=steps).map(|i| {
+        let t = if forward { i as f32 / steps as f32 } else { 1.0 - i as f32 / steps as f32 };
+        cubic_point(p0, p1, p2, p3, t)
+    });
+    out.extend(samples);
+}
+
+fn cubic_point(p0: [f32; 2], p1: [f32; 2], p2: [f32; 2], p3: [f32; 2], t: f32) -> [f32; 2] {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    [
+        a * p0[0] + b * p1[0] + c * p2[0] + d * p3[0],
+        a * p0[1] + b * p1[1] + c * p2[1] + d * p3[1],
+    ]
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt()
+}
+
+/// Bounding box of every point in a set of shapes
+pub fn shapes_bounds(shapes: &[Vec<Contour>]) -> Option<Rect> {
+    let mut points = shapes.iter().flatten().flatten();
+    let first = points.next()?;
+    let (mut min, mut max) = (*first, *first);
+    for p in points {
+        min = [min[0].min(p[0]), min[1].min(p[1])];
+        max = [max[0].max(p[0]), max[1].max(p[1])];
+    }
+    Some(Rect::new(min[0], min[1], max[0] - min[0], max[1] - min[1]))
+}
+
+/// Area covered by shapes (holes subtracted)
+pub fn shapes_area(shapes: &[Vec<Contour>]) -> f32 {
+    shapes.iter().flatten().map(signed_area).sum::<f32>().abs()
+}
+
+fn signed_area(contour: &Contour) -> f32 {
+    let n = contour.len();
+    (0..n)
+        .map(|i| {
+            let (a, b) = (contour[i], contour[(i + 1) % n]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f32>()
+        / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<Contour> {
+        VectorNetwork::from_rectangle(x, y, size, size).to_contours()
+    }
+
+    #[test]
+    fn test_boolean_ops_on_squares() {
+        let a = square(0.0, 0.0, 10.0);
+        let b = square(5.0, 5.0, 10.0);
+        let operands = [a, b];
+
+        let area = |op| shapes_area(&boolean(op, &operands));
+        assert!((area(BooleanOp::Union) - 175.0).abs() < 1e-3);
+        assert!((area(BooleanOp::Subtract) - 75.0).abs() < 1e-3);
+        assert!((area(BooleanOp::Intersect) - 25.0).abs() < 1e-3);
+        assert!((area(BooleanOp::Exclude) - 150.0).abs() < 1e-3);
+
+        // A square with a hole survives the round trip through a network
+        let framed = boolean(BooleanOp::Subtract, &[square(0.0, 0.0, 10.0), square(3.0, 3.0, 4.0)]);
+        let network = VectorNetwork::from_shapes(&framed);
+        assert_eq!(network.regions().len(), 1);
+        let round_trip = boolean(BooleanOp::Union, &[network.to_contours()]);
+        assert!((shapes_area(&round_trip) - 84.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_curves_are_flattened() {
+        let circle = VectorNetwork::from_ellipse(0.0, 0.0, 50.0, 50.0).to_contours();
+        assert_eq!(circle.len(), 1);
+        let area = shapes_area(&[circle]);
+        assert!((area - std::f32::consts::PI * 2500.0).abs() < 20.0, "area {}", area);
+    }
+}
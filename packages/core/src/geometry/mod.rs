@@ -4,12 +4,12 @@
 //! Unlike traditional paths (sequences of points), vector networks allow
 //! any point to connect to any other point.
 
+mod boolean;
 mod network;
 mod point;
 mod segment;
 
+pub use boolean::*;
 pub use network::*;
 pub use point::*;
 pub use segment::*;
-
-use wasm_bindgen::prelude::*;
@@ -182,7 +182,7 @@ impl VectorNetwork {
     }
 
     /// Toggle fill for a region containing a point
-    pub fn toggle_fill_at(&mut self, x: f32, y: f32) {
+    pub fn toggle_fill_at(&mut self, _x: f32, _y: f32) {
         // Find which region contains this point
         // This is a simplified implementation
         // A proper implementation would trace the boundary
@@ -267,12 +267,46 @@ impl VectorNetwork {
         network
     }
 
+    /// Create a rectangle network with rounded corners
+    pub fn from_rounded_rectangle(x: f32, y: f32, width: f32, height: f32, radius: f32) -> Self {
+        let r = radius.min(width / 2.0).min(height / 2.0);
+        if r <= 0.0 {
+            return Self::from_rectangle(x, y, width, height);
+        }
+
+        let mut network = Self::new();
+        let k = 0.552_284_8 * r;
+        let (right, bottom) = (x + width, y + height);
+
+        // Two points per corner, clockwise from the top edge
+        let points = [
+            network.add_point_with_handles(x + r, y, -k, 0.0, 0.0, 0.0),
+            network.add_point_with_handles(right - r, y, 0.0, 0.0, k, 0.0),
+            network.add_point_with_handles(right, y + r, 0.0, -k, 0.0, 0.0),
+            network.add_point_with_handles(right, bottom - r, 0.0, 0.0, 0.0, k),
+            network.add_point_with_handles(right - r, bottom, k, 0.0, 0.0, 0.0),
+            network.add_point_with_handles(x + r, bottom, 0.0, 0.0, -k, 0.0),
+            network.add_point_with_handles(x, bottom - r, 0.0, k, 0.0, 0.0),
+            network.add_point_with_handles(x, y + r, 0.0, 0.0, 0.0, -k),
+        ];
+        for i in 0..points.len() {
+            network.connect(points[i], points[(i + 1) % points.len()]);
+        }
+
+        network.regions.push(VectorRegion {
+            segments: (0..points.len() as u32).collect(),
+            filled: true,
+        });
+
+        network
+    }
+
     /// Create an ellipse network (approximated with bezier curves)
     pub fn from_ellipse(cx: f32, cy: f32, rx: f32, ry: f32) -> Self {
         let mut network = Self::new();
         
         // 4 points with bezier handles to approximate ellipse
-        let k = 0.5522847498; // Magic number for bezier approximation of circle
+        let k = 0.552_284_8; // Magic number for bezier approximation of circle
         let kx = rx * k;
         let ky = ry * k;
         
@@ -320,4 +354,9 @@ impl VectorNetwork {
     pub fn regions(&self) -> &[VectorRegion] {
         &self.regions
     }
+
+    /// Add a region made of existing segments, listed in order
+    pub fn add_region(&mut self, segments: Vec<u32>, filled: bool) {
+        self.regions.push(VectorRegion { segments, filled });
+    }
 }
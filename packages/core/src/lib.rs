@@ -31,6 +31,7 @@
 //! - **`document`**: Scene graph and object hierarchy (pages, frames, shapes)
 //! - **`geometry`**: Bezier paths, vector networks, hit testing
 //! - **`renderer`**: WebGL2 context, shaders, batched rendering
+//! - **`text`**: Fonts, line breaking and glyph positions
 //! - **`math`**: 2D transforms, vectors, matrices, bounding boxes
 //! - **`tools`**: Drawing tools (pen, rectangle, ellipse, etc.)
 //! - **`multiplayer`**: CRDT-based conflict-free collaborative editing
@@ -79,6 +80,7 @@ pub mod geometry;   // Bezier paths, vector networks, hit testing
 pub mod math;       // 2D math: Vec2, Transform, Rect, Matrix
 pub mod multiplayer; // CRDT-based multiplayer sync
 pub mod renderer;   // WebGL2 rendering: shaders, buffers, draw calls
+pub mod text;       // Text layout: fonts, line breaking, glyph positions
 pub mod tools;      // Drawing tools: pen, shape tools, selection
 
 // Re-export commonly used types for convenience
@@ -68,6 +68,36 @@ impl Transform2D {
     pub fn to_array(&self) -> Vec<f32> {
         self.matrix.to_cols_array().to_vec()
     }
+
+    /// Axis-aligned bounding box of a rectangle after transforming it
+    pub fn transform_rect(&self, rect: &Rect) -> Rect {
+        let corners = [
+            Vec2::new(rect.x, rect.y),
+            Vec2::new(rect.right(), rect.y),
+            Vec2::new(rect.x, rect.bottom()),
+            Vec2::new(rect.right(), rect.bottom()),
+        ]
+        .map(|corner| self.apply(corner));
+
+        let min = corners.iter().fold(Vec2::splat(f32::INFINITY), |acc, p| acc.min(*p));
+        let max = corners.iter().fold(Vec2::splat(f32::NEG_INFINITY), |acc, p| acc.max(*p));
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+}
+
+impl Transform2D {
+    pub fn from_matrix(matrix: Mat3) -> Self {
+        Self { matrix }
+    }
+
+    pub fn matrix(&self) -> Mat3 {
+        self.matrix
+    }
+
+    /// Transform a point (Rust-side version of `transform_point`)
+    pub fn apply(&self, point: Vec2) -> Vec2 {
+        self.matrix.transform_point2(point)
+    }
 }
 
 impl Default for Transform2D {
@@ -119,6 +149,17 @@ impl Rect {
     pub fn bottom(&self) -> f32 {
         self.y + self.height
     }
+
+    /// The smallest rectangle containing both
+    pub fn union(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+
+    /// Grow by `amount` on every side (shrink if negative)
+    pub fn expand(&self, amount: f32) -> Rect {
+        Rect::new(self.x - amount, self.y - amount, self.width + amount * 2.0, self.height + amount * 2.0)
+    }
 }
 
 /// Linear interpolation
@@ -1,9 +1,12 @@
 //! Multiplayer messages
 
-use crate::document::{ObjectId, Property, PropertyValue};
-use crate::multiplayer::ClientId;
+use crate::document::{ObjectId, Property};
 use serde::{Deserialize, Serialize};
 
+/// `Message::Error` code sent by a client whose document became inconsistent
+/// after applying a remote change
+pub const ERROR_INVALID_DOCUMENT: u32 = 1;
+
 /// Messages sent between client and server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
@@ -41,7 +44,7 @@ pub enum Message {
         object_id: ObjectId,
         object_type: String,
         parent_id: ObjectId,
-        order_index: String, // Fractional index
+        order_index: String, // Fractional index key (see document/fractional.rs)
         sequence: u64,
     },
     /// Delete an object
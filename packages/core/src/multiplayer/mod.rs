@@ -12,7 +12,6 @@ pub use message::*;
 pub use sync::*;
 
 use wasm_bindgen::prelude::*;
-use crate::document::ObjectId;
 
 /// Unique identifier for a connected client
 #[wasm_bindgen]
@@ -1,7 +1,7 @@
 //! Sync engine for multiplayer collaboration
 
-use crate::document::{Document, ObjectId, Property, PropertyValue};
-use crate::multiplayer::{ClientId, Message, UserCursor};
+use crate::document::{Document, NodeType, ObjectId, Property, PropertyValue, TreeError};
+use crate::multiplayer::{ClientId, Message, UserCursor, ERROR_INVALID_DOCUMENT};
 use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 
@@ -10,6 +10,7 @@ use wasm_bindgen::prelude::*;
 struct PendingChange {
     object_id: ObjectId,
     property: Property,
+    #[allow(dead_code)] // Kept so unacknowledged changes can be resent
     value: PropertyValue,
     sequence: u64,
 }
@@ -85,6 +86,27 @@ impl SyncEngine {
         })
     }
 
+    /// Create a move message for a node that was just moved locally.
+    ///
+    /// The message carries the node's new order index rather than a position,
+    /// so the node lands between the same siblings on every client.
+    pub fn create_move_message(&mut self, document: &Document, object_id: ObjectId) -> Option<String> {
+        let client_id = self.client_id?;
+        let new_parent_id = document.get_parent(object_id)?;
+        let (_, order_index) = document.placement(object_id);
+        self.sequence += 1;
+        Some(
+            Message::MoveObject {
+                client_id: client_id.value(),
+                object_id,
+                new_parent_id,
+                order_index,
+                sequence: self.sequence,
+            }
+            .to_json(),
+        )
+    }
+
     /// Process an incoming message
     pub fn process_message(&mut self, json: &str, document: &mut Document) -> Option<String> {
         let message = Message::from_json(json)?;
@@ -113,11 +135,23 @@ impl SyncEngine {
                 // Apply the change if it doesn't conflict with pending changes
                 if !self.has_pending_change(object_id, property) {
                     if let Ok(prop_value) = serde_json::from_str::<PropertyValue>(&value) {
-                        document.set_node_property(object_id, property, prop_value);
+                        document.apply_remote_property(object_id, property, prop_value);
                     }
                 }
                 None
             }
+            Message::CreateObject { client_id: _, object_id, object_type, parent_id, order_index, sequence: _ } => {
+                let Ok(node_type) = serde_json::from_value::<NodeType>(serde_json::Value::String(object_type)) else {
+                    return Self::diverged(format!("Unknown node type for {}", object_id));
+                };
+                Self::check_applied(document.apply_remote_create(object_id, node_type, parent_id, order_index))
+            }
+            Message::DeleteObject { client_id: _, object_id, sequence: _ } => {
+                Self::check_applied(document.apply_remote_delete(object_id))
+            }
+            Message::MoveObject { client_id: _, object_id, new_parent_id, order_index, sequence: _ } => {
+                Self::check_applied(document.apply_remote_move(object_id, new_parent_id, order_index))
+            }
             Message::Ack { sequence } => {
                 // Remove acknowledged changes
                 self.pending_changes.retain(|c| c.sequence != sequence);
@@ -134,6 +168,20 @@ impl SyncEngine {
         }
     }
 
+    /// Remote creates, deletes and moves are checked against the nodes they
+    /// touch before they're applied (see `DocumentTree::check_reparent` and
+    /// `check_subtree`), which keeps the tree sound without validating all
+    /// of it per message. One that can't be applied means the local copy has
+    /// diverged, and the server is told so it can send a fresh copy of the
+    /// document.
+    fn check_applied(result: Result<(), TreeError>) -> Option<String> {
+        Self::diverged(result.err()?.to_string())
+    }
+
+    fn diverged(message: String) -> Option<String> {
+        Some(Message::Error { code: ERROR_INVALID_DOCUMENT, message }.to_json())
+    }
+
     /// Check if we have a pending change for this property
     fn has_pending_change(&self, object_id: ObjectId, property: Property) -> bool {
         self.pending_changes.iter().any(|c| c.object_id == object_id && c.property == property)
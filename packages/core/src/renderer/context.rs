@@ -1,11 +1,68 @@
 //! WebGL rendering context wrapper
 
-use crate::document::Color;
-use crate::math::Rect;
-use crate::renderer::Viewport;
+use crate::document::{BlendMode, Color, ColorStop, DecodedImage, Gradient, GradientType};
+use crate::math::{Rect, Transform2D};
+use crate::renderer::{Vertex, Viewport};
+use lyon::tessellation::VertexBuffers;
+use std::collections::HashMap;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlCanvasElement, WebGl2RenderingContext as GL, WebGlProgram, WebGlBuffer, WebGlVertexArrayObject};
+use web_sys::{
+    HtmlCanvasElement, WebGl2RenderingContext as GL, WebGlBuffer, WebGlFramebuffer, WebGlProgram, WebGlRenderbuffer,
+    WebGlTexture, WebGlVertexArrayObject,
+};
+
+/// Gradients with more stops than this are resampled to this many
+const MAX_GRADIENT_STOPS: usize = 16;
+
+/// Clips nest this deep at most (the stencil buffer has 8 bits)
+const MAX_CLIP_DEPTH: u32 = 255;
+
+/// The outline a fill is clipped to
+#[derive(Debug, Clone, Copy)]
+pub enum FillShape<'a> {
+    /// The bounds, with rounded corners of this radius
+    Rect(f32),
+    /// The ellipse inscribed in the bounds
+    Ellipse,
+    /// A tessellated path in the same local space as the bounds
+    Path(&'a VertexBuffers<Vertex, u16>),
+}
+
+/// What a fill draws, with everything looked up that the GPU needs
+#[derive(Debug, Clone, Copy)]
+pub enum PaintSource<'a> {
+    Solid(Color),
+    Gradient(&'a Gradient),
+    /// An uploaded image; `image_rect` is where the whole image (or one
+    /// tile, when `tile` is set) goes in the fill's local space
+    Image { hash: &'a str, image_rect: Rect, tile: bool },
+    /// An offscreen layer, pixel for pixel. With a tint, only the layer's
+    /// coverage is used (inverted if `invert` is set) to draw the tint color.
+    Layer { layer: &'a RenderTarget, tint: Option<Color>, invert: bool },
+    /// The shape alone, with hard edges, for drawing clips into the stencil
+    /// buffer (see `push_clip`)
+    Clip,
+}
+
+/// One paint of a fill, ready to draw. Paints are drawn with normal
+/// blending; other modes go through a layer (see `composite_layer`).
+#[derive(Debug, Clone, Copy)]
+pub struct FillPaint<'a> {
+    pub source: PaintSource<'a>,
+    pub opacity: f32,
+}
+
+/// An offscreen color buffer the size of the canvas, holding premultiplied
+/// colors, with a stencil buffer for its clips
+#[derive(Debug)]
+pub struct RenderTarget {
+    framebuffer: WebGlFramebuffer,
+    texture: WebGlTexture,
+    stencil: WebGlRenderbuffer,
+    width: u32,
+    height: u32,
+}
 
 /// WebGL rendering context
 pub struct RenderContext {
@@ -17,32 +74,69 @@ pub struct RenderContext {
     rect_program: WebGlProgram,
     ellipse_program: WebGlProgram,
     line_program: WebGlProgram,
+    path_program: WebGlProgram,
+    /// Paints (solid, gradient, image) over a quad and over a path
+    paint_program: WebGlProgram,
+    paint_path_program: WebGlProgram,
+    /// One direction of a separable Gaussian blur over a layer
+    blur_program: WebGlProgram,
+    /// Draws a layer onto the target below it
+    composite_program: WebGlProgram,
     // Buffers
     quad_vao: WebGlVertexArrayObject,
+    #[allow(dead_code)] // Owned here so the buffer lives as long as the VAO
     quad_buffer: WebGlBuffer,
+    /// Tessellated paths are streamed through these on every draw
+    path_vao: WebGlVertexArrayObject,
+    path_vertex_buffer: WebGlBuffer,
+    path_index_buffer: WebGlBuffer,
+    /// Uploaded images by content hash; `None` for images that couldn't be decoded
+    textures: HashMap<String, Option<WebGlTexture>>,
+    max_texture_size: u32,
+    /// Layers being drawn into, innermost last; empty when drawing to the canvas
+    layers: Vec<RenderTarget>,
+    /// Released targets, kept for reuse until the canvas is resized
+    spare_targets: Vec<RenderTarget>,
+    /// How many clips are pushed on the canvas, then on each layer. Drawing
+    /// only touches pixels whose stencil value equals the innermost depth.
+    clip_depths: Vec<u32>,
 }
 
 impl RenderContext {
     pub fn new(canvas: HtmlCanvasElement) -> Result<Self, JsValue> {
+        // Clips are drawn into the stencil buffer
+        let options = js_sys::Object::new();
+        js_sys::Reflect::set(&options, &"stencil".into(), &true.into())?;
         let gl = canvas
-            .get_context("webgl2")?
+            .get_context_with_context_options("webgl2", &options)?
             .ok_or("Failed to get WebGL2 context")?
             .dyn_into::<GL>()?;
 
-        // Enable blending for transparency
+        // Enable blending for transparency. Alpha is accumulated separately
+        // so offscreen layers end up holding premultiplied colors.
         gl.enable(GL::BLEND);
-        gl.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        gl.blend_func_separate(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA, GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
         
         // Create shader programs
         let rect_program = create_rect_program(&gl)?;
         let ellipse_program = create_ellipse_program(&gl)?;
         let line_program = create_line_program(&gl)?;
+        let path_program = create_path_program(&gl)?;
+        let paint_program = create_paint_program(&gl, PAINT_VERTEX_SHADER)?;
+        let paint_path_program = create_paint_program(&gl, PAINT_PATH_VERTEX_SHADER)?;
+        let blur_program = create_layer_program(&gl, BLUR_FRAGMENT_SHADER)?;
+        let composite_program = create_layer_program(&gl, COMPOSITE_FRAGMENT_SHADER)?;
         
         // Create quad geometry
         let (quad_vao, quad_buffer) = create_quad_geometry(&gl)?;
+        let (path_vao, path_vertex_buffer, path_index_buffer) = create_path_buffers(&gl)?;
         
         let width = canvas.width();
         let height = canvas.height();
+        let max_texture_size = gl
+            .get_parameter(GL::MAX_TEXTURE_SIZE)?
+            .as_f64()
+            .map_or(4096, |size| size as u32);
         
         Ok(Self {
             gl,
@@ -52,8 +146,21 @@ impl RenderContext {
             rect_program,
             ellipse_program,
             line_program,
+            path_program,
+            paint_program,
+            paint_path_program,
+            blur_program,
+            composite_program,
             quad_vao,
             quad_buffer,
+            path_vao,
+            path_vertex_buffer,
+            path_index_buffer,
+            textures: HashMap::new(),
+            max_texture_size,
+            layers: Vec::new(),
+            spare_targets: Vec::new(),
+            clip_depths: vec![0],
         })
     }
 
@@ -63,6 +170,9 @@ impl RenderContext {
         self.width = width;
         self.height = height;
         self.gl.viewport(0, 0, width as i32, height as i32);
+        for target in std::mem::take(&mut self.spare_targets) {
+            self.delete_target(target);
+        }
     }
     
     pub fn width(&self) -> u32 {
@@ -75,14 +185,76 @@ impl RenderContext {
 
     pub fn clear(&self, color: Color) {
         self.gl.clear_color(color.r, color.g, color.b, color.a);
-        self.gl.clear(GL::COLOR_BUFFER_BIT);
+        self.gl.clear(GL::COLOR_BUFFER_BIT | GL::STENCIL_BUFFER_BIT);
     }
 
     pub fn flush(&self) {
         self.gl.flush();
     }
 
+    /// The largest texture side the GPU accepts
+    pub fn max_texture_size(&self) -> u32 {
+        self.max_texture_size
+    }
+
+    /// Whether an image has been uploaded (or has failed to decode)
+    pub fn has_texture(&self, hash: &str) -> bool {
+        self.textures.contains_key(hash)
+    }
+
+    /// Upload an image as a mipmapped texture. `None` records that the image
+    /// can't be drawn, so it isn't decoded again every frame.
+    pub fn upload_image(&mut self, hash: &str, image: Option<&DecodedImage>) {
+        let texture = image.and_then(|image| self.create_texture(image));
+        self.textures.insert(hash.to_string(), texture);
+    }
+
+    /// Delete one uploaded image
+    pub fn delete_texture(&mut self, hash: &str) {
+        if let Some(Some(texture)) = self.textures.remove(hash) {
+            self.gl.delete_texture(Some(&texture));
+        }
+    }
+
+    /// Delete every uploaded image
+    pub fn clear_textures(&mut self) {
+        for texture in self.textures.drain().filter_map(|(_, texture)| texture) {
+            self.gl.delete_texture(Some(&texture));
+        }
+    }
+
+    fn create_texture(&self, image: &DecodedImage) -> Option<WebGlTexture> {
+        let texture = self.gl.create_texture()?;
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        self.gl.pixel_storei(GL::UNPACK_ALIGNMENT, 1);
+        let uploaded = self.gl.tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+            GL::TEXTURE_2D,
+            0,
+            GL::RGBA as i32,
+            image.width as i32,
+            image.height as i32,
+            0,
+            GL::RGBA,
+            GL::UNSIGNED_BYTE,
+            Some(&image.pixels),
+        );
+        if uploaded.is_err() {
+            self.gl.delete_texture(Some(&texture));
+            return None;
+        }
+        // Mipmaps keep zoomed-out images from shimmering
+        self.gl.generate_mipmap(GL::TEXTURE_2D);
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR_MIPMAP_LINEAR as i32);
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        Some(texture)
+    }
+
     pub fn draw_rect(&self, rect: Rect, color: Color, viewport: &Viewport, corner_radius: f32) {
+        self.draw_rect_transformed(rect, &Transform2D::new(), color, viewport, corner_radius);
+    }
+
+    /// Draw a rectangle given in a node's local space, placed on the canvas by `transform`
+    pub fn draw_rect_transformed(&self, rect: Rect, transform: &Transform2D, color: Color, viewport: &Viewport, corner_radius: f32) {
         self.gl.use_program(Some(&self.rect_program));
         self.gl.bind_vertex_array(Some(&self.quad_vao));
         
@@ -92,9 +264,11 @@ impl RenderContext {
         let color_loc = self.gl.get_uniform_location(&self.rect_program, "u_color");
         let viewport_loc = self.gl.get_uniform_location(&self.rect_program, "u_viewport");
         let radius_loc = self.gl.get_uniform_location(&self.rect_program, "u_cornerRadius");
+        let transform_loc = self.gl.get_uniform_location(&self.rect_program, "u_transform");
         
         self.gl.uniform2f(resolution_loc.as_ref(), self.width as f32, self.height as f32);
         self.gl.uniform4f(rect_loc.as_ref(), rect.x, rect.y, rect.width, rect.height);
+        self.gl.uniform_matrix3fv_with_f32_array(transform_loc.as_ref(), false, &transform.matrix().to_cols_array());
         self.gl.uniform4f(color_loc.as_ref(), color.r, color.g, color.b, color.a);
         self.gl.uniform3f(viewport_loc.as_ref(), viewport.x, viewport.y, viewport.zoom);
         self.gl.uniform1f(radius_loc.as_ref(), corner_radius);
@@ -103,20 +277,29 @@ impl RenderContext {
     }
 
     pub fn draw_rect_stroke(&self, rect: Rect, color: Color, viewport: &Viewport, stroke_width: f32) {
+        self.draw_rect_stroke_transformed(rect, &Transform2D::new(), color, viewport, stroke_width);
+    }
+
+    pub fn draw_rect_stroke_transformed(&self, rect: Rect, transform: &Transform2D, color: Color, viewport: &Viewport, stroke_width: f32) {
         // Draw four thin rectangles for the stroke
         let sw = stroke_width / viewport.zoom;
         
         // Top
-        self.draw_rect(Rect::new(rect.x - sw, rect.y - sw, rect.width + sw * 2.0, sw), color, viewport, 0.0);
+        self.draw_rect_transformed(Rect::new(rect.x - sw, rect.y - sw, rect.width + sw * 2.0, sw), transform, color, viewport, 0.0);
         // Bottom
-        self.draw_rect(Rect::new(rect.x - sw, rect.y + rect.height, rect.width + sw * 2.0, sw), color, viewport, 0.0);
+        self.draw_rect_transformed(Rect::new(rect.x - sw, rect.y + rect.height, rect.width + sw * 2.0, sw), transform, color, viewport, 0.0);
         // Left
-        self.draw_rect(Rect::new(rect.x - sw, rect.y, sw, rect.height), color, viewport, 0.0);
+        self.draw_rect_transformed(Rect::new(rect.x - sw, rect.y, sw, rect.height), transform, color, viewport, 0.0);
         // Right
-        self.draw_rect(Rect::new(rect.x + rect.width, rect.y, sw, rect.height), color, viewport, 0.0);
+        self.draw_rect_transformed(Rect::new(rect.x + rect.width, rect.y, sw, rect.height), transform, color, viewport, 0.0);
     }
 
     pub fn draw_ellipse(&self, x: f32, y: f32, width: f32, height: f32, color: Color, viewport: &Viewport) {
+        self.draw_ellipse_transformed(Rect::new(x, y, width, height), &Transform2D::new(), color, viewport);
+    }
+
+    /// Draw an ellipse inscribed in a local-space rectangle, placed on the canvas by `transform`
+    pub fn draw_ellipse_transformed(&self, rect: Rect, transform: &Transform2D, color: Color, viewport: &Viewport) {
         self.gl.use_program(Some(&self.ellipse_program));
         self.gl.bind_vertex_array(Some(&self.quad_vao));
         
@@ -124,15 +307,436 @@ impl RenderContext {
         let rect_loc = self.gl.get_uniform_location(&self.ellipse_program, "u_rect");
         let color_loc = self.gl.get_uniform_location(&self.ellipse_program, "u_color");
         let viewport_loc = self.gl.get_uniform_location(&self.ellipse_program, "u_viewport");
+        let transform_loc = self.gl.get_uniform_location(&self.ellipse_program, "u_transform");
         
         self.gl.uniform2f(resolution_loc.as_ref(), self.width as f32, self.height as f32);
-        self.gl.uniform4f(rect_loc.as_ref(), x, y, width, height);
+        self.gl.uniform4f(rect_loc.as_ref(), rect.x, rect.y, rect.width, rect.height);
+        self.gl.uniform_matrix3fv_with_f32_array(transform_loc.as_ref(), false, &transform.matrix().to_cols_array());
         self.gl.uniform4f(color_loc.as_ref(), color.r, color.g, color.b, color.a);
         self.gl.uniform3f(viewport_loc.as_ref(), viewport.x, viewport.y, viewport.zoom);
         
         self.gl.draw_arrays(GL::TRIANGLES, 0, 6);
     }
 
+    /// Draw a tessellated path given in a node's local space, placed on the
+    /// canvas by `transform`
+    pub fn draw_path_transformed(&self, geometry: &VertexBuffers<Vertex, u16>, transform: &Transform2D, color: Color, viewport: &Viewport) {
+        if geometry.indices.is_empty() {
+            return;
+        }
+        self.gl.use_program(Some(&self.path_program));
+        self.upload_path(geometry);
+
+        let resolution_loc = self.gl.get_uniform_location(&self.path_program, "u_resolution");
+        let color_loc = self.gl.get_uniform_location(&self.path_program, "u_color");
+        let viewport_loc = self.gl.get_uniform_location(&self.path_program, "u_viewport");
+        let transform_loc = self.gl.get_uniform_location(&self.path_program, "u_transform");
+
+        self.gl.uniform2f(resolution_loc.as_ref(), self.width as f32, self.height as f32);
+        self.gl.uniform_matrix3fv_with_f32_array(transform_loc.as_ref(), false, &transform.matrix().to_cols_array());
+        self.gl.uniform4f(color_loc.as_ref(), color.r, color.g, color.b, color.a);
+        self.gl.uniform3f(viewport_loc.as_ref(), viewport.x, viewport.y, viewport.zoom);
+
+        self.gl.draw_elements_with_i32(GL::TRIANGLES, geometry.indices.len() as i32, GL::UNSIGNED_SHORT, 0);
+    }
+
+    /// Stream a tessellated path into the path buffers and bind them
+    fn upload_path(&self, geometry: &VertexBuffers<Vertex, u16>) {
+        self.gl.bind_vertex_array(Some(&self.path_vao));
+        let positions: Vec<f32> = geometry.vertices.iter().flat_map(|v| v.position).collect();
+        self.gl.bind_buffer(GL::ARRAY_BUFFER, Some(&self.path_vertex_buffer));
+        unsafe {
+            let array = js_sys::Float32Array::view(&positions);
+            self.gl.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &array, GL::DYNAMIC_DRAW);
+        }
+        self.gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&self.path_index_buffer));
+        unsafe {
+            let array = js_sys::Uint16Array::view(&geometry.indices);
+            self.gl.buffer_data_with_array_buffer_view(GL::ELEMENT_ARRAY_BUFFER, &array, GL::DYNAMIC_DRAW);
+        }
+    }
+
+    /// Draw one paint over a local-space rectangle, clipped to `shape` and
+    /// placed on the canvas by `transform`.
+    ///
+    /// Gradients are laid out over `rect`, also for paths. Returns `false`
+    /// if the paint is an image that hasn't been uploaded.
+    pub fn draw_paint_transformed(
+        &self,
+        paint: &FillPaint,
+        rect: Rect,
+        shape: FillShape,
+        transform: &Transform2D,
+        viewport: &Viewport,
+    ) -> bool {
+        let program = match shape {
+            FillShape::Path(geometry) => {
+                if geometry.indices.is_empty() {
+                    return true;
+                }
+                self.upload_path(geometry);
+                &self.paint_path_program
+            }
+            FillShape::Rect(_) | FillShape::Ellipse => {
+                self.gl.bind_vertex_array(Some(&self.quad_vao));
+                &self.paint_program
+            }
+        };
+        self.gl.use_program(Some(program));
+        let uniform = |name: &str| self.gl.get_uniform_location(program, name);
+
+        // Paint
+        match paint.source {
+            PaintSource::Solid(color) => {
+                self.gl.uniform1i(uniform("u_paintType").as_ref(), 0);
+                self.gl.uniform4f(uniform("u_color").as_ref(), color.r, color.g, color.b, color.a);
+            }
+            PaintSource::Gradient(gradient) => {
+                let paint_type = match gradient.gradient_type {
+                    GradientType::Linear => 1,
+                    GradientType::Radial => 2,
+                    GradientType::Angular => 3,
+                    GradientType::Diamond => 4,
+                };
+                let (positions, colors) = gradient_uniforms(gradient);
+                self.gl.uniform1i(uniform("u_paintType").as_ref(), paint_type);
+                self.gl.uniform1i(uniform("u_stopCount").as_ref(), positions.len() as i32);
+                self.gl.uniform1fv_with_f32_array(uniform("u_stopPositions").as_ref(), &positions);
+                self.gl.uniform4fv_with_f32_array(uniform("u_stopColors").as_ref(), &colors);
+                self.gl.uniform_matrix3fv_with_f32_array(
+                    uniform("u_gradientTransform").as_ref(),
+                    false,
+                    &gradient.matrix().to_cols_array(),
+                );
+            }
+            PaintSource::Image { hash, image_rect, tile } => {
+                let Some(Some(texture)) = self.textures.get(hash) else {
+                    return false;
+                };
+                self.gl.active_texture(GL::TEXTURE0);
+                self.gl.bind_texture(GL::TEXTURE_2D, Some(texture));
+                // Clamp single images so their edges don't pick up the opposite side
+                let wrap = if tile { GL::REPEAT } else { GL::CLAMP_TO_EDGE } as i32;
+                self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, wrap);
+                self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, wrap);
+                self.gl.uniform1i(uniform("u_paintType").as_ref(), 5);
+                self.gl.uniform1i(uniform("u_texture").as_ref(), 0);
+                self.gl.uniform1i(uniform("u_tile").as_ref(), tile as i32);
+                let r = image_rect;
+                self.gl.uniform4f(uniform("u_imageRect").as_ref(), r.x, r.y, r.width, r.height);
+            }
+            PaintSource::Layer { layer, tint, invert } => {
+                let tint_color = tint.unwrap_or_default();
+                self.gl.active_texture(GL::TEXTURE0);
+                self.gl.bind_texture(GL::TEXTURE_2D, Some(&layer.texture));
+                self.gl.uniform1i(uniform("u_paintType").as_ref(), 6);
+                self.gl.uniform1i(uniform("u_texture").as_ref(), 0);
+                self.gl.uniform1i(uniform("u_tinted").as_ref(), tint.is_some() as i32);
+                self.gl.uniform1i(uniform("u_invert").as_ref(), invert as i32);
+                self.gl.uniform4f(uniform("u_color").as_ref(), tint_color.r, tint_color.g, tint_color.b, tint_color.a);
+            }
+            PaintSource::Clip => {
+                self.gl.uniform1i(uniform("u_paintType").as_ref(), 7);
+            }
+        }
+        self.gl.uniform1f(uniform("u_opacity").as_ref(), paint.opacity);
+
+        // Geometry
+        let (shape_type, corner_radius) = match shape {
+            FillShape::Rect(radius) => (1, radius),
+            FillShape::Ellipse => (2, 0.0),
+            FillShape::Path(_) => (0, 0.0),
+        };
+        self.gl.uniform2f(uniform("u_resolution").as_ref(), self.width as f32, self.height as f32);
+        self.gl.uniform4f(uniform("u_rect").as_ref(), rect.x, rect.y, rect.width, rect.height);
+        self.gl.uniform_matrix3fv_with_f32_array(uniform("u_transform").as_ref(), false, &transform.matrix().to_cols_array());
+        self.gl.uniform3f(uniform("u_viewport").as_ref(), viewport.x, viewport.y, viewport.zoom);
+        self.gl.uniform1i(uniform("u_shape").as_ref(), shape_type);
+        // The fragment shader measures in screen pixels
+        self.gl.uniform1f(uniform("u_cornerRadius").as_ref(), corner_radius * viewport.zoom);
+
+        // The shader outputs premultiplied colors
+        self.gl.blend_func(GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+        match shape {
+            FillShape::Path(geometry) => {
+                self.gl.draw_elements_with_i32(GL::TRIANGLES, geometry.indices.len() as i32, GL::UNSIGNED_SHORT, 0)
+            }
+            FillShape::Rect(_) | FillShape::Ellipse => self.gl.draw_arrays(GL::TRIANGLES, 0, 6),
+        }
+        self.reset_blend();
+        true
+    }
+
+    /// Start drawing into a fresh transparent layer. Everything drawn until
+    /// the matching `end_layer` goes into it.
+    ///
+    /// Returns `false`, and keeps drawing where it was, if no layer could be
+    /// created; don't call `end_layer` then.
+    pub fn begin_layer(&mut self) -> bool {
+        let Some(target) = self.acquire_target() else {
+            return false;
+        };
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&target.framebuffer));
+        self.gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        self.gl.clear(GL::COLOR_BUFFER_BIT | GL::STENCIL_BUFFER_BIT);
+        self.layers.push(target);
+        // The layer starts unclipped; it is clipped as a whole when composited
+        self.clip_depths.push(0);
+        self.apply_stencil();
+        true
+    }
+
+    /// Stop drawing into the innermost layer and return it, to be drawn with
+    /// `composite_layer` and handed back with `release_layer`
+    pub fn end_layer(&mut self) -> Option<RenderTarget> {
+        let layer = self.layers.pop()?;
+        self.clip_depths.pop();
+        self.bind_current_target();
+        self.apply_stencil();
+        Some(layer)
+    }
+
+    /// Hand a layer back for reuse
+    pub fn release_layer(&mut self, layer: RenderTarget) {
+        if layer.width == self.width && layer.height == self.height {
+            self.spare_targets.push(layer);
+        } else {
+            self.delete_target(layer);
+        }
+    }
+
+    /// A copy of what has been drawn so far into the current target
+    pub fn copy_backdrop(&mut self) -> Option<RenderTarget> {
+        let target = self.acquire_target()?;
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&target.texture));
+        self.gl.copy_tex_sub_image_2d(GL::TEXTURE_2D, 0, 0, 0, 0, 0, self.width as i32, self.height as i32);
+        Some(target)
+    }
+
+    /// Blur a layer in place with a Gaussian of `sigma` pixels, as a
+    /// horizontal pass into a scratch target and a vertical pass back
+    pub fn blur_layer(&mut self, layer: &RenderTarget, sigma: f32) {
+        if sigma < 0.5 {
+            return;
+        }
+        let Some(scratch) = self.acquire_target() else {
+            return;
+        };
+        self.gl.disable(GL::BLEND);
+        self.gl.disable(GL::STENCIL_TEST);
+        self.gl.use_program(Some(&self.blur_program));
+        self.gl.bind_vertex_array(Some(&self.quad_vao));
+        self.gl.active_texture(GL::TEXTURE0);
+        let texture_loc = self.gl.get_uniform_location(&self.blur_program, "u_texture");
+        let direction_loc = self.gl.get_uniform_location(&self.blur_program, "u_direction");
+        let sigma_loc = self.gl.get_uniform_location(&self.blur_program, "u_sigma");
+        self.gl.uniform1i(texture_loc.as_ref(), 0);
+        self.gl.uniform1f(sigma_loc.as_ref(), sigma);
+
+        let passes = [(layer, &scratch, [1.0 / layer.width as f32, 0.0]), (&scratch, layer, [0.0, 1.0 / layer.height as f32])];
+        for (source, destination, direction) in passes {
+            self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&destination.framebuffer));
+            self.gl.bind_texture(GL::TEXTURE_2D, Some(&source.texture));
+            self.gl.uniform2f(direction_loc.as_ref(), direction[0], direction[1]);
+            self.gl.draw_arrays(GL::TRIANGLES, 0, 6);
+        }
+
+        self.gl.enable(GL::BLEND);
+        self.release_layer(scratch);
+        self.bind_current_target();
+        self.apply_stencil();
+    }
+
+    /// Draw a layer over the current target with `opacity` and
+    /// `blend_mode`. With a tint, the layer's coverage is drawn in the tint
+    /// color instead (for shadows).
+    ///
+    /// Normal blending is left to the GPU. The other modes read a copy of the
+    /// current target and compute the result in the shader.
+    pub fn composite_layer(&mut self, layer: &RenderTarget, tint: Option<Color>, opacity: f32, blend_mode: BlendMode) {
+        let backdrop = match blend_mode {
+            BlendMode::Normal => None,
+            _ => self.copy_backdrop(),
+        };
+        self.gl.use_program(Some(&self.composite_program));
+        self.gl.bind_vertex_array(Some(&self.quad_vao));
+        self.gl.active_texture(GL::TEXTURE0);
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&layer.texture));
+        if let Some(backdrop) = &backdrop {
+            self.gl.active_texture(GL::TEXTURE1);
+            self.gl.bind_texture(GL::TEXTURE_2D, Some(&backdrop.texture));
+        }
+
+        let uniform = |name: &str| self.gl.get_uniform_location(&self.composite_program, name);
+        let tint_color = tint.unwrap_or_default();
+        self.gl.uniform1i(uniform("u_texture").as_ref(), 0);
+        self.gl.uniform1i(uniform("u_backdrop").as_ref(), 1);
+        self.gl.uniform1f(uniform("u_opacity").as_ref(), opacity);
+        self.gl.uniform1i(uniform("u_tinted").as_ref(), tint.is_some() as i32);
+        self.gl.uniform4f(uniform("u_tint").as_ref(), tint_color.r, tint_color.g, tint_color.b, tint_color.a);
+        // The shader numbers modes in declaration order; without a backdrop
+        // copy, fall back to normal
+        let mode = if backdrop.is_some() { blend_mode as i32 } else { 0 };
+        self.gl.uniform1i(uniform("u_blendMode").as_ref(), mode);
+
+        if backdrop.is_some() {
+            // Every pixel of the result is computed in the shader
+            self.gl.disable(GL::BLEND);
+        } else {
+            self.gl.blend_func(GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+        }
+        self.gl.draw_arrays(GL::TRIANGLES, 0, 6);
+        self.gl.enable(GL::BLEND);
+        self.reset_blend();
+        self.gl.active_texture(GL::TEXTURE0);
+        if let Some(backdrop) = backdrop {
+            self.release_layer(backdrop);
+        }
+    }
+
+    /// Keep only the parts of `layer` where `mask` is opaque, fading it where
+    /// the mask is translucent
+    pub fn mask_layer(&mut self, layer: &RenderTarget, mask: &RenderTarget) {
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&layer.framebuffer));
+        self.gl.disable(GL::STENCIL_TEST);
+        self.gl.use_program(Some(&self.composite_program));
+        self.gl.bind_vertex_array(Some(&self.quad_vao));
+        self.gl.active_texture(GL::TEXTURE0);
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&mask.texture));
+        let uniform = |name: &str| self.gl.get_uniform_location(&self.composite_program, name);
+        self.gl.uniform1i(uniform("u_texture").as_ref(), 0);
+        self.gl.uniform1f(uniform("u_opacity").as_ref(), 1.0);
+        self.gl.uniform1i(uniform("u_tinted").as_ref(), 0);
+        self.gl.uniform1i(uniform("u_blendMode").as_ref(), 0);
+        // Destination in: scale what's there by the mask's alpha
+        self.gl.blend_func(GL::ZERO, GL::SRC_ALPHA);
+        self.gl.draw_arrays(GL::TRIANGLES, 0, 6);
+        self.reset_blend();
+        self.bind_current_target();
+        self.apply_stencil();
+    }
+
+    /// Clip everything drawn to the current target to a shape (given as for
+    /// `draw_paint_transformed`) until the matching `pop_clip`. Clips nest:
+    /// drawing shows only inside all of them.
+    ///
+    /// The shape is drawn into the stencil buffer, raising the clip depth
+    /// where it covers the current clip. Edges are hard, so any transform
+    /// and corner radius work. Returns `false`, without clipping, if clips
+    /// are nested too deep; don't call `pop_clip` then.
+    pub fn push_clip(&mut self, rect: Rect, shape: FillShape, transform: &Transform2D, viewport: &Viewport) -> bool {
+        let depth = self.clip_depth();
+        if depth >= MAX_CLIP_DEPTH {
+            return false;
+        }
+        self.draw_clip(rect, shape, transform, viewport, GL::INCR);
+        if let Some(depth) = self.clip_depths.last_mut() {
+            *depth += 1;
+        }
+        self.apply_stencil();
+        true
+    }
+
+    /// Remove the innermost clip, drawing the same shape `push_clip` was
+    /// given to lower the clip depth back
+    pub fn pop_clip(&mut self, rect: Rect, shape: FillShape, transform: &Transform2D, viewport: &Viewport) {
+        if self.clip_depth() == 0 {
+            return;
+        }
+        self.draw_clip(rect, shape, transform, viewport, GL::DECR);
+        if let Some(depth) = self.clip_depths.last_mut() {
+            *depth -= 1;
+        }
+        self.apply_stencil();
+    }
+
+    fn clip_depth(&self) -> u32 {
+        self.clip_depths.last().copied().unwrap_or(0)
+    }
+
+    /// Apply `operation` to the stencil inside a shape and the current clip,
+    /// leaving the colors alone
+    fn draw_clip(&self, rect: Rect, shape: FillShape, transform: &Transform2D, viewport: &Viewport, operation: u32) {
+        let paint = FillPaint { source: PaintSource::Clip, opacity: 1.0 };
+        self.gl.enable(GL::STENCIL_TEST);
+        self.gl.stencil_func(GL::EQUAL, self.clip_depth() as i32, 0xFF);
+        self.gl.stencil_op(GL::KEEP, GL::KEEP, operation);
+        self.gl.color_mask(false, false, false, false);
+        self.draw_paint_transformed(&paint, rect, shape, transform, viewport);
+        self.gl.color_mask(true, true, true, true);
+    }
+
+    /// Set up the stencil test for the current target's clip depth
+    fn apply_stencil(&self) {
+        let depth = self.clip_depth();
+        if depth == 0 {
+            self.gl.disable(GL::STENCIL_TEST);
+            return;
+        }
+        self.gl.enable(GL::STENCIL_TEST);
+        self.gl.stencil_func(GL::EQUAL, depth as i32, 0xFF);
+        self.gl.stencil_op(GL::KEEP, GL::KEEP, GL::KEEP);
+    }
+
+    /// Back to the blending every program but the paint and layer ones expects
+    fn reset_blend(&self) {
+        self.gl.blend_func_separate(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA, GL::ONE, GL::ONE_MINUS_SRC_ALPHA);
+    }
+
+    /// Bind the innermost layer, or the canvas
+    fn bind_current_target(&self) {
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, self.layers.last().map(|target| &target.framebuffer));
+    }
+
+    /// A canvas-sized target, reused if one is spare. Leaves the current
+    /// target unbound if it had to create one.
+    fn acquire_target(&mut self) -> Option<RenderTarget> {
+        if let Some(target) = self.spare_targets.pop() {
+            return Some(target);
+        }
+        let texture = self.gl.create_texture()?;
+        self.gl.bind_texture(GL::TEXTURE_2D, Some(&texture));
+        self.gl
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                GL::TEXTURE_2D,
+                0,
+                GL::RGBA as i32,
+                self.width as i32,
+                self.height as i32,
+                0,
+                GL::RGBA,
+                GL::UNSIGNED_BYTE,
+                None,
+            )
+            .ok()?;
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MIN_FILTER, GL::LINEAR as i32);
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_MAG_FILTER, GL::LINEAR as i32);
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_S, GL::CLAMP_TO_EDGE as i32);
+        self.gl.tex_parameteri(GL::TEXTURE_2D, GL::TEXTURE_WRAP_T, GL::CLAMP_TO_EDGE as i32);
+
+        // WebGL only guarantees stencil attachments combined with depth
+        let stencil = self.gl.create_renderbuffer()?;
+        self.gl.bind_renderbuffer(GL::RENDERBUFFER, Some(&stencil));
+        self.gl
+            .renderbuffer_storage(GL::RENDERBUFFER, GL::DEPTH24_STENCIL8, self.width as i32, self.height as i32);
+
+        let framebuffer = self.gl.create_framebuffer()?;
+        self.gl.bind_framebuffer(GL::FRAMEBUFFER, Some(&framebuffer));
+        self.gl
+            .framebuffer_texture_2d(GL::FRAMEBUFFER, GL::COLOR_ATTACHMENT0, GL::TEXTURE_2D, Some(&texture), 0);
+        self.gl
+            .framebuffer_renderbuffer(GL::FRAMEBUFFER, GL::DEPTH_STENCIL_ATTACHMENT, GL::RENDERBUFFER, Some(&stencil));
+        self.bind_current_target();
+        Some(RenderTarget { framebuffer, texture, stencil, width: self.width, height: self.height })
+    }
+
+    fn delete_target(&self, target: RenderTarget) {
+        self.gl.delete_framebuffer(Some(&target.framebuffer));
+        self.gl.delete_texture(Some(&target.texture));
+        self.gl.delete_renderbuffer(Some(&target.stencil));
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_line(&self, x1: f32, y1: f32, x2: f32, y2: f32, color: Color, viewport: &Viewport, width: f32) {
         self.gl.use_program(Some(&self.line_program));
         self.gl.bind_vertex_array(Some(&self.quad_vao));
@@ -173,6 +777,42 @@ fn create_line_program(gl: &GL) -> Result<WebGlProgram, JsValue> {
     link_program(gl, &vertex_shader, &fragment_shader)
 }
 
+fn create_path_program(gl: &GL) -> Result<WebGlProgram, JsValue> {
+    let vertex_shader = compile_shader(gl, GL::VERTEX_SHADER, PATH_VERTEX_SHADER)?;
+    let fragment_shader = compile_shader(gl, GL::FRAGMENT_SHADER, PATH_FRAGMENT_SHADER)?;
+    link_program(gl, &vertex_shader, &fragment_shader)
+}
+
+fn create_layer_program(gl: &GL, fragment_source: &str) -> Result<WebGlProgram, JsValue> {
+    let vertex_shader = compile_shader(gl, GL::VERTEX_SHADER, LAYER_VERTEX_SHADER)?;
+    let fragment_shader = compile_shader(gl, GL::FRAGMENT_SHADER, fragment_source)?;
+    link_program(gl, &vertex_shader, &fragment_shader)
+}
+
+fn create_paint_program(gl: &GL, vertex_source: &str) -> Result<WebGlProgram, JsValue> {
+    let vertex_shader = compile_shader(gl, GL::VERTEX_SHADER, vertex_source)?;
+    let fragment_shader = compile_shader(gl, GL::FRAGMENT_SHADER, PAINT_FRAGMENT_SHADER)?;
+    link_program(gl, &vertex_shader, &fragment_shader)
+}
+
+/// Stop positions and colors for the paint shader, sorted and limited to
+/// `MAX_GRADIENT_STOPS`
+fn gradient_uniforms(gradient: &Gradient) -> (Vec<f32>, Vec<f32>) {
+    let mut stops = gradient.sorted_stops();
+    if stops.len() > MAX_GRADIENT_STOPS {
+        let last = (MAX_GRADIENT_STOPS - 1) as f32;
+        stops = (0..MAX_GRADIENT_STOPS)
+            .map(|i| {
+                let position = i as f32 / last;
+                ColorStop { position, color: gradient.color_at(position) }
+            })
+            .collect();
+    }
+    let positions = stops.iter().map(|stop| stop.position).collect();
+    let colors = stops.iter().flat_map(|stop| [stop.color.r, stop.color.g, stop.color.b, stop.color.a]).collect();
+    (positions, colors)
+}
+
 fn compile_shader(gl: &GL, shader_type: u32, source: &str) -> Result<web_sys::WebGlShader, JsValue> {
     let shader = gl.create_shader(shader_type).ok_or("Failed to create shader")?;
     gl.shader_source(&shader, source);
@@ -230,6 +870,22 @@ fn create_quad_geometry(gl: &GL) -> Result<(WebGlVertexArrayObject, WebGlBuffer)
     Ok((vao, buffer))
 }
 
+fn create_path_buffers(gl: &GL) -> Result<(WebGlVertexArrayObject, WebGlBuffer, WebGlBuffer), JsValue> {
+    let vao = gl.create_vertex_array().ok_or("Failed to create VAO")?;
+    gl.bind_vertex_array(Some(&vao));
+
+    let vertex_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+    gl.bind_buffer(GL::ARRAY_BUFFER, Some(&vertex_buffer));
+    gl.vertex_attrib_pointer_with_i32(0, 2, GL::FLOAT, false, 0, 0);
+    gl.enable_vertex_attrib_array(0);
+
+    // The element buffer binding is part of the VAO state
+    let index_buffer = gl.create_buffer().ok_or("Failed to create buffer")?;
+    gl.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
+
+    Ok((vao, vertex_buffer, index_buffer))
+}
+
 // Shader sources
 const RECT_VERTEX_SHADER: &str = r#"#version 300 es
 precision highp float;
@@ -237,16 +893,18 @@ layout(location = 0) in vec2 a_position;
 uniform vec2 u_resolution;
 uniform vec4 u_rect;
 uniform vec3 u_viewport;
+uniform mat3 u_transform;
 out vec2 v_uv;
 out vec2 v_size;
 
 void main() {
-    // Convert rect to screen space
-    vec2 pos = u_rect.xy * u_viewport.z + u_viewport.xy;
-    vec2 size = u_rect.zw * u_viewport.z;
+    // Map -1..1 to rect bounds in the node's local space
+    vec2 local = u_rect.xy + (a_position * 0.5 + 0.5) * u_rect.zw;
     
-    // Map -1..1 to rect bounds
-    vec2 p = pos + (a_position * 0.5 + 0.5) * size;
+    // Local space -> canvas -> screen space
+    vec2 canvas = (u_transform * vec3(local, 1.0)).xy;
+    vec2 p = canvas * u_viewport.z + u_viewport.xy;
+    vec2 size = u_rect.zw * u_viewport.z;
     
     // Convert to clip space
     vec2 clipSpace = (p / u_resolution) * 2.0 - 1.0;
@@ -291,12 +949,13 @@ layout(location = 0) in vec2 a_position;
 uniform vec2 u_resolution;
 uniform vec4 u_rect;
 uniform vec3 u_viewport;
+uniform mat3 u_transform;
 out vec2 v_uv;
 
 void main() {
-    vec2 pos = u_rect.xy * u_viewport.z + u_viewport.xy;
-    vec2 size = u_rect.zw * u_viewport.z;
-    vec2 p = pos + (a_position * 0.5 + 0.5) * size;
+    vec2 local = u_rect.xy + (a_position * 0.5 + 0.5) * u_rect.zw;
+    vec2 canvas = (u_transform * vec3(local, 1.0)).xy;
+    vec2 p = canvas * u_viewport.z + u_viewport.xy;
     vec2 clipSpace = (p / u_resolution) * 2.0 - 1.0;
     clipSpace.y = -clipSpace.y;
     gl_Position = vec4(clipSpace, 0.0, 1.0);
@@ -360,3 +1019,336 @@ void main() {
     fragColor = u_color;
 }
 "#;
+
+const PATH_VERTEX_SHADER: &str = r#"#version 300 es
+precision highp float;
+layout(location = 0) in vec2 a_position;
+uniform vec2 u_resolution;
+uniform vec3 u_viewport;
+uniform mat3 u_transform;
+
+void main() {
+    vec2 canvas = (u_transform * vec3(a_position, 1.0)).xy;
+    vec2 p = canvas * u_viewport.z + u_viewport.xy;
+    vec2 clipSpace = (p / u_resolution) * 2.0 - 1.0;
+    clipSpace.y = -clipSpace.y;
+    gl_Position = vec4(clipSpace, 0.0, 1.0);
+}
+"#;
+
+const PATH_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+uniform vec4 u_color;
+out vec4 fragColor;
+
+void main() {
+    fragColor = u_color;
+}
+"#;
+
+const PAINT_VERTEX_SHADER: &str = r#"#version 300 es
+precision highp float;
+layout(location = 0) in vec2 a_position;
+uniform vec2 u_resolution;
+uniform vec4 u_rect;
+uniform vec3 u_viewport;
+uniform mat3 u_transform;
+out vec2 v_local;
+out vec2 v_unit;
+out vec2 v_size;
+
+void main() {
+    vec2 local = u_rect.xy + (a_position * 0.5 + 0.5) * u_rect.zw;
+    vec2 canvas = (u_transform * vec3(local, 1.0)).xy;
+    vec2 p = canvas * u_viewport.z + u_viewport.xy;
+    vec2 clipSpace = (p / u_resolution) * 2.0 - 1.0;
+    clipSpace.y = -clipSpace.y;
+    gl_Position = vec4(clipSpace, 0.0, 1.0);
+    v_local = local;
+    v_unit = a_position * 0.5 + 0.5;
+    v_size = u_rect.zw * u_viewport.z;
+}
+"#;
+
+const PAINT_PATH_VERTEX_SHADER: &str = r#"#version 300 es
+precision highp float;
+layout(location = 0) in vec2 a_position;
+uniform vec2 u_resolution;
+uniform vec4 u_rect;
+uniform vec3 u_viewport;
+uniform mat3 u_transform;
+out vec2 v_local;
+out vec2 v_unit;
+out vec2 v_size;
+
+void main() {
+    vec2 canvas = (u_transform * vec3(a_position, 1.0)).xy;
+    vec2 p = canvas * u_viewport.z + u_viewport.xy;
+    vec2 clipSpace = (p / u_resolution) * 2.0 - 1.0;
+    clipSpace.y = -clipSpace.y;
+    gl_Position = vec4(clipSpace, 0.0, 1.0);
+    v_local = a_position;
+    v_unit = (a_position - u_rect.xy) / max(u_rect.zw, vec2(1e-6));
+    v_size = u_rect.zw * u_viewport.z;
+}
+"#;
+
+const PAINT_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+const int MAX_STOPS = 16;
+const float TAU = 6.28318530718;
+
+// 0 solid, 1 linear, 2 radial, 3 angular, 4 diamond, 5 image, 6 layer,
+// 7 clip (hard-edged coverage for the stencil buffer)
+uniform int u_paintType;
+uniform vec4 u_color;
+uniform int u_stopCount;
+uniform float u_stopPositions[MAX_STOPS];
+uniform vec4 u_stopColors[MAX_STOPS];
+uniform mat3 u_gradientTransform;
+uniform sampler2D u_texture;
+uniform vec4 u_imageRect;
+uniform bool u_tile;
+uniform bool u_tinted;
+uniform bool u_invert;
+uniform vec2 u_resolution;
+uniform float u_opacity;
+// 0 none (paths), 1 rounded rect, 2 ellipse
+uniform int u_shape;
+uniform float u_cornerRadius;
+in vec2 v_local;
+in vec2 v_unit;
+in vec2 v_size;
+out vec4 fragColor;
+
+float roundedBoxSDF(vec2 p, vec2 b, float r) {
+    vec2 q = abs(p) - b + r;
+    return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+}
+
+vec4 gradientColor(float t) {
+    vec4 color = u_stopColors[0];
+    for (int i = 1; i < MAX_STOPS; i++) {
+        if (i >= u_stopCount) {
+            break;
+        }
+        float a = u_stopPositions[i - 1];
+        float b = u_stopPositions[i];
+        if (t >= b) {
+            color = u_stopColors[i];
+        } else if (t > a) {
+            color = mix(u_stopColors[i - 1], u_stopColors[i], (t - a) / (b - a));
+            break;
+        }
+    }
+    return color;
+}
+
+void main() {
+    vec4 color;
+    if (u_paintType == 0) {
+        color = u_color;
+    } else if (u_paintType == 7) {
+        color = vec4(1.0);
+    } else if (u_paintType == 5) {
+        vec2 uv = (v_local - u_imageRect.xy) / u_imageRect.zw;
+        // Outside the image in fit mode
+        if (!u_tile && (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))))) {
+            discard;
+        }
+        color = texture(u_texture, uv);
+    } else if (u_paintType == 6) {
+        // Layers hold premultiplied colors
+        vec4 texel = texture(u_texture, gl_FragCoord.xy / u_resolution);
+        if (u_tinted) {
+            float coverage = u_invert ? 1.0 - texel.a : texel.a;
+            color = vec4(u_color.rgb, u_color.a * coverage);
+        } else {
+            color = texel.a > 0.0 ? vec4(texel.rgb / texel.a, texel.a) : vec4(0.0);
+        }
+    } else {
+        vec2 g = (u_gradientTransform * vec3(v_unit, 1.0)).xy;
+        vec2 c = g - 0.5;
+        float t;
+        if (u_paintType == 1) {
+            t = g.x;
+        } else if (u_paintType == 2) {
+            t = length(c) * 2.0;
+        } else if (u_paintType == 3) {
+            t = mod(atan(c.y, c.x), TAU) / TAU;
+        } else {
+            t = (abs(c.x) + abs(c.y)) * 2.0;
+        }
+        color = gradientColor(clamp(t, 0.0, 1.0));
+    }
+
+    float alpha = u_opacity;
+    if (u_shape == 2) {
+        float d = length(v_unit * 2.0 - 1.0);
+        float aa = fwidth(d);
+        alpha *= 1.0 - smoothstep(1.0 - aa, 1.0 + aa, d);
+    } else if (u_shape == 1 && u_cornerRadius > 0.0) {
+        float d = roundedBoxSDF((v_unit - 0.5) * v_size, v_size * 0.5, u_cornerRadius);
+        alpha *= 1.0 - smoothstep(-1.0, 1.0, d);
+    }
+    if (u_paintType == 7 && alpha < 0.5) {
+        discard;
+    }
+
+    // Premultiplied, so layers composite correctly
+    float a = color.a * alpha;
+    fragColor = vec4(color.rgb * a, a);
+}
+"#;
+
+const LAYER_VERTEX_SHADER: &str = r#"#version 300 es
+precision highp float;
+layout(location = 0) in vec2 a_position;
+out vec2 v_uv;
+
+void main() {
+    // The quad covers the whole target
+    gl_Position = vec4(a_position, 0.0, 1.0);
+    v_uv = a_position * 0.5 + 0.5;
+}
+"#;
+
+const BLUR_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+const int MAX_TAPS = 32;
+uniform sampler2D u_texture;
+// One texel along the blur direction
+uniform vec2 u_direction;
+uniform float u_sigma;
+in vec2 v_uv;
+out vec4 fragColor;
+
+void main() {
+    // Cover three deviations; wide blurs step over several texels per tap
+    float reach = ceil(u_sigma * 3.0);
+    float stride = max(1.0, reach / float(MAX_TAPS));
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int i = -MAX_TAPS; i <= MAX_TAPS; i++) {
+        float x = float(i) * stride;
+        if (abs(x) > reach) {
+            continue;
+        }
+        float weight = exp(-(x * x) / (2.0 * u_sigma * u_sigma));
+        sum += texture(u_texture, v_uv + u_direction * x) * weight;
+        total += weight;
+    }
+    fragColor = sum / total;
+}
+"#;
+
+const COMPOSITE_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision highp float;
+uniform sampler2D u_texture;
+uniform sampler2D u_backdrop;
+uniform float u_opacity;
+uniform bool u_tinted;
+uniform vec4 u_tint;
+// BlendMode in declaration order: 0 normal, 1 multiply, 2 screen, 3 overlay,
+// 4 darken, 5 lighten, 6 color dodge, 7 color burn, 8 hard light,
+// 9 soft light, 10 difference, 11 exclusion, 12 hue, 13 saturation,
+// 14 color, 15 luminosity
+uniform int u_blendMode;
+in vec2 v_uv;
+out vec4 fragColor;
+
+// Separable and non-separable blend functions from the W3C compositing spec,
+// on unpremultiplied colors (b = backdrop, s = source)
+
+float colorDodge(float b, float s) {
+    if (b == 0.0) return 0.0;
+    if (s == 1.0) return 1.0;
+    return min(1.0, b / (1.0 - s));
+}
+
+float colorBurn(float b, float s) {
+    if (b == 1.0) return 1.0;
+    if (s == 0.0) return 0.0;
+    return 1.0 - min(1.0, (1.0 - b) / s);
+}
+
+vec3 hardLight(vec3 b, vec3 s) {
+    vec3 multiply = b * 2.0 * s;
+    vec3 screen = b + (2.0 * s - 1.0) - b * (2.0 * s - 1.0);
+    return mix(multiply, screen, step(0.5, s));
+}
+
+float softLight(float b, float s) {
+    if (s <= 0.5) {
+        return b - (1.0 - 2.0 * s) * b * (1.0 - b);
+    }
+    float d = b <= 0.25 ? ((16.0 * b - 12.0) * b + 4.0) * b : sqrt(b);
+    return b + (2.0 * s - 1.0) * (d - b);
+}
+
+float lum(vec3 c) {
+    return dot(c, vec3(0.3, 0.59, 0.11));
+}
+
+vec3 clipColor(vec3 c) {
+    float l = lum(c);
+    float n = min(min(c.r, c.g), c.b);
+    float x = max(max(c.r, c.g), c.b);
+    if (n < 0.0) c = l + (c - l) * l / (l - n);
+    if (x > 1.0) c = l + (c - l) * (1.0 - l) / (x - l);
+    return c;
+}
+
+vec3 setLum(vec3 c, float l) {
+    return clipColor(c + (l - lum(c)));
+}
+
+float sat(vec3 c) {
+    return max(max(c.r, c.g), c.b) - min(min(c.r, c.g), c.b);
+}
+
+vec3 setSat(vec3 c, float s) {
+    float n = min(min(c.r, c.g), c.b);
+    float x = max(max(c.r, c.g), c.b);
+    return x > n ? (c - n) * s / (x - n) : vec3(0.0);
+}
+
+vec3 blend(vec3 b, vec3 s) {
+    switch (u_blendMode) {
+        case 1: return b * s;
+        case 2: return b + s - b * s;
+        case 3: return hardLight(s, b);
+        case 4: return min(b, s);
+        case 5: return max(b, s);
+        case 6: return vec3(colorDodge(b.r, s.r), colorDodge(b.g, s.g), colorDodge(b.b, s.b));
+        case 7: return vec3(colorBurn(b.r, s.r), colorBurn(b.g, s.g), colorBurn(b.b, s.b));
+        case 8: return hardLight(b, s);
+        case 9: return vec3(softLight(b.r, s.r), softLight(b.g, s.g), softLight(b.b, s.b));
+        case 10: return abs(b - s);
+        case 11: return b + s - 2.0 * b * s;
+        case 12: return setLum(setSat(s, sat(b)), lum(b));
+        case 13: return setLum(setSat(b, sat(s)), lum(b));
+        case 14: return setLum(s, lum(b));
+        case 15: return setLum(b, lum(s));
+        default: return s;
+    }
+}
+
+void main() {
+    vec4 texel = texture(u_texture, v_uv);
+    vec4 source = u_tinted ? vec4(u_tint.rgb * u_tint.a, u_tint.a) * texel.a : texel;
+    source *= u_opacity;
+    if (u_blendMode == 0) {
+        fragColor = source;
+        return;
+    }
+
+    // Premultiplied source-over with the blended color where both overlap
+    vec4 backdrop = texture(u_backdrop, v_uv);
+    vec3 s = source.a > 0.0 ? source.rgb / source.a : vec3(0.0);
+    vec3 b = backdrop.a > 0.0 ? backdrop.rgb / backdrop.a : vec3(0.0);
+    vec3 color = source.rgb * (1.0 - backdrop.a) + backdrop.rgb * (1.0 - source.a)
+        + source.a * backdrop.a * clamp(blend(b, s), 0.0, 1.0);
+    fragColor = vec4(color, source.a + backdrop.a * (1.0 - source.a));
+}
+"#;
@@ -0,0 +1,146 @@
+//! Drawing node effects
+//!
+//! Shadows and background blurs follow the node's outline (its rectangle,
+//! ellipse or path), not the pixels of its fills. Each is drawn into an
+//! offscreen layer, blurred there and composited:
+//!
+//! ```text
+//! drop shadow      outline, offset and grown by spread → blur → tint, under the node
+//! background blur  copy of what's drawn so far → blur → through the outline
+//! inner shadow     outline, offset and shrunk by spread → blur → tint the
+//!                  uncovered part, through the outline, over the fills
+//! layer blur       the node and its children in a layer → blur
+//! ```
+//!
+//! Groups, lines and text have no outline, so only a layer blur applies to
+//! them.
+//! Spread grows paths (by stroking them) but doesn't shrink them.
+
+use super::{network_path, tessellate_fill, tessellate_stroke, FillPaint, FillShape, PaintSource, RenderTarget, Renderer};
+use crate::document::{blur_sigma, BlendMode, Color, Effect, EffectKind, Node, NodeType, Shadow};
+use crate::math::{Rect, Transform2D};
+use lyon::path::Path;
+
+/// The outline a node's shadows, background blurs and clips follow
+pub(super) enum Outline {
+    Rect(f32),
+    Ellipse,
+    Path(Path),
+}
+
+impl Outline {
+    pub(super) fn of(node: &Node) -> Option<Self> {
+        match node.node_type() {
+            NodeType::Rectangle | NodeType::Image => Some(Outline::Rect(node.corner_radius())),
+            NodeType::Ellipse => Some(Outline::Ellipse),
+            NodeType::Vector | NodeType::BooleanOperation => match node.vector_network() {
+                Some(network) => Some(Outline::Path(network_path(&network))),
+                None => Some(Outline::Rect(0.0)),
+            },
+            // Text is drawn from glyphs, which have no single outline
+            NodeType::Group | NodeType::Line | NodeType::Text => None,
+            node_type if node_type.is_canvas() => Some(Outline::Rect(node.corner_radius())),
+            _ => None,
+        }
+    }
+}
+
+impl Renderer {
+    /// Draw the effects that go under a node: drop shadows, then background
+    /// blurs
+    pub(super) fn draw_effects_below(&mut self, node: &Node, world: &Transform2D, effects: &[Effect]) {
+        let Some(outline) = Outline::of(node) else {
+            return;
+        };
+        for effect in effects {
+            match effect.kind {
+                EffectKind::DropShadow(shadow) => self.draw_drop_shadow(node, &outline, world, &shadow),
+                EffectKind::BackgroundBlur { radius } => self.draw_background_blur(node, &outline, world, radius),
+                EffectKind::InnerShadow(_) | EffectKind::LayerBlur { .. } => {}
+            }
+        }
+    }
+
+    /// Draw a node's inner shadows, over its fills
+    pub(super) fn draw_inner_shadows(&mut self, node: &Node, world: &Transform2D, effects: &[Effect]) {
+        let Some(outline) = Outline::of(node) else {
+            return;
+        };
+        for effect in effects {
+            if let EffectKind::InnerShadow(shadow) = effect.kind {
+                self.draw_inner_shadow(node, &outline, world, &shadow);
+            }
+        }
+    }
+
+    fn draw_drop_shadow(&mut self, node: &Node, outline: &Outline, world: &Transform2D, shadow: &Shadow) {
+        let offset = world.multiply(&Transform2D::translate(shadow.offset_x, shadow.offset_y));
+        let Some(layer) = self.draw_in_layer(|renderer| {
+            renderer.fill_outline(node, outline, &offset, PaintSource::Solid(Color::white()), shadow.spread)
+        }) else {
+            return;
+        };
+        self.context.blur_layer(&layer, blur_sigma(shadow.blur) * self.viewport.zoom);
+        self.context.composite_layer(&layer, Some(shadow.color), 1.0, BlendMode::Normal);
+        self.context.release_layer(layer);
+    }
+
+    fn draw_inner_shadow(&mut self, node: &Node, outline: &Outline, world: &Transform2D, shadow: &Shadow) {
+        let offset = world.multiply(&Transform2D::translate(shadow.offset_x, shadow.offset_y));
+        let Some(layer) = self.draw_in_layer(|renderer| {
+            renderer.fill_outline(node, outline, &offset, PaintSource::Solid(Color::white()), -shadow.spread)
+        }) else {
+            return;
+        };
+        self.context.blur_layer(&layer, blur_sigma(shadow.blur) * self.viewport.zoom);
+        let source = PaintSource::Layer { layer: &layer, tint: Some(shadow.color), invert: true };
+        self.fill_outline(node, outline, world, source, 0.0);
+        self.context.release_layer(layer);
+    }
+
+    fn draw_background_blur(&mut self, node: &Node, outline: &Outline, world: &Transform2D, radius: f32) {
+        let Some(backdrop) = self.context.copy_backdrop() else {
+            return;
+        };
+        self.context.blur_layer(&backdrop, blur_sigma(radius) * self.viewport.zoom);
+        let source = PaintSource::Layer { layer: &backdrop, tint: None, invert: false };
+        self.fill_outline(node, outline, world, source, 0.0);
+        self.context.release_layer(backdrop);
+    }
+
+    /// Run `draw` into a fresh layer and return the layer
+    pub(super) fn draw_in_layer(&mut self, draw: impl FnOnce(&mut Self)) -> Option<RenderTarget> {
+        if !self.context.begin_layer() {
+            return None;
+        }
+        draw(self);
+        self.context.end_layer()
+    }
+
+    /// Fill a node's outline, grown by `spread` (shrunk if negative)
+    fn fill_outline(&self, node: &Node, outline: &Outline, world: &Transform2D, source: PaintSource, spread: f32) {
+        let paint = FillPaint { source, opacity: 1.0 };
+        let bounds = Rect::new(0.0, 0.0, node.width(), node.height());
+        let grown = bounds.expand(spread);
+        if grown.width <= 0.0 || grown.height <= 0.0 {
+            return;
+        }
+        let viewport = &self.viewport;
+        match outline {
+            Outline::Rect(radius) => {
+                let shape = FillShape::Rect((radius + spread).max(0.0));
+                self.context.draw_paint_transformed(&paint, grown, shape, world, viewport);
+            }
+            Outline::Ellipse => {
+                self.context.draw_paint_transformed(&paint, grown, FillShape::Ellipse, world, viewport);
+            }
+            Outline::Path(path) => {
+                self.context.draw_paint_transformed(&paint, bounds, FillShape::Path(&tessellate_fill(path)), world, viewport);
+                if spread > 0.0 {
+                    let stroke = tessellate_stroke(path, spread * 2.0);
+                    self.context.draw_paint_transformed(&paint, bounds, FillShape::Path(&stroke), world, viewport);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Drawing clipped content and masks
+//!
+//! Frames that clip their content and vector masks clip with the stencil
+//! buffer (see `RenderContext::push_clip`), so rotated frames, rounded
+//! corners, ellipses and paths all clip exactly. Alpha masks need the mask's
+//! pixels instead:
+//!
+//! ```text
+//! mask layer     the mask node (and its children), drawn as usual
+//! content layer  the siblings it masks
+//! result         content × mask alpha, composited over what's below
+//! ```
+//!
+//! Groups and lines have no outline, so they always mask by alpha.
+
+use super::effects::Outline;
+use super::{tessellate_fill, FillShape, Renderer};
+use crate::document::{BlendMode, Document, MaskType, Node, ObjectId};
+use crate::math::{Rect, Transform2D};
+use std::collections::HashSet;
+
+impl Renderer {
+    /// Render a node's children in order. Masks aren't drawn; each one masks
+    /// the siblings after it, up to the next mask.
+    pub(super) fn render_children(&mut self, document: &Document, children: &[ObjectId], on_screen: &HashSet<ObjectId>) {
+        let mut rest = children;
+        while let Some((&id, after)) = rest.split_first() {
+            let Some(mask) = document.resolved_node(id).filter(|node| node.is_mask()) else {
+                self.render_node_recursive(document, id, on_screen);
+                rest = after;
+                continue;
+            };
+            let end = after.iter().position(|&sibling| document.is_mask(sibling)).unwrap_or(after.len());
+            let (masked, next) = after.split_at(end);
+            // A hidden mask leaves its siblings unmasked
+            if mask.visible() {
+                self.render_masked(document, id, &mask, masked, on_screen);
+            } else {
+                self.render_all(document, masked, on_screen);
+            }
+            rest = next;
+        }
+    }
+
+    /// Run `draw` clipped to a node's outline
+    pub(super) fn with_clip(&mut self, node: &Node, outline: &Outline, world: &Transform2D, draw: impl FnOnce(&mut Self)) {
+        let bounds = Rect::new(0.0, 0.0, node.width(), node.height());
+        let geometry;
+        let shape = match outline {
+            Outline::Rect(radius) => FillShape::Rect(*radius),
+            Outline::Ellipse => FillShape::Ellipse,
+            Outline::Path(path) => {
+                geometry = tessellate_fill(path);
+                FillShape::Path(&geometry)
+            }
+        };
+        let viewport = self.viewport;
+        let clipped = self.context.push_clip(bounds, shape, world, &viewport);
+        draw(self);
+        if clipped {
+            self.context.pop_clip(bounds, shape, world, &viewport);
+        }
+    }
+
+    fn render_masked(
+        &mut self,
+        document: &Document,
+        mask_id: ObjectId,
+        mask: &Node,
+        masked: &[ObjectId],
+        on_screen: &HashSet<ObjectId>,
+    ) {
+        let world = document.world_transform(mask_id);
+        if mask.mask_type() == MaskType::Vector {
+            if let Some(outline) = Outline::of(mask) {
+                self.with_clip(mask, &outline, &world, |renderer| renderer.render_all(document, masked, on_screen));
+                return;
+            }
+        }
+
+        let Some(mask_layer) = self.draw_in_layer(|renderer| renderer.render_node_recursive(document, mask_id, on_screen))
+        else {
+            self.render_all(document, masked, on_screen);
+            return;
+        };
+        match self.draw_in_layer(|renderer| renderer.render_all(document, masked, on_screen)) {
+            Some(layer) => {
+                self.context.mask_layer(&layer, &mask_layer);
+                self.context.composite_layer(&layer, None, 1.0, BlendMode::Normal);
+                self.context.release_layer(layer);
+            }
+            None => self.render_all(document, masked, on_screen),
+        }
+        self.context.release_layer(mask_layer);
+    }
+
+    fn render_all(&mut self, document: &Document, ids: &[ObjectId], on_screen: &HashSet<ObjectId>) {
+        for &id in ids {
+            self.render_node_recursive(document, id, on_screen);
+        }
+    }
+}
@@ -3,19 +3,31 @@
 //! Implements a custom 2D renderer using WebGL2, inspired by Figma's approach.
 
 mod context;
+mod effects;
+mod masks;
 mod shaders;
 mod shapes;
+mod text;
 mod viewport;
 
 pub use context::*;
+#[allow(unused_imports)] // Nothing to export until shaders move out of context.rs
 pub use shaders::*;
 pub use shapes::*;
 pub use viewport::*;
 
-use crate::document::{Color, Document, Node, NodeType, ObjectId};
-use crate::math::{Rect, Transform2D};
+use effects::Outline;
+
+use crate::document::{
+    blur_sigma, BlendMode, Color, Document, Effect, EffectKind, Node, NodeType, ObjectId, Paint, PaintKind, Property,
+    ScaleMode,
+};
+use crate::math::{Rect, Transform2D, Vec2};
+use lyon::geom::point;
+use lyon::path::Path;
+use std::collections::HashSet;
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGl2RenderingContext as GL, HtmlCanvasElement};
+use web_sys::HtmlCanvasElement;
 
 /// The main renderer for Anatsui
 #[wasm_bindgen]
@@ -23,6 +35,7 @@ pub struct Renderer {
     context: RenderContext,
     viewport: Viewport,
     background_color: Color,
+    glyphs: text::GlyphCache,
 }
 
 #[wasm_bindgen]
@@ -37,6 +50,7 @@ impl Renderer {
             context,
             viewport,
             background_color: Color::from_hex("#F5F5F5"),
+            glyphs: text::GlyphCache::default(),
         })
     }
 
@@ -52,7 +66,7 @@ impl Renderer {
 
     /// Get the viewport
     pub fn viewport(&self) -> Viewport {
-        self.viewport.clone()
+        self.viewport
     }
 
     /// Set viewport position
@@ -63,7 +77,7 @@ impl Renderer {
 
     /// Set viewport zoom
     pub fn set_viewport_zoom(&mut self, zoom: f32) {
-        self.viewport.zoom = zoom.max(0.01).min(256.0);
+        self.viewport.zoom = zoom.clamp(0.01, 256.0);
     }
 
     /// Pan the viewport
@@ -75,7 +89,7 @@ impl Renderer {
     /// Zoom at a point
     pub fn zoom_at(&mut self, x: f32, y: f32, delta: f32) {
         let old_zoom = self.viewport.zoom;
-        let new_zoom = (old_zoom * (1.0 + delta * 0.1)).max(0.01).min(256.0);
+        let new_zoom = (old_zoom * (1.0 + delta * 0.1)).clamp(0.01, 256.0);
         
         // Adjust position to keep the point under cursor
         let scale_change = new_zoom / old_zoom;
@@ -107,70 +121,227 @@ impl Renderer {
     pub fn render_document(&mut self, document: &Document) {
         self.clear();
         
-        let root_id = document.root_id();
-        self.render_node_recursive(document, root_id);
+        // Only draw nodes that draw onto the screen, shadows included
+        let visible = self.viewport.visible_rect(self.context.width() as f32, self.context.height() as f32);
+        let on_screen: HashSet<ObjectId> = document
+            .nodes_drawn_in_rect(visible.x, visible.y, visible.width, visible.height)
+            .into_iter()
+            .collect();
+        
+        // Other pages, styles and variables are never drawn
+        if let Some(page_id) = document.get_active_page() {
+            self.render_node_recursive(document, page_id, &on_screen);
+        }
     }
 
-    fn render_node_recursive(&mut self, document: &Document, node_id: ObjectId) {
-        if let Some(node) = document.get_node(node_id) {
-            if !node.visible() {
-                return;
+    fn render_node_recursive(&mut self, document: &Document, node_id: ObjectId, on_screen: &HashSet<ObjectId>) {
+        let Some(node) = document.resolved_node(node_id) else {
+            return;
+        };
+        let opacity = node.opacity().clamp(0.0, 1.0);
+        if !node.visible() || opacity <= 0.0 {
+            return;
+        }
+        let world = document.world_transform(node_id);
+        let effects: Vec<Effect> = node.effects().into_iter().filter(|effect| effect.visible).collect();
+        // A boolean's children are only operands; its result is drawn below
+        let children = match node.node_type() {
+            NodeType::BooleanOperation => Vec::new(),
+            _ => document.get_children(node_id),
+        };
+
+        // Isolate the node in a layer of its own when it has to be
+        // composited as a whole (see the paint module docs)
+        let blend_mode = node.blend_mode();
+        let isolated = opacity < 1.0
+            || blend_mode.is_some_and(|mode| mode != BlendMode::Normal)
+            || (blend_mode.is_some() && !children.is_empty());
+        let isolated = isolated && self.context.begin_layer();
+
+        // Render this node in canvas space. Off-screen nodes are skipped,
+        // but their children may still overflow onto the screen.
+        let drawn = on_screen.contains(&node_id);
+        if drawn {
+            self.draw_effects_below(&node, &world, &effects);
+        }
+
+        // A layer blur blurs the node together with its children
+        let layer_blur = effects
+            .iter()
+            .filter_map(|effect| match effect.kind {
+                EffectKind::LayerBlur { radius } => Some(radius),
+                _ => None,
+            })
+            .fold(0.0, f32::max);
+        let in_layer = layer_blur > 0.0 && self.context.begin_layer();
+
+        if drawn {
+            self.render_node(document, &node, &world);
+            self.draw_inner_shadows(&node, &world, &effects);
+        }
+
+        // Children of a frame that clips its content only show inside it
+        let clip = if node.clips_content() && !children.is_empty() { Outline::of(&node) } else { None };
+        match clip {
+            Some(outline) => {
+                self.with_clip(&node, &outline, &world, |renderer| renderer.render_children(document, &children, on_screen))
+            }
+            None => self.render_children(document, &children, on_screen),
+        }
+
+        if in_layer {
+            if let Some(layer) = self.context.end_layer() {
+                self.context.blur_layer(&layer, blur_sigma(layer_blur) * self.viewport.zoom);
+                self.context.composite_layer(&layer, None, 1.0, BlendMode::Normal);
+                self.context.release_layer(layer);
             }
-            
-            // Render this node
-            self.render_node(&node);
-            
-            // Render children
-            for child_id in document.get_children(node_id) {
-                self.render_node_recursive(document, child_id);
+        }
+        if isolated {
+            if let Some(layer) = self.context.end_layer() {
+                let blend_mode = blend_mode.unwrap_or_default();
+                self.context.composite_layer(&layer, None, opacity, blend_mode);
+                self.context.release_layer(layer);
             }
         }
     }
 }
 
 impl Renderer {
-    /// Render a single node (internal, not exported to WASM)
-    fn render_node(&mut self, node: &Node) {
-        let x = node.x();
-        let y = node.y();
-        let width = node.width();
-        let height = node.height();
+    /// Render a single node (internal, not exported to WASM).
+    ///
+    /// Shapes are drawn in the node's local space (origin at its top-left
+    /// corner) and placed on the canvas by its world transform.
+    fn render_node(&mut self, document: &Document, node: &Node, world: &Transform2D) {
+        let bounds = Rect::new(0.0, 0.0, node.width(), node.height());
+        // Copied so drawing fills can borrow `self` mutably
+        let viewport = &{ self.viewport };
         
         match node.node_type() {
+            NodeType::Document
+            | NodeType::Page
+            | NodeType::Style
+            | NodeType::VariableCollection
+            | NodeType::VariableMode
+            | NodeType::Variable
+            | NodeType::Group => {}
             NodeType::Rectangle => {
-                self.draw_rectangle(x, y, width, height, node.fill_color(), node.corner_radius());
-                if node.stroke_width() > 0.0 {
-                    self.draw_rectangle_stroke(x, y, width, height, node.stroke_color(), node.stroke_width());
-                }
+                self.draw_fills(document, node, world, FillShape::Rect(node.corner_radius()));
+                let outline = rounded_rect_path(0.0, 0.0, bounds.width, bounds.height, node.corner_radius());
+                self.draw_strokes(document, node, world, &outline, node.stroke_width());
             }
             NodeType::Ellipse => {
-                self.draw_ellipse(x, y, width, height, node.fill_color());
+                self.draw_fills(document, node, world, FillShape::Ellipse);
+                let (rx, ry) = (bounds.width / 2.0, bounds.height / 2.0);
+                self.draw_strokes(document, node, world, &ellipse_path(rx, ry, rx, ry), node.stroke_width());
             }
             NodeType::Frame => {
-                // Frames have a background
-                self.draw_rectangle(x, y, width, height, Color::white(), 0.0);
-                // And a subtle border
-                self.draw_rectangle_stroke(x, y, width, height, Color::from_hex("#E0E0E0"), 1.0);
+                // Frames have a white background unless given a fill
+                if node.get_property(Property::Fills).is_none() && node.get_property(Property::FillColor).is_none() {
+                    self.context.draw_rect_transformed(bounds, world, Color::white(), viewport, node.corner_radius());
+                }
+                self.draw_fills(document, node, world, FillShape::Rect(node.corner_radius()));
+                // And a subtle border under its own strokes
+                self.context.draw_rect_stroke_transformed(bounds, world, Color::from_hex("#E0E0E0"), viewport, 1.0);
+                let outline = rounded_rect_path(0.0, 0.0, bounds.width, bounds.height, node.corner_radius());
+                self.draw_strokes(document, node, world, &outline, node.stroke_width());
             }
-            NodeType::Text => {
-                // Text rendering is handled separately
-                // For now, draw a placeholder
-                let text_color = node.fill_color();
-                self.draw_text_placeholder(x, y, width, 20.0, text_color);
+            NodeType::Text => self.draw_text(document, node, world),
+            NodeType::Vector | NodeType::BooleanOperation => {
+                let Some(network) = node.vector_network() else {
+                    self.draw_fills(document, node, world, FillShape::Rect(0.0));
+                    return;
+                };
+                let path = network_path(&network);
+                self.draw_fills(document, node, world, FillShape::Path(&tessellate_fill(&path)));
+                self.draw_strokes(document, node, world, &path, node.stroke_width());
+            }
+            NodeType::Image => {
+                // Grey until the image is available (or if it can't be read)
+                if !self.draw_fills(document, node, world, FillShape::Rect(node.corner_radius())) {
+                    self.context.draw_rect_transformed(bounds, world, Color::new(0.8, 0.8, 0.8, 1.0), viewport, 0.0);
+                }
             }
             NodeType::Line => {
-                // Draw a line
-                let stroke_color = node.stroke_color();
-                let stroke_width = node.stroke_width().max(1.0);
-                self.draw_line(x, y, x + width, y + height, stroke_color, stroke_width);
+                // Draw a line from the local origin to the opposite corner
+                let mut builder = Path::builder();
+                builder.begin(point(0.0, 0.0));
+                builder.line_to(point(bounds.width, bounds.height));
+                builder.end(false);
+                self.draw_strokes(document, node, world, &builder.build(), node.stroke_width().max(1.0));
             }
             _ => {
                 // Default: draw as rectangle
-                self.draw_rectangle(x, y, width, height, node.fill_color(), 0.0);
+                self.draw_fills(document, node, world, FillShape::Rect(0.0));
             }
         }
     }
 
+    /// Draw a node's fill paints over its bounds, bottom first, uploading
+    /// images the first time they are used. Returns `false` if nothing was
+    /// drawn.
+    fn draw_fills(&mut self, document: &Document, node: &Node, world: &Transform2D, shape: FillShape) -> bool {
+        self.draw_paints(document, node, &node.fills(), world, shape)
+    }
+
+    /// Draw a node's stroke paints along `outline` (in the node's local
+    /// space), `width` wide and centred on it
+    fn draw_strokes(&mut self, document: &Document, node: &Node, world: &Transform2D, outline: &Path, width: f32) {
+        let strokes = node.strokes();
+        if width <= 0.0 || !strokes.iter().any(Paint::is_drawn) {
+            return;
+        }
+        let geometry = tessellate_stroke(outline, width);
+        self.draw_paints(document, node, &strokes, world, FillShape::Path(&geometry));
+    }
+
+    /// Draw paints, bottom first, clipped to `shape`; gradients and images
+    /// are laid out over the node's bounds
+    fn draw_paints(
+        &mut self,
+        document: &Document,
+        node: &Node,
+        paints: &[Paint],
+        world: &Transform2D,
+        shape: FillShape,
+    ) -> bool {
+        let size = Vec2::new(node.width(), node.height());
+        let bounds = Rect::new(0.0, 0.0, size.x, size.y);
+        let mut drawn = false;
+        for paint in paints.iter().filter(|paint| paint.is_drawn()) {
+            let source = match &paint.kind {
+                PaintKind::Solid { color } => PaintSource::Solid(*color),
+                PaintKind::Gradient(gradient) => PaintSource::Gradient(gradient),
+                PaintKind::Image(image) => {
+                    let Some(asset) = document.image(&image.image) else {
+                        continue;
+                    };
+                    if !self.context.has_texture(&image.image) {
+                        let max_size = self.context.max_texture_size();
+                        let decoded = asset.decode().ok().map(|decoded| decoded.fit_within(max_size));
+                        self.context.upload_image(&image.image, decoded.as_ref());
+                    }
+                    PaintSource::Image {
+                        hash: &image.image,
+                        image_rect: image.image_rect(size, asset.width(), asset.height()),
+                        tile: image.scale_mode == ScaleMode::Tile,
+                    }
+                }
+            };
+            let fill = FillPaint { source, opacity: paint.opacity };
+            if paint.blend_mode == BlendMode::Normal || !self.context.begin_layer() {
+                drawn |= self.context.draw_paint_transformed(&fill, bounds, shape, world, &self.viewport);
+                continue;
+            }
+            // Other modes blend the paint, drawn on its own, with what's below
+            drawn |= self.context.draw_paint_transformed(&fill, bounds, shape, world, &self.viewport);
+            if let Some(layer) = self.context.end_layer() {
+                self.context.composite_layer(&layer, None, 1.0, paint.blend_mode);
+                self.context.release_layer(layer);
+            }
+        }
+        drawn
+    }
+
     /// Draw a filled rectangle
     pub fn draw_rectangle(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color, corner_radius: f32) {
         let rect = Rect::new(x, y, width, height);
@@ -193,12 +364,6 @@ impl Renderer {
         self.context.draw_line(x1, y1, x2, y2, color, &self.viewport, width);
     }
 
-    /// Draw text placeholder (actual text rendering TBD)
-    pub fn draw_text_placeholder(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
-        // For now, just draw a small colored rectangle as placeholder
-        self.context.draw_rect(Rect::new(x, y, width, height), color, &self.viewport, 0.0);
-    }
-
     /// Draw selection handles around a node
     pub fn draw_selection(&mut self, x: f32, y: f32, width: f32, height: f32) {
         let handle_size = 8.0 / self.viewport.zoom;
@@ -314,6 +479,7 @@ impl Renderer {
     /// width, height: Size in canvas coordinates  
     /// r, g, b, a: Color components (0.0 - 1.0)
     /// corner_radius: Radius of rounded corners (0 for sharp corners)
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_rect_js(&mut self, x: f32, y: f32, width: f32, height: f32, r: f32, g: f32, b: f32, a: f32, corner_radius: f32) {
         let color = Color::new(r, g, b, a);
         self.draw_rectangle(x, y, width, height, color, corner_radius);
@@ -321,18 +487,21 @@ impl Renderer {
     
     /// Draw a rectangle outline/stroke
     /// stroke_width: Width of the stroke in canvas coordinates
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_rect_stroke_js(&mut self, x: f32, y: f32, width: f32, height: f32, r: f32, g: f32, b: f32, a: f32, stroke_width: f32) {
         let color = Color::new(r, g, b, a);
         self.draw_rectangle_stroke(x, y, width, height, color, stroke_width);
     }
     
     /// Draw a filled ellipse
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_ellipse_js(&mut self, x: f32, y: f32, width: f32, height: f32, r: f32, g: f32, b: f32, a: f32) {
         let color = Color::new(r, g, b, a);
         self.draw_ellipse(x, y, width, height, color);
     }
     
     /// Draw a line between two points
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_line_js(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, r: f32, g: f32, b: f32, a: f32, width: f32) {
         let color = Color::new(r, g, b, a);
         self.draw_line(x1, y1, x2, y2, color, width);
@@ -348,6 +517,13 @@ impl Renderer {
         self.draw_selection_rect(x, y, width, height);
     }
     
+    /// Free the GPU copies of every image. They are uploaded again as
+    /// needed, e.g. after switching to another document.
+    pub fn clear_image_cache(&mut self) {
+        self.context.clear_textures();
+        self.glyphs.clear();
+    }
+
     /// Set the dark canvas background color
     pub fn set_dark_background(&mut self) {
         self.background_color = Color::from_hex("#2C2C2C");
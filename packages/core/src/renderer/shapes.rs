@@ -1,7 +1,7 @@
 //! Shape rendering utilities - bezier curves, paths, etc.
 
-use crate::math::Vec2;
-use lyon::geom::{CubicBezierSegment, QuadraticBezierSegment, point};
+use crate::geometry::VectorNetwork;
+use lyon::geom::point;
 use lyon::path::Path;
 use lyon::tessellation::{BuffersBuilder, FillOptions, FillTessellator, VertexBuffers, StrokeOptions, StrokeTessellator};
 
@@ -50,8 +50,6 @@ pub fn tessellate_stroke(path: &Path, line_width: f32) -> VertexBuffers<Vertex,
 
 /// Build a rounded rectangle path
 pub fn rounded_rect_path(x: f32, y: f32, width: f32, height: f32, radius: f32) -> Path {
-    use lyon::path::builder::*;
-    
     let r = radius.min(width / 2.0).min(height / 2.0);
     let mut builder = Path::builder();
     
@@ -98,13 +96,11 @@ pub fn rounded_rect_path(x: f32, y: f32, width: f32, height: f32, radius: f32) -
 
 /// Build an ellipse path
 pub fn ellipse_path(cx: f32, cy: f32, rx: f32, ry: f32) -> Path {
-    use lyon::path::builder::*;
-    
     let mut builder = Path::builder();
     
     // Approximate ellipse with cubic beziers
     // Magic number for bezier control point distance: 0.5522847498
-    let k = 0.5522847498;
+    let k = 0.552_284_8;
     let kx = rx * k;
     let ky = ry * k;
     
@@ -141,3 +137,18 @@ pub fn ellipse_path(cx: f32, cy: f32, rx: f32, ry: f32) -> Path {
     builder.close();
     builder.build()
 }
+
+/// Build a path from a vector network's closed loops (curves are flattened)
+pub fn network_path(network: &VectorNetwork) -> Path {
+    let mut builder = Path::builder();
+    for contour in network.to_contours() {
+        let mut points = contour.iter();
+        let Some(first) = points.next() else { continue };
+        builder.begin(point(first[0], first[1]));
+        for p in points {
+            builder.line_to(point(p[0], p[1]));
+        }
+        builder.close();
+    }
+    builder.build()
+}
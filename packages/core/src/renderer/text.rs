@@ -0,0 +1,162 @@
+//! Drawing text
+//!
+//! Glyphs are rasterized by fontdue at their size on screen, rounded up to
+//! one of a few sizes so zooming doesn't rasterize them again at every step,
+//! and cached as textures keyed by font file, character and that size. The
+//! least recently drawn glyphs are deleted once the cache is full. Glyphs
+//! are drawn into a layer that is then filled:
+//!
+//! ```text
+//! glyph layer  every glyph's coverage, placed by the cached text layout
+//! solid fill   the layer tinted with the fill color
+//! other fills  the node's fills, drawn over its bounds, × glyph coverage
+//! ```
+//!
+//! Until a font is loaded, the glyph layer holds a bar per line instead.
+
+use super::{FillPaint, FillShape, PaintSource, Renderer};
+use crate::document::{text_style, BlendMode, Color, DecodedImage, Document, Node, PaintKind};
+use crate::math::{Rect, Transform2D};
+use crate::text::{FontFace, TextLayout};
+use std::collections::HashMap;
+
+/// Glyphs are rasterized at no more than this many pixels per em; larger
+/// ones are scaled up
+const MAX_GLYPH_PX: f32 = 256.0;
+
+/// Rasterized sizes are this many steps per doubling apart
+const GLYPH_SIZE_STEPS: f32 = 4.0;
+
+/// How many glyph textures are kept. Past this, the least recently drawn
+/// quarter is deleted.
+const MAX_GLYPH_TEXTURES: usize = 2048;
+
+/// The size a glyph shown at `px` pixels per em is rasterized at: the next
+/// of the sizes a quarter of a doubling apart
+fn glyph_px(px: f32) -> f32 {
+    let px = px.clamp(1.0, MAX_GLYPH_PX);
+    ((px.log2() * GLYPH_SIZE_STEPS).ceil() / GLYPH_SIZE_STEPS).exp2().min(MAX_GLYPH_PX)
+}
+
+/// Which glyph textures are uploaded, and when each was last drawn
+#[derive(Default)]
+pub(super) struct GlyphCache {
+    last_used: HashMap<String, u64>,
+    clock: u64,
+}
+
+impl GlyphCache {
+    /// Mark a glyph as drawn, returning whether it still needs uploading
+    fn touch(&mut self, key: &str) -> bool {
+        self.clock += 1;
+        match self.last_used.get_mut(key) {
+            Some(used) => {
+                *used = self.clock;
+                false
+            }
+            None => {
+                self.last_used.insert(key.to_string(), self.clock);
+                true
+            }
+        }
+    }
+
+    /// Once over the cap, remove the least recently drawn glyphs, returning
+    /// their keys
+    fn evict(&mut self) -> Vec<String> {
+        if self.last_used.len() <= MAX_GLYPH_TEXTURES {
+            return Vec::new();
+        }
+        let mut keys: Vec<(u64, String)> = self.last_used.iter().map(|(key, &used)| (used, key.clone())).collect();
+        keys.sort_unstable();
+        keys.truncate(self.last_used.len() - MAX_GLYPH_TEXTURES * 3 / 4);
+        for (_, key) in &keys {
+            self.last_used.remove(key);
+        }
+        keys.into_iter().map(|(_, key)| key).collect()
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.last_used.clear();
+    }
+}
+
+impl Renderer {
+    /// Draw a text node's glyphs with its fills
+    pub(super) fn draw_text(&mut self, document: &Document, node: &Node, world: &Transform2D) {
+        let layout = document.text_layout(node);
+        let face = document.font_for(node);
+        let glyphs = self.draw_in_layer(|renderer| match face {
+            Some(face) => renderer.draw_glyphs(face, node, &layout, world),
+            None => renderer.draw_text_bars(node, &layout, world),
+        });
+        let Some(glyphs) = glyphs else {
+            return;
+        };
+
+        // A single solid fill only needs the glyphs tinted
+        let fills = node.fills();
+        let drawn: Vec<_> = fills.iter().filter(|paint| paint.is_drawn()).collect();
+        if let [paint] = drawn.as_slice() {
+            if let PaintKind::Solid { color } = &paint.kind {
+                if paint.blend_mode == BlendMode::Normal {
+                    let tint = Color { a: color.a * paint.opacity, ..*color };
+                    self.context.composite_layer(&glyphs, Some(tint), 1.0, BlendMode::Normal);
+                    self.context.release_layer(glyphs);
+                    return;
+                }
+            }
+        }
+
+        let filled = self.draw_in_layer(|renderer| {
+            renderer.draw_fills(document, node, world, FillShape::Rect(0.0));
+        });
+        if let Some(filled) = filled {
+            self.context.mask_layer(&filled, &glyphs);
+            self.context.composite_layer(&filled, None, 1.0, BlendMode::Normal);
+            self.context.release_layer(filled);
+        }
+        self.context.release_layer(glyphs);
+    }
+
+    fn draw_glyphs(&mut self, face: &FontFace, node: &Node, layout: &TextLayout, world: &Transform2D) {
+        let font_size = text_style(node).font_size;
+        let px = glyph_px(font_size * self.viewport.zoom);
+        let scale = font_size / px;
+        let viewport = self.viewport;
+        for glyph in layout.glyphs.iter().filter(|glyph| !glyph.character.is_whitespace()) {
+            let key = format!("glyph:{:x}:{}:{}", face.file_hash(), glyph.character as u32, px);
+            if self.glyphs.touch(&key) || !self.context.has_texture(&key) {
+                let (metrics, coverage) = face.font().rasterize(glyph.character, px);
+                let image = (!coverage.is_empty()).then(|| DecodedImage {
+                    width: metrics.width as u32,
+                    height: metrics.height as u32,
+                    pixels: coverage.iter().flat_map(|&alpha| [255, 255, 255, alpha]).collect(),
+                });
+                self.context.upload_image(&key, image.as_ref());
+            }
+            let metrics = face.font().metrics(glyph.character, px);
+            let rect = Rect::new(
+                glyph.x + metrics.xmin as f32 * scale,
+                glyph.baseline - (metrics.ymin as f32 + metrics.height as f32) * scale,
+                metrics.width as f32 * scale,
+                metrics.height as f32 * scale,
+            );
+            let paint = FillPaint { source: PaintSource::Image { hash: &key, image_rect: rect, tile: false }, opacity: 1.0 };
+            self.context.draw_paint_transformed(&paint, rect, FillShape::Rect(0.0), world, &viewport);
+        }
+        for key in self.glyphs.evict() {
+            self.context.delete_texture(&key);
+        }
+    }
+
+    /// Stand-in for text whose font isn't loaded: a bar per line, about as
+    /// tall as its lowercase letters
+    fn draw_text_bars(&self, node: &Node, layout: &TextLayout, world: &Transform2D) {
+        let font_size = text_style(node).font_size;
+        for line in layout.lines.iter().filter(|line| line.width > 0.0) {
+            let bar = Rect::new(line.x, line.baseline - font_size * 0.5, line.width, font_size * 0.5);
+            self.context.draw_rect_transformed(bar, world, Color::white(), &self.viewport, 0.0);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! Viewport management
 
+use crate::math::Rect;
 use wasm_bindgen::prelude::*;
 
 /// Viewport state for the canvas
@@ -31,10 +32,11 @@ impl Viewport {
     }
 
     /// Fit a rectangle in the viewport
+    #[allow(clippy::too_many_arguments)]
     pub fn fit_rect(&mut self, x: f32, y: f32, width: f32, height: f32, screen_width: f32, screen_height: f32, padding: f32) {
         let zoom_x = (screen_width - padding * 2.0) / width;
         let zoom_y = (screen_height - padding * 2.0) / height;
-        self.zoom = zoom_x.min(zoom_y).max(0.01).min(256.0);
+        self.zoom = zoom_x.min(zoom_y).clamp(0.01, 256.0);
         
         let center_x = x + width / 2.0;
         let center_y = y + height / 2.0;
@@ -43,6 +45,18 @@ impl Viewport {
     }
 }
 
+impl Viewport {
+    /// The part of the canvas visible on a screen of the given size
+    pub fn visible_rect(&self, screen_width: f32, screen_height: f32) -> Rect {
+        Rect::new(
+            -self.x / self.zoom,
+            -self.y / self.zoom,
+            screen_width / self.zoom,
+            screen_height / self.zoom,
+        )
+    }
+}
+
 impl Default for Viewport {
     fn default() -> Self {
         Self::new(0.0, 0.0, 1.0)
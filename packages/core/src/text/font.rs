@@ -0,0 +1,95 @@
+//! Fonts
+//!
+//! Font files are registered by family, weight and style. A text node gets
+//! the face of its `FontFamily` with the nearest `FontWeight`, preferring its
+//! `FontStyle`. A family that isn't loaded falls back to the first family
+//! that is.
+
+use fontdue::{Font, FontSettings};
+
+/// Errors from reading a font file
+#[derive(Debug, Clone, PartialEq)]
+pub enum FontError {
+    /// Not a TrueType or OpenType font, or a damaged one
+    Decode(String),
+}
+
+impl std::fmt::Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontError::Decode(message) => write!(f, "Can't read font: {message}"),
+        }
+    }
+}
+
+/// A loaded font file
+pub struct FontFace {
+    family: String,
+    weight: f32,
+    italic: bool,
+    font: Font,
+}
+
+impl FontFace {
+    pub fn family(&self) -> &str {
+        &self.family
+    }
+
+    pub fn weight(&self) -> f32 {
+        self.weight
+    }
+
+    pub fn italic(&self) -> bool {
+        self.italic
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    /// Identifies the font file, e.g. to cache its glyphs
+    pub fn file_hash(&self) -> usize {
+        self.font.file_hash()
+    }
+}
+
+/// The fonts text can be laid out with
+#[derive(Default)]
+pub struct FontLibrary {
+    faces: Vec<FontFace>,
+}
+
+impl FontLibrary {
+    /// Read a TrueType or OpenType file and register it, replacing a face
+    /// with the same family, weight and style
+    pub fn add(&mut self, family: &str, weight: f32, italic: bool, bytes: &[u8]) -> Result<(), FontError> {
+        let font = Font::from_bytes(bytes, FontSettings::default()).map_err(|e| FontError::Decode(e.into()))?;
+        self.faces.retain(|face| !(same_family(&face.family, family) && face.weight == weight && face.italic == italic));
+        self.faces.push(FontFace { family: family.into(), weight, italic, font });
+        Ok(())
+    }
+
+    /// Whether any face of a family is loaded
+    pub fn has_family(&self, family: &str) -> bool {
+        self.faces.iter().any(|face| same_family(&face.family, family))
+    }
+
+    /// The face to draw a family at a weight and style with (see the module
+    /// docs). `None` if no font is loaded at all.
+    pub fn find(&self, family: &str, weight: f32, italic: bool) -> Option<&FontFace> {
+        let family = if self.has_family(family) { family } else { &self.faces.first()?.family };
+        self.faces
+            .iter()
+            .filter(|face| same_family(&face.family, family))
+            .min_by(|a, b| {
+                let distance = |face: &FontFace| ((face.weight - weight).abs(), face.italic != italic);
+                let (a, b) = (distance(a), distance(b));
+                a.0.total_cmp(&b.0).then(a.1.cmp(&b.1))
+            })
+    }
+}
+
+/// Family names match ignoring case
+fn same_family(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
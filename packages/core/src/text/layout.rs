@@ -0,0 +1,244 @@
+//! Line breaking and glyph placement (see the module docs)
+
+use fontdue::Font;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Lines this much wider than the maximum still fit, so text laid out at
+/// its own measured width doesn't wrap
+const FIT_EPSILON: f32 = 1e-3;
+
+/// Where glyph metrics come from
+#[derive(Clone, Copy)]
+pub enum TextFont<'a> {
+    Font(&'a Font),
+    /// Rough metrics for text whose font isn't loaded: every character is
+    /// half an em wide and lines are 1.2 em
+    Fallback,
+}
+
+impl TextFont<'_> {
+    fn advance(&self, character: char, size: f32) -> f32 {
+        match self {
+            TextFont::Font(font) => font.metrics(character, size).advance_width,
+            TextFont::Fallback => size * 0.5,
+        }
+    }
+
+    fn kern(&self, left: char, right: char, size: f32) -> f32 {
+        match self {
+            TextFont::Font(font) => font.horizontal_kern(left, right, size).unwrap_or(0.0),
+            TextFont::Fallback => 0.0,
+        }
+    }
+
+    /// Ascent and descent (both positive) and the natural line height
+    fn line_metrics(&self, size: f32) -> (f32, f32, f32) {
+        let metrics = match self {
+            TextFont::Font(font) => font.horizontal_line_metrics(size),
+            TextFont::Fallback => None,
+        };
+        match metrics {
+            Some(metrics) => (metrics.ascent, -metrics.descent, metrics.new_line_size),
+            None => (size * 0.9, size * 0.3, size * 1.2),
+        }
+    }
+}
+
+/// How lines are placed across the layout width
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    pub fn parse(align: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::from(align)).ok()
+    }
+}
+
+/// The text properties layout depends on, besides the font
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub font_size: f32,
+    /// Distance from one line to the next; `None` uses the font's
+    pub line_height: Option<f32>,
+    pub letter_spacing: f32,
+    pub align: TextAlign,
+}
+
+/// A character placed by layout
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PositionedGlyph {
+    pub character: char,
+    /// Left edge of the character's advance
+    pub x: f32,
+    /// The baseline it sits on
+    pub baseline: f32,
+    pub advance: f32,
+}
+
+/// One laid out line
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TextLine {
+    /// The line's glyphs are `glyphs[start..end]`
+    pub start: usize,
+    pub end: usize,
+    /// Left edge, after alignment
+    pub x: f32,
+    /// Width without trailing spaces
+    pub width: f32,
+    pub top: f32,
+    pub baseline: f32,
+}
+
+/// Glyph positions and measured bounds of a text
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    pub lines: Vec<TextLine>,
+    /// Width of the widest line
+    pub width: f32,
+    /// Height of all the lines
+    pub height: f32,
+}
+
+/// Lay out a text, wrapping lines to `max_width` if given
+pub fn layout_text(text: &str, font: TextFont, style: &TextStyle, max_width: Option<f32>) -> TextLayout {
+    let size = style.font_size.max(0.0);
+    let (ascent, descent, natural_height) = font.line_metrics(size);
+    let line_height = style.line_height.filter(|height| *height > 0.0).unwrap_or(natural_height);
+    let mut layout = TextLayout::default();
+
+    for paragraph in text.split('\n') {
+        let chars: Vec<char> = paragraph.trim_end_matches('\r').chars().collect();
+        for range in break_paragraph(&chars, font, style, max_width) {
+            let top = layout.lines.len() as f32 * line_height;
+            let baseline = top + (line_height - ascent - descent) / 2.0 + ascent;
+            let start = layout.glyphs.len();
+            let mut pen = 0.0;
+            let mut width: f32 = 0.0;
+            let mut previous = None;
+            for &character in &chars[range] {
+                if let Some(previous) = previous {
+                    pen += font.kern(previous, character, size);
+                }
+                let advance = font.advance(character, size);
+                layout.glyphs.push(PositionedGlyph { character, x: pen, baseline, advance });
+                if !character.is_whitespace() {
+                    width = pen + advance;
+                }
+                pen += advance + style.letter_spacing;
+                previous = Some(character);
+            }
+            let end = layout.glyphs.len();
+            layout.lines.push(TextLine { start, end, x: 0.0, width, top, baseline });
+            layout.width = layout.width.max(width);
+        }
+    }
+    layout.height = layout.lines.len() as f32 * line_height;
+
+    let layout_width = max_width.unwrap_or(layout.width);
+    for line in &mut layout.lines {
+        line.x = match style.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => (layout_width - line.width) / 2.0,
+            TextAlign::Right => layout_width - line.width,
+        };
+        for glyph in &mut layout.glyphs[line.start..line.end] {
+            glyph.x += line.x;
+        }
+    }
+    layout
+}
+
+/// Split a paragraph into lines that fit `max_width`, breaking after spaces
+/// where possible. Spaces at a break stay at the end of the line.
+fn break_paragraph(chars: &[char], font: TextFont, style: &TextStyle, max_width: Option<f32>) -> Vec<Range<usize>> {
+    // Without a maximum, only `\n` breaks lines
+    let max_width = max_width.unwrap_or(f32::INFINITY);
+    let size = style.font_size.max(0.0);
+    let mut lines = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let mut pen = 0.0;
+        let mut end = start;
+        let mut last_break = None;
+        while end < chars.len() {
+            let character = chars[end];
+            let kern = if end > start { font.kern(chars[end - 1], character, size) } else { 0.0 };
+            let advance = font.advance(character, size);
+            // Every line keeps at least one character
+            if !character.is_whitespace() && end > start && pen + kern + advance > max_width + FIT_EPSILON {
+                break;
+            }
+            pen += kern + advance + style.letter_spacing;
+            end += 1;
+            if character.is_whitespace() && chars.get(end).is_some_and(|next| !next.is_whitespace()) {
+                last_break = Some(end);
+            }
+        }
+        if end < chars.len() {
+            end = last_break.unwrap_or(end);
+        }
+        lines.push(start..end);
+        start = end;
+    }
+    if lines.is_empty() {
+        lines.push(0..0);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(align: TextAlign) -> TextStyle {
+        TextStyle { font_size: 10.0, line_height: None, letter_spacing: 0.0, align }
+    }
+
+    fn line_text(layout: &TextLayout, line: &TextLine) -> String {
+        layout.glyphs[line.start..line.end].iter().map(|glyph| glyph.character).collect()
+    }
+
+    #[test]
+    fn test_line_breaking_and_alignment() {
+        // Fallback metrics: 5 wide per character, 12 per line
+        let layout = layout_text("Hello brave new world", TextFont::Fallback, &style(TextAlign::Center), Some(60.0));
+        let lines: Vec<String> = layout.lines.iter().map(|line| line_text(&layout, line)).collect();
+        assert_eq!(lines, vec!["Hello brave ", "new world"]);
+        assert_eq!((layout.width, layout.height), (55.0, 24.0));
+        assert_eq!((layout.lines[0].x, layout.lines[1].x), (2.5, 7.5));
+        assert_eq!(layout.glyphs[12].x, 7.5);
+        assert_eq!(layout.lines[1].baseline, 21.0);
+
+        // Words longer than the line break anywhere; empty lines keep their height
+        let layout = layout_text("abcdefghij\n\nb", TextFont::Fallback, &style(TextAlign::Right), Some(20.0));
+        let lines: Vec<String> = layout.lines.iter().map(|line| line_text(&layout, line)).collect();
+        assert_eq!(lines, vec!["abcd", "efgh", "ij", "", "b"]);
+        assert_eq!(layout.lines[2].x, 10.0);
+        assert_eq!(layout.height, 60.0);
+
+        // Laid out at its own width, text doesn't wrap
+        let wide = layout_text("Hello brave new world", TextFont::Fallback, &style(TextAlign::Left), None);
+        let again = layout_text("Hello brave new world", TextFont::Fallback, &style(TextAlign::Left), Some(wide.width));
+        assert_eq!((wide.lines.len(), again.lines.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_letter_spacing_and_line_height() {
+        let style = TextStyle { line_height: Some(20.0), letter_spacing: 2.0, ..style(TextAlign::Left) };
+        let layout = layout_text("abc", TextFont::Fallback, &style, None);
+        let positions: Vec<f32> = layout.glyphs.iter().map(|glyph| glyph.x).collect();
+        assert_eq!(positions, vec![0.0, 7.0, 14.0]);
+        // No spacing after the last character
+        assert_eq!(layout.width, 19.0);
+        // The 12 high line box is centred in the 20 high line
+        assert_eq!((layout.height, layout.lines[0].baseline), (20.0, 13.0));
+    }
+}
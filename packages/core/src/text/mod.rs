@@ -0,0 +1,34 @@
+//! Text layout
+//!
+//! Turns a string and its text properties into positioned glyphs, using
+//! font files loaded with fontdue:
+//!
+//! ```text
+//! "Hello brave new world"        FontSize 16, LetterSpacing 0,
+//!  max width 90                  TextAlign "center"
+//!         │
+//!         ▼
+//!  ┌──────────────────────────┐  line 0: "Hello brave " (trailing space
+//!  │      Hello brave         │          hangs past the edge)
+//!  │       new world          │  line 1: "new world"
+//!  └──────────────────────────┘  bounds: widest line × 2 line heights
+//! ```
+//!
+//! - Lines break at `\n` and, within a maximum width, after spaces. A word
+//!   longer than the whole line is broken between characters.
+//! - Letter spacing is added after every character but the last on a line.
+//!   Kerning pairs from the font apply between neighbours on a line.
+//! - The line height is `LineHeight` if set, otherwise the font's ascent,
+//!   descent and line gap. Glyphs sit on a baseline centred in their line.
+//! - Alignment moves each line within the layout width: the maximum width
+//!   if there is one, otherwise the widest line.
+//!
+//! Positions are in the text node's local space, in canvas units, so they
+//! don't change with zoom. Until a font is loaded, layout falls back to
+//! rough metrics (see [`TextFont::Fallback`]) so nodes still get a size.
+
+mod font;
+mod layout;
+
+pub use font::*;
+pub use layout::*;
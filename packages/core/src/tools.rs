@@ -3,7 +3,7 @@
 //! Tools for selecting, drawing, and editing elements.
 
 use wasm_bindgen::prelude::*;
-use crate::document::ObjectId;
+use crate::document::{Document, ObjectId};
 
 /// Tool types available in Anatsui
 #[wasm_bindgen]
@@ -160,6 +160,23 @@ impl ToolState {
     pub fn is_selected(&self, id: ObjectId) -> bool {
         self.selection.contains(&id)
     }
+
+    /// Select the nodes touched by the drag bounds (marquee selection).
+    /// With `additive` (shift-drag) the current selection is kept.
+    pub fn select_in_marquee(&mut self, document: &Document, additive: bool) {
+        if !additive {
+            self.selection.clear();
+        }
+        let hits = document.nodes_in_marquee(
+            self.drag_bounds_x(),
+            self.drag_bounds_y(),
+            self.drag_bounds_width(),
+            self.drag_bounds_height(),
+        );
+        for id in hits {
+            self.add_to_selection(id);
+        }
+    }
 }
 
 impl ToolState {
@@ -237,6 +254,14 @@ impl HitResult {
         }
     }
 
+    pub fn edge(id: ObjectId, index: u32) -> Self {
+        Self {
+            hit_type: HitType::Edge,
+            object_id: Some(id),
+            handle_index: Some(index),
+        }
+    }
+
     pub fn hit_type(&self) -> HitType {
         self.hit_type
     }
@@ -245,6 +270,7 @@ impl HitResult {
         self.object_id
     }
 
+    /// Index of the handle or edge that was hit (see `document::hit_test`)
     pub fn handle_index(&self) -> Option<u32> {
         self.handle_index
     }